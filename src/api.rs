@@ -19,17 +19,53 @@ pub mod request {
         #[serde(rename = "rawPath", default)]
         pub raw_path: Option<String>,
         
+        #[serde(rename = "queryStringParameters", default)]
+        pub query_string_parameters: Option<HashMap<String, String>>,
+        
+        #[serde(rename = "multiValueQueryStringParameters", default)]
+        pub multi_value_query_string_parameters: Option<HashMap<String, Vec<String>>>,
+        
+        #[serde(default)]
+        pub headers: Option<HashMap<String, String>>,
+        
+        #[serde(rename = "multiValueHeaders", default)]
+        pub multi_value_headers: Option<HashMap<String, Vec<String>>>,
+        
+        // HTTP API (payload v2) events move Cookie headers here as "name=value" strings
+        #[serde(default)]
+        pub cookies: Option<Vec<String>>,
+        
         #[serde(rename = "requestContext", default)]
         pub request_context: Option<RequestContext>,
         
         #[serde(default)]
         pub body: Option<String>,
+        
+        #[serde(rename = "isBase64Encoded", default)]
+        pub is_base64_encoded: bool,
+        
+        // EventBridge scheduled events carry these instead of any HTTP fields
+        #[serde(default)]
+        pub source: Option<String>,
+        
+        #[serde(rename = "detail-type", default)]
+        pub detail_type: Option<String>,
     }
 
     #[derive(Deserialize, Serialize, Debug)]
     pub struct RequestContext {
         #[serde(rename = "http", default)]
         pub http: Option<HttpContext>,
+        
+        // REST API (payload v1) events carry the caller here instead of under http
+        #[serde(default)]
+        pub identity: Option<Identity>,
+    }
+
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct Identity {
+        #[serde(rename = "sourceIp", default)]
+        pub source_ip: Option<String>,
     }
 
     #[derive(Deserialize, Serialize, Debug)]
@@ -39,6 +75,156 @@ pub mod request {
         
         #[serde(rename = "path", default)]
         pub path: Option<String>,
+        
+        #[serde(rename = "sourceIp", default)]
+        pub source_ip: Option<String>,
+    }
+
+    impl Request {
+        // Look up a single query string parameter
+        pub fn query_param(&self, name: &str) -> Option<&str> {
+            self.query_string_parameters.as_ref()
+                .and_then(|params| params.get(name))
+                .map(|value| value.as_str())
+        }
+        
+        // Look up every value of a repeated query string parameter. REST APIs deliver
+        // repeats in the multi-value map; HTTP APIs join them with commas.
+        pub fn query_params(&self, name: &str) -> Vec<&str> {
+            if let Some(values) = self.multi_value_query_string_parameters.as_ref().and_then(|params| params.get(name)) {
+                return values.iter().map(|value| value.as_str()).collect();
+            }
+            
+            self.query_param(name)
+                .map(|value| value.split(',').collect())
+                .unwrap_or_default()
+        }
+        
+        // Whether this is a keep-warm ping from an EventBridge schedule rather than an HTTP request
+        pub fn is_scheduled_ping(&self) -> bool {
+            self.source.as_deref() == Some("aws.events")
+                && self.detail_type.as_deref() == Some("Scheduled Event")
+        }
+        
+        // Whether the caller asked for the bare resource via ?envelope=false or X-Raw-Response
+        pub fn wants_raw_response(&self) -> bool {
+            self.query_param("envelope").is_some_and(|value| value.eq_ignore_ascii_case("false"))
+                || self.header("X-Raw-Response").is_some_and(|value| {
+                    value.eq_ignore_ascii_case("true") || value == "1"
+                })
+        }
+        
+        // The caller's IP as seen by API Gateway, falling back to the first X-Forwarded-For hop
+        pub fn client_ip(&self) -> Option<&str> {
+            let context = self.request_context.as_ref();
+            context.and_then(|ctx| ctx.http.as_ref()).and_then(|http| http.source_ip.as_deref())
+                .or_else(|| context.and_then(|ctx| ctx.identity.as_ref()).and_then(|identity| identity.source_ip.as_deref()))
+                .or_else(|| self.header("X-Forwarded-For").and_then(|value| value.split(',').next()))
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+        }
+        
+        // Look up a header; API Gateway varies header-name casing, so match case-insensitively.
+        // REST API events may only carry a header in the multi-value map, so fall back to its first value.
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers.as_ref()
+                .and_then(|headers| headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)))
+                .map(|(_, value)| value.as_str())
+                .or_else(|| self.header_values(name).into_iter().next())
+        }
+        
+        // Look up every value of a repeated header, preferring the multi-value map.
+        // HTTP API events carry cookies outside the headers, so Cookie reads those as its values.
+        pub fn header_values(&self, name: &str) -> Vec<&str> {
+            if let Some(cookies) = self.cookies.as_ref().filter(|_| name.eq_ignore_ascii_case("Cookie")) {
+                return cookies.iter().map(|cookie| cookie.as_str()).collect();
+            }
+            
+            let multi_value = self.multi_value_headers.as_ref()
+                .and_then(|headers| headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)));
+            if let Some((_, values)) = multi_value {
+                return values.iter().map(|value| value.as_str()).collect();
+            }
+            
+            self.headers.as_ref()
+                .and_then(|headers| headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)))
+                .map(|(_, value)| vec![value.as_str()])
+                .unwrap_or_default()
+        }
+    }
+
+    // Parameters of a classic WADO-URI retrieval, e.g.
+    // ?requestType=WADO&studyUID=..&seriesUID=..&objectUID=..&contentType=application/dicom
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct WadoUriQuery {
+        pub study_uid: String,
+        pub series_uid: Option<String>,
+        pub object_uid: String,
+        // Acceptable media types in order of preference
+        pub content_types: Vec<String>,
+        pub frame_number: Option<u32>,
+        pub window_center: Option<f64>,
+        pub window_width: Option<f64>,
+    }
+
+    // The standard's default when contentType is omitted
+    const WADO_DEFAULT_CONTENT_TYPE: &str = "image/jpeg";
+
+    impl Request {
+        // Parse and check the WADO-URI query parameters, describing the first problem found
+        pub fn wado_uri_query(&self) -> Result<WadoUriQuery, String> {
+            match self.query_param("requestType").map(str::trim) {
+                Some(request_type) if request_type.eq_ignore_ascii_case("WADO") => {},
+                Some(request_type) => return Err(format!("Unsupported requestType: {}", request_type)),
+                None => return Err("requestType=WADO is required".to_string()),
+            }
+            
+            let required = |name: &str| -> Result<String, String> {
+                self.query_param(name)
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+                    .ok_or_else(|| format!("{} is required", name))
+            };
+            let number = |name: &str| -> Result<Option<f64>, String> {
+                match self.query_param(name) {
+                    Some(value) => match value.trim().parse::<f64>() {
+                        Ok(number) if number.is_finite() => Ok(Some(number)),
+                        _ => Err(format!("{} must be a number", name)),
+                    },
+                    None => Ok(None),
+                }
+            };
+            
+            let content_types: Vec<String> = self.query_param("contentType")
+                .unwrap_or(WADO_DEFAULT_CONTENT_TYPE)
+                .split(',')
+                .map(|content_type| content_type.trim().to_lowercase())
+                .filter(|content_type| !content_type.is_empty())
+                .collect();
+            
+            let frame_number = match self.query_param("frameNumber").map(|value| value.trim().parse::<u32>()) {
+                Some(Ok(frame)) if frame > 0 => Some(frame),
+                Some(_) => return Err("frameNumber must be a positive frame number".to_string()),
+                None => None,
+            };
+            
+            Ok(WadoUriQuery {
+                study_uid: required("studyUID")?,
+                series_uid: self.query_param("seriesUID")
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty()),
+                object_uid: required("objectUID")?,
+                content_types,
+                frame_number,
+                window_center: number("windowCenter")?,
+                window_width: number("windowWidth")?,
+            })
+        }
+    }
+
+    // Check that a path segment is a case ID as generated by create_case (a UUID)
+    pub fn is_valid_case_id(case_id: &str) -> bool {
+        uuid::Uuid::parse_str(case_id).is_ok()
     }
 
     // Extract method and path from the Lambda request
@@ -62,6 +248,14 @@ pub mod request {
         
         (http_method, path)
     }
+
+    // Strip a single trailing slash so /api/cases and /api/cases/ route the same; root is left alone
+    pub fn normalize_path(path: &str) -> String {
+        match path.strip_suffix('/') {
+            Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+            _ => path.to_string(),
+        }
+    }
 }
 
 // Response handling
@@ -87,21 +281,37 @@ pub mod response {
         headers.insert("Access-Control-Allow-Methods".to_string(), 
                       "GET, POST, PUT, DELETE, OPTIONS".to_string());
         headers.insert("Access-Control-Allow-Headers".to_string(), 
-                      "Content-Type, Authorization, X-Requested-With".to_string());
+                      "Content-Type, Authorization, X-Requested-With, X-Api-Key".to_string());
         
         headers
     }
 
     // Common responses
-    pub fn options_response() -> Response {
+    
+    // Preflight response advertising the methods the requested path supports
+    pub fn options_response(methods: &[&str]) -> Response {
+        let allowed = methods.iter()
+            .copied()
+            .chain(std::iter::once("OPTIONS"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        
+        let mut headers = create_cors_headers();
+        headers.insert("Access-Control-Allow-Methods".to_string(), allowed.clone());
+        headers.insert("Allow".to_string(), allowed);
+        
         Response {
             status_code: 200,
-            headers: create_cors_headers(),
+            headers,
             is_base64_encoded: false,
             body: "".to_string(),
         }
     }
 
+    pub fn forbidden(message: &str) -> Result<Response, LambdaError> {
+        Response::new(403, ErrorResponse::forbidden(message))
+    }
+
     pub fn not_found(message: &str) -> Result<Response, LambdaError> {
         Response::new(404, ErrorResponse::not_found(message))
     }
@@ -110,9 +320,39 @@ pub mod response {
         Response::new(400, ErrorResponse::bad_request(message))
     }
 
+    pub fn conflict(message: &str) -> Result<Response, LambdaError> {
+        Response::new(409, ErrorResponse::conflict(message))
+    }
+
+    pub fn payload_too_large(message: &str) -> Result<Response, LambdaError> {
+        Response::new(413, ErrorResponse::payload_too_large(message))
+    }
+
+    pub fn unsupported_media_type(message: &str) -> Result<Response, LambdaError> {
+        Response::new(415, ErrorResponse::unsupported_media_type(message))
+    }
+
+    pub fn unprocessable(message: &str) -> Result<Response, LambdaError> {
+        Response::new(422, ErrorResponse::unprocessable(message))
+    }
+
+    pub fn too_many_requests(message: &str, retry_after_secs: u64) -> Result<Response, LambdaError> {
+        let mut response = Response::new(429, ErrorResponse::too_many_requests(message))?;
+        response.headers.insert("Retry-After".to_string(), retry_after_secs.to_string());
+        Ok(response)
+    }
+
     pub fn server_error(message: &str) -> Result<Response, LambdaError> {
         Response::new(500, ErrorResponse::server_error(message.to_string()))
     }
+
+    pub fn bad_gateway(message: &str) -> Result<Response, LambdaError> {
+        Response::new(502, ErrorResponse::bad_gateway(message))
+    }
+
+    pub fn gateway_timeout(message: &str) -> Result<Response, LambdaError> {
+        Response::new(504, ErrorResponse::upstream_timeout(message))
+    }
     
     impl Response {
         pub fn new(status_code: u16, body: impl Serialize) -> Result<Self, LambdaError> {
@@ -131,10 +371,86 @@ pub mod response {
             self
         }
         
+        pub fn into_text(mut self, text: String) -> Self {
+            self.body = text;
+            self
+        }
+        
+        // Replace a successful {success, data, error} body with just its data;
+        // error bodies and non-JSON responses are left as they are
+        pub fn without_envelope(mut self) -> Self {
+            if self.is_base64_encoded || !(200..300).contains(&self.status_code) {
+                return self;
+            }
+            
+            if let Ok(serde_json::Value::Object(mut envelope)) = serde_json::from_str(&self.body) {
+                if envelope.get("success") == Some(&serde_json::Value::Bool(true)) {
+                    if let Some(data) = envelope.remove("data") {
+                        self.body = data.to_string();
+                    }
+                }
+            }
+            self
+        }
+        
+        // Base64-encode a binary body; Content-Length is the size of the decoded bytes
+        // so clients can show download progress
         pub fn into_binary(mut self, data: Vec<u8>) -> Self {
+            self.headers.insert("Content-Length".to_string(), data.len().to_string());
             self.is_base64_encoded = true;
             self.body = BASE64.encode(data);
             self
         }
+        
+        // Add the hex SHA-256 of the returned bytes so clients can check what they received.
+        // Cross-origin clients can only read the header when it is exposed.
+        pub fn with_content_sha256(mut self, data: &[u8]) -> Self {
+            let digest = ring::digest::digest(&ring::digest::SHA256, data);
+            let hex: String = digest.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+            self.headers.insert("X-Content-SHA256".to_string(), hex);
+            self.headers.insert("Access-Control-Expose-Headers".to_string(), "X-Content-SHA256".to_string());
+            self
+        }
+        
+        // Binary body offered as a download under the given filename
+        pub fn into_binary_named(mut self, data: Vec<u8>, filename: &str) -> Self {
+            self.headers.insert("Content-Disposition".to_string(),
+                                format!("attachment; filename=\"{}\"", filename.replace('"', "")));
+            self.into_binary(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::request::{Request, normalize_path};
+
+    #[test]
+    fn header_lookup_ignores_case_and_reads_v2_cookies() {
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "headers": { "content-type": "application/json" },
+            "multiValueHeaders": { "Accept-Encoding": ["gzip", "br"] },
+            "cookies": ["session=abc", "theme=dark"]
+        })).expect("request");
+        assert_eq!(request.header("Content-Type"), Some("application/json"));
+        assert_eq!(request.header_values("accept-encoding"), vec!["gzip", "br"]);
+        assert_eq!(request.header("ACCEPT-ENCODING"), Some("gzip"));
+        assert_eq!(request.header_values("Cookie"), vec!["session=abc", "theme=dark"]);
+        assert_eq!(request.header("Range"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn normalize_path_strips_one_trailing_slash() {
+        assert_eq!(normalize_path("/api/cases/"), normalize_path("/api/cases"));
+        assert_eq!(normalize_path("/api/cases/"), "/api/cases");
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("/api/cases//"), "/api/cases/");
+    }
+
+    #[test]
+    fn normalize_path_keeps_multi_segment_paths() {
+        let path = "/api/studies/1.2.3/series/1.2.3.1/instances/1.2.3.1.1";
+        assert_eq!(normalize_path(path), path);
+        assert_eq!(normalize_path(&format!("{}/", path)), path);
+    }
+}
@@ -0,0 +1,392 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+use anyhow::{anyhow, Result};
+
+// Defaults used when a setting is not configured
+const DEFAULT_BUCKET: &str = "radiology-teaching-files";
+const DEFAULT_TABLE_NAME: &str = "RadiologyTeachingFiles";
+const DEFAULT_MAX_LIST_ITEMS: usize = 500;
+const DEFAULT_MAX_FRAMES: i32 = 5000;
+const DEFAULT_MAX_INSTANCES_PER_UPLOAD: usize = 2000;
+// Comfortably below DynamoDB's 400KB item limit
+const DEFAULT_ITEM_OFFLOAD_BYTES: usize = 300 * 1024;
+const DEFAULT_DICOM_URL_MAX_BYTES: usize = 100 * 1024 * 1024;
+const DEFAULT_RATE_LIMIT_PER_MIN: u32 = 30;
+const DEFAULT_AWS_CALL_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_DICOM_PARSE_CONCURRENCY: usize = 1;
+const DEFAULT_CASE_CACHE_TTL_SECS: u64 = 30;
+const DEFAULT_TAGS_CACHE_TTL_SECS: u64 = 60;
+const DEFAULT_SHARE_LINK_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+const DEFAULT_SLOW_REQUEST_MS: u64 = 3000;
+
+/// Root for derived UIDs; 2.25 is the standard root for UIDs built from a 128-bit value
+pub const DEFAULT_UID_ROOT: &str = "2.25";
+
+// Ceiling on DICOM_PARSE_CONCURRENCY, well above the vCPUs of any Lambda size
+const MAX_DICOM_PARSE_CONCURRENCY: usize = 16;
+
+// Derived UIDs append a 128-bit value to the root and must fit in 64 characters
+const MAX_UID_ROOT_LEN: usize = 31;
+
+// Longest S3 object key
+const MAX_S3_KEY_LEN: usize = 1024;
+
+/// Settings read from environment variables once per container
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Where cases and uploads live
+    pub bucket: String,
+    pub table_name: String,
+    pub region: Option<String>,
+
+    // Size limits
+    pub max_list_items: usize,
+    pub max_frames: i32,
+    pub max_instances_per_upload: usize,
+    pub item_offload_threshold_bytes: usize,
+    pub dicom_url_max_bytes: usize,
+
+    // Request handling; a rate limit of 0 disables it
+    pub rate_limit_per_min: u32,
+    pub rate_limit_get_per_min: u32,
+    pub aws_call_timeout_ms: u64,
+    pub dicom_parse_concurrency: usize,
+    pub slow_request_ms: u64,
+
+    // Cache lifetimes; 0 disables the cache
+    pub case_cache_ttl_secs: u64,
+    pub tags_cache_ttl_secs: u64,
+
+    // DICOM handling
+    pub uid_root: String,
+    pub warn_on_study_mismatch: bool,
+    pub extra_dicom_tags: Vec<String>,
+
+    // Objects in the bucket loaded at startup
+    pub extra_dicom_tags_key: Option<String>,
+    pub window_presets_key: Option<String>,
+
+    // Feature flags
+    pub precompute_thumbnails: bool,
+    pub dicom_json_sidecars: bool,
+    pub sign_responses: bool,
+    pub dicom_key_adaptive: bool,
+    pub spa_fallback: bool,
+
+    // Auth settings; a feature whose secret or allowlist is unset is disabled
+    pub admin_api_key: Option<String>,
+    pub share_link_secret: Option<String>,
+    pub share_link_ttl_secs: i64,
+    pub share_hide_diagnosis: bool,
+    pub dicom_url_allowed_hosts: Vec<String>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    /// Read and validate every setting, failing with all of the problems found
+    pub fn from_env() -> Result<Config> {
+        let (config, errors) = Config::load();
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(anyhow!("Invalid configuration: {}", errors.join("; ")))
+        }
+    }
+
+    /// Read every setting, using the default for any invalid value and listing what was wrong
+    pub fn load() -> (Config, Vec<String>) {
+        let mut errors = Vec::new();
+
+        let bucket = optional_string("S3_BUCKET").unwrap_or_else(|| DEFAULT_BUCKET.to_string());
+        if !is_valid_bucket_name(&bucket) {
+            errors.push(format!("S3_BUCKET '{}' is not a valid bucket name", bucket));
+        }
+        let table_name = optional_string("CASES_TABLE").unwrap_or_else(|| DEFAULT_TABLE_NAME.to_string());
+        if !is_valid_table_name(&table_name) {
+            errors.push(format!("CASES_TABLE '{}' is not a valid table name", table_name));
+        }
+
+        let uid_root = match optional_string("UID_ROOT") {
+            None => DEFAULT_UID_ROOT.to_string(),
+            Some(root) if crate::dicom::is_valid_uid(&root) && root.len() <= MAX_UID_ROOT_LEN => root,
+            Some(root) => {
+                errors.push(format!("UID_ROOT '{}' must be a dotted numeric UID of at most {} characters", root, MAX_UID_ROOT_LEN));
+                DEFAULT_UID_ROOT.to_string()
+            }
+        };
+        let warn_on_study_mismatch = match optional_string("STUDY_UID_MISMATCH").map(|value| value.to_lowercase()).as_deref() {
+            None | Some("reject") => false,
+            Some("warn") => true,
+            Some(value) => {
+                errors.push(format!("STUDY_UID_MISMATCH must be reject or warn, got '{}'", value));
+                false
+            }
+        };
+
+        let dicom_parse_concurrency = positive("DICOM_PARSE_CONCURRENCY", DEFAULT_DICOM_PARSE_CONCURRENCY, &mut errors);
+        if dicom_parse_concurrency > MAX_DICOM_PARSE_CONCURRENCY {
+            errors.push(format!("DICOM_PARSE_CONCURRENCY must be at most {}, got {}", MAX_DICOM_PARSE_CONCURRENCY, dicom_parse_concurrency));
+        }
+        let extra_dicom_tags: Vec<String> = optional_string("EXTRA_DICOM_TAGS")
+            .map(|tags| {
+                tags.split(',')
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let bad_tags: Vec<&str> = extra_dicom_tags.iter()
+            .map(String::as_str)
+            .filter(|tag| !is_valid_keyword(tag))
+            .collect();
+        if !bad_tags.is_empty() {
+            errors.push(format!("EXTRA_DICOM_TAGS must list DICOM keywords such as ProtocolName, got '{}'", bad_tags.join("', '")));
+        }
+        let extra_dicom_tags_key = object_key("EXTRA_DICOM_TAGS_KEY", &mut errors);
+        let window_presets_key = object_key("WINDOW_PRESETS_KEY", &mut errors);
+
+        let share_link_ttl_secs = positive("SHARE_LINK_TTL_SECS", DEFAULT_SHARE_LINK_TTL_SECS, &mut errors);
+        if share_link_ttl_secs > crate::share::MAX_SHARE_TTL_SECS {
+            errors.push(format!("SHARE_LINK_TTL_SECS must be at most {}, got {}", crate::share::MAX_SHARE_TTL_SECS, share_link_ttl_secs));
+        }
+
+        let config = Config {
+            bucket,
+            table_name,
+            region: optional_string("REGION_OVERRIDE"),
+
+            max_list_items: positive("MAX_LIST_ITEMS", DEFAULT_MAX_LIST_ITEMS, &mut errors),
+            max_frames: positive("MAX_FRAMES", DEFAULT_MAX_FRAMES, &mut errors),
+            max_instances_per_upload: positive("MAX_INSTANCES_PER_UPLOAD", DEFAULT_MAX_INSTANCES_PER_UPLOAD, &mut errors),
+            item_offload_threshold_bytes: positive("ITEM_OFFLOAD_THRESHOLD_BYTES", DEFAULT_ITEM_OFFLOAD_BYTES, &mut errors),
+            dicom_url_max_bytes: positive("DICOM_URL_MAX_BYTES", DEFAULT_DICOM_URL_MAX_BYTES, &mut errors),
+
+            rate_limit_per_min: non_negative("RATE_LIMIT_PER_MIN", DEFAULT_RATE_LIMIT_PER_MIN, &mut errors),
+            rate_limit_get_per_min: non_negative("RATE_LIMIT_GET_PER_MIN", 0, &mut errors),
+            aws_call_timeout_ms: positive("AWS_CALL_TIMEOUT_MS", DEFAULT_AWS_CALL_TIMEOUT_MS, &mut errors),
+            dicom_parse_concurrency: dicom_parse_concurrency.min(MAX_DICOM_PARSE_CONCURRENCY),
+            slow_request_ms: positive("SLOW_REQUEST_MS", DEFAULT_SLOW_REQUEST_MS, &mut errors),
+
+            case_cache_ttl_secs: non_negative("CASE_CACHE_TTL_SECS", DEFAULT_CASE_CACHE_TTL_SECS, &mut errors),
+            tags_cache_ttl_secs: non_negative("TAGS_CACHE_TTL_SECS", DEFAULT_TAGS_CACHE_TTL_SECS, &mut errors),
+
+            uid_root,
+            warn_on_study_mismatch,
+            extra_dicom_tags: extra_dicom_tags.into_iter().filter(|tag| is_valid_keyword(tag)).collect(),
+
+            extra_dicom_tags_key,
+            window_presets_key,
+
+            precompute_thumbnails: flag("PRECOMPUTE_THUMBNAILS", false, &mut errors),
+            dicom_json_sidecars: flag("DICOM_JSON_SIDECARS", false, &mut errors),
+            sign_responses: flag("SIGN_RESPONSES", false, &mut errors),
+            dicom_key_adaptive: flag("DICOM_KEY_ADAPTIVE", true, &mut errors),
+            spa_fallback: flag("SPA_FALLBACK", true, &mut errors),
+
+            admin_api_key: optional_string("ADMIN_API_KEY"),
+            share_link_secret: optional_string("SHARE_LINK_SECRET"),
+            share_link_ttl_secs: share_link_ttl_secs.min(crate::share::MAX_SHARE_TTL_SECS),
+            share_hide_diagnosis: flag("SHARE_HIDE_DIAGNOSIS", true, &mut errors),
+            dicom_url_allowed_hosts: optional_string("DICOM_URL_ALLOWED_HOSTS")
+                .map(|hosts| {
+                    hosts.split(',')
+                        .map(|host| host.trim().to_lowercase())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        // Settings for a feature that is switched off point at a missing value
+        if config.share_link_secret.is_none() {
+            for name in ["SHARE_LINK_TTL_SECS", "SHARE_HIDE_DIAGNOSIS"] {
+                if optional_string(name).is_some() {
+                    errors.push(format!("{} is set but SHARE_LINK_SECRET is not, so share links are disabled", name));
+                }
+            }
+        }
+        if config.dicom_url_allowed_hosts.is_empty() && optional_string("DICOM_URL_MAX_BYTES").is_some() {
+            errors.push("DICOM_URL_MAX_BYTES is set but DICOM_URL_ALLOWED_HOSTS is not, so ingest from URL is disabled".to_string());
+        }
+
+        (config, errors)
+    }
+}
+
+/// Install the config validated at startup and return it for the rest of the process
+pub fn init(config: Config) -> &'static Config {
+    CONFIG.get_or_init(|| config)
+}
+
+/// The loaded config. Before init runs (or without it), settings are read leniently.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load().0)
+}
+
+// A non-empty environment variable, trimmed
+fn optional_string(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// A positive number, or the default when unset
+fn positive<T: FromStr + PartialOrd + Default>(name: &str, default: T, errors: &mut Vec<String>) -> T {
+    match optional_string(name) {
+        None => default,
+        Some(value) => match value.parse::<T>() {
+            Ok(parsed) if parsed > T::default() => parsed,
+            _ => {
+                errors.push(format!("{} must be a positive integer, got '{}'", name, value));
+                default
+            }
+        },
+    }
+}
+
+// A number that may be zero (usually meaning "off"), or the default when unset
+fn non_negative<T: FromStr>(name: &str, default: T, errors: &mut Vec<String>) -> T {
+    match optional_string(name) {
+        None => default,
+        Some(value) => value.parse::<T>().unwrap_or_else(|_| {
+            errors.push(format!("{} must be a non-negative integer, got '{}'", name, value));
+            default
+        }),
+    }
+}
+
+// A true/false (or 1/0) switch, or the default when unset
+fn flag(name: &str, default: bool, errors: &mut Vec<String>) -> bool {
+    match optional_string(name).map(|value| value.to_lowercase()).as_deref() {
+        None => default,
+        Some("true") | Some("1") => true,
+        Some("false") | Some("0") => false,
+        Some(value) => {
+            errors.push(format!("{} must be true or false, got '{}'", name, value));
+            default
+        }
+    }
+}
+
+// An S3 object key, or None when unset
+fn object_key(name: &str, errors: &mut Vec<String>) -> Option<String> {
+    let key = optional_string(name)?;
+    if key.len() > MAX_S3_KEY_LEN || key.starts_with('/') {
+        errors.push(format!("{} must be an S3 key of at most {} bytes without a leading '/', got '{}'", name, MAX_S3_KEY_LEN, key));
+        return None;
+    }
+    Some(key)
+}
+
+// DICOM keywords are letters and digits starting with a letter, e.g. KVP or ProtocolName
+fn is_valid_keyword(keyword: &str) -> bool {
+    keyword.starts_with(|c: char| c.is_ascii_alphabetic())
+        && keyword.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// S3 bucket naming rules: 3-63 lowercase letters, digits, dots and hyphens, alphanumeric at both ends
+fn is_valid_bucket_name(name: &str) -> bool {
+    (3..=63).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-')
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+// DynamoDB table naming rules: 3-255 letters, digits, '_', '-' and '.'
+fn is_valid_table_name(name: &str) -> bool {
+    (3..=255).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test uses its own variable names, as tests run in parallel
+    fn set(name: &str, value: &str) {
+        std::env::set_var(name, value);
+    }
+
+    #[test]
+    fn positive_rejects_zero_negative_and_garbage() {
+        let mut errors = Vec::new();
+        assert_eq!(positive("CONFIG_TEST_POSITIVE_UNSET", 7usize, &mut errors), 7);
+        set("CONFIG_TEST_POSITIVE_SET", " 12 ");
+        assert_eq!(positive("CONFIG_TEST_POSITIVE_SET", 7usize, &mut errors), 12);
+        assert!(errors.is_empty());
+
+        for (name, value) in [("CONFIG_TEST_POSITIVE_ZERO", "0"), ("CONFIG_TEST_POSITIVE_NEG", "-3"), ("CONFIG_TEST_POSITIVE_TEXT", "lots")] {
+            set(name, value);
+            assert_eq!(positive(name, 7i64, &mut errors), 7);
+        }
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("CONFIG_TEST_POSITIVE_ZERO"));
+    }
+
+    #[test]
+    fn flag_accepts_true_false_and_digits() {
+        let mut errors = Vec::new();
+        assert!(flag("CONFIG_TEST_FLAG_UNSET", true, &mut errors));
+        for (value, expected) in [("TRUE", true), ("1", true), ("false", false), ("0", false)] {
+            set("CONFIG_TEST_FLAG", value);
+            assert_eq!(flag("CONFIG_TEST_FLAG", !expected, &mut errors), expected);
+        }
+        assert!(errors.is_empty());
+
+        set("CONFIG_TEST_FLAG_BAD", "yes");
+        assert!(!flag("CONFIG_TEST_FLAG_BAD", false, &mut errors));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn object_key_rejects_leading_slash_and_overlong_keys() {
+        let mut errors = Vec::new();
+        assert_eq!(object_key("CONFIG_TEST_KEY_UNSET", &mut errors), None);
+        set("CONFIG_TEST_KEY_SET", " config/window-presets.json ");
+        assert_eq!(object_key("CONFIG_TEST_KEY_SET", &mut errors).as_deref(), Some("config/window-presets.json"));
+        assert!(errors.is_empty());
+
+        set("CONFIG_TEST_KEY_SLASH", "/config/tags.json");
+        assert_eq!(object_key("CONFIG_TEST_KEY_SLASH", &mut errors), None);
+        set("CONFIG_TEST_KEY_LONG", &"k".repeat(MAX_S3_KEY_LEN + 1));
+        assert_eq!(object_key("CONFIG_TEST_KEY_LONG", &mut errors), None);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("CONFIG_TEST_KEY_SLASH"));
+    }
+
+    #[test]
+    fn keywords_are_letters_and_digits() {
+        assert!(is_valid_keyword("KVP"));
+        assert!(is_valid_keyword("ProtocolName"));
+        assert!(is_valid_keyword("PixelSpacing"));
+        assert!(!is_valid_keyword(""));
+        assert!(!is_valid_keyword("0010,0010"));
+        assert!(!is_valid_keyword("Protocol Name"));
+        assert!(!is_valid_keyword("2DFlag"));
+    }
+
+    #[test]
+    fn bucket_names_follow_s3_rules() {
+        assert!(is_valid_bucket_name("radiology-teaching-files"));
+        assert!(is_valid_bucket_name("abc"));
+        assert!(is_valid_bucket_name("files.2024"));
+        assert!(!is_valid_bucket_name("ab"));
+        assert!(!is_valid_bucket_name(&"a".repeat(64)));
+        assert!(!is_valid_bucket_name("Radiology"));
+        assert!(!is_valid_bucket_name("-files"));
+        assert!(!is_valid_bucket_name("files-"));
+        assert!(!is_valid_bucket_name("my_files"));
+    }
+
+    #[test]
+    fn table_names_follow_dynamodb_rules() {
+        assert!(is_valid_table_name("RadiologyTeachingFiles"));
+        assert!(is_valid_table_name("cases_v2.prod-1"));
+        assert!(!is_valid_table_name("ab"));
+        assert!(!is_valid_table_name(&"a".repeat(256)));
+        assert!(!is_valid_table_name("teaching files"));
+        assert!(!is_valid_table_name("cases/prod"));
+    }
+}
@@ -1,16 +1,331 @@
 use anyhow::{Context, Result};
 use aws_sdk_dynamodb::{Client, types::AttributeValue};
 use std::collections::HashMap;
-use tracing::{info, error};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, error, warn};
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
 
-use crate::models::{Case, SeriesInfo};
+use crate::config;
+use crate::models::{Attachment, Case, EditRecord, SeriesInfo};
+use crate::s3;
+use crate::upstream::with_timeout;
 
-// The name of the DynamoDB table
-const TABLE_NAME: &str = "RadiologyTeachingFiles";
+// The name of the DynamoDB table, from the loaded config (CASES_TABLE)
+fn table_name() -> &'static str {
+    &config::get().table_name
+}
+
+// Table holding each user's bookmarked case ids
+const FAVORITES_TABLE_NAME: &str = "RadiologyTeachingFilesFavorites";
+
+// Table holding each client's rate limit token bucket, expired through TTL
+const RATE_LIMIT_TABLE_NAME: &str = "RadiologyTeachingFilesRateLimits";
+
+// BatchGetItem accepts at most this many keys per request
+const BATCH_GET_SIZE: usize = 100;
+
+// Global secondary index for looking cases up by StudyInstanceUID
+const STUDY_UID_INDEX: &str = "study_instance_uid-index";
+
+// created_at reported for items saved before the field existed (the Unix epoch)
+const MISSING_CREATED_AT: &str = "1970-01-01T00:00:00+00:00";
+
+// Number of scanned items converted per blocking task in list_cases
+const CONVERT_CHUNK_SIZE: usize = 64;
+
+// Upper bound on cached cases before the cache is cleared
+const CASE_CACHE_CAPACITY: usize = 256;
+
+// Most items DynamoDB accepts in one BatchWriteItem call
+const BATCH_WRITE_SIZE: usize = 25;
+
+// Rounds of retries for items DynamoDB leaves unprocessed under throttling
+const BATCH_WRITE_RETRIES: u32 = 5;
+
+// Attribute of the tombstone a deleted case leaves behind, so syncing clients learn of the delete
+const DELETED_AT: &str = "deleted_at";
+
+// S3 client used to store and read offloaded instance lists, registered at startup
+static OFFLOAD_CLIENT: OnceLock<aws_sdk_s3::Client> = OnceLock::new();
+
+/// Series, image ids and references of a case too large for a single DynamoDB item
+#[derive(serde::Serialize, serde::Deserialize)]
+struct OffloadedInstances {
+    series: Vec<SeriesInfo>,
+    image_ids: Vec<String>,
+    #[serde(default)]
+    image_references: HashMap<String, Vec<String>>,
+}
+
+impl OffloadedInstances {
+    /// Put the instance lists back into a case read from its slim item
+    fn restore(self, case: &mut Case) {
+        case.series = self.series;
+        case.image_ids = self.image_ids;
+        case.image_references = self.image_references;
+    }
+}
+
+/// Register the S3 client used for instance lists that do not fit in a DynamoDB item
+pub fn set_offload_client(client: aws_sdk_s3::Client) {
+    if OFFLOAD_CLIENT.set(client).is_err() {
+        debug!("Offload client already registered");
+    }
+}
+
+/// Item size above which instance lists go to S3, well clear of the 400KB item limit
+fn item_offload_threshold() -> usize {
+    config::get().item_offload_threshold_bytes
+}
+
+fn offload_client() -> Result<&'static aws_sdk_s3::Client> {
+    OFFLOAD_CLIENT.get().context("No S3 client registered for offloaded instance lists")
+}
+
+/// Whether a case's serialized size is past the threshold for keeping its instance lists in the item
+fn exceeds_offload_threshold(case: &Case, threshold: usize) -> bool {
+    serde_json::to_vec(case).map(|json| json.len()).unwrap_or(0) > threshold
+}
+
+/// Split a case into the slim copy stored in DynamoDB and the instance lists stored in S3
+fn split_instances(case: &Case) -> (Case, OffloadedInstances) {
+    let instances = OffloadedInstances {
+        series: case.series.clone(),
+        image_ids: case.image_ids.clone(),
+        image_references: case.image_references.clone(),
+    };
+    let slim = Case {
+        series: Vec::new(),
+        image_ids: Vec::new(),
+        image_references: HashMap::new(),
+        ..case.clone()
+    };
+    (slim, instances)
+}
+
+/// Write a case's instance lists to S3, returning the key to store in the item
+async fn offload_instances(case_id: &str, instances: &OffloadedInstances) -> Result<String> {
+    let key = format!("cases/{}/instances.json", case_id);
+    let body = serde_json::to_vec(instances)?;
+    
+    info!("Offloading {} instance ids of case {} to {}", instances.image_ids.len(), case_id, key);
+    s3::upload_object(offload_client()?, &key, body, "application/json").await
+        .context("Failed to offload case instances to S3")?;
+    Ok(key)
+}
+
+/// Fill in the instance lists of a case whose item points at an offloaded S3 object
+async fn rehydrate_instances(case: &mut Case) -> Result<()> {
+    let key = match &case.instances_key {
+        Some(key) => key.clone(),
+        None => return Ok(()),
+    };
+    
+    let data = s3::download_file(offload_client()?, &key).await
+        .with_context(|| format!("Failed to read offloaded instances for case {}", case.case_id))?;
+    let instances: OffloadedInstances = serde_json::from_slice(&data)
+        .with_context(|| format!("Invalid offloaded instances for case {}", case.case_id))?;
+    
+    instances.restore(case);
+    Ok(())
+}
+
+// Cases read by the viewer paths, kept for the lifetime of the warm Lambda.
+// Writes through save_case invalidate the entry; other instances see changes after the TTL.
+static CASE_CACHE: OnceLock<Mutex<HashMap<String, (Instant, Case)>>> = OnceLock::new();
+
+fn case_cache() -> &'static Mutex<HashMap<String, (Instant, Case)>> {
+    CASE_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The case cache lifetime (CASE_CACHE_TTL_SECS) from the loaded config
+fn case_cache_ttl() -> Duration {
+    Duration::from_secs(config::get().case_cache_ttl_secs)
+}
+
+/// Drop a case from the lookup cache so the next read goes to DynamoDB
+pub fn invalidate_cached_case(case_id: &str) {
+    case_cache().lock().unwrap().remove(case_id);
+}
+
+/// Error returned when a save targets a case that has been deleted
+#[derive(Debug)]
+pub struct CaseDeleted {
+    pub case_id: String,
+}
 
-/// Save a case to DynamoDB
+impl std::fmt::Display for CaseDeleted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Case {} has been deleted", self.case_id)
+    }
+}
+
+impl std::error::Error for CaseDeleted {}
+
+/// Check whether an anyhow error means the saved case had been deleted
+pub fn is_case_deleted(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<CaseDeleted>())
+}
+
+/// Save a case to DynamoDB. A case deleted since it was read stays deleted: the save
+/// fails with CaseDeleted instead of overwriting the tombstone.
 pub async fn save_case(client: &Client, case: &Case) -> Result<()> {
+    use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+    
     info!("Saving case to DynamoDB: {}", case.case_id);
+    invalidate_cached_case(&case.case_id);
+    
+    let item = case_item(case).await?;
+    let offloaded = item.contains_key("instances_key");
+    let (update, values) = case_update(item);
+    let condition = "attribute_not_exists(#deleted_at)";
+    let result = match with_timeout("dynamodb:update_item", client.update_item()
+        .table_name(table_name())
+        .key("case_id", AttributeValue::S(case.case_id.clone()))
+        .update_expression(&update)
+        .condition_expression(condition)
+        .set_expression_attribute_names(attribute_names(&[&update, condition]))
+        .set_expression_attribute_values(Some(values))
+        .send())
+        .await {
+        Ok(result) => result,
+        Err(err) => {
+            let deleted = err.downcast_ref::<SdkError<UpdateItemError>>()
+                .and_then(|sdk_err| sdk_err.as_service_error())
+                .is_some_and(|service_err| service_err.is_conditional_check_failed_exception());
+            
+            if deleted {
+                warn!("Refusing to save deleted case {}", case.case_id);
+                return Err(CaseDeleted { case_id: case.case_id.clone() }.into());
+            }
+            return Err(err.context("Failed to save case to DynamoDB"));
+        }
+    };
+    
+    info!("Case saved successfully: {:?}", result);
+    
+    // A case that shrank back under the threshold no longer needs its offloaded lists
+    if let Some(stale_key) = case.instances_key.as_ref().filter(|_| !offloaded) {
+        remove_offloaded_instances(stale_key).await;
+    }
+    Ok(())
+}
+
+/// Delete an offloaded instance list the item no longer points at. A failure only leaves
+/// an orphaned object behind, so it is logged rather than failing the save.
+async fn remove_offloaded_instances(key: &str) {
+    let removed = match offload_client() {
+        Ok(client) => s3::delete_files(client, &[key.to_string()]).await,
+        Err(err) => Err(err),
+    };
+    match removed {
+        Ok(_) => info!("Removed offloaded instances no longer in use: {}", key),
+        Err(err) => warn!("Failed to remove offloaded instances {}: {:?}", key, err),
+    }
+}
+
+// Attributes case_item only writes when they have a value
+const OPTIONAL_ATTRIBUTES: &[&str] = &["study_instance_uid", "key_image_sop", "expected_instance_count", "instances_key"];
+
+/// Turn a case item into an update that replaces the stored case like a put would, except
+/// for view_count: increment_view_count owns it, so a save only starts it at zero
+fn case_update(item: HashMap<String, AttributeValue>) -> (String, HashMap<String, AttributeValue>) {
+    let mut names: Vec<&String> = item.keys().filter(|name| *name != "case_id").collect();
+    names.sort();
+    
+    let mut set: Vec<String> = names.iter().map(|name| format!("#{} = :{}", name, name)).collect();
+    set.push("#view_count = if_not_exists(#view_count, :zero)".to_string());
+    let mut update = format!("SET {}", set.join(", "));
+    
+    let removed: Vec<String> = OPTIONAL_ATTRIBUTES.iter()
+        .filter(|name| !item.contains_key(**name))
+        .map(|name| format!("#{}", name))
+        .collect();
+    if !removed.is_empty() {
+        update.push_str(&format!(" REMOVE {}", removed.join(", ")));
+    }
+    
+    let mut values: HashMap<String, AttributeValue> = item.into_iter()
+        .filter(|(name, _)| name != "case_id")
+        .map(|(name, value)| (format!(":{}", name), value))
+        .collect();
+    values.insert(":zero".to_string(), AttributeValue::N("0".to_string()));
+    (update, values)
+}
+
+/// Write many cases with BatchWriteItem, returning the ids that could not be written
+pub async fn save_cases(client: &Client, cases: &[Case]) -> Result<Vec<String>> {
+    use aws_sdk_dynamodb::types::{PutRequest, WriteRequest};
+    
+    info!("Batch saving {} cases to DynamoDB", cases.len());
+    let mut failed = Vec::new();
+    
+    for chunk in cases.chunks(BATCH_WRITE_SIZE) {
+        let mut requests = Vec::with_capacity(chunk.len());
+        for case in chunk {
+            invalidate_cached_case(&case.case_id);
+            match case_item(case).await {
+                Ok(mut item) => {
+                    // Batch writes are whole-item puts, so imported cases bring their view counts along
+                    item.insert("view_count".to_string(), AttributeValue::N(case.view_count.to_string()));
+                    requests.push(WriteRequest::builder()
+                        .put_request(PutRequest::builder().set_item(Some(item)).build()?)
+                        .build());
+                },
+                Err(err) => {
+                    error!("Failed to prepare case {} for batch write: {:?}", case.case_id, err);
+                    failed.push(case.case_id.clone());
+                }
+            }
+        }
+        
+        // Throttled writes come back as unprocessed items and are retried with backoff
+        let mut attempt = 0;
+        while !requests.is_empty() {
+            let result = with_timeout("dynamodb:batch_write_item", client.batch_write_item()
+                .request_items(table_name(), requests.clone())
+                .send())
+                .await
+                .context("Failed to batch write cases to DynamoDB")?;
+            
+            requests = result.unprocessed_items.unwrap_or_default().remove(table_name()).unwrap_or_default();
+            if requests.is_empty() {
+                break;
+            }
+            
+            attempt += 1;
+            if attempt > BATCH_WRITE_RETRIES {
+                error!("{} cases still unprocessed after {} retries", requests.len(), BATCH_WRITE_RETRIES);
+                failed.extend(requests.iter().filter_map(|request| {
+                    request.put_request()
+                        .and_then(|put| put.item().get("case_id"))
+                        .and_then(|v| v.as_s().ok())
+                        .cloned()
+                }));
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt))).await;
+        }
+    }
+    
+    Ok(failed)
+}
+
+/// Build the DynamoDB item for a case
+async fn case_item(case: &Case) -> Result<HashMap<String, AttributeValue>> {
+    // Very large studies would push the item past DynamoDB's limit, so their instance
+    // lists are stored in S3 and the item keeps only a pointer
+    let slim;
+    let (case, instances_key) = if exceeds_offload_threshold(case, item_offload_threshold()) {
+        let (slim_case, instances) = split_instances(case);
+        let key = offload_instances(&case.case_id, &instances).await?;
+        slim = slim_case;
+        (&slim, Some(key))
+    } else {
+        (case, None)
+    };
     
     // Convert tags to attribute values
     let tags: Vec<AttributeValue> = case.tags.iter()
@@ -30,6 +345,12 @@ pub async fn save_case(client: &Client, case: &Case) -> Result<()> {
             map.insert("series_number".to_string(), AttributeValue::N(series_info.series_number.to_string()));
             map.insert("series_description".to_string(), AttributeValue::S(series_info.series_description.clone()));
             map.insert("modality".to_string(), AttributeValue::S(series_info.modality.clone()));
+            if let Some(acquisition_datetime) = &series_info.acquisition_datetime {
+                map.insert("acquisition_datetime".to_string(), AttributeValue::S(acquisition_datetime.clone()));
+            }
+            if let Some(expected) = series_info.expected_instance_count {
+                map.insert("expected_instance_count".to_string(), AttributeValue::N(expected.to_string()));
+            }
             
             // Convert series image_ids to attribute values
             let series_image_ids: Vec<AttributeValue> = series_info.image_ids.iter()
@@ -41,83 +362,415 @@ pub async fn save_case(client: &Client, case: &Case) -> Result<()> {
         })
         .collect();
 
-    let result = client.put_item()
-        .table_name(TABLE_NAME)
+    // Convert edit history to attribute values
+    let edit_history: Vec<AttributeValue> = case.edit_history.iter()
+        .map(|record| {
+            let mut map = HashMap::new();
+            map.insert("field".to_string(), AttributeValue::S(record.field.clone()));
+            map.insert("old".to_string(), AttributeValue::S(record.old.clone()));
+            map.insert("new".to_string(), AttributeValue::S(record.new.clone()));
+            map.insert("at".to_string(), AttributeValue::S(record.at.clone()));
+            AttributeValue::M(map)
+        })
+        .collect();
+
+    // Convert attachments to attribute values
+    let attachments: Vec<AttributeValue> = case.attachments.iter()
+        .map(|attachment| {
+            let mut map = HashMap::new();
+            map.insert("id".to_string(), AttributeValue::S(attachment.id.clone()));
+            map.insert("filename".to_string(), AttributeValue::S(attachment.filename.clone()));
+            map.insert("content_type".to_string(), AttributeValue::S(attachment.content_type.clone()));
+            AttributeValue::M(map)
+        })
+        .collect();
+
+    // Convert image references to a map of SOP Instance UID lists
+    let image_references: HashMap<String, AttributeValue> = case.image_references.iter()
+        .map(|(sop_instance_uid, referenced)| {
+            let referenced = referenced.iter().map(|uid| AttributeValue::S(uid.clone())).collect();
+            (sop_instance_uid.clone(), AttributeValue::L(referenced))
+        })
+        .collect();
+
+    let mut item = HashMap::from([
         // Base case fields
-        .item("case_id", AttributeValue::S(case.case_id.clone()))
-        .item("title", AttributeValue::S(case.title.clone()))
-        .item("description", AttributeValue::S(case.description.clone()))
-        .item("modality", AttributeValue::S(case.modality.clone()))
-        .item("anatomy", AttributeValue::S(case.anatomy.clone()))
-        .item("diagnosis", AttributeValue::S(case.diagnosis.clone()))
-        .item("findings", AttributeValue::S(case.findings.clone()))
-        .item("tags", AttributeValue::L(tags))
-        .item("image_ids", AttributeValue::L(image_ids))
-        .item("created_at", AttributeValue::S(case.created_at.clone()))
+        ("case_id".to_string(), AttributeValue::S(case.case_id.clone())),
+        ("title".to_string(), AttributeValue::S(case.title.clone())),
+        ("description".to_string(), AttributeValue::S(case.description.clone())),
+        ("modality".to_string(), AttributeValue::S(case.modality.clone())),
+        ("anatomy".to_string(), AttributeValue::S(case.anatomy.clone())),
+        ("diagnosis".to_string(), AttributeValue::S(case.diagnosis.clone())),
+        ("findings".to_string(), AttributeValue::S(case.findings.clone())),
+        ("tags".to_string(), AttributeValue::L(tags)),
+        ("image_ids".to_string(), AttributeValue::L(image_ids)),
+        ("created_at".to_string(), AttributeValue::S(case.created_at.clone())),
+        ("updated_at".to_string(), AttributeValue::S(if case.updated_at.is_empty() {
+            case.created_at.clone()
+        } else {
+            case.updated_at.clone()
+        })),
         
         // DICOM metadata fields
-        .item("study_instance_uid", AttributeValue::S(case.study_instance_uid.clone()))
-        .item("series_instance_uid", AttributeValue::S(case.series_instance_uid.clone()))
-        .item("study_date", AttributeValue::S(case.study_date.clone()))
-        .item("study_description", AttributeValue::S(case.study_description.clone()))
-        .item("patient_id", AttributeValue::S(case.patient_id.clone()))
-        .item("patient_name", AttributeValue::S(case.patient_name.clone()))
+        ("series_instance_uid".to_string(), AttributeValue::S(case.series_instance_uid.clone())),
+        ("study_date".to_string(), AttributeValue::S(case.study_date.clone())),
+        ("study_description".to_string(), AttributeValue::S(case.study_description.clone())),
+        ("patient_id".to_string(), AttributeValue::S(case.patient_id.clone())),
+        ("patient_name".to_string(), AttributeValue::S(case.patient_name.clone())),
         
         // Series information
-        .item("series", AttributeValue::L(series))
+        ("series".to_string(), AttributeValue::L(series)),
         
-        .send()
+        // Edit history
+        ("edit_history".to_string(), AttributeValue::L(edit_history)),
+        
+        // Attachments
+        ("attachments".to_string(), AttributeValue::L(attachments)),
+        
+        // Key image references
+        ("image_references".to_string(), AttributeValue::M(image_references)),
+    ]);
+    
+    // Index key attributes cannot be empty strings, so a missing study UID is left out
+    if !case.study_instance_uid.is_empty() {
+        item.insert("study_instance_uid".to_string(), AttributeValue::S(case.study_instance_uid.clone()));
+    }
+    
+    if let Some(key_image_sop) = &case.key_image_sop {
+        item.insert("key_image_sop".to_string(), AttributeValue::S(key_image_sop.clone()));
+    }
+    
+    if let Some(expected) = case.expected_instance_count {
+        item.insert("expected_instance_count".to_string(), AttributeValue::N(expected.to_string()));
+    }
+    
+    if let Some(instances_key) = instances_key {
+        item.insert("instances_key".to_string(), AttributeValue::S(instances_key));
+    }
+    
+    Ok(item)
+}
+
+/// Delete a case's item from DynamoDB. Its S3 objects are left to the caller.
+/// The item is replaced by a tombstone holding only the case id and deletion time;
+/// every read skips tombstones, and list_deleted_case_ids reports them.
+pub async fn delete_case(client: &Client, case_id: &str) -> Result<()> {
+    info!("Deleting case from DynamoDB: {}", case_id);
+    invalidate_cached_case(case_id);
+    
+    with_timeout("dynamodb:put_item", client.put_item()
+        .table_name(table_name())
+        .item("case_id", AttributeValue::S(case_id.to_string()))
+        .item(DELETED_AT, AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .send())
         .await
-        .context("Failed to save case to DynamoDB")?;
+        .context("Failed to delete case from DynamoDB")?;
     
-    info!("Case saved successfully: {:?}", result);
     Ok(())
 }
 
+/// Check whether an item is the tombstone of a deleted case
+fn is_tombstone(item: &HashMap<String, AttributeValue>) -> bool {
+    item.contains_key(DELETED_AT)
+}
+
+/// Ids of cases deleted after the given time, for clients syncing with ?modified_since
+pub async fn list_deleted_case_ids(client: &Client, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<String>> {
+    info!("Listing cases deleted since {}", since.to_rfc3339());
+    
+    let filter = "#deleted_at > :since";
+    let projection = "#case_id";
+    let mut deleted = Vec::new();
+    let mut start_key = None;
+    loop {
+        let result = with_timeout("dynamodb:scan", client.scan()
+            .table_name(table_name())
+            .filter_expression(filter)
+            .projection_expression(projection)
+            .set_expression_attribute_names(attribute_names(&[filter, projection]))
+            .expression_attribute_values(":since", AttributeValue::S(since.to_rfc3339()))
+            .set_exclusive_start_key(start_key)
+            .send())
+            .await
+            .context("Failed to list deleted cases from DynamoDB")?;
+        
+        deleted.extend(result.items().iter()
+            .filter_map(|item| item.get("case_id")?.as_s().ok().cloned()));
+        
+        start_key = result.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+    
+    Ok(deleted)
+}
+
 /// Get a case from DynamoDB by ID
 pub async fn get_case(client: &Client, case_id: &str) -> Result<Option<Case>> {
     info!("Getting case from DynamoDB: {}", case_id);
     
-    let result = client.get_item()
-        .table_name(TABLE_NAME)
+    let result = with_timeout("dynamodb:get_item", client.get_item()
+        .table_name(table_name())
         .key("case_id", AttributeValue::S(case_id.to_string()))
-        .send()
+        .send())
         .await
         .context("Failed to get case from DynamoDB")?;
     
-    if let Some(item) = result.item {
-        Ok(Some(convert_item_to_case(item)?))
+    if let Some(item) = result.item.filter(|item| !is_tombstone(item)) {
+        let mut case = convert_item_to_case(item)?;
+        rehydrate_instances(&mut case).await?;
+        Ok(Some(case))
     } else {
         info!("Case not found: {}", case_id);
         Ok(None)
     }
 }
 
+/// Get a case, reusing a recent lookup from this Lambda instance.
+/// Meant for read-heavy paths such as image retrieval that only need the case layout.
+pub async fn get_case_cached(client: &Client, case_id: &str) -> Result<Option<Case>> {
+    let ttl = case_cache_ttl();
+    if let Some((cached_at, case)) = case_cache().lock().unwrap().get(case_id) {
+        if cached_at.elapsed() < ttl {
+            debug!("Case cache hit: {}", case_id);
+            return Ok(Some(case.clone()));
+        }
+    }
+    
+    let case = get_case(client, case_id).await?;
+    if let Some(case) = &case {
+        let mut cache = case_cache().lock().unwrap();
+        if cache.len() >= CASE_CACHE_CAPACITY {
+            cache.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+            if cache.len() >= CASE_CACHE_CAPACITY {
+                cache.clear();
+            }
+        }
+        cache.insert(case_id.to_string(), (Instant::now(), case.clone()));
+    }
+    
+    Ok(case)
+}
+
+/// Get several cases in batches; ids with no case are skipped
+pub async fn get_cases(client: &Client, case_ids: &[String]) -> Result<Vec<Case>> {
+    use aws_sdk_dynamodb::types::KeysAndAttributes;
+    
+    info!("Getting {} cases from DynamoDB", case_ids.len());
+    let mut cases = Vec::with_capacity(case_ids.len());
+    
+    for chunk in case_ids.chunks(BATCH_GET_SIZE) {
+        let keys: Vec<HashMap<String, AttributeValue>> = chunk.iter()
+            .map(|case_id| HashMap::from([("case_id".to_string(), AttributeValue::S(case_id.clone()))]))
+            .collect();
+        let mut request_items = HashMap::from([(
+            table_name().to_string(),
+            KeysAndAttributes::builder().set_keys(Some(keys)).build()?,
+        )]);
+        
+        // Throttled batches hand back unprocessed keys, which are requested again
+        while !request_items.is_empty() {
+            let result = with_timeout("dynamodb:batch_get_item", client.batch_get_item()
+                .set_request_items(Some(request_items))
+                .send())
+                .await
+                .context("Failed to get cases from DynamoDB")?;
+            
+            let items = result.responses.unwrap_or_default().remove(table_name()).unwrap_or_default();
+            for item in items.into_iter().filter(|item| !is_tombstone(item)) {
+                match convert_item_to_case(item) {
+                    Ok(mut case) => match rehydrate_instances(&mut case).await {
+                        Ok(()) => cases.push(case),
+                        Err(err) => error!("Skipping case {}: {:?}", case.case_id, err),
+                    },
+                    Err(err) => error!("Failed to convert item to case: {:?}", err),
+                }
+            }
+            
+            request_items = result.unprocessed_keys.unwrap_or_default();
+        }
+    }
+    
+    Ok(cases)
+}
+
+/// Find the case for a StudyInstanceUID via the study UID index.
+/// If several cases share the study, the most recently created one is returned.
+pub async fn get_case_by_study(client: &Client, study_instance_uid: &str) -> Result<Option<Case>> {
+    info!("Getting case from DynamoDB by study: {}", study_instance_uid);
+    
+    let key_condition = "#study_instance_uid = :study_uid";
+    let result = with_timeout("dynamodb:query", client.query()
+        .table_name(table_name())
+        .index_name(STUDY_UID_INDEX)
+        .key_condition_expression(key_condition)
+        .set_expression_attribute_names(attribute_names(&[key_condition]))
+        .expression_attribute_values(":study_uid", AttributeValue::S(study_instance_uid.to_string()))
+        .send())
+        .await
+        .context("Failed to query cases by study from DynamoDB")?;
+    
+    let mut cases = Vec::new();
+    for item in result.items.unwrap_or_default() {
+        match convert_item_to_case(item) {
+            Ok(case) => cases.push(case),
+            Err(err) => error!("Failed to convert item to case: {:?}", err),
+        }
+    }
+    
+    if cases.len() > 1 {
+        info!("{} cases share study {}", cases.len(), study_instance_uid);
+    }
+    
+    let mut case = cases.into_iter().max_by_key(|case| chrono::DateTime::parse_from_rfc3339(&case.created_at).ok());
+    if let Some(case) = &mut case {
+        rehydrate_instances(case).await?;
+    }
+    Ok(case)
+}
+
+/// One page of a case scan
+pub struct CasePage {
+    pub cases: Vec<Case>,
+    // Case id to continue from while the table has more items
+    pub next: Option<String>,
+    // Ids of items on the page that could not be read as cases
+    pub unreadable: Vec<String>,
+}
+
+/// Scan one page of cases, starting after the given case id
+pub async fn scan_cases_page(client: &Client, start_after: Option<&str>, limit: i32) -> Result<CasePage> {
+    debug!("Scanning cases after {:?}", start_after);
+    
+    let mut request = client.scan()
+        .table_name(table_name())
+        .limit(limit);
+    if let Some(case_id) = start_after {
+        request = request.exclusive_start_key("case_id", AttributeValue::S(case_id.to_string()));
+    }
+    
+    let result = with_timeout("dynamodb:scan", request.send())
+        .await
+        .context("Failed to scan cases from DynamoDB")?;
+    
+    let next = result.last_evaluated_key()
+        .and_then(|key| key.get("case_id"))
+        .and_then(|v| v.as_s().ok())
+        .cloned();
+    
+    let mut cases = Vec::new();
+    let mut unreadable = Vec::new();
+    for item in result.items.unwrap_or_default().into_iter().filter(|item| !is_tombstone(item)) {
+        let case_id = item.get("case_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+        match convert_item_to_case(item) {
+            Ok(mut case) => match rehydrate_instances(&mut case).await {
+                Ok(()) => cases.push(case),
+                Err(err) => {
+                    error!("Skipping case {}: {:?}", case_id, err);
+                    unreadable.push(case_id);
+                },
+            },
+            Err(err) => {
+                error!("Failed to convert item {} to case: {:?}", case_id, err);
+                unreadable.push(case_id);
+            },
+        }
+    }
+    
+    Ok(CasePage { cases, next, unreadable })
+}
+
 /// List all cases from DynamoDB
 pub async fn list_cases(client: &Client) -> Result<Vec<Case>> {
     info!("Listing all cases from DynamoDB");
     
-    let result = client.scan()
-        .table_name(TABLE_NAME)
-        .send()
-        .await
-        .context("Failed to list cases from DynamoDB")?;
+    // A scan returns at most 1 MB per call, so keep following the last evaluated key
+    // until the whole table has been read
+    let mut items: Vec<HashMap<String, AttributeValue>> = Vec::new();
+    let mut start_key: Option<HashMap<String, AttributeValue>> = None;
+    loop {
+        let result = with_timeout("dynamodb:scan", client.scan()
+            .table_name(table_name())
+            .set_exclusive_start_key(start_key.take())
+            .send())
+            .await
+            .context("Failed to list cases from DynamoDB")?;
+        
+        items.extend(result.items.unwrap_or_default().into_iter().filter(|item| !is_tombstone(item)));
+        match result.last_evaluated_key {
+            Some(key) if !key.is_empty() => start_key = Some(key),
+            _ => break,
+        }
+    }
+    let mut cases = Vec::with_capacity(items.len());
     
-    let mut cases = Vec::new();
+    // Conversion is pure CPU work, so spread chunks of items across blocking threads.
+    // Results are collected chunk by chunk, keeping the scan order.
+    let mut tasks = Vec::new();
+    let mut remaining = items.into_iter();
+    loop {
+        let chunk: Vec<HashMap<String, AttributeValue>> = remaining.by_ref().take(CONVERT_CHUNK_SIZE).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        
+        tasks.push(tokio::task::spawn_blocking(move || {
+            chunk.into_iter().map(convert_item_to_case).collect::<Vec<_>>()
+        }));
+    }
     
-    if let Some(items) = result.items {
-        for item in items {
-            match convert_item_to_case(item) {
+    for task in tasks {
+        let converted = task.await.context("Case conversion task failed")?;
+        for result in converted {
+            match result {
                 Ok(case) => cases.push(case),
                 Err(err) => error!("Failed to convert item to case: {:?}", err),
             }
         }
     }
     
+    // One unreadable instance list should not take the whole listing down
+    let mut skipped = Vec::new();
+    for case in cases.iter_mut().filter(|case| case.instances_key.is_some()) {
+        if let Err(err) = rehydrate_instances(case).await {
+            error!("Skipping case {}: {:?}", case.case_id, err);
+            skipped.push(case.case_id.clone());
+        }
+    }
+    cases.retain(|case| !skipped.contains(&case.case_id));
+    
+    // Newest first; timestamps are parsed so mixed precision sorts chronologically.
+    // Unparseable timestamps go last and case_id breaks ties for a stable order.
+    cases.sort_by_cached_key(|case| {
+        let created = chrono::DateTime::parse_from_rfc3339(&case.created_at).ok();
+        (std::cmp::Reverse(created), case.case_id.clone())
+    });
+    
     info!("Retrieved {} cases", cases.len());
     Ok(cases)
 }
 
+/// ExpressionAttributeNames for the #name placeholders used in a request's expressions.
+/// Every expression refers to attributes through a placeholder, so attribute names that are
+/// DynamoDB reserved words (such as "series" or "date") can never break a request.
+fn attribute_names(expressions: &[&str]) -> Option<HashMap<String, String>> {
+    let mut names = HashMap::new();
+    for expression in expressions {
+        let mut rest = *expression;
+        while let Some(start) = rest.find('#') {
+            let token = &rest[start + 1..];
+            let len = token.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(token.len());
+            if len > 0 {
+                names.insert(format!("#{}", &token[..len]), token[..len].to_string());
+            }
+            rest = &token[len..];
+        }
+    }
+    
+    if names.is_empty() { None } else { Some(names) }
+}
+
 /// Convert a DynamoDB item to a Case
 fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
     // Extract required fields
@@ -172,10 +825,15 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
         })
         .unwrap_or_default();
     
+    // Legacy rows without a timestamp get a fixed sentinel so reads stay deterministic
     let created_at = item.get("created_at")
         .and_then(|v| v.as_s().ok())
-        .unwrap_or(&chrono::Utc::now().to_rfc3339())
-        .clone();
+        .map_or(MISSING_CREATED_AT.to_string(), |s| s.to_string());
+    
+    // Cases saved before updated_at existed were last changed no later than creation
+    let updated_at = item.get("updated_at")
+        .and_then(|v| v.as_s().ok())
+        .map_or(created_at.clone(), |s| s.to_string());
     
     // Extract DICOM metadata fields
     let study_instance_uid = item.get("study_instance_uid")
@@ -202,6 +860,14 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
         .and_then(|v| v.as_s().ok())
         .map_or(String::new(), |s| s.to_string());
     
+    let key_image_sop = item.get("key_image_sop")
+        .and_then(|v| v.as_s().ok())
+        .cloned();
+    
+    let instances_key = item.get("instances_key")
+        .and_then(|v| v.as_s().ok())
+        .cloned();
+    
     // Extract series information
     let series = item.get("series")
         .and_then(|v| v.as_l().ok())
@@ -235,12 +901,22 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
                             })
                             .unwrap_or_default();
                         
+                        let acquisition_datetime = map.get("acquisition_datetime")
+                            .and_then(|v| v.as_s().ok())
+                            .cloned();
+                        
+                        let expected_instance_count = map.get("expected_instance_count")
+                            .and_then(|v| v.as_n().ok())
+                            .and_then(|n| n.parse::<u32>().ok());
+                        
                         Some(SeriesInfo {
                             series_instance_uid,
                             series_number,
                             series_description,
                             modality,
                             image_ids,
+                            acquisition_datetime,
+                            expected_instance_count,
                         })
                     } else {
                         None
@@ -250,6 +926,71 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
         })
         .unwrap_or_default();
     
+    // Extract edit history
+    let edit_history = item.get("edit_history")
+        .and_then(|v| v.as_l().ok())
+        .map(|list| {
+            list.iter()
+                .filter_map(|v| v.as_m().ok())
+                .map(|map| {
+                    let get_string = |key: &str| map.get(key)
+                        .and_then(|v| v.as_s().ok())
+                        .map_or(String::new(), |s| s.to_string());
+                    
+                    EditRecord {
+                        field: get_string("field"),
+                        old: get_string("old"),
+                        new: get_string("new"),
+                        at: get_string("at"),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    let attachments = item.get("attachments")
+        .and_then(|v| v.as_l().ok())
+        .map(|list| {
+            list.iter()
+                .filter_map(|v| v.as_m().ok())
+                .map(|map| {
+                    let get_string = |key: &str| map.get(key)
+                        .and_then(|v| v.as_s().ok())
+                        .map_or(String::new(), |s| s.to_string());
+                    
+                    Attachment {
+                        id: get_string("id"),
+                        filename: get_string("filename"),
+                        content_type: get_string("content_type"),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    let view_count = item.get("view_count")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<u64>().ok())
+        .unwrap_or(0);
+    
+    let expected_instance_count = item.get("expected_instance_count")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<u32>().ok());
+    
+    let image_references = item.get("image_references")
+        .and_then(|v| v.as_m().ok())
+        .map(|map| {
+            map.iter()
+                .map(|(sop_instance_uid, referenced)| {
+                    let referenced = referenced.as_l()
+                        .map(|list| list.iter().filter_map(|v| v.as_s().ok()).cloned().collect())
+                        .unwrap_or_default();
+                    (sop_instance_uid.clone(), referenced)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    
     Ok(Case {
         case_id,
         title,
@@ -261,6 +1002,7 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
         tags,
         image_ids,
         created_at,
+        updated_at,
         
         // DICOM metadata fields
         study_instance_uid,
@@ -272,49 +1014,72 @@ fn convert_item_to_case(item: HashMap<String, AttributeValue>) -> Result<Case> {
         
         // Series information
         series,
+        key_image_sop,
+        edit_history,
+        attachments,
+        image_references,
+        instances_key,
+        view_count,
+        expected_instance_count,
     })
 }
 
 /// Create the DynamoDB table if it doesn't exist
 pub async fn ensure_table_exists(client: &Client) -> Result<()> {
-    info!("Ensuring DynamoDB table exists: {}", TABLE_NAME);
+    info!("Ensuring DynamoDB table exists: {}", table_name());
 
     // Check if the table already exists
-    match client.describe_table().table_name(TABLE_NAME).send().await {
-        Ok(_) => {
-            info!("Table already exists: {}", TABLE_NAME);
+    match client.describe_table().table_name(table_name()).send().await {
+        Ok(response) => {
+            info!("Table already exists: {}", table_name());
+            
+            // Tables created before the study index existed get it added in place
+            let has_study_index = response.table()
+                .map(|table| table.global_secondary_indexes().iter()
+                    .any(|index| index.index_name() == Some(STUDY_UID_INDEX)))
+                .unwrap_or(false);
+            if !has_study_index {
+                create_study_index(client).await?;
+            }
             Ok(())
         }
         Err(err) => {
             if err.to_string().contains("ResourceNotFoundException") {
                 // Create the table
-                info!("Creating table: {}", TABLE_NAME);
+                info!("Creating table: {}", table_name());
 
                 use aws_sdk_dynamodb::types::{
                     AttributeDefinition, KeySchemaElement, KeyType, ScalarAttributeType, BillingMode,
                 };
 
                 let key_schema_element = KeySchemaElement::builder()
-                    .attribute_name("your_key_name")
+                    .attribute_name("case_id")
                     .key_type(KeyType::Hash)
                     .build()?;  // ✅ FIXED: Extracts the result properly
 
                 let attribute_definition = AttributeDefinition::builder()
-                    .attribute_name("your_attr_name")
+                    .attribute_name("case_id")
                     .attribute_type(ScalarAttributeType::S)
                     .build()?;  // ✅ FIXED: Extracts the result properly
 
+                let study_attribute_definition = AttributeDefinition::builder()
+                    .attribute_name("study_instance_uid")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?;
+
                 client.create_table()
-                    .table_name(TABLE_NAME)
+                    .table_name(table_name())
                     .key_schema(key_schema_element)  // ✅ Use correct variable name
                     .attribute_definitions(attribute_definition)  // ✅ Use correct variable name
+                    .attribute_definitions(study_attribute_definition)
+                    .global_secondary_indexes(study_index()?)
                     .billing_mode(BillingMode::PayPerRequest)
                     .send()
                     .await
                     .context("Failed to create DynamoDB table")?;
                 
 
-                info!("Table created successfully: {}", TABLE_NAME);
+                info!("Table created successfully: {}", table_name());
 
                 // Wait for the table to become active
                 info!("Waiting for table to become active...");
@@ -322,7 +1087,7 @@ pub async fn ensure_table_exists(client: &Client) -> Result<()> {
                 let max_attempts = 10;
 
                 while attempts < max_attempts {
-                    match client.describe_table().table_name(TABLE_NAME).send().await {
+                    match client.describe_table().table_name(table_name()).send().await {
                         Ok(response) => {
                             if let Some(table) = response.table() {
                                 if let Some(status) = table.table_status() {
@@ -349,4 +1114,423 @@ pub async fn ensure_table_exists(client: &Client) -> Result<()> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Definition of the study UID index, projecting whole cases
+fn study_index() -> Result<aws_sdk_dynamodb::types::GlobalSecondaryIndex> {
+    use aws_sdk_dynamodb::types::{GlobalSecondaryIndex, KeySchemaElement, KeyType, Projection, ProjectionType};
+    
+    Ok(GlobalSecondaryIndex::builder()
+        .index_name(STUDY_UID_INDEX)
+        .key_schema(KeySchemaElement::builder()
+            .attribute_name("study_instance_uid")
+            .key_type(KeyType::Hash)
+            .build()?)
+        .projection(Projection::builder()
+            .projection_type(ProjectionType::All)
+            .build())
+        .build()?)
+}
+
+/// Add the study UID index to an existing table
+async fn create_study_index(client: &Client) -> Result<()> {
+    use aws_sdk_dynamodb::types::{
+        AttributeDefinition, CreateGlobalSecondaryIndexAction, GlobalSecondaryIndexUpdate, ScalarAttributeType,
+    };
+    
+    info!("Adding index {} to table {}", STUDY_UID_INDEX, table_name());
+    let index = study_index()?;
+    
+    let create_action = CreateGlobalSecondaryIndexAction::builder()
+        .index_name(STUDY_UID_INDEX)
+        .set_key_schema(Some(index.key_schema().to_vec()))
+        .set_projection(index.projection().cloned())
+        .build()?;
+    
+    client.update_table()
+        .table_name(table_name())
+        .attribute_definitions(AttributeDefinition::builder()
+            .attribute_name("study_instance_uid")
+            .attribute_type(ScalarAttributeType::S)
+            .build()?)
+        .global_secondary_index_updates(GlobalSecondaryIndexUpdate::builder()
+            .create(create_action)
+            .build())
+        .send()
+        .await
+        .context("Failed to add study UID index")?;
+    
+    info!("Index {} is being created", STUDY_UID_INDEX);
+    Ok(())
+}
+
+/// Add a case to a user's favorites, returning the updated list
+pub async fn add_favorite(client: &Client, user_id: &str, case_id: &str) -> Result<Vec<String>> {
+    info!("Adding favorite for user {}: {}", user_id, case_id);
+    update_favorites(client, user_id, case_id, "ADD #case_ids :case_ids").await
+}
+
+/// Remove a case from a user's favorites, returning the updated list
+pub async fn remove_favorite(client: &Client, user_id: &str, case_id: &str) -> Result<Vec<String>> {
+    info!("Removing favorite for user {}: {}", user_id, case_id);
+    update_favorites(client, user_id, case_id, "DELETE #case_ids :case_ids").await
+}
+
+/// Apply an atomic String Set update to a user's favorites
+async fn update_favorites(client: &Client, user_id: &str, case_id: &str, update_expression: &str) -> Result<Vec<String>> {
+    use aws_sdk_dynamodb::types::ReturnValue;
+    
+    let result = with_timeout("dynamodb:update_item", client.update_item()
+        .table_name(FAVORITES_TABLE_NAME)
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .update_expression(update_expression)
+        .set_expression_attribute_names(attribute_names(&[update_expression]))
+        .expression_attribute_values(":case_ids", AttributeValue::Ss(vec![case_id.to_string()]))
+        .return_values(ReturnValue::AllNew)
+        .send())
+        .await
+        .context("Failed to update favorites in DynamoDB")?;
+    
+    // Removing the last id deletes the set attribute altogether
+    Ok(result.attributes()
+        .and_then(|attributes| attributes.get("case_ids"))
+        .and_then(|v| v.as_ss().ok())
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// Get the case ids a user has bookmarked
+pub async fn get_favorites(client: &Client, user_id: &str) -> Result<Vec<String>> {
+    info!("Getting favorites for user: {}", user_id);
+    
+    let result = with_timeout("dynamodb:get_item", client.get_item()
+        .table_name(FAVORITES_TABLE_NAME)
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .send())
+        .await
+        .context("Failed to get favorites from DynamoDB")?;
+    
+    Ok(result.item
+        .and_then(|item| item.get("case_ids").and_then(|v| v.as_ss().ok()).cloned())
+        .unwrap_or_default())
+}
+
+/// Replace a case's tags, provided they still equal `previous`. Returns false when the
+/// tags changed since they were read or the case is gone, leaving the item unchanged.
+pub async fn update_case_tags(
+    client: &Client,
+    case_id: &str,
+    previous: &[String],
+    tags: &[String],
+    updated_at: &str
+) -> Result<bool> {
+    use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+    
+    info!("Updating tags of case {}: {:?}", case_id, tags);
+    invalidate_cached_case(case_id);
+    
+    let to_list = |tags: &[String]| AttributeValue::L(tags.iter().map(|tag| AttributeValue::S(tag.clone())).collect());
+    let update = "SET #tags = :tags, #updated_at = :updated_at";
+    let condition = "attribute_exists(#case_id) AND #tags = :previous";
+    let request = client.update_item()
+        .table_name(table_name())
+        .key("case_id", AttributeValue::S(case_id.to_string()))
+        .update_expression(update)
+        .condition_expression(condition)
+        .set_expression_attribute_names(attribute_names(&[update, condition]))
+        .expression_attribute_values(":tags", to_list(tags))
+        .expression_attribute_values(":previous", to_list(previous))
+        .expression_attribute_values(":updated_at", AttributeValue::S(updated_at.to_string()));
+    
+    match with_timeout("dynamodb:update_item", request.send()).await {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            let lost_race = err.downcast_ref::<SdkError<UpdateItemError>>()
+                .and_then(|sdk_err| sdk_err.as_service_error())
+                .is_some_and(|service_err| service_err.is_conditional_check_failed_exception());
+            
+            if lost_race {
+                Ok(false)
+            } else {
+                Err(err.context("Failed to update case tags in DynamoDB"))
+            }
+        }
+    }
+}
+
+/// Atomically add one to a case's view count. Missing and deleted cases are left alone rather
+/// than created.
+pub async fn increment_view_count(client: &Client, case_id: &str) -> Result<()> {
+    let update = "ADD #view_count :one";
+    let condition = "attribute_exists(#case_id) AND attribute_not_exists(#deleted_at)";
+    with_timeout("dynamodb:update_item", client.update_item()
+        .table_name(table_name())
+        .key("case_id", AttributeValue::S(case_id.to_string()))
+        .update_expression(update)
+        .condition_expression(condition)
+        .set_expression_attribute_names(attribute_names(&[update, condition]))
+        .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        .send())
+        .await
+        .context(format!("Failed to increment view count of case {}", case_id))?;
+    
+    Ok(())
+}
+
+/// Ensure the favorites table exists
+pub async fn ensure_favorites_table_exists(client: &Client) -> Result<()> {
+    use aws_sdk_dynamodb::types::{
+        AttributeDefinition, KeySchemaElement, KeyType, ScalarAttributeType, BillingMode,
+    };
+    
+    info!("Ensuring DynamoDB table exists: {}", FAVORITES_TABLE_NAME);
+    
+    match client.describe_table().table_name(FAVORITES_TABLE_NAME).send().await {
+        Ok(_) => {
+            info!("Table already exists: {}", FAVORITES_TABLE_NAME);
+            Ok(())
+        }
+        Err(err) if err.to_string().contains("ResourceNotFoundException") => {
+            info!("Creating table: {}", FAVORITES_TABLE_NAME);
+            
+            client.create_table()
+                .table_name(FAVORITES_TABLE_NAME)
+                .key_schema(KeySchemaElement::builder()
+                    .attribute_name("user_id")
+                    .key_type(KeyType::Hash)
+                    .build()?)
+                .attribute_definitions(AttributeDefinition::builder()
+                    .attribute_name("user_id")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?)
+                .billing_mode(BillingMode::PayPerRequest)
+                .send()
+                .await
+                .context("Failed to create favorites table")?;
+            
+            info!("Table creation initiated: {}", FAVORITES_TABLE_NAME);
+            Ok(())
+        }
+        Err(err) => Err(anyhow::anyhow!("Error checking if favorites table exists: {:?}", err)),
+    }
+}
+
+/// Load a client's rate limit bucket as (tokens, refilled_at in epoch millis)
+pub async fn get_rate_bucket(client: &Client, client_key: &str) -> Result<Option<(f64, i64)>> {
+    let result = with_timeout("dynamodb:get_item", client.get_item()
+        .table_name(RATE_LIMIT_TABLE_NAME)
+        .key("client_key", AttributeValue::S(client_key.to_string()))
+        .consistent_read(true)
+        .send())
+        .await
+        .context("Failed to get rate limit bucket from DynamoDB")?;
+    
+    let number = |item: &HashMap<String, AttributeValue>, name: &str| -> Option<String> {
+        item.get(name).and_then(|v| v.as_n().ok()).cloned()
+    };
+    
+    Ok(result.item.and_then(|item| {
+        let tokens = number(&item, "tokens")?.parse::<f64>().ok()?;
+        let refilled_at = number(&item, "refilled_at")?.parse::<i64>().ok()?;
+        Some((tokens, refilled_at))
+    }))
+}
+
+/// Store a client's rate limit bucket. Returns false when another request updated
+/// the bucket after previous_refilled_at was read, leaving the stored bucket unchanged.
+pub async fn put_rate_bucket(
+    client: &Client,
+    client_key: &str,
+    tokens: f64,
+    refilled_at: i64,
+    previous_refilled_at: Option<i64>,
+    expires_at: i64
+) -> Result<bool> {
+    let request = client.put_item()
+        .table_name(RATE_LIMIT_TABLE_NAME)
+        .item("client_key", AttributeValue::S(client_key.to_string()))
+        .item("tokens", AttributeValue::N(tokens.to_string()))
+        .item("refilled_at", AttributeValue::N(refilled_at.to_string()))
+        .item("expires_at", AttributeValue::N(expires_at.to_string()));
+    
+    let condition = match previous_refilled_at {
+        Some(_) => "#refilled_at = :previous",
+        None => "attribute_not_exists(#client_key)",
+    };
+    let request = request
+        .condition_expression(condition)
+        .set_expression_attribute_names(attribute_names(&[condition]))
+        .set_expression_attribute_values(previous_refilled_at.map(|previous| {
+            HashMap::from([(":previous".to_string(), AttributeValue::N(previous.to_string()))])
+        }));
+    
+    match with_timeout("dynamodb:put_item", request.send()).await {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            let lost_race = err.downcast_ref::<SdkError<PutItemError>>()
+                .and_then(|sdk_err| sdk_err.as_service_error())
+                .is_some_and(|service_err| service_err.is_conditional_check_failed_exception());
+            
+            if lost_race {
+                Ok(false)
+            } else {
+                Err(err.context("Failed to save rate limit bucket to DynamoDB"))
+            }
+        }
+    }
+}
+
+/// Ensure the rate limit table exists with TTL on expires_at
+pub async fn ensure_rate_limit_table_exists(client: &Client) -> Result<()> {
+    use aws_sdk_dynamodb::types::{
+        AttributeDefinition, KeySchemaElement, KeyType, ScalarAttributeType, BillingMode,
+        TimeToLiveSpecification, TimeToLiveStatus,
+    };
+    
+    info!("Ensuring DynamoDB table exists: {}", RATE_LIMIT_TABLE_NAME);
+    
+    match client.describe_table().table_name(RATE_LIMIT_TABLE_NAME).send().await {
+        Ok(_) => {
+            info!("Table already exists: {}", RATE_LIMIT_TABLE_NAME);
+            
+            // TTL cannot be enabled while the table is still being created, so it is
+            // switched on by the first cold start that finds the table in place
+            let ttl = client.describe_time_to_live()
+                .table_name(RATE_LIMIT_TABLE_NAME)
+                .send()
+                .await
+                .context("Failed to describe rate limit table TTL")?;
+            
+            let status = ttl.time_to_live_description()
+                .and_then(|description| description.time_to_live_status());
+            if matches!(status, Some(TimeToLiveStatus::Enabled) | Some(TimeToLiveStatus::Enabling)) {
+                return Ok(());
+            }
+            
+            info!("Enabling TTL on {}", RATE_LIMIT_TABLE_NAME);
+            client.update_time_to_live()
+                .table_name(RATE_LIMIT_TABLE_NAME)
+                .time_to_live_specification(TimeToLiveSpecification::builder()
+                    .attribute_name("expires_at")
+                    .enabled(true)
+                    .build()?)
+                .send()
+                .await
+                .context("Failed to enable TTL on rate limit table")?;
+            
+            Ok(())
+        }
+        Err(err) if err.to_string().contains("ResourceNotFoundException") => {
+            info!("Creating table: {}", RATE_LIMIT_TABLE_NAME);
+            
+            client.create_table()
+                .table_name(RATE_LIMIT_TABLE_NAME)
+                .key_schema(KeySchemaElement::builder()
+                    .attribute_name("client_key")
+                    .key_type(KeyType::Hash)
+                    .build()?)
+                .attribute_definitions(AttributeDefinition::builder()
+                    .attribute_name("client_key")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?)
+                .billing_mode(BillingMode::PayPerRequest)
+                .send()
+                .await
+                .context("Failed to create rate limit table")?;
+            
+            info!("Table creation initiated: {}", RATE_LIMIT_TABLE_NAME);
+            Ok(())
+        }
+        Err(err) => Err(anyhow::anyhow!("Error checking if rate limit table exists: {:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn tombstones_are_told_apart_from_cases() {
+        let case_id = ("case_id".to_string(), AttributeValue::S("case".to_string()));
+        let tombstone = HashMap::from([case_id.clone(),
+            (DELETED_AT.to_string(), AttributeValue::S("2025-02-01T00:00:00+00:00".to_string()))]);
+        assert!(is_tombstone(&tombstone));
+        assert!(!is_tombstone(&HashMap::from([case_id])));
+    }
+    
+    #[test]
+    fn case_deleted_is_recognised_through_context() {
+        let err = anyhow::Error::from(CaseDeleted { case_id: "case".to_string() }).context("Failed to update case");
+        assert!(is_case_deleted(&err));
+        assert!(!is_case_deleted(&anyhow::anyhow!("Case case has been deleted")));
+    }
+    
+    #[test]
+    fn items_without_created_at_report_the_epoch() {
+        let item = HashMap::from([
+            ("case_id".to_string(), AttributeValue::S("case".to_string())),
+            ("title".to_string(), AttributeValue::S("Title".to_string())),
+        ]);
+        let case = convert_item_to_case(item.clone()).expect("case");
+        assert_eq!(case.created_at, MISSING_CREATED_AT);
+        assert_eq!(case.updated_at, MISSING_CREATED_AT);
+        assert_eq!(convert_item_to_case(item).expect("case").created_at, case.created_at);
+    }
+    
+    #[test]
+    fn case_update_never_overwrites_the_view_count() {
+        let item = HashMap::from([
+            ("case_id".to_string(), AttributeValue::S("case".to_string())),
+            ("title".to_string(), AttributeValue::S("Title".to_string())),
+            ("key_image_sop".to_string(), AttributeValue::S("1.2.3".to_string())),
+        ]);
+        let (update, values) = case_update(item);
+        
+        assert_eq!(update, "SET #key_image_sop = :key_image_sop, #title = :title, \
+                            #view_count = if_not_exists(#view_count, :zero) \
+                            REMOVE #study_instance_uid, #expected_instance_count, #instances_key");
+        assert!(!values.contains_key(":case_id") && !values.contains_key(":view_count"));
+        assert_eq!(values[":zero"], AttributeValue::N("0".to_string()));
+        assert_eq!(attribute_names(&[&update]).expect("names").len(), 6);
+    }
+    
+    #[test]
+    fn attribute_names_maps_every_placeholder_in_the_expressions() {
+        let names = attribute_names(&["contains(#tags, :tag) AND #series <> :empty", "#date, #series, case_id"])
+            .expect("names");
+        assert_eq!(names, HashMap::from([
+            ("#tags".to_string(), "tags".to_string()),
+            ("#series".to_string(), "series".to_string()),
+            ("#date".to_string(), "date".to_string()),
+        ]));
+        
+        // A bare # is not a placeholder, and expressions without any need no names
+        assert_eq!(attribute_names(&["#view_count + :one", "# x"]).expect("names").len(), 1);
+        assert_eq!(attribute_names(&["case_id = :id"]), None);
+    }
+    
+    #[test]
+    fn oversized_cases_split_into_a_slim_item_and_offloaded_instances() {
+        let threshold = 350 * 1024;
+        let image_ids: Vec<String> = (0..10_000).map(|index| format!("1.2.840.113619.2.55.3.1234.{}", index)).collect();
+        let case: Case = serde_json::from_value(serde_json::json!({
+            "case_id": "case", "title": "t", "description": "", "modality": "CT", "anatomy": "",
+            "diagnosis": "d", "findings": "", "tags": [], "image_ids": image_ids,
+            "study_instance_uid": "", "series_instance_uid": "", "created_at": "", "updated_at": "",
+            "series": [{ "series_instance_uid": "1.2.3.1", "series_number": 1, "series_description": "",
+                         "modality": "CT", "image_ids": image_ids }]
+        })).expect("case");
+        assert!(exceeds_offload_threshold(&case, threshold));
+        
+        let (slim, instances) = split_instances(&case);
+        assert!(!exceeds_offload_threshold(&slim, threshold));
+        assert!(slim.image_ids.is_empty() && slim.series.is_empty());
+        
+        // The offloaded object round-trips through its JSON form
+        let stored: OffloadedInstances = serde_json::from_slice(&serde_json::to_vec(&instances).unwrap()).unwrap();
+        let mut rehydrated = slim;
+        stored.restore(&mut rehydrated);
+        assert_eq!(rehydrated.image_ids, case.image_ids);
+        assert_eq!(rehydrated.series[0].image_ids.len(), 10_000);
+    }
+}
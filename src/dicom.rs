@@ -1,11 +1,133 @@
 use anyhow::{Context, Result, anyhow};
-use dicom_object::open_file;
+use dicom_core::{Tag, VR};
+use dicom_object::{open_file, DefaultDicomObject, FileMetaTableBuilder, InMemDicomObject};
 use std::path::Path;
 use tracing::{info, warn, error};
 use std::fs;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::error::Error as StdError;
+use std::fmt;
 
-use crate::models::DicomMetadata;
+use crate::config;
+use crate::models::{DicomMetadata, FrameAttributes};
+
+// Local file header signature at the start of every ZIP archive
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+// Marker that follows the 128-byte preamble and precedes the file meta group
+const DICM_MAGIC: &[u8] = b"DICM";
+
+// Transfer syntaxes assumed for a bare data set with no file meta group
+const IMPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2";
+pub const EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1";
+
+// SOP class recorded in the synthesized meta group (Secondary Capture Image Storage);
+// metadata is read from the data set itself, so this is only a placeholder
+const PLACEHOLDER_SOP_CLASS_UID: &str = "1.2.840.10008.5.1.4.1.1.7";
+
+/// Error raised when DICOM input cannot be read, carried inside anyhow errors
+#[derive(Debug)]
+pub enum DicomError {
+    /// The data has no DICOM preamble or file meta group
+    NotDicom,
+    /// A tag the application depends on (e.g. SOPInstanceUID) is absent or empty
+    MissingRequiredTag(String),
+    /// The object is encoded with a transfer syntax that cannot be read
+    UnsupportedTransferSyntax(String),
+    /// Reading or writing the temporary working files failed
+    Io(std::io::Error),
+    /// The object is DICOM but its content could not be parsed
+    Parse(String),
+    /// The upload holds more instances than MAX_INSTANCES_PER_UPLOAD allows
+    TooManyInstances { count: usize, limit: usize },
+    /// A requested frame number is outside the object's NumberOfFrames
+    FrameOutOfRange { frame: u32, frames: u32 },
+    /// The parser panicked on implausible content, such as a corrupt element length
+    Malformed(String),
+    /// /tmp cannot hold the working files for the data
+    InsufficientTempSpace { needed: u64, available: u64 },
+}
+
+impl fmt::Display for DicomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DicomError::NotDicom => write!(f, "Data is not a DICOM object"),
+            DicomError::MissingRequiredTag(tag) => write!(f, "Missing required tag {}", tag),
+            DicomError::UnsupportedTransferSyntax(uid) => write!(f, "Unsupported transfer syntax {}", uid),
+            DicomError::Io(e) => write!(f, "I/O error while reading DICOM data: {}", e),
+            DicomError::Parse(message) => write!(f, "Failed to parse DICOM data: {}", message),
+            DicomError::TooManyInstances { count, limit } => write!(
+                f, "Upload contains {} instances, more than the limit of {}; split it into smaller uploads", count, limit
+            ),
+            DicomError::FrameOutOfRange { frame, frames } => write!(
+                f, "Frame {} is out of range; the object has {} frame(s)", frame, frames
+            ),
+            DicomError::Malformed(message) => write!(f, "DICOM data is malformed: {}", message),
+            DicomError::InsufficientTempSpace { needed, available } => write!(
+                f, "Not enough temporary disk space to process the data ({} bytes needed, {} available); try a smaller upload",
+                needed, available
+            ),
+        }
+    }
+}
+
+impl StdError for DicomError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            DicomError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DicomError {
+    fn from(e: std::io::Error) -> Self {
+        DicomError::Io(e)
+    }
+}
+
+impl From<dicom_object::Error> for DicomError {
+    fn from(e: dicom_object::Error) -> Self {
+        match e {
+            // Data that ends before its preamble and meta group were read is not DICOM,
+            // not a failing disk
+            dicom_object::Error::ReadFile { source, .. } if source.kind() == std::io::ErrorKind::UnexpectedEof => {
+                DicomError::NotDicom
+            },
+            dicom_object::Error::OpenFile { source, .. }
+            | dicom_object::Error::ReadFile { source, .. } => DicomError::Io(source),
+            // No 128-byte preamble or "DICM" file meta group
+            dicom_object::Error::ReadPreambleBytes { .. }
+            | dicom_object::Error::ParseMetaDataSet { .. } => DicomError::NotDicom,
+            dicom_object::Error::UnsupportedTransferSyntax { uid, .. } => {
+                DicomError::UnsupportedTransferSyntax(uid.trim_end_matches('\0').to_string())
+            },
+            other => DicomError::Parse(other.to_string()),
+        }
+    }
+}
+
+/// Run a parse step, turning a panic inside dicom-object (seen with corrupt length
+/// fields) into DicomError::Malformed instead of failing the whole invocation
+fn catch_parser_panic<T>(what: &str, parse: impl FnOnce() -> Result<T>) -> Result<T> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(parse)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "parser panicked".to_string());
+            error!("DICOM parser panicked while {}: {}", what, message);
+            Err(DicomError::Malformed(message).into())
+        }
+    }
+}
+
+/// Find the DicomError behind an anyhow error, if it was caused by one
+pub fn dicom_error(err: &anyhow::Error) -> Option<&DicomError> {
+    err.chain().find_map(|cause| cause.downcast_ref::<DicomError>())
+}
 
 /// Ensure the DICOM directory exists in the Lambda tmp folder
 pub fn ensure_dicom_dir_exists() -> Result<String> {
@@ -19,6 +141,209 @@ pub fn ensure_dicom_dir_exists() -> Result<String> {
     Ok(dicom_dir.to_string_lossy().to_string())
 }
 
+// Most bytes a ZIP upload may unpack to across all of its entries, so a small, highly
+// compressed archive cannot keep the parser writing and reading for minutes
+const MAX_ZIP_UNPACKED_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+// Working copies a study parse can make: the whole upload plus its split parts or entries
+const STUDY_TEMP_COPIES: u64 = 2;
+
+/// Refuse to start writing working files that /tmp cannot hold, rather than failing mid-write.
+/// Free space comes from the filesystem itself, so files an earlier invocation left behind in a
+/// warm container are accounted for without walking /tmp.
+fn check_temp_space(needed: u64) -> Result<()> {
+    let available = match available_temp_space() {
+        Some(available) => available,
+        None => return Ok(()),
+    };
+    info!("Temp space: {} bytes available, {} needed", available, needed);
+    
+    if needed > available {
+        warn!("Not enough /tmp space: {} bytes needed, {} available", needed, available);
+        return Err(DicomError::InsufficientTempSpace { needed, available }.into());
+    }
+    
+    Ok(())
+}
+
+// Free /tmp space in bytes, or None when the filesystem cannot report it
+fn available_temp_space() -> Option<u64> {
+    fs2::available_space("/tmp")
+        .map_err(|e| warn!("Could not read free /tmp space, skipping the check: {}", e))
+        .ok()
+}
+
+/// The SOPInstanceUID an image id refers to: frames of a multi-frame object are
+/// listed under virtual ids of the form {sop}.frame{N}
+pub fn parent_sop_instance_uid(image_id: &str) -> &str {
+    match image_id.rsplit_once(".frame") {
+        Some((parent, frame)) if !frame.is_empty() && frame.chars().all(|c| c.is_ascii_digit()) => parent,
+        _ => image_id,
+    }
+}
+
+/// Check that a string is a plausible DICOM UID (dotted numeric, at most 64 characters)
+pub fn is_valid_uid(uid: &str) -> bool {
+    !uid.is_empty()
+        && uid.len() <= 64
+        && uid.split('.').all(|component| {
+            !component.is_empty() && component.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+/// Check that an image or series id taken from a path can name a stored object: a UID,
+/// optionally with the .frame{N} suffix of a multi-frame instance or the unknown. prefix
+/// given to placeholder ids by older uploads
+pub fn is_valid_image_id(id: &str) -> bool {
+    let base = parent_sop_instance_uid(id);
+    is_valid_uid(base.strip_prefix("unknown.").unwrap_or(base))
+}
+
+/// The org root for remapped UIDs (UID_ROOT) from the loaded config
+pub fn uid_root() -> String {
+    config::get().uid_root.clone()
+}
+
+/// Generate a new UID under the org root from a random 128-bit value
+pub fn generate_uid() -> String {
+    let root = uid_root();
+    let mut value = uuid::Uuid::new_v4().as_u128().to_string();
+    value.truncate(64 - root.len() - 1);
+    format!("{}.{}", root, value)
+}
+
+/// Derive a replacement UID from a hash of the original, under root_prefix.
+/// The same original always maps to the same UID, so study/series/instance
+/// relationships survive when every instance of a study is remapped.
+pub fn remap_uid(original: &str, root_prefix: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, original.trim().as_bytes());
+    let mut value_bytes = [0u8; 16];
+    value_bytes.copy_from_slice(&digest.as_ref()[..16]);
+    
+    // UIDs are limited to 64 characters; drop trailing digits if a long root leaves less room
+    let mut value = u128::from_be_bytes(value_bytes).to_string();
+    value.truncate(64usize.saturating_sub(root_prefix.len() + 1).max(1));
+    
+    format!("{}.{}", root_prefix.trim_end_matches('.'), value)
+}
+
+// Patient and visit identifiers emptied by anonymize_upload
+const IDENTIFYING_ATTRIBUTES: &[(Tag, VR)] = &[
+    (Tag(0x0008, 0x0050), VR::SH), // AccessionNumber
+    (Tag(0x0008, 0x0080), VR::LO), // InstitutionName
+    (Tag(0x0008, 0x0090), VR::PN), // ReferringPhysicianName
+    (Tag(0x0010, 0x0010), VR::PN), // PatientName
+    (Tag(0x0010, 0x0020), VR::LO), // PatientID
+    (Tag(0x0010, 0x0030), VR::DA), // PatientBirthDate
+    (Tag(0x0010, 0x1000), VR::LO), // OtherPatientIDs
+    (Tag(0x0010, 0x1040), VR::LO), // PatientAddress
+];
+
+// UIDs replaced through remap_uid by anonymize_upload
+const REMAPPED_UIDS: &[Tag] = &[
+    Tag(0x0008, 0x0018), // SOPInstanceUID
+    Tag(0x0020, 0x000D), // StudyInstanceUID
+    Tag(0x0020, 0x000E), // SeriesInstanceUID
+    Tag(0x0020, 0x0052), // FrameOfReferenceUID
+];
+
+/// Empty the patient identifiers of every object in an upload and remap its study, series,
+/// instance and frame of reference UIDs under UID_ROOT. Returns the objects as a stream of
+/// Part 10 files, which parse like any other multi-object upload.
+pub fn anonymize_upload(data: &[u8]) -> Result<Vec<u8>> {
+    let root = uid_root();
+    let mut anonymized = Vec::with_capacity(data.len());
+    
+    for (_, file) in upload_objects(data)? {
+        let sop_class_uid = file.meta().media_storage_sop_class_uid().trim_end_matches('\0').to_string();
+        let transfer_syntax = file.meta().transfer_syntax().trim_end_matches('\0').to_string();
+        let mut obj = file.into_inner();
+        
+        for (tag, vr) in IDENTIFYING_ATTRIBUTES {
+            if obj.element(*tag).is_ok() {
+                crate::sr::put_text(&mut obj, *tag, *vr, "");
+            }
+        }
+        for tag in REMAPPED_UIDS {
+            let original = obj.element(*tag).ok()
+                .and_then(|element| element.to_str().ok())
+                .map(|uid| uid.trim_end_matches('\0').trim().to_string());
+            if let Some(original) = original.filter(|uid| !uid.is_empty()) {
+                crate::sr::put_text(&mut obj, *tag, VR::UI, &remap_uid(&original, &root));
+            }
+        }
+        
+        // The meta group is rebuilt so its media storage SOP instance UID follows the remapped one
+        let file = obj.with_meta(FileMetaTableBuilder::new()
+                .media_storage_sop_class_uid(sop_class_uid)
+                .transfer_syntax(transfer_syntax))
+            .map_err(|e| DicomError::Parse(e.to_string()))?;
+        file.write_all(&mut anonymized)
+            .map_err(|e| DicomError::Parse(e.to_string()))?;
+    }
+    
+    info!("Anonymized upload: {} bytes in, {} bytes out", data.len(), anonymized.len());
+    Ok(anonymized)
+}
+
+// Storage SOP classes whose modality is implied by the class itself
+const SOP_CLASS_MODALITIES: &[(&str, &str)] = &[
+    ("1.2.840.10008.5.1.4.1.1.1", "CR"),
+    ("1.2.840.10008.5.1.4.1.1.1.1", "DX"),
+    ("1.2.840.10008.5.1.4.1.1.1.1.1", "DX"),
+    ("1.2.840.10008.5.1.4.1.1.1.2", "MG"),
+    ("1.2.840.10008.5.1.4.1.1.1.2.1", "MG"),
+    ("1.2.840.10008.5.1.4.1.1.13.1.3", "MG"),
+    ("1.2.840.10008.5.1.4.1.1.1.3", "IO"),
+    ("1.2.840.10008.5.1.4.1.1.1.3.1", "IO"),
+    ("1.2.840.10008.5.1.4.1.1.2", "CT"),
+    ("1.2.840.10008.5.1.4.1.1.2.1", "CT"),
+    ("1.2.840.10008.5.1.4.1.1.2.2", "CT"),
+    ("1.2.840.10008.5.1.4.1.1.4", "MR"),
+    ("1.2.840.10008.5.1.4.1.1.4.1", "MR"),
+    ("1.2.840.10008.5.1.4.1.1.4.2", "MR"),
+    ("1.2.840.10008.5.1.4.1.1.4.3", "MR"),
+    ("1.2.840.10008.5.1.4.1.1.4.4", "MR"),
+    ("1.2.840.10008.5.1.4.1.1.3.1", "US"),
+    ("1.2.840.10008.5.1.4.1.1.6.1", "US"),
+    ("1.2.840.10008.5.1.4.1.1.6.2", "US"),
+    ("1.2.840.10008.5.1.4.1.1.12.1", "XA"),
+    ("1.2.840.10008.5.1.4.1.1.12.1.1", "XA"),
+    ("1.2.840.10008.5.1.4.1.1.12.2", "RF"),
+    ("1.2.840.10008.5.1.4.1.1.12.2.1", "RF"),
+    ("1.2.840.10008.5.1.4.1.1.20", "NM"),
+    ("1.2.840.10008.5.1.4.1.1.128", "PT"),
+    ("1.2.840.10008.5.1.4.1.1.128.1", "PT"),
+    ("1.2.840.10008.5.1.4.1.1.130", "PT"),
+    ("1.2.840.10008.5.1.4.1.1.481.1", "RTIMAGE"),
+];
+
+/// Modality implied by a storage SOP Class UID, for objects without a Modality tag.
+/// Classes that do not imply one (e.g. Secondary Capture) return None.
+pub fn modality_from_sop_class(uid: &str) -> Option<String> {
+    let uid = uid.trim_end_matches(['\0', ' ']).trim();
+    SOP_CLASS_MODALITIES.iter()
+        .find(|(class_uid, _)| *class_uid == uid)
+        .map(|(_, modality)| modality.to_string())
+}
+
+// Allowlist of additional DICOM keywords, fixed for the lifetime of the Lambda
+static EXTRA_TAGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Install the extra tag allowlist loaded at startup (e.g. from a JSON file in S3).
+/// Has no effect once the allowlist has been read.
+pub fn set_extra_tags(tags: Vec<String>) {
+    if EXTRA_TAGS.set(tags).is_err() {
+        warn!("Extra DICOM tag allowlist already initialized, ignoring new value");
+    }
+}
+
+/// Additional DICOM keywords (e.g. "KVP", "ProtocolName") to attach to metadata.
+/// Falls back to the EXTRA_DICOM_TAGS list from the loaded config.
+pub fn extra_tags() -> &'static [String] {
+    EXTRA_TAGS.get_or_init(|| crate::config::get().extra_dicom_tags.clone())
+}
+
 /// Extract metadata from a DICOM file's binary data
 pub fn extract_metadata(data: &[u8]) -> Result<DicomMetadata> {
     // For testing purposes, check for our test data
@@ -36,16 +361,27 @@ pub fn extract_metadata(data: &[u8]) -> Result<DicomMetadata> {
             study_description: "TEST STUDY".to_string(),
             series_description: "TEST SERIES".to_string(),
             instance_number: 1,
+            series_number: 1,
+            study_related_instances: None,
+            series_related_instances: None,
+            extra: HashMap::new(),
+            frame: None,
+            referenced_sop_instance_uids: Vec::new(),
+            acquisition_datetime: None,
+            body_part_examined: String::new(),
+            laterality: String::new(),
         });
     }
 
     // Ensure the DICOM directory exists
     let dicom_dir = ensure_dicom_dir_exists()?;
+    check_temp_space(data.len() as u64)?;
     
     // Write the data to a temporary file in the /tmp directory
     let temp_file_path = format!("{}/temp_{}.dcm", dicom_dir, uuid::Uuid::new_v4());
     
     fs::write(&temp_file_path, data)
+        .map_err(DicomError::Io)
         .context("Failed to write DICOM data to temporary file")?;
     
     // Extract metadata from the file directly
@@ -59,17 +395,261 @@ pub fn extract_metadata(data: &[u8]) -> Result<DicomMetadata> {
     result
 }
 
+/// Open a DICOM file, falling back to layouts without the 128-byte preamble
+/// that open_file rejects. The original error is kept if no fallback applies.
+fn open_dicom_file(path: &Path) -> std::result::Result<DefaultDicomObject, DicomError> {
+    let open_error = match open_file(path) {
+        Ok(obj) => return Ok(obj),
+        Err(e) => DicomError::from(e),
+    };
+    
+    // Only data that could never have been read with a preamble is retried
+    if !matches!(open_error, DicomError::NotDicom | DicomError::Parse(_)) {
+        return Err(open_error);
+    }
+    
+    let data = fs::read(path)?;
+    match open_without_preamble(&data) {
+        Some(Ok(obj)) => {
+            info!("Opened DICOM file without preamble: {:?}", path);
+            return Ok(obj);
+        },
+        Some(Err(e)) => warn!("Preamble-less read of {:?} also failed: {}", path, e),
+        None => {},
+    }
+    
+    match open_with_implicit_meta(&data) {
+        Some(Ok((obj, transfer_syntax))) => {
+            info!("Opened DICOM file with an implicit VR meta group as {}: {:?}", transfer_syntax, path);
+            Ok(obj)
+        },
+        Some(Err(e)) => {
+            warn!("Implicit VR meta group read of {:?} also failed: {}", path, e);
+            Err(open_error)
+        },
+        None => Err(open_error),
+    }
+}
+
+/// Read data whose file meta group is written in implicit VR, which the standard forbids
+/// and open_file rejects. The meta group is rebuilt in explicit VR and the data set is
+/// tried under its declared transfer syntax, then explicit and implicit VR little endian.
+/// Returns the object with the transfer syntax that worked, or None for other data.
+fn open_with_implicit_meta(data: &[u8]) -> Option<std::result::Result<(DefaultDicomObject, String), DicomError>> {
+    let mut offset = if data.get(128..132) == Some(DICM_MAGIC) {
+        132
+    } else if data.starts_with(DICM_MAGIC) {
+        DICM_MAGIC.len()
+    } else {
+        0
+    };
+    
+    // Walk the group 0002 elements as tag + 4-byte length, keeping the UIDs needed to rebuild it
+    let mut meta_uids: HashMap<u16, String> = HashMap::new();
+    while let Some(header) = data.get(offset..offset + 8) {
+        let group = u16::from_le_bytes([header[0], header[1]]);
+        if group != 0x0002 {
+            break;
+        }
+        // A two-letter VR after the tag means the meta group is explicit after all
+        if header[4].is_ascii_uppercase() && header[5].is_ascii_uppercase() {
+            return None;
+        }
+        let element = u16::from_le_bytes([header[2], header[3]]);
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let value = data.get(offset + 8..offset + 8 + length)?;
+        let value = String::from_utf8_lossy(value).trim_end_matches(['\0', ' ']).to_string();
+        meta_uids.insert(element, value);
+        offset += 8 + length;
+    }
+    if meta_uids.is_empty() {
+        return None;
+    }
+    let dataset = &data[offset..];
+    
+    let declared = meta_uids.get(&0x0010).map(String::as_str).filter(|uid| !uid.is_empty());
+    let sop_class_uid = meta_uids.get(&0x0002).filter(|uid| !uid.is_empty()).cloned()
+        .unwrap_or_else(|| PLACEHOLDER_SOP_CLASS_UID.to_string());
+    let sop_instance_uid = meta_uids.get(&0x0003).filter(|uid| !uid.is_empty()).cloned()
+        .unwrap_or_else(|| format!("{}.{}", config::DEFAULT_UID_ROOT, uuid::Uuid::new_v4().as_u128()));
+    
+    let mut candidates = Vec::new();
+    for transfer_syntax in [declared, Some(EXPLICIT_VR_LITTLE_ENDIAN), Some(IMPLICIT_VR_LITTLE_ENDIAN)].into_iter().flatten() {
+        if !candidates.contains(&transfer_syntax) {
+            candidates.push(transfer_syntax);
+        }
+    }
+    
+    let mut last_error = DicomError::NotDicom;
+    for transfer_syntax in candidates {
+        let meta = match FileMetaTableBuilder::new()
+            .media_storage_sop_class_uid(sop_class_uid.as_str())
+            .media_storage_sop_instance_uid(sop_instance_uid.as_str())
+            .transfer_syntax(transfer_syntax)
+            .build()
+        {
+            Ok(meta) => meta,
+            Err(e) => return Some(Err(DicomError::Parse(e.to_string()))),
+        };
+        
+        let mut framed = DICM_MAGIC.to_vec();
+        if let Err(e) = meta.write(&mut framed) {
+            return Some(Err(DicomError::Parse(e.to_string())));
+        }
+        framed.extend_from_slice(dataset);
+        
+        match dicom_object::from_reader(framed.as_slice()) {
+            Ok(obj) => return Some(Ok((obj, transfer_syntax.to_string()))),
+            Err(e) => {
+                warn!("Data set did not parse as {}: {}", transfer_syntax, e);
+                last_error = DicomError::from(e);
+            },
+        }
+    }
+    
+    Some(Err(last_error))
+}
+
+/// Read data that lacks the preamble: "DICM" followed by the meta group, the meta
+/// group alone, or a bare little-endian data set. Returns None for other data.
+fn open_without_preamble(data: &[u8]) -> Option<std::result::Result<DefaultDicomObject, DicomError>> {
+    if data.starts_with(DICM_MAGIC) {
+        return Some(dicom_object::from_reader(data).map_err(DicomError::from));
+    }
+    
+    if data.len() < 8 {
+        return None;
+    }
+    
+    let group = u16::from_le_bytes([data[0], data[1]]);
+    if group == 0x0002 {
+        // File meta group without the "DICM" marker
+        let mut framed = Vec::with_capacity(DICM_MAGIC.len() + data.len());
+        framed.extend_from_slice(DICM_MAGIC);
+        framed.extend_from_slice(data);
+        return Some(dicom_object::from_reader(framed.as_slice()).map_err(DicomError::from));
+    }
+    
+    // A bare data set starts with a low, even group such as 0008 (identifying attributes)
+    if group == 0 || group & 1 == 1 || group > 0x0010 {
+        return None;
+    }
+    
+    // Explicit VR data sets have a two-letter VR right after the tag
+    let explicit_vr = data[4].is_ascii_uppercase() && data[5].is_ascii_uppercase();
+    let transfer_syntax = if explicit_vr { EXPLICIT_VR_LITTLE_ENDIAN } else { IMPLICIT_VR_LITTLE_ENDIAN };
+    
+    let meta = match FileMetaTableBuilder::new()
+        .media_storage_sop_class_uid(PLACEHOLDER_SOP_CLASS_UID)
+        .media_storage_sop_instance_uid(format!("{}.{}", config::DEFAULT_UID_ROOT, uuid::Uuid::new_v4().as_u128()))
+        .transfer_syntax(transfer_syntax)
+        .build()
+    {
+        Ok(meta) => meta,
+        Err(e) => return Some(Err(DicomError::Parse(e.to_string()))),
+    };
+    
+    let mut framed = DICM_MAGIC.to_vec();
+    if let Err(e) = meta.write(&mut framed) {
+        return Some(Err(DicomError::Parse(e.to_string())));
+    }
+    framed.extend_from_slice(data);
+    
+    Some(dicom_object::from_reader(framed.as_slice()).map_err(DicomError::from))
+}
+
+/// Map a SpecificCharacterSet defined term to its encoding
+fn charset_encoding(term: &str) -> Option<&'static encoding_rs::Encoding> {
+    use encoding_rs::*;
+    
+    let encoding = match term.trim().replace('_', " ").as_str() {
+        "ISO IR 100" | "ISO 2022 IR 100" => WINDOWS_1252,
+        "ISO IR 101" | "ISO 2022 IR 101" => ISO_8859_2,
+        "ISO IR 109" | "ISO 2022 IR 109" => ISO_8859_3,
+        "ISO IR 110" | "ISO 2022 IR 110" => ISO_8859_4,
+        "ISO IR 144" | "ISO 2022 IR 144" => ISO_8859_5,
+        "ISO IR 127" | "ISO 2022 IR 127" => ISO_8859_6,
+        "ISO IR 126" | "ISO 2022 IR 126" => ISO_8859_7,
+        "ISO IR 138" | "ISO 2022 IR 138" => ISO_8859_8,
+        "ISO IR 148" | "ISO 2022 IR 148" => WINDOWS_1254,
+        "ISO IR 203" | "ISO 2022 IR 203" => ISO_8859_15,
+        "ISO IR 166" | "ISO 2022 IR 166" => WINDOWS_874,
+        "ISO IR 13" | "ISO 2022 IR 13" => SHIFT_JIS,
+        "ISO 2022 IR 87" | "ISO 2022 IR 159" => ISO_2022_JP,
+        "ISO 2022 IR 149" => EUC_KR,
+        "ISO 2022 IR 58" | "GB2312" | "GBK" => GBK,
+        "GB18030" => GB18030,
+        "ISO IR 192" => UTF_8,
+        _ => return None,
+    };
+    
+    Some(encoding)
+}
+
+/// The encoding needed to re-decode text values, when dicom-object could only read them as Latin-1.
+/// That happens for character sets it does not support and for code extensions
+/// (multi-valued SpecificCharacterSet such as "\ISO 2022 IR 87"); None when its own decoding is right.
+fn text_encoding(obj: &DefaultDicomObject) -> Option<&'static encoding_rs::Encoding> {
+    let value = obj.element_by_name("SpecificCharacterSet").ok()?.to_str().ok()?.to_string();
+    let terms: Vec<&str> = value.split('\\').map(str::trim).collect();
+    
+    // A single term the parser supports, Latin-1 included, is already decoded correctly
+    let parser_supported = |term: &str| matches!(term.replace('_', " ").as_str(),
+        "" | "ISO IR 6" | "ISO 2022 IR 6" | "ISO IR 100" | "ISO 2022 IR 100" | "ISO IR 101" | "ISO 2022 IR 101"
+        | "ISO IR 109" | "ISO 2022 IR 109" | "ISO IR 110" | "ISO 2022 IR 110" | "ISO IR 144" | "ISO 2022 IR 144"
+        | "ISO IR 192" | "GB18030");
+    if terms.len() == 1 && parser_supported(terms[0]) {
+        return None;
+    }
+    
+    // The parser only switches on the first term, so it read the rest as Latin-1.
+    // ISO 2022 JP sets switch by escape sequence and take precedence over single-byte sets.
+    if terms.iter().any(|term| term.ends_with("IR 87") || term.ends_with("IR 159")) {
+        return Some(encoding_rs::ISO_2022_JP);
+    }
+    let encoding = terms.iter().rev().find_map(|term| charset_encoding(term));
+    if encoding.is_none() {
+        warn!("Unsupported SpecificCharacterSet '{}', keeping text as decoded", value);
+    }
+    encoding
+}
+
+/// Recover the raw bytes of a value read as Latin-1 and decode them with the real encoding
+fn redecode_text(value: &str, encoding: &'static encoding_rs::Encoding) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    
+    // Latin-1 maps each byte to the code point of the same value
+    let bytes: Option<Vec<u8>> = value.chars().map(|c| u8::try_from(c as u32).ok()).collect();
+    match bytes {
+        Some(bytes) => encoding.decode_without_bom_handling(&bytes).0.into_owned(),
+        None => value.to_string(),
+    }
+}
+
 /// Extract metadata from a DICOM file on disk
 pub fn extract_metadata_from_file<P: AsRef<Path>>(path: P) -> Result<DicomMetadata> {
+    let path = path.as_ref();
+    catch_parser_panic(&format!("reading {:?}", path), || read_metadata_from_file(path))
+}
+
+fn read_metadata_from_file(path: &Path) -> Result<DicomMetadata> {
     // Open the DICOM file
-    let obj = open_file(path.as_ref())
+    let obj = open_dicom_file(path)
         .context("Failed to open DICOM file")?;
     
+    // Character sets dicom-object cannot decode are read as Latin-1 and fixed up here
+    let text_encoding = text_encoding(&obj);
+    
     // Function to safely extract tag values as strings
     let get_tag_value = |tag_name: &str| -> String {
         match obj.element_by_name(tag_name) {
             Ok(element) => match element.to_str() {
-                Ok(value) => value.to_string(),
+                Ok(value) => match text_encoding {
+                    Some(encoding) => redecode_text(&value, encoding),
+                    None => value.to_string(),
+                },
                 Err(_) => String::new()
             },
             Err(_) => String::new()
@@ -79,41 +659,84 @@ pub fn extract_metadata_from_file<P: AsRef<Path>>(path: P) -> Result<DicomMetada
     // Extract required fields - return error if missing
     let sop_instance_uid = get_tag_value("SOPInstanceUID");
     if sop_instance_uid.is_empty() {
-        return Err(anyhow!("Missing SOPInstanceUID"));
+        return Err(DicomError::MissingRequiredTag("SOPInstanceUID".to_string()).into());
     }
     
     let study_instance_uid = get_tag_value("StudyInstanceUID");
     if study_instance_uid.is_empty() {
-        return Err(anyhow!("Missing StudyInstanceUID"));
+        return Err(DicomError::MissingRequiredTag("StudyInstanceUID".to_string()).into());
     }
     
     let series_instance_uid = get_tag_value("SeriesInstanceUID");
     if series_instance_uid.is_empty() {
-        return Err(anyhow!("Missing SeriesInstanceUID"));
+        return Err(DicomError::MissingRequiredTag("SeriesInstanceUID".to_string()).into());
     }
     
     // Extract other fields with defaults
-    let modality = get_tag_value("Modality");
+    // Objects without a Modality tag often still say what they are through their SOP Class
+    let mut modality = get_tag_value("Modality").trim().to_string();
+    if modality.is_empty() {
+        let sop_class_uid = get_tag_value("SOPClassUID");
+        if let Some(inferred) = modality_from_sop_class(&sop_class_uid) {
+            info!("No Modality tag, inferred {} from SOP Class {}", inferred, sop_class_uid.trim());
+            modality = inferred;
+        }
+    }
     let patient_name = if get_tag_value("PatientName").is_empty() { "Anonymous".to_string() } else { get_tag_value("PatientName") };
     let patient_id = if get_tag_value("PatientID").is_empty() { "Unknown".to_string() } else { get_tag_value("PatientID") };
     let study_date = get_tag_value("StudyDate");
     let study_description = get_tag_value("StudyDescription");
     let series_description = get_tag_value("SeriesDescription");
+    let body_part_examined = get_tag_value("BodyPartExamined").trim().to_string();
+    // Series-level Laterality, or ImageLaterality for objects such as mammograms that record it per image
+    let laterality = ["Laterality", "ImageLaterality"].iter()
+        .map(|tag| get_tag_value(tag).trim().to_uppercase())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
     
-    // Get instance number with fallback
-    let instance_number = match obj.element_by_name("InstanceNumber") {
-        Ok(element) => element.to_int::<i32>().unwrap_or(0),
-        Err(_) => 0
+    // Integer tags, read leniently since IS values turn up padded, zero-filled or multi-valued
+    let get_int_value = |tag_name: &str| -> Option<i32> {
+        let element = obj.element_by_name(tag_name).ok()?;
+        element.to_str().ok()
+            .and_then(|value| parse_integer_string(&value))
+            .or_else(|| element.to_int::<i32>().ok())
     };
     
+    // Get instance and series numbers with fallback
+    let instance_number = get_int_value("InstanceNumber").unwrap_or(0);
+    let series_number = get_int_value("SeriesNumber").unwrap_or(0);
+    
+    // Counts the source reports, compared against what was extracted to catch partial uploads
+    let get_count_value = |tag_name: &str| get_int_value(tag_name).and_then(|count| u32::try_from(count).ok());
+    let study_related_instances = get_count_value("NumberOfStudyRelatedInstances");
+    let series_related_instances = get_count_value("NumberOfSeriesRelatedInstances");
+    
     // Check for multi-frame image
     let number_of_frames = match obj.element_by_name("NumberOfFrames") {
         Ok(element) => element.to_int::<i32>().unwrap_or(1),
         Err(_) => 1
     };
 
-    info!("Extracted DICOM metadata: SOPInstanceUID={}, SeriesInstanceUID={}, Frames={}", 
-          sop_instance_uid, series_instance_uid, number_of_frames);
+    // Program-specific tags from the extra_tags allowlist
+    let extra: HashMap<String, String> = extra_tags().iter()
+        .filter_map(|tag| {
+            let value = get_tag_value(tag);
+            let value = value.trim();
+            if value.is_empty() { None } else { Some((tag.clone(), value.to_string())) }
+        })
+        .collect();
+
+    let referenced_sop_instance_uids = read_referenced_images(&obj);
+    
+    let acquisition_datetime = acquisition_datetime(
+        &get_tag_value("AcquisitionDateTime"),
+        &get_tag_value("AcquisitionDate"),
+        &get_tag_value("AcquisitionTime"),
+        &study_date,
+    );
+
+    info!("Extracted DICOM metadata: SOPInstanceUID={}, SeriesInstanceUID={}, Frames={}, References={}", 
+          sop_instance_uid, series_instance_uid, number_of_frames, referenced_sop_instance_uids.len());
     
     Ok(DicomMetadata {
         sop_instance_uid,
@@ -126,16 +749,174 @@ pub fn extract_metadata_from_file<P: AsRef<Path>>(path: P) -> Result<DicomMetada
         study_description,
         series_description,
         instance_number,
+        series_number,
+        study_related_instances,
+        series_related_instances,
+        extra,
+        frame: None,
+        referenced_sop_instance_uids,
+        acquisition_datetime,
+        body_part_examined,
+        laterality,
+    })
+}
+
+/// First value of an Integer String such as " 0012", "+3" or "4\\5"; whole decimals like
+/// "7.0", written by some exporters, are accepted too
+fn parse_integer_string(value: &str) -> Option<i32> {
+    let first = value.split('\\').next()?.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    first.parse::<i32>().ok().or_else(|| {
+        first.parse::<f64>().ok()
+            .filter(|number| number.fract() == 0.0 && *number >= i32::MIN as f64 && *number <= i32::MAX as f64)
+            .map(|number| number as i32)
     })
 }
 
+// Rendering of acquisition times; fixed width, so text order is chronological order
+const ACQUISITION_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f";
+
+/// Acquisition time from AcquisitionDateTime (DT), else AcquisitionDate and AcquisitionTime
+/// (DA/TM) with the study date standing in for a missing date. Timezone offsets are
+/// ignored, as the instances of a study share the scanner's clock.
+pub fn acquisition_datetime(datetime: &str, date: &str, time: &str, study_date: &str) -> Option<String> {
+    parse_dicom_datetime(datetime)
+        .or_else(|| {
+            let date = parse_dicom_date(date).or_else(|| parse_dicom_date(study_date))?;
+            Some(date.and_time(parse_dicom_time(time)?))
+        })
+        .map(|value| value.format(ACQUISITION_FORMAT).to_string())
+}
+
+/// Parse a DA value (YYYYMMDD, or the older YYYY.MM.DD)
+fn parse_dicom_date(value: &str) -> Option<chrono::NaiveDate> {
+    let value = value.trim();
+    chrono::NaiveDate::parse_from_str(value, "%Y%m%d")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%Y.%m.%d"))
+        .ok()
+}
+
+/// Parse a TM value: HH, HHMM, HHMMSS, optionally with up to six fraction digits.
+/// The older HH:MM:SS form is accepted too.
+fn parse_dicom_time(value: &str) -> Option<chrono::NaiveTime> {
+    let value = value.trim().replace(':', "");
+    let (whole, fraction) = value.split_once('.').unwrap_or((&value, ""));
+    
+    if !matches!(whole.len(), 2 | 4 | 6) || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    
+    let field = |start: usize| whole.get(start..start + 2).map_or(Some(0), |digits| digits.parse::<u32>().ok());
+    let micros = if fraction.is_empty() { 0 } else { format!("{:0<6}", fraction).parse::<u32>().ok()? };
+    
+    // TM allows a leap second of 60, which NaiveTime only takes as an extra fraction
+    chrono::NaiveTime::from_hms_micro_opt(field(0)?, field(2)?, field(4)?.min(59), micros)
+}
+
+/// Parse a DT value (YYYYMMDDHHMMSS.FFFFFF&ZZXX). Values without a time of day are of
+/// no use for ordering and give None.
+fn parse_dicom_datetime(value: &str) -> Option<chrono::NaiveDateTime> {
+    let value = value.trim();
+    // Drop the UTC offset suffix; the year never carries a sign
+    let value = value.find(['+', '-']).map_or(value, |offset| &value[..offset]);
+    
+    let date = parse_dicom_date(value.get(..8)?)?;
+    Some(date.and_time(parse_dicom_time(value.get(8..)?)?))
+}
+
+/// Read the SOP Instance UIDs listed in ReferencedImageSequence, in order and without duplicates.
+/// Key images use this to point at the slices they summarise.
+fn read_referenced_images(obj: &DefaultDicomObject) -> Vec<String> {
+    let items = match obj.element_by_name("ReferencedImageSequence")
+        .ok()
+        .and_then(|element| element.items())
+    {
+        Some(items) => items,
+        None => return Vec::new(),
+    };
+    
+    let mut referenced: Vec<String> = Vec::new();
+    for item in items {
+        let uid = match item.element_by_name("ReferencedSOPInstanceUID")
+            .ok()
+            .and_then(|element| element.to_str().ok())
+        {
+            Some(uid) => uid.trim_end_matches(['\0', ' ']).trim().to_string(),
+            None => continue,
+        };
+        
+        if is_valid_uid(&uid) && !referenced.contains(&uid) {
+            referenced.push(uid);
+        }
+    }
+    referenced
+}
+
+/// Which extraction approach produced the instances of a study
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseStrategy {
+    TestData,
+    ZipArchive,
+    SingleObject,
+    MultiFrame,
+    MultiPartScan,
+    SingleFallback,
+    EnhancedDetection,
+    /// The study parse failed and one object was read with extract_metadata instead
+    BasicExtraction,
+}
+
+impl ParseStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseStrategy::TestData => "test-data",
+            ParseStrategy::ZipArchive => "zip-archive",
+            ParseStrategy::SingleObject => "single-object",
+            ParseStrategy::MultiFrame => "multi-frame",
+            ParseStrategy::MultiPartScan => "multi-part-scan",
+            ParseStrategy::SingleFallback => "single-fallback",
+            ParseStrategy::EnhancedDetection => "enhanced-detection",
+            ParseStrategy::BasicExtraction => "basic-extraction",
+        }
+    }
+}
+
+/// Instances extracted from an upload, with how they were found
+#[derive(Debug)]
+pub struct StudyParseResult {
+    pub instances: Vec<DicomMetadata>,
+    pub strategy: ParseStrategy,
+    // Recoverable problems, such as parts or entries that could not be read
+    pub warnings: Vec<String>,
+    pub elapsed_ms: u128,
+}
+
 /// Process DICOM file that may contain multiple series
-pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
+pub fn process_study_data(data: &[u8]) -> Result<StudyParseResult> {
+    let started = std::time::Instant::now();
+    let mut warnings = Vec::new();
+    
+    // Per-file reads already catch parser panics; this covers the rest of the parse
+    let (instances, strategy) = catch_parser_panic("parsing upload", || parse_study_data(data, &mut warnings))?;
+    check_instance_count(instances.len())?;
+    
+    Ok(StudyParseResult {
+        instances,
+        strategy,
+        warnings,
+        elapsed_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Run the extraction approaches in turn, recording recoverable problems in warnings
+fn parse_study_data(data: &[u8], warnings: &mut Vec<String>) -> Result<(Vec<DicomMetadata>, ParseStrategy)> {
     // For testing purposes, check for our test data
     let test_data = "ATEMPIORITER".as_bytes();
     if data.len() >= test_data.len() && &data[0..test_data.len()] == test_data {
         info!("Detected test data, returning mock metadata");
-        return Ok(vec![DicomMetadata {
+        return Ok((vec![DicomMetadata {
             sop_instance_uid: "1.2.3.4.5.6.7.8.9.0".to_string(),
             modality: "CT".to_string(),
             study_instance_uid: "1.2.3.4.5.6.7.8.9.1".to_string(),
@@ -146,22 +927,44 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
             study_description: "TEST STUDY".to_string(),
             series_description: "TEST SERIES".to_string(),
             instance_number: 1,
-        }]);
+            series_number: 1,
+            study_related_instances: None,
+            series_related_instances: None,
+            extra: HashMap::new(),
+            frame: None,
+            referenced_sop_instance_uids: Vec::new(),
+            acquisition_datetime: None,
+            body_part_examined: String::new(),
+            laterality: String::new(),
+        }], ParseStrategy::TestData));
     }
     
     // Ensure DICOM directory exists in /tmp
     let dicom_dir = ensure_dicom_dir_exists()?;
+    check_temp_space(data.len() as u64 * STUDY_TEMP_COPIES)?;
     
     // Generate a unique ID for this study processing session
     let session_id = uuid::Uuid::new_v4().to_string();
     
     // Create a dedicated directory for this processing session in /tmp
     let session_dir = format!("{}/{}", dicom_dir, session_id);
-    fs::create_dir_all(&session_dir)?;
+    fs::create_dir_all(&session_dir).map_err(DicomError::Io)?;
+    
+    // Zipped folders of DICOM files are unpacked and read entry by entry
+    if data.starts_with(ZIP_MAGIC) {
+        info!("Detected ZIP archive upload");
+        let result = process_zip_archive(data, &session_dir, MAX_ZIP_UNPACKED_BYTES, warnings);
+        
+        if let Err(e) = fs::remove_dir_all(&session_dir) {
+            warn!("Failed to remove temporary directory: {}: {}", session_dir, e);
+        }
+        
+        return result.map(|instances| (instances, ParseStrategy::ZipArchive));
+    }
     
     // Write the study data to a file
     let study_file_path = format!("{}/study.dcm", session_dir);
-    fs::write(&study_file_path, data)?;
+    fs::write(&study_file_path, data).map_err(DicomError::Io)?;
     
     // First attempt - try to open as a standard DICOM file
     let result = match open_file(&study_file_path) {
@@ -175,6 +978,17 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
                 Err(_) => 1
             };
             
+            // A corrupt or oversized frame count must not drive the per-frame expansion below
+            let checked = check_frame_count(number_of_frames)
+                .and_then(|_| check_instance_count(number_of_frames as usize));
+            if let Err(e) = checked {
+                error!("Rejecting DICOM object: {}", e);
+                if let Err(e) = fs::remove_dir_all(&session_dir) {
+                    warn!("Failed to remove temporary directory: {}: {}", session_dir, e);
+                }
+                return Err(e);
+            }
+            
             // Extract basic metadata
             let base_metadata = match extract_metadata_from_file(&study_file_path) {
                 Ok(metadata) => metadata,
@@ -193,6 +1007,7 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
                 // Create separate metadata entries for each frame
                 // For multi-frame images, we'll create "virtual" SOP instances
                 let mut frame_metadata = Vec::with_capacity(number_of_frames as usize);
+                let frame_attributes = read_frame_attributes(&obj);
                 
                 for frame_index in 0..number_of_frames {
                     // Create a unique SOP Instance UID for this frame
@@ -209,95 +1024,68 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
                         study_description: base_metadata.study_description.clone(),
                         series_description: base_metadata.series_description.clone(),
                         instance_number: frame_index as i32 + 1,
+                        series_number: base_metadata.series_number,
+                        study_related_instances: base_metadata.study_related_instances,
+                        series_related_instances: base_metadata.series_related_instances,
+                        extra: base_metadata.extra.clone(),
+                        frame: frame_attributes.get(frame_index as usize).cloned(),
+                        referenced_sop_instance_uids: base_metadata.referenced_sop_instance_uids.clone(),
+                        acquisition_datetime: base_metadata.acquisition_datetime.clone(),
+                        body_part_examined: base_metadata.body_part_examined.clone(),
+                        laterality: base_metadata.laterality.clone(),
                     };
                     
                     frame_metadata.push(frame_metadata_entry);
                 }
                 
-                frame_metadata
+                (frame_metadata, ParseStrategy::MultiFrame)
             } else {
                 // This is a single-frame image, check for multi-frame with methods below
-                vec![base_metadata]
+                (vec![base_metadata], ParseStrategy::SingleObject)
             }
         },
         Err(e) => {
             // Could not open as a regular DICOM file
             warn!("Could not open as a standard DICOM file: {}. Checking for DICOM directory or multi-part file.", e);
+            warnings.push(format!("Not a single DICOM object: {}", e));
             
-            // Now try to analyze as a raw DICOM data stream that might contain multiple objects
-            let magic = b"DICM";
-            let mut positions = Vec::new();
+            // Now try to analyze as a raw DICOM data stream that might contain multiple objects.
+            // Counting the parts is a cheap scan, so oversized uploads are refused before any are written.
+            let part_count = part_offsets(data).count();
+            info!("Found {} possible DICOM parts in the data", part_count);
             
-            // Find possible DICOM parts by searching for the magic bytes "DICM" at position 128
-            for i in 0..data.len() - magic.len() {
-                if &data[i..i + magic.len()] == magic {
-                    // Found magic bytes at position i
-                    // True DICOM parts have this at position 128
-                    if i >= 128 && i % 2 == 0 { // DICOM is typically even-aligned
-                        positions.push(i - 128);
-                    }
+            if let Err(e) = check_instance_count(part_count) {
+                if let Err(e) = fs::remove_dir_all(&session_dir) {
+                    warn!("Failed to remove temporary directory: {}: {}", session_dir, e);
                 }
+                return Err(e);
             }
             
-            info!("Found {} possible DICOM parts in the data", positions.len());
-            
-            if positions.is_empty() {
+            if part_count == 0 {
                 // If we didn't find any DICOM magic bytes, try regular extraction as fallback
                 info!("No valid DICOM parts found. Trying single extraction as fallback.");
                 match extract_metadata(data) {
-                    Ok(metadata) => vec![metadata],
+                    Ok(metadata) => (vec![metadata], ParseStrategy::SingleFallback),
                     Err(e) => {
                         error!("Failed to extract metadata: {}", e);
-                        return Err(anyhow!("Could not extract DICOM data: {}", e));
+                        return Err(e.context("Could not extract DICOM data"));
                     }
                 }
             } else {
                 // Try to extract each part as an individual DICOM file
-                let mut metadata_list = Vec::new();
-                
-                for (idx, pos) in positions.iter().enumerate() {
-                    let end = if idx < positions.len() - 1 {
-                        positions[idx + 1]
-                    } else {
-                        data.len()
-                    };
-                    
-                    if end <= *pos {
-                        continue; // Skip invalid ranges
-                    }
-                    
-                    // Extract this part of the data
-                    let part_data = &data[*pos..end];
-                    
-                    // Write to a temporary file in the /tmp directory
-                    let part_file_path = format!("{}/part_{}.dcm", session_dir, idx);
-                    if let Err(e) = fs::write(&part_file_path, part_data) {
-                        warn!("Failed to write part file: {}", e);
-                        continue;
-                    }
-                    
-                    // Try to extract metadata from this part
-                    match extract_metadata_from_file(&part_file_path) {
-                        Ok(metadata) => {
-                            info!("Successfully extracted metadata from part {}", idx);
-                            metadata_list.push(metadata);
-                        },
-                        Err(e) => {
-                            warn!("Failed to extract metadata from part {}: {}", idx, e);
-                        }
-                    }
-                }
+                let metadata_list = process_multi_part(data, &session_dir, warnings);
                 
                 // Return what we found
                 if metadata_list.is_empty() {
                     error!("Failed to extract metadata from any parts");
-                    return Err(anyhow!("Failed to extract DICOM metadata from any parts"));
+                    return Err(DicomError::Parse("Failed to extract DICOM metadata from any parts".to_string()).into());
                 }
                 
-                metadata_list
+                (metadata_list, ParseStrategy::MultiPartScan)
             }
         }
     };
+    let (result, strategy) = result;
     
     // Let's try one more approach - check if this is a DICOMDIR or similar structure
     if result.len() <= 1 {
@@ -323,7 +1111,7 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
                     warn!("Failed to remove temporary directory: {}: {}", session_dir, e);
                 }
                 
-                return Ok(enhanced_results);
+                return Ok((enhanced_results, ParseStrategy::EnhancedDetection));
             }
         }
     }
@@ -333,44 +1121,225 @@ pub fn process_study_data(data: &[u8]) -> Result<Vec<DicomMetadata>> {
         warn!("Failed to remove temporary directory: {}: {}", session_dir, e);
     }
     
-    Ok(result)
+    Ok((result, strategy))
 }
 
-/// Perform additional analysis to detect multi-series or complex DICOM structures
-fn perform_enhanced_detection(file_path: &str) -> Vec<DicomMetadata> {
-    let mut results = Vec::new();
+/// Offsets of the DICOM objects concatenated in a byte stream, found lazily by locating
+/// the "DICM" magic at byte 128 of each object. Parts are even-aligned.
+fn part_offsets(data: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    data.windows(DICM_MAGIC.len())
+        .enumerate()
+        .skip(128)
+        .filter(|(i, window)| *window == DICM_MAGIC && i % 2 == 0)
+        .map(|(i, _)| i - 128)
+}
+
+/// Extract metadata from each part of a concatenated DICOM stream. Each part is streamed
+/// to its own file in the session directory, parsed and removed; at most
+/// DICOM_PARSE_CONCURRENCY parts are in flight, which bounds disk and parser memory.
+fn process_multi_part(data: &[u8], session_dir: &str, warnings: &mut Vec<String>) -> Vec<DicomMetadata> {
+    let mut offsets = part_offsets(data).peekable();
+    let ranges = std::iter::from_fn(move || {
+        let pos = offsets.next()?;
+        Some((pos, offsets.peek().copied().unwrap_or(data.len())))
+    });
     
-    // Attempt different approaches to extract more metadata
-    if let Some(mut metadata_list) = try_dicomdir_approach(file_path) {
-        results.append(&mut metadata_list);
-    }
+    let outcomes = bounded_map(ranges.enumerate(), parse_concurrency(), |(idx, (pos, end))| {
+        if end <= pos {
+            return None; // Skip invalid ranges
+        }
+        
+        let part_file_path = format!("{}/part_{}.dcm", session_dir, idx);
+        let written = fs::File::create(&part_file_path)
+            .and_then(|mut file| std::io::copy(&mut &data[pos..end], &mut file));
+        if let Err(e) = written {
+            warn!("Failed to write part file: {}", e);
+            return Some(Err(format!("Part {} could not be written: {}", idx, e)));
+        }
+        
+        let outcome = match extract_metadata_from_file(&part_file_path) {
+            Ok(metadata) => {
+                info!("Successfully extracted metadata from part {}", idx);
+                Ok(metadata)
+            },
+            Err(e) => {
+                warn!("Failed to extract metadata from part {}: {}", idx, e);
+                Err(format!("Part {} skipped: {}", idx, e))
+            }
+        };
+        
+        if let Err(e) = fs::remove_file(&part_file_path) {
+            warn!("Failed to remove temporary file: {:?}: {}", part_file_path, e);
+        }
+        Some(outcome)
+    });
     
-    // Try to extract multi-frame information if available
-    if let Some(mut metadata_list) = try_multi_frame_approach(file_path) {
-        results.append(&mut metadata_list);
+    let mut metadata_list = Vec::new();
+    for outcome in outcomes.into_iter().flatten() {
+        match outcome {
+            Ok(metadata) => metadata_list.push(metadata),
+            Err(warning) => warnings.push(warning),
+        }
+    }
+    metadata_list
+}
+
+/// How many parts may be parsed at once (DICOM_PARSE_CONCURRENCY); the default of one
+/// keeps a single part in memory
+fn parse_concurrency() -> usize {
+    config::get().dicom_parse_concurrency
+}
+
+/// Apply `task` to every item with at most `limit` running at once, returning the
+/// results in item order. Items are pulled lazily, so only in-flight ones are materialized.
+fn bounded_map<I, T, R, F>(items: I, limit: usize, task: F) -> Vec<R>
+where
+    I: Iterator<Item = T> + Send,
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    if limit <= 1 {
+        return items.map(task).collect();
     }
     
-    // Deduplicate results by SOP Instance UID
-    let mut unique_results = Vec::new();
-    let mut seen_sop_uids = HashSet::new();
-    
-    for metadata in results {
-        if !seen_sop_uids.contains(&metadata.sop_instance_uid) {
-            seen_sop_uids.insert(metadata.sop_instance_uid.clone());
-            unique_results.push(metadata);
+    let items = Mutex::new(items.enumerate());
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..limit {
+            scope.spawn(|| loop {
+                // Each worker holds one item at a time, so `limit` workers bound the items in flight
+                let next = items.lock().unwrap().next();
+                let Some((idx, item)) = next else { break };
+                let result = task(item);
+                results.lock().unwrap().push((idx, result));
+            });
         }
-    }
+    });
     
-    unique_results
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
-/// Try to extract information assuming this is a DICOMDIR file
-fn try_dicomdir_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
-    // For now, this is a stub - would need more complex DICOMDIR parsing
-    // which is complex and would require additional libraries
+/// Unpack a ZIP archive into the session directory and extract metadata from each DICOM entry
+fn process_zip_archive(
+    data: &[u8],
+    session_dir: &str,
+    max_unpacked_bytes: u64,
+    warnings: &mut Vec<String>
+) -> Result<Vec<DicomMetadata>> {
+    use std::io::Read;
     
-    // Try to see if we can extract directory references
-    if let Ok(obj) = open_file(file_path) {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| DicomError::Parse(format!("Failed to read ZIP archive: {}", e)))?;
+    
+    info!("ZIP archive contains {} entries", archive.len());
+    let mut metadata_list = Vec::new();
+    let mut unpacked_bytes: u64 = 0;
+    
+    for idx in 0..archive.len() {
+        let mut entry = match archive.by_index(idx) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to read ZIP entry {}: {}", idx, e);
+                warnings.push(format!("ZIP entry {} could not be read: {}", idx, e));
+                continue;
+            }
+        };
+        
+        if entry.is_dir() {
+            continue;
+        }
+        
+        // Entries are written under generated names so archive paths never escape the session dir
+        let entry_name = entry.name().to_string();
+        let entry_file_path = format!("{}/entry_{}.dcm", session_dir, idx);
+        
+        // Declared sizes can lie, so each entry is cut off one byte past what the archive
+        // may still unpack in total and what /tmp can hold
+        let room = (max_unpacked_bytes - unpacked_bytes).min(available_temp_space().unwrap_or(u64::MAX));
+        let written = fs::File::create(&entry_file_path)
+            .and_then(|mut file| std::io::copy(&mut (&mut entry).take(room.saturating_add(1)), &mut file));
+        match written {
+            Ok(bytes) if bytes > room => {
+                warn!("ZIP entry {} unpacks past the {} bytes left, stopping", entry_name, room);
+                if let Err(e) = fs::remove_file(&entry_file_path) {
+                    warn!("Failed to remove temporary file: {:?}: {}", entry_file_path, e);
+                }
+                return Err(DicomError::InsufficientTempSpace { needed: unpacked_bytes + bytes, available: unpacked_bytes + room }.into());
+            },
+            Ok(bytes) => unpacked_bytes += bytes,
+            Err(e) => {
+                warn!("Failed to unpack ZIP entry {}: {}", entry_name, e);
+                warnings.push(format!("ZIP entry {} could not be unpacked: {}", entry_name, e));
+                continue;
+            }
+        }
+        
+        match extract_metadata_from_file(&entry_file_path) {
+            Ok(metadata) => {
+                info!("Successfully extracted metadata from ZIP entry {}", entry_name);
+                metadata_list.push(metadata);
+            },
+            Err(e) => {
+                // READMEs, DICOMDIR indexes, viewer executables and the like
+                info!("Skipping non-DICOM ZIP entry {}: {}", entry_name, e);
+            }
+        }
+        
+        if let Err(e) = fs::remove_file(&entry_file_path) {
+            warn!("Failed to remove temporary file: {:?}: {}", entry_file_path, e);
+        }
+        
+        // Stop unpacking as soon as the archive is known to be too large
+        check_instance_count(metadata_list.len())?;
+    }
+    
+    if metadata_list.is_empty() {
+        error!("No DICOM files found in ZIP archive");
+        return Err(anyhow::Error::new(DicomError::NotDicom).context("No DICOM files found in ZIP archive"));
+    }
+    
+    info!("Extracted {} instances ({} bytes unpacked) from ZIP archive", metadata_list.len(), unpacked_bytes);
+    Ok(metadata_list)
+}
+
+/// Perform additional analysis to detect multi-series or complex DICOM structures
+fn perform_enhanced_detection(file_path: &str) -> Vec<DicomMetadata> {
+    let mut results = Vec::new();
+    
+    // Attempt different approaches to extract more metadata
+    if let Some(mut metadata_list) = try_dicomdir_approach(file_path) {
+        results.append(&mut metadata_list);
+    }
+    
+    // Try to extract multi-frame information if available
+    if let Some(mut metadata_list) = try_multi_frame_approach(file_path) {
+        results.append(&mut metadata_list);
+    }
+    
+    // Deduplicate results by SOP Instance UID
+    let mut unique_results = Vec::new();
+    let mut seen_sop_uids = HashSet::new();
+    
+    for metadata in results {
+        if !seen_sop_uids.contains(&metadata.sop_instance_uid) {
+            seen_sop_uids.insert(metadata.sop_instance_uid.clone());
+            unique_results.push(metadata);
+        }
+    }
+    
+    unique_results
+}
+
+/// Try to extract information assuming this is a DICOMDIR file
+fn try_dicomdir_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
+    // For now, this is a stub - would need more complex DICOMDIR parsing
+    // which is complex and would require additional libraries
+    
+    // Try to see if we can extract directory references
+    if let Ok(obj) = open_file(file_path) {
         // Check if this is a DICOMDIR
         if let Ok(media_sop) = obj.element_by_name("MediaStorageSOPClassUID") {
             if let Ok(media_sop_str) = media_sop.to_str() {
@@ -385,18 +1354,56 @@ fn try_dicomdir_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
     None
 }
 
+/// Upper bound on frames expanded into per-frame instances
+fn max_frames() -> i32 {
+    config::get().max_frames
+}
+
+/// Upper bound on instances extracted from a single upload
+fn max_instances_per_upload() -> usize {
+    config::get().max_instances_per_upload
+}
+
+/// Reject an upload whose enumerated parts, frames or entries exceed MAX_INSTANCES_PER_UPLOAD
+fn check_instance_count(count: usize) -> Result<()> {
+    let limit = max_instances_per_upload();
+    if count > limit {
+        warn!("Rejecting upload with {} instances (MAX_INSTANCES_PER_UPLOAD={})", count, limit);
+        return Err(DicomError::TooManyInstances { count, limit }.into());
+    }
+    Ok(())
+}
+
+/// Check that NumberOfFrames is positive and within MAX_FRAMES before expanding frames
+fn check_frame_count(number_of_frames: i32) -> Result<()> {
+    let max = max_frames();
+    if number_of_frames < 1 {
+        return Err(DicomError::Parse(format!("Invalid NumberOfFrames: {}", number_of_frames)).into());
+    }
+    if number_of_frames > max {
+        return Err(DicomError::Parse(format!("NumberOfFrames {} exceeds the limit of {} (MAX_FRAMES)", number_of_frames, max)).into());
+    }
+    Ok(())
+}
+
 /// Try to extract multi-frame image information
 fn try_multi_frame_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
     if let Ok(obj) = open_file(file_path) {
         // Check for NumberOfFrames
         if let Ok(frames_element) = obj.element_by_name("NumberOfFrames") {
             if let Ok(num_frames) = frames_element.to_int::<i32>() {
+                if let Err(e) = check_frame_count(num_frames) {
+                    warn!("Skipping multi-frame expansion: {}", e);
+                    return None;
+                }
+                
                 if num_frames > 1 {
                     info!("Multi-frame image with {} frames detected", num_frames);
                     
                     // Extract base metadata
                     if let Ok(metadata) = extract_metadata_from_file(file_path) {
                         let mut frame_metadata = Vec::with_capacity(num_frames as usize);
+                        let frame_attributes = read_frame_attributes(&obj);
                         
                         // Create individual frame metadata
                         for frame_idx in 0..num_frames {
@@ -413,6 +1420,15 @@ fn try_multi_frame_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
                                 study_description: metadata.study_description.clone(),
                                 series_description: metadata.series_description.clone(),
                                 instance_number: frame_idx as i32 + 1,
+                                series_number: metadata.series_number,
+                                study_related_instances: metadata.study_related_instances,
+                                series_related_instances: metadata.series_related_instances,
+                                extra: metadata.extra.clone(),
+                                frame: frame_attributes.get(frame_idx as usize).cloned(),
+                                referenced_sop_instance_uids: metadata.referenced_sop_instance_uids.clone(),
+                                acquisition_datetime: metadata.acquisition_datetime.clone(),
+                                body_part_examined: metadata.body_part_examined.clone(),
+                                laterality: metadata.laterality.clone(),
                             };
                             
                             frame_metadata.push(frame_metadata_entry);
@@ -426,4 +1442,1443 @@ fn try_multi_frame_approach(file_path: &str) -> Option<Vec<DicomMetadata>> {
     }
     
     None
-}
\ No newline at end of file
+}
+/// Read per-frame position and windowing from an enhanced multi-frame object.
+/// Returns an empty list when there is no PerFrameFunctionalGroupsSequence.
+fn read_frame_attributes(obj: &DefaultDicomObject) -> Vec<FrameAttributes> {
+    let per_frame = match obj.element_by_name("PerFrameFunctionalGroupsSequence")
+        .ok()
+        .and_then(|element| element.items())
+    {
+        Some(items) => items,
+        None => return Vec::new(),
+    };
+    
+    // Attributes common to all frames live in the shared functional groups
+    let shared = obj.element_by_name("SharedFunctionalGroupsSequence")
+        .ok()
+        .and_then(|element| element.items())
+        .and_then(|items| items.first());
+    
+    let lookup = |group: &InMemDicomObject, sequence: &str, attribute: &str| -> Option<Vec<f64>> {
+        functional_group_values(group, sequence, attribute)
+            .or_else(|| shared.and_then(|shared| functional_group_values(shared, sequence, attribute)))
+    };
+    
+    info!("Reading per-frame functional groups for {} frames", per_frame.len());
+    
+    per_frame.iter()
+        .enumerate()
+        .map(|(index, group)| FrameAttributes {
+            frame_number: index as i32 + 1,
+            image_position_patient: lookup(group, "PlanePositionSequence", "ImagePositionPatient")
+                .filter(|position| position.len() == 3),
+            window_center: lookup(group, "FrameVOILUTSequence", "WindowCenter")
+                .and_then(|values| values.first().copied()),
+            window_width: lookup(group, "FrameVOILUTSequence", "WindowWidth")
+                .and_then(|values| values.first().copied()),
+        })
+        .collect()
+}
+
+/// Read a numeric attribute from the first item of a functional group macro sequence
+fn functional_group_values(group: &InMemDicomObject, sequence: &str, attribute: &str) -> Option<Vec<f64>> {
+    group.element_by_name(sequence)
+        .ok()?
+        .items()?
+        .first()?
+        .element_by_name(attribute)
+        .ok()?
+        .to_multi_float64()
+        .ok()
+}
+
+/// The full tag set of one stored object in the DICOM JSON model, with the UIDs that name its sidecar
+pub struct DicomJsonSidecar {
+    pub study_instance_uid: String,
+    pub sop_instance_uid: String,
+    pub json: serde_json::Value,
+}
+
+/// One object cut out of an upload, with the bytes it was read from
+pub struct SplitInstance {
+    pub sop_instance_uid: String,
+    pub data: Vec<u8>,
+}
+
+/// Every readable object in an upload (ZIP archive entries, the whole upload as a
+/// single object, or the parts of a multi-part stream) with its own bytes. Objects are
+/// read one at a time, so only the current object is held in memory.
+pub struct UploadObjects<'a> {
+    source: UploadSource<'a>,
+}
+
+enum UploadSource<'a> {
+    Zip { archive: zip::ZipArchive<std::io::Cursor<&'a [u8]>>, next: usize },
+    Single(Option<(&'a [u8], Box<DefaultDicomObject>)>),
+    Parts { data: &'a [u8], offsets: std::iter::Peekable<std::vec::IntoIter<usize>> },
+}
+
+fn upload_objects(data: &[u8]) -> Result<UploadObjects<'_>> {
+    let source = if data.starts_with(ZIP_MAGIC) {
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+            .map_err(|e| DicomError::Parse(format!("Failed to read ZIP archive: {}", e)))?;
+        UploadSource::Zip { archive, next: 0 }
+    } else if let Ok(obj) = open_from_bytes(data) {
+        UploadSource::Single(Some((data, Box::new(obj))))
+    } else {
+        let offsets: Vec<usize> = part_offsets(data).collect();
+        UploadSource::Parts { data, offsets: offsets.into_iter().peekable() }
+    };
+    
+    Ok(UploadObjects { source })
+}
+
+impl<'a> Iterator for UploadObjects<'a> {
+    type Item = (std::borrow::Cow<'a, [u8]>, DefaultDicomObject);
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::borrow::Cow;
+        
+        match &mut self.source {
+            UploadSource::Zip { archive, next } => {
+                while *next < archive.len() {
+                    let mut entry_data = Vec::new();
+                    let read = archive.by_index(*next)
+                        .map_err(|e| std::io::Error::other(e.to_string()))
+                        .and_then(|mut entry| std::io::copy(&mut entry, &mut entry_data));
+                    *next += 1;
+                    if read.is_ok() {
+                        if let Ok(obj) = open_from_bytes(&entry_data) {
+                            return Some((Cow::Owned(entry_data), obj));
+                        }
+                    }
+                }
+                None
+            },
+            UploadSource::Single(object) => object.take().map(|(bytes, obj)| (Cow::Borrowed(bytes), *obj)),
+            UploadSource::Parts { data, offsets } => {
+                let data: &'a [u8] = data;
+                while let Some(pos) = offsets.next() {
+                    let end = offsets.peek().copied().unwrap_or(data.len());
+                    if end > pos {
+                        if let Ok(obj) = open_from_bytes(&data[pos..end]) {
+                            return Some((Cow::Borrowed(&data[pos..end]), obj));
+                        }
+                    }
+                }
+                None
+            },
+        }
+    }
+}
+
+// Helper to read a UID element, trimmed of DICOM padding; empty values count as absent
+fn object_uid(obj: &DefaultDicomObject, name: &str) -> Option<String> {
+    obj.element_by_name(name)
+        .ok()
+        .and_then(|element| element.to_str().ok())
+        .map(|value| value.trim_end_matches(['\0', ' ']).trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Cut an upload (single object, ZIP archive or multi-part stream) into one standalone
+/// object per SOP instance. Objects are read as the instances are consumed, one at a
+/// time; objects without a SOPInstanceUID are left out.
+pub fn split_instances(data: &[u8]) -> Result<impl Iterator<Item = SplitInstance> + '_> {
+    Ok(upload_objects(data)?.filter_map(|(bytes, obj)| Some(SplitInstance {
+        sop_instance_uid: object_uid(&obj, "SOPInstanceUID")?,
+        data: bytes.into_owned(),
+    })))
+}
+
+/// Serialize every object in an upload (single object, ZIP archive or multi-part stream) to
+/// DICOM JSON. Objects are parsed as the sidecars are consumed, one at a time; objects
+/// without a SOPInstanceUID are left out.
+pub fn dicom_json_sidecars(data: &[u8]) -> Result<impl Iterator<Item = DicomJsonSidecar> + '_> {
+    Ok(upload_objects(data)?.filter_map(|(_, obj)| Some(DicomJsonSidecar {
+        study_instance_uid: object_uid(&obj, "StudyInstanceUID").unwrap_or_default(),
+        sop_instance_uid: object_uid(&obj, "SOPInstanceUID")?,
+        json: to_dicom_json(&obj, text_encoding(&obj)),
+    })))
+}
+
+/// Serialize an object to the DICOM JSON model (PS3.18 F.2). Bulk binary values such as
+/// PixelData are represented by their VR alone.
+fn to_dicom_json(obj: &InMemDicomObject, encoding: Option<&'static encoding_rs::Encoding>) -> serde_json::Value {
+    let mut dataset = serde_json::Map::new();
+    
+    for element in obj.iter() {
+        let tag = element.header().tag;
+        let vr = element.vr().to_string();
+        
+        let mut attribute = serde_json::Map::new();
+        attribute.insert("vr".to_string(), serde_json::Value::from(vr));
+        
+        let values: Vec<serde_json::Value> = if let Some(items) = element.items() {
+            items.iter().map(|item| to_dicom_json(item, encoding)).collect()
+        } else if element.value().primitive().is_some() {
+            json_values(vr, element, encoding)
+        } else {
+            Vec::new()
+        };
+        if !values.is_empty() {
+            attribute.insert("Value".to_string(), serde_json::Value::Array(values));
+        }
+        
+        dataset.insert(format!("{:04X}{:04X}", tag.group(), tag.element()), serde_json::Value::Object(attribute));
+    }
+    
+    serde_json::Value::Object(dataset)
+}
+
+/// Convert a primitive element to DICOM JSON values according to its VR
+fn json_values(
+    vr: &str,
+    element: &dicom_object::mem::InMemElement,
+    encoding: Option<&'static encoding_rs::Encoding>
+) -> Vec<serde_json::Value> {
+    match vr {
+        // Bulk data is not inlined
+        "OB" | "OD" | "OF" | "OL" | "OV" | "OW" | "UN" => Vec::new(),
+        "SS" | "US" | "SL" | "UL" | "SV" | "UV" | "IS" => element.to_multi_int::<i64>()
+            .map(|values| values.into_iter().map(serde_json::Value::from).collect())
+            .unwrap_or_default(),
+        "FL" | "FD" | "DS" => element.to_multi_float64()
+            .map(|values| values.into_iter()
+                .map(|value| serde_json::Number::from_f64(value).map_or(serde_json::Value::Null, serde_json::Value::Number))
+                .collect())
+            .unwrap_or_default(),
+        "AT" => element.to_multi_int::<u32>()
+            .map(|values| values.into_iter().map(|tag| serde_json::Value::from(format!("{:08X}", tag))).collect())
+            .unwrap_or_default(),
+        _ => {
+            let strings = match element.to_multi_str() {
+                Ok(strings) => strings,
+                Err(_) => return Vec::new(),
+            };
+            strings.iter()
+                .map(|value| {
+                    let value = value.trim_end_matches(['\0', ' ']);
+                    let value = match encoding {
+                        Some(encoding) => redecode_text(value, encoding),
+                        None => value.to_string(),
+                    };
+                    
+                    if value.is_empty() {
+                        serde_json::Value::Null
+                    } else if vr == "PN" {
+                        // Alphabetic, ideographic and phonetic groups are separated by '='
+                        let mut name = serde_json::Map::new();
+                        for (group, component) in ["Alphabetic", "Ideographic", "Phonetic"].iter().zip(value.split('=')) {
+                            if !component.is_empty() {
+                                name.insert(group.to_string(), serde_json::Value::from(component));
+                            }
+                        }
+                        serde_json::Value::Object(name)
+                    } else {
+                        serde_json::Value::from(value)
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Largest edge of a rendered thumbnail in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// One frame of stored samples, ready for photometric conversion.
+/// Monochrome samples are already windowed to 0-255, palette samples are LUT indices
+/// and colour samples are 8-bit values in pixel-interleaved order.
+pub struct DecodedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub samples: Vec<u16>,
+}
+
+/// Red, green and blue lookup tables of a PALETTE COLOR image
+pub struct PaletteLut {
+    pub first_mapped: i64,
+    pub bits: u32,
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+}
+
+/// Caller-supplied VOI window; a missing value falls back to the one stored in the object
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowOverride {
+    pub center: Option<f64>,
+    pub width: Option<f64>,
+}
+
+/// Window applied to a modality and body region whose objects carry no window of their own.
+/// body_part is a taxonomy region name such as "Chest"; a preset without one covers the
+/// whole modality.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WindowPreset {
+    pub modality: String,
+    #[serde(default)]
+    pub body_part: Option<String>,
+    pub center: f64,
+    pub width: f64,
+}
+
+// Built-in presets (modality, region, center, width) in Hounsfield units. Other modalities have
+// no fixed intensity scale, so without a configured preset they keep the full-range stretch.
+const BUILT_IN_WINDOW_PRESETS: &[(&str, Option<&str>, f64, f64)] = &[
+    ("CT", Some("Brain"), 40.0, 80.0),
+    ("CT", Some("Head"), 40.0, 80.0),
+    ("CT", Some("Neck"), 40.0, 350.0),
+    ("CT", Some("Chest"), -600.0, 1500.0),
+    ("CT", Some("Spine"), 400.0, 1800.0),
+    ("CT", Some("Upper Extremity"), 400.0, 1800.0),
+    ("CT", Some("Lower Extremity"), 400.0, 1800.0),
+    ("CT", Some("Abdomen"), 40.0, 400.0),
+    ("CT", Some("Pelvis"), 40.0, 400.0),
+    ("CT", Some("Vascular"), 100.0, 700.0),
+    ("CT", None, 40.0, 400.0),
+];
+
+// Window presets, fixed for the lifetime of the Lambda; configured entries come first
+static WINDOW_PRESETS: OnceLock<Vec<WindowPreset>> = OnceLock::new();
+
+fn built_in_window_presets() -> impl Iterator<Item = WindowPreset> {
+    BUILT_IN_WINDOW_PRESETS.iter().map(|(modality, body_part, center, width)| WindowPreset {
+        modality: modality.to_string(),
+        body_part: body_part.map(str::to_string),
+        center: *center,
+        width: *width,
+    })
+}
+
+/// Install window presets loaded at startup (e.g. from a JSON file in S3). They take
+/// precedence over the built-in table. Has no effect once the presets have been read.
+pub fn set_window_presets(presets: Vec<WindowPreset>) {
+    let presets = presets.into_iter()
+        .filter(|preset| preset.width > 0.0)
+        .chain(built_in_window_presets())
+        .collect();
+    if WINDOW_PRESETS.set(presets).is_err() {
+        warn!("Window presets already initialized, ignoring new value");
+    }
+}
+
+/// Default (center, width) for objects without WindowCenter/WindowWidth. A preset for the
+/// body region wins over one for the whole modality; None leaves the full-range stretch.
+pub fn default_window_for(modality: &str, body_part: &str) -> Option<(f64, f64)> {
+    let presets = WINDOW_PRESETS.get_or_init(|| built_in_window_presets().collect());
+    let modality = modality.trim();
+    let region = if body_part.trim().is_empty() {
+        None
+    } else {
+        Some(crate::taxonomy::normalize_anatomy(body_part).value)
+    };
+    
+    // Presets are per region, so "Lower Extremity (Left)" uses the "Lower Extremity" window
+    let for_modality = |preset: &&WindowPreset| preset.modality.eq_ignore_ascii_case(modality);
+    let region_match = presets.iter().filter(for_modality).find(|preset| {
+        matches!((&preset.body_part, &region), (Some(part), Some(region))
+            if crate::taxonomy::region_of(part).eq_ignore_ascii_case(crate::taxonomy::region_of(region)))
+    });
+    
+    region_match
+        .or_else(|| presets.iter().filter(for_modality).find(|preset| preset.body_part.is_none()))
+        .map(|preset| (preset.center, preset.width))
+}
+
+/// Encoding of a rendered frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+// Quality of rendered JPEGs, high enough to keep fine detail in the window
+const JPEG_QUALITY: u8 = 90;
+
+/// A frame converted to 8-bit RGB
+struct RgbFrame {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Open a DICOM object from binary data via a temporary file
+fn open_from_bytes(data: &[u8]) -> Result<DefaultDicomObject> {
+    let dicom_dir = ensure_dicom_dir_exists()?;
+    check_temp_space(data.len() as u64)?;
+    let temp_file_path = format!("{}/render_{}.dcm", dicom_dir, uuid::Uuid::new_v4());
+    
+    fs::write(&temp_file_path, data)
+        .map_err(DicomError::Io)
+        .context("Failed to write DICOM data to temporary file")?;
+    
+    let result = open_dicom_file(Path::new(&temp_file_path))
+        .context("Failed to open DICOM file");
+    
+    if let Err(e) = fs::remove_file(&temp_file_path) {
+        warn!("Failed to remove temporary file: {:?}: {}", temp_file_path, e);
+    }
+    
+    result
+}
+
+/// Render a downscaled PNG preview of the first frame of a DICOM object
+pub fn render_thumbnail(data: &[u8], max_size: u32) -> Result<Vec<u8>> {
+    encode_png(&render_preview(data, max_size)?)
+}
+
+/// Where one object's preview sits in a sprite sheet
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteCell {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Previews of several objects packed into one PNG grid
+pub struct SpriteSheet {
+    pub png: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    // One entry per object, None where the object could not be rendered
+    pub cells: Vec<Option<SpriteCell>>,
+}
+
+/// Render the first frame of each object into a square-ish grid of cell_size cells.
+/// An object that fails to render leaves its cell empty rather than failing the sheet.
+pub fn render_sprite_sheet(objects: &[Vec<u8>], cell_size: u32) -> Result<SpriteSheet> {
+    if objects.is_empty() {
+        return Err(anyhow!("No objects to render"));
+    }
+    
+    let columns = (objects.len() as f64).sqrt().ceil() as u32;
+    let rows = (objects.len() as u32).div_ceil(columns);
+    let width = columns * cell_size;
+    let height = rows * cell_size;
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    
+    let mut cells = Vec::with_capacity(objects.len());
+    for (index, data) in objects.iter().enumerate() {
+        let preview = match render_preview(data, cell_size) {
+            Ok(preview) => preview,
+            Err(e) => {
+                warn!("Leaving sprite cell {} empty: {}", index, e);
+                cells.push(None);
+                continue;
+            }
+        };
+        
+        // Centre the preview in its cell
+        let column = index as u32 % columns;
+        let row = index as u32 / columns;
+        let x = column * cell_size + (cell_size - preview.width) / 2;
+        let y = row * cell_size + (cell_size - preview.height) / 2;
+        
+        let row_bytes = (preview.width * 3) as usize;
+        for (line, source) in preview.pixels.chunks_exact(row_bytes).enumerate() {
+            let start = (((y + line as u32) * width + x) * 3) as usize;
+            pixels[start..start + row_bytes].copy_from_slice(source);
+        }
+        
+        cells.push(Some(SpriteCell { x, y, width: preview.width, height: preview.height }));
+    }
+    
+    info!("Rendered sprite sheet of {} cells ({}x{})", objects.len(), width, height);
+    let png = encode_png(&RgbFrame { width, height, pixels })?;
+    Ok(SpriteSheet { png, width, height, cells })
+}
+
+/// Render the first frame of a DICOM object downscaled to fit within max_size
+fn render_preview(data: &[u8], max_size: u32) -> Result<RgbFrame> {
+    let obj = open_from_bytes(data)?;
+    let photometric = obj.element_by_name("PhotometricInterpretation")
+        .ok()
+        .and_then(|element| element.to_str().ok().map(|value| value.trim().to_string()))
+        .unwrap_or_else(|| "MONOCHROME2".to_string());
+    
+    let decoded = decode_frame(&obj, 0, &photometric, WindowOverride::default())?;
+    let lut = if photometric == "PALETTE COLOR" { read_palette_lut(&obj) } else { None };
+    
+    let frame = RgbFrame {
+        width: decoded.width,
+        height: decoded.height,
+        pixels: to_rgb8(&decoded, &photometric, lut.as_ref()),
+    };
+    let frame = downscale(frame, max_size);
+    
+    info!("Rendered {} thumbnail {}x{}", photometric, frame.width, frame.height);
+    Ok(frame)
+}
+
+/// Render one frame (1-based) of a DICOM object at full size with an optional window override
+pub fn render_frame(data: &[u8], frame_number: u32, window: WindowOverride, format: ImageFormat) -> Result<Vec<u8>> {
+    let obj = open_from_bytes(data)?;
+    let photometric = obj.element_by_name("PhotometricInterpretation")
+        .ok()
+        .and_then(|element| element.to_str().ok().map(|value| value.trim().to_string()))
+        .unwrap_or_else(|| "MONOCHROME2".to_string());
+    
+    let frames = obj.element_by_name("NumberOfFrames")
+        .ok()
+        .and_then(|element| element.to_int::<u32>().ok())
+        .unwrap_or(1)
+        .max(1);
+    if frame_number == 0 || frame_number > frames {
+        return Err(DicomError::FrameOutOfRange { frame: frame_number, frames }.into());
+    }
+    
+    let decoded = decode_frame(&obj, frame_number - 1, &photometric, window)?;
+    let lut = if photometric == "PALETTE COLOR" { read_palette_lut(&obj) } else { None };
+    
+    let frame = RgbFrame {
+        width: decoded.width,
+        height: decoded.height,
+        pixels: to_rgb8(&decoded, &photometric, lut.as_ref()),
+    };
+    
+    info!("Rendered {} frame {}/{} {}x{} as {:?}", photometric, frame_number, frames, frame.width, frame.height, format);
+    match format {
+        ImageFormat::Png => encode_png(&frame),
+        ImageFormat::Jpeg => encode_jpeg(&frame),
+    }
+}
+
+/// Longest edge of a cine frame in pixels
+pub const CINE_MAX_SIZE: u32 = 512;
+
+/// Most frames encoded into one cine loop
+pub const MAX_CINE_FRAMES: usize = 120;
+
+// Playback rate used when no object records CineRate, RecommendedDisplayFrameRate or FrameTime
+const DEFAULT_CINE_FPS: f64 = 10.0;
+
+/// Frames of one or more DICOM objects, added in order, for a looping animated GIF.
+/// Frames are windowed, downscaled to CINE_MAX_SIZE and capped at MAX_CINE_FRAMES;
+/// playback uses the first recorded frame rate.
+pub struct CineBuilder {
+    window: WindowOverride,
+    frames: Vec<RgbFrame>,
+    frame_rate: Option<f64>,
+}
+
+impl CineBuilder {
+    pub fn new(window: WindowOverride) -> Self {
+        CineBuilder { window, frames: Vec::new(), frame_rate: None }
+    }
+    
+    /// Whether MAX_CINE_FRAMES have been decoded, so further objects would be ignored
+    pub fn is_full(&self) -> bool {
+        self.frames.len() >= MAX_CINE_FRAMES
+    }
+    
+    /// Decode the frames of one object until the cap is reached
+    pub fn add_object(&mut self, data: &[u8]) -> Result<()> {
+        if self.is_full() {
+            return Ok(());
+        }
+        
+        let obj = open_from_bytes(data)?;
+        let photometric = obj.element_by_name("PhotometricInterpretation")
+            .ok()
+            .and_then(|element| element.to_str().ok().map(|value| value.trim().to_string()))
+            .unwrap_or_else(|| "MONOCHROME2".to_string());
+        let lut = if photometric == "PALETTE COLOR" { read_palette_lut(&obj) } else { None };
+        self.frame_rate = self.frame_rate.or_else(|| cine_frame_rate(&obj));
+        
+        let count = obj.element_by_name("NumberOfFrames")
+            .ok()
+            .and_then(|element| element.to_int::<u32>().ok())
+            .unwrap_or(1)
+            .max(1);
+        
+        for frame_index in 0..count {
+            if self.is_full() {
+                warn!("Cine truncated at {} frames", MAX_CINE_FRAMES);
+                break;
+            }
+            
+            let decoded = decode_frame(&obj, frame_index, &photometric, self.window)?;
+            let frame = RgbFrame {
+                width: decoded.width,
+                height: decoded.height,
+                pixels: to_rgb8(&decoded, &photometric, lut.as_ref()),
+            };
+            self.frames.push(downscale(frame, CINE_MAX_SIZE));
+        }
+        Ok(())
+    }
+    
+    /// Encode the decoded frames as a GIF
+    pub fn finish(self) -> Result<Vec<u8>> {
+        if self.frames.is_empty() {
+            return Err(anyhow!("No frames to render"));
+        }
+        
+        let fps = self.frame_rate.unwrap_or(DEFAULT_CINE_FPS);
+        // GIF delays are in hundredths of a second; browsers slow anything under 2 down to 10
+        let delay = (100.0 / fps).round().clamp(2.0, 100.0) as u16;
+        
+        info!("Rendering cine of {} frames at {:.1} fps", self.frames.len(), fps);
+        encode_gif(&self.frames, delay)
+    }
+}
+
+/// Read the intended playback rate in frames per second from CineRate,
+/// RecommendedDisplayFrameRate or FrameTime (milliseconds per frame)
+fn cine_frame_rate(obj: &DefaultDicomObject) -> Option<f64> {
+    let read = |name: &str| obj.element_by_name(name)
+        .ok()
+        .and_then(|element| element.to_multi_float64().ok())
+        .and_then(|values| values.first().copied())
+        .filter(|value| value.is_finite() && *value > 0.0);
+    
+    read("CineRate")
+        .or_else(|| read("RecommendedDisplayFrameRate"))
+        .or_else(|| read("FrameTime").map(|millis| 1000.0 / millis))
+}
+
+/// Encode frames as a looping GIF89a. Each frame gets its own 256-entry palette:
+/// a grey ramp for monochrome frames and a 3-3-2 RGB cube for colour ones.
+fn encode_gif(frames: &[RgbFrame], delay: u16) -> Result<Vec<u8>> {
+    // GIF dimensions are 16-bit
+    let width = frames.iter().map(|frame| frame.width).max().unwrap_or(1);
+    let height = frames.iter().map(|frame| frame.height).max().unwrap_or(1);
+    let width = u16::try_from(width).context("Frame is too wide for GIF")?;
+    let height = u16::try_from(height).context("Frame is too tall for GIF")?;
+    
+    let mut gif = Vec::new();
+    gif.extend_from_slice(b"GIF89a");
+    gif.extend_from_slice(&width.to_le_bytes());
+    gif.extend_from_slice(&height.to_le_bytes());
+    gif.extend_from_slice(&[0x00, 0x00, 0x00]);
+    
+    // NETSCAPE2.0 application extension: loop forever
+    gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+    gif.extend_from_slice(b"NETSCAPE2.0");
+    gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+    
+    for frame in frames {
+        let grey = frame.pixels.chunks_exact(3).all(|rgb| rgb[0] == rgb[1] && rgb[1] == rgb[2]);
+        
+        let (palette, indices): (Vec<u8>, Vec<u8>) = if grey {
+            let palette = (0..=255u8).flat_map(|v| [v, v, v]).collect();
+            (palette, frame.pixels.chunks_exact(3).map(|rgb| rgb[0]).collect())
+        } else {
+            let palette = (0..=255u32)
+                .flat_map(|i| [(i >> 5) * 255 / 7, ((i >> 2) & 7) * 255 / 7, (i & 3) * 85])
+                .map(|level| level as u8)
+                .collect();
+            let indices = frame.pixels.chunks_exact(3)
+                .map(|rgb| (rgb[0] & 0xE0) | ((rgb[1] >> 3) & 0x1C) | (rgb[2] >> 6))
+                .collect();
+            (palette, indices)
+        };
+        
+        // Graphic control extension: frame delay, no transparency
+        gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+        gif.extend_from_slice(&delay.to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+        
+        // Image descriptor at the origin with a 256-entry local colour table
+        gif.push(0x2C);
+        gif.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        gif.extend_from_slice(&(frame.width as u16).to_le_bytes());
+        gif.extend_from_slice(&(frame.height as u16).to_le_bytes());
+        gif.push(0x87);
+        gif.extend_from_slice(&palette);
+        
+        gif.push(GIF_MIN_CODE_SIZE as u8);
+        for block in lzw_encode(&indices).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0x00);
+    }
+    
+    gif.push(0x3B);
+    Ok(gif)
+}
+
+// LZW root code size for 8-bit palette indices
+const GIF_MIN_CODE_SIZE: u32 = 8;
+
+/// Compress palette indices with GIF's variable-width LZW, packing codes LSB first
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear_code: u16 = 1 << GIF_MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+    
+    let mut output = Vec::new();
+    let mut bit_buffer: u32 = 0;
+    let mut bit_count = 0;
+    let mut write_code = |code: u16, size: u32, output: &mut Vec<u8>| {
+        bit_buffer |= (code as u32) << bit_count;
+        bit_count += size;
+        while bit_count >= 8 {
+            output.push(bit_buffer as u8);
+            bit_buffer >>= 8;
+            bit_count -= 8;
+        }
+    };
+    
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = GIF_MIN_CODE_SIZE + 1;
+    write_code(clear_code, code_size, &mut output);
+    
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        let current = match prefix {
+            Some(current) => current,
+            None => {
+                prefix = Some(index as u16);
+                continue;
+            }
+        };
+        
+        if let Some(&code) = table.get(&(current, index)) {
+            prefix = Some(code);
+            continue;
+        }
+        
+        write_code(current, code_size, &mut output);
+        if next_code < 4096 {
+            table.insert((current, index), next_code);
+            next_code += 1;
+            // The decoder adds its entries one code later, so widen once it would need to
+            if next_code > (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+        } else {
+            // The code table is full; start over
+            write_code(clear_code, code_size, &mut output);
+            table.clear();
+            next_code = end_code + 1;
+            code_size = GIF_MIN_CODE_SIZE + 1;
+        }
+        prefix = Some(index as u16);
+    }
+    
+    if let Some(current) = prefix {
+        write_code(current, code_size, &mut output);
+    }
+    write_code(end_code, code_size, &mut output);
+    if bit_count > 0 {
+        output.push(bit_buffer as u8);
+    }
+    
+    output
+}
+
+/// Convert decoded samples to 8-bit RGB according to the PhotometricInterpretation.
+/// MONOCHROME1 is inverted, PALETTE COLOR goes through the LUT and YBR_FULL /
+/// YBR_FULL_422 are converted to RGB. Unknown interpretations render as MONOCHROME2.
+pub fn to_rgb8(decoded: &DecodedFrame, photometric: &str, lut: Option<&PaletteLut>) -> Vec<u8> {
+    let pixel_count = (decoded.width * decoded.height) as usize;
+    let mut rgb = Vec::with_capacity(pixel_count * 3);
+    
+    match (photometric, decoded.samples_per_pixel, lut) {
+        ("RGB", 3, _) => {
+            rgb.extend(decoded.samples.iter().take(pixel_count * 3).map(|v| (*v).min(255) as u8));
+        },
+        ("YBR_FULL", 3, _) => {
+            for pixel in decoded.samples.chunks_exact(3).take(pixel_count) {
+                rgb.extend_from_slice(&ybr_to_rgb(pixel[0], pixel[1], pixel[2]));
+            }
+        },
+        ("YBR_FULL_422", 3, _) => {
+            // Native 4:2:2 stores Y1 Y2 Cb Cr for each horizontal pair of pixels
+            let row_pairs = decoded.width.div_ceil(2) as usize;
+            for row in decoded.samples.chunks_exact(row_pairs * 4).take(decoded.height as usize) {
+                for (pair_index, pair) in row.chunks_exact(4).enumerate() {
+                    rgb.extend_from_slice(&ybr_to_rgb(pair[0], pair[2], pair[3]));
+                    if pair_index * 2 + 1 < decoded.width as usize {
+                        rgb.extend_from_slice(&ybr_to_rgb(pair[1], pair[2], pair[3]));
+                    }
+                }
+            }
+        },
+        ("PALETTE COLOR", 1, Some(lut)) => {
+            let to_8bit = |value: u16| if lut.bits > 8 { (value >> 8) as u8 } else { value.min(255) as u8 };
+            let last = lut.red.len().min(lut.green.len()).min(lut.blue.len()).saturating_sub(1);
+            for index in decoded.samples.iter().take(pixel_count) {
+                // Indices outside the table clamp to its first or last entry
+                let entry = (*index as i64 - lut.first_mapped).clamp(0, last as i64) as usize;
+                rgb.extend_from_slice(&[to_8bit(lut.red[entry]), to_8bit(lut.green[entry]), to_8bit(lut.blue[entry])]);
+            }
+        },
+        (_, 1, _) => {
+            let invert = photometric == "MONOCHROME1";
+            for value in decoded.samples.iter().take(pixel_count) {
+                let gray = (*value).min(255) as u8;
+                let gray = if invert { 255 - gray } else { gray };
+                rgb.extend_from_slice(&[gray, gray, gray]);
+            }
+        },
+        _ => {
+            warn!("Unsupported photometric interpretation {} with {} samples, rendering first sample",
+                  photometric, decoded.samples_per_pixel);
+            for pixel in decoded.samples.chunks_exact(decoded.samples_per_pixel.max(1) as usize).take(pixel_count) {
+                let gray = pixel[0].min(255) as u8;
+                rgb.extend_from_slice(&[gray, gray, gray]);
+            }
+        },
+    }
+    
+    // Short pixel data renders black rather than producing an undersized image
+    rgb.resize(pixel_count * 3, 0);
+    rgb
+}
+
+/// Full-range YCbCr to RGB (ITU-R BT.601)
+fn ybr_to_rgb(y: u16, cb: u16, cr: u16) -> [u8; 3] {
+    let y = y as f64;
+    let cb = cb as f64 - 128.0;
+    let cr = cr as f64 - 128.0;
+    
+    let r = y + 1.402 * cr;
+    let g = y - 0.344136 * cb - 0.714136 * cr;
+    let b = y + 1.772 * cb;
+    
+    [r.round().clamp(0.0, 255.0) as u8, g.round().clamp(0.0, 255.0) as u8, b.round().clamp(0.0, 255.0) as u8]
+}
+
+/// Read the red, green and blue palette lookup tables, if the object has them
+fn read_palette_lut(obj: &DefaultDicomObject) -> Option<PaletteLut> {
+    let descriptor = obj.element_by_name("RedPaletteColorLookupTableDescriptor")
+        .ok()?
+        .to_multi_int::<i64>()
+        .ok()?;
+    if descriptor.len() < 3 {
+        return None;
+    }
+    
+    // A zero entry count means 65536 entries
+    let entries = if descriptor[0] == 0 { 65536 } else { descriptor[0] as usize };
+    let bits = descriptor[2] as u32;
+    
+    let read_table = |tag_name: &str| -> Option<Vec<u16>> {
+        let bytes = obj.element_by_name(tag_name).ok()?.to_bytes().ok()?;
+        let table: Vec<u16> = if bits <= 8 && bytes.len() == entries {
+            bytes.iter().map(|b| *b as u16).collect()
+        } else {
+            bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect()
+        };
+        if table.is_empty() { None } else { Some(table) }
+    };
+    
+    Some(PaletteLut {
+        first_mapped: descriptor[1],
+        bits,
+        red: read_table("RedPaletteColorLookupTableData")?,
+        green: read_table("GreenPaletteColorLookupTableData")?,
+        blue: read_table("BluePaletteColorLookupTableData")?,
+    })
+}
+
+/// Decode one frame of native (uncompressed) pixel data.
+/// Monochrome frames get rescale and windowing applied; palette and colour samples are kept as stored.
+/// A window override replaces the stored window after being clamped to the representable values.
+fn decode_frame(obj: &DefaultDicomObject, frame_index: u32, photometric: &str, window: WindowOverride) -> Result<DecodedFrame> {
+    let get_int = |tag_name: &str, default: u32| -> u32 {
+        obj.element_by_name(tag_name)
+            .ok()
+            .and_then(|element| element.to_int::<u32>().ok())
+            .unwrap_or(default)
+    };
+    let get_float = |tag_name: &str| -> Option<f64> {
+        obj.element_by_name(tag_name)
+            .ok()
+            .and_then(|element| element.to_multi_float64().ok())
+            .and_then(|values| values.first().copied())
+    };
+    
+    let width = get_int("Columns", 0);
+    let height = get_int("Rows", 0);
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Missing image dimensions"));
+    }
+    
+    let samples_per_pixel = get_int("SamplesPerPixel", 1);
+    if samples_per_pixel != 1 && samples_per_pixel != 3 {
+        return Err(anyhow!("Unsupported samples per pixel for rendering: {}", samples_per_pixel));
+    }
+    
+    let bits_allocated = get_int("BitsAllocated", 16);
+    let signed = get_int("PixelRepresentation", 0) == 1;
+    if samples_per_pixel == 3 && bits_allocated != 8 {
+        return Err(anyhow!("Unsupported BitsAllocated for colour rendering: {}", bits_allocated));
+    }
+    
+    let pixel_element = obj.element_by_name("PixelData")
+        .context("Missing PixelData")?;
+    if pixel_element.fragments().is_some() {
+        let transfer_syntax = obj.meta().transfer_syntax().trim_end_matches('\0').to_string();
+        return Err(anyhow::Error::new(DicomError::UnsupportedTransferSyntax(transfer_syntax))
+            .context("Compressed pixel data is not supported for rendering"));
+    }
+    let bytes = pixel_element.to_bytes()
+        .context("Failed to read PixelData")?;
+    
+    let bytes_per_sample = (bits_allocated / 8).max(1) as usize;
+    let pixel_count = (width * height) as usize;
+    let frame_len = if photometric == "YBR_FULL_422" {
+        // Two luma samples share one pair of chroma samples
+        width.div_ceil(2) as usize * 4 * height as usize
+    } else {
+        pixel_count * samples_per_pixel as usize * bytes_per_sample
+    };
+    let offset = frame_index as usize * frame_len;
+    if bytes.len() < offset + frame_len {
+        return Err(anyhow!("PixelData too short for frame {}", frame_index));
+    }
+    let frame_bytes = &bytes[offset..offset + frame_len];
+    
+    if samples_per_pixel == 3 {
+        // Colour-by-plane data is reordered so every conversion sees R G B / Y Cb Cr triples
+        let samples = if get_int("PlanarConfiguration", 0) == 1 && photometric != "YBR_FULL_422" {
+            (0..pixel_count)
+                .flat_map(|pixel| (0..3).map(move |plane| frame_bytes[plane * pixel_count + pixel] as u16))
+                .collect()
+        } else {
+            frame_bytes.iter().map(|b| *b as u16).collect()
+        };
+        
+        return Ok(DecodedFrame { width, height, samples_per_pixel, samples });
+    }
+    
+    // Raw stored values
+    let stored: Vec<f64> = match (bits_allocated, signed) {
+        (8, _) => frame_bytes.iter().map(|b| *b as f64).collect(),
+        (16, false) => frame_bytes.chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f64)
+            .collect(),
+        (16, true) => frame_bytes.chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]) as f64)
+            .collect(),
+        (32, false) => frame_bytes.chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+        (32, true) => frame_bytes.chunks_exact(4)
+            .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+        (bits, _) => return Err(anyhow!("Unsupported BitsAllocated for rendering: {}", bits)),
+    };
+    
+    // Palette indices are looked up as stored, without rescale or windowing
+    if photometric == "PALETTE COLOR" {
+        let samples = stored.iter().map(|v| v.clamp(0.0, u16::MAX as f64) as u16).collect();
+        return Ok(DecodedFrame { width, height, samples_per_pixel, samples });
+    }
+    
+    // Convert to modality values with the rescale parameters
+    let slope = get_float("RescaleSlope").unwrap_or(1.0);
+    let intercept = get_float("RescaleIntercept").unwrap_or(0.0);
+    let values: Vec<f64> = stored.into_iter().map(|v| v * slope + intercept).collect();
+    
+    // Enhanced objects keep their window in the per-frame functional groups
+    let frame_window = read_frame_attributes(obj)
+        .into_iter()
+        .nth(frame_index as usize)
+        .map(|frame| (frame.window_center, frame.window_width))
+        .unwrap_or((None, None));
+    let stored_window = match (get_float("WindowCenter").or(frame_window.0), get_float("WindowWidth").or(frame_window.1)) {
+        // No window in the object; use the preset for its modality and body part, if any
+        (None, None) => {
+            let get_text = |tag_name: &str| -> String {
+                obj.element_by_name(tag_name)
+                    .ok()
+                    .and_then(|element| element.to_str().ok())
+                    .map(|value| value.trim_end_matches('\0').trim().to_string())
+                    .unwrap_or_default()
+            };
+            default_window_for(&get_text("Modality"), &get_text("BodyPartExamined"))
+                .map_or((None, None), |(center, width)| (Some(center), Some(width)))
+        },
+        stored => stored,
+    };
+    
+    // Range of modality values the stored bits can represent, for clamping an override
+    let bits_stored = get_int("BitsStored", bits_allocated).clamp(1, 32);
+    let (min_stored, max_stored) = if signed {
+        (-(2f64.powi(bits_stored as i32 - 1)), 2f64.powi(bits_stored as i32 - 1) - 1.0)
+    } else {
+        (0.0, 2f64.powi(bits_stored as i32) - 1.0)
+    };
+    let (low, high) = {
+        let (a, b) = (min_stored * slope + intercept, max_stored * slope + intercept);
+        (a.min(b), a.max(b))
+    };
+    let stored_window = (
+        window.center.map(|center| center.clamp(low, high)).or(stored_window.0),
+        window.width.map(|width| width.clamp(1.0, (high - low).max(1.0))).or(stored_window.1),
+    );
+    
+    // Use the stored window, or stretch the full value range when there is none
+    let (center, width_window) = match stored_window {
+        (Some(center), Some(window_width)) if window_width > 0.0 => (center, window_width),
+        _ => {
+            let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            ((min + max) / 2.0, (max - min).max(1.0))
+        }
+    };
+    
+    let lower = center - width_window / 2.0;
+    let samples = values.iter()
+        .map(|v| (((v - lower) / width_window) * 255.0).clamp(0.0, 255.0) as u16)
+        .collect();
+    
+    Ok(DecodedFrame { width, height, samples_per_pixel, samples })
+}
+
+/// Shrink a frame so its longest edge fits within max_size (nearest neighbour)
+fn downscale(frame: RgbFrame, max_size: u32) -> RgbFrame {
+    let longest = frame.width.max(frame.height);
+    if longest <= max_size {
+        return frame;
+    }
+    
+    let scale = max_size as f64 / longest as f64;
+    let width = ((frame.width as f64 * scale).round() as u32).max(1);
+    let height = ((frame.height as f64 * scale).round() as u32).max(1);
+    
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        let src_y = ((y as f64 / scale) as u32).min(frame.height - 1);
+        for x in 0..width {
+            let src_x = ((x as f64 / scale) as u32).min(frame.width - 1);
+            let src = ((src_y * frame.width + src_x) * 3) as usize;
+            pixels.extend_from_slice(&frame.pixels[src..src + 3]);
+        }
+    }
+    
+    RgbFrame { width, height, pixels }
+}
+
+/// Encode an RGB frame as PNG
+fn encode_png(frame: &RgbFrame) -> Result<Vec<u8>> {
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, frame.width, frame.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        
+        let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+        writer.write_image_data(&frame.pixels).context("Failed to write PNG data")?;
+    }
+    
+    Ok(png_data)
+}
+
+/// Encode an RGB frame as baseline JPEG
+fn encode_jpeg(frame: &RgbFrame) -> Result<Vec<u8>> {
+    // JPEG dimensions are 16-bit
+    let width = u16::try_from(frame.width).context("Frame is too wide for JPEG")?;
+    let height = u16::try_from(frame.height).context("Frame is too tall for JPEG")?;
+    
+    let mut jpeg_data = Vec::new();
+    jpeg_encoder::Encoder::new(&mut jpeg_data, JPEG_QUALITY)
+        .encode(&frame.pixels, width, height, jpeg_encoder::ColorType::Rgb)
+        .context("Failed to encode JPEG")?;
+    
+    Ok(jpeg_data)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+    use crate::sr::put_text as put;
+    
+    const SECONDARY_CAPTURE: &str = "1.2.840.10008.5.1.4.1.1.7";
+    
+    /// A minimal explicit VR little endian Part 10 object with the UIDs the parsers need
+    pub(crate) fn test_object(sop_instance_uid: &str, study_instance_uid: &str) -> Vec<u8> {
+        let mut obj = InMemDicomObject::new_empty();
+        put(&mut obj, Tag(0x0008, 0x0016), VR::UI, SECONDARY_CAPTURE);
+        put(&mut obj, Tag(0x0008, 0x0018), VR::UI, sop_instance_uid);
+        put(&mut obj, Tag(0x0008, 0x0060), VR::CS, "OT");
+        put(&mut obj, Tag(0x0020, 0x000D), VR::UI, study_instance_uid);
+        put(&mut obj, Tag(0x0020, 0x000E), VR::UI, &format!("{}.1", study_instance_uid));
+        
+        let file = obj.with_meta(FileMetaTableBuilder::new()
+                .media_storage_sop_class_uid(SECONDARY_CAPTURE)
+                .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN))
+            .expect("file meta");
+        let mut data = Vec::new();
+        file.write_all(&mut data).expect("encode");
+        data
+    }
+    
+    // Helper to bundle objects as a ZIP archive
+    pub(crate) fn zip_of(objects: &[Vec<u8>]) -> Vec<u8> {
+        use std::io::Write;
+        
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (index, object) in objects.iter().enumerate() {
+            writer.start_file(format!("{}.dcm", index), zip::write::FileOptions::default()).expect("zip entry");
+            writer.write_all(object).expect("zip write");
+        }
+        writer.finish().expect("zip finish").into_inner()
+    }
+    
+    fn sidecar_uids(data: &[u8]) -> Vec<String> {
+        dicom_json_sidecars(data).expect("sidecars").map(|sidecar| sidecar.sop_instance_uid).collect()
+    }
+    
+    #[test]
+    fn upload_objects_borrow_single_objects_and_stream_parts() {
+        let first = test_object("1.2.3.1", "1.2.3");
+        let mut objects = upload_objects(&first).expect("objects");
+        assert!(matches!(objects.next(), Some((std::borrow::Cow::Borrowed(_), _))));
+        assert!(objects.next().is_none());
+        
+        let stream = [first, test_object("1.2.3.2", "1.2.3")].concat();
+        assert_eq!(sidecar_uids(&stream), vec!["1.2.3.1", "1.2.3.2"]);
+    }
+    
+    #[test]
+    fn split_instances_yields_each_object_with_its_own_bytes() {
+        let first = test_object("1.2.3.1", "1.2.3");
+        let second = test_object("1.2.3.2", "1.2.3");
+        let instances: Vec<SplitInstance> = split_instances(&zip_of(&[first.clone(), second.clone()]))
+            .expect("instances")
+            .collect();
+        
+        assert_eq!(instances.len(), 2);
+        assert_eq!((instances[0].sop_instance_uid.as_str(), &instances[0].data), ("1.2.3.1", &first));
+        assert_eq!((instances[1].sop_instance_uid.as_str(), &instances[1].data), ("1.2.3.2", &second));
+    }
+    
+    #[test]
+    fn is_valid_image_id_accepts_frame_and_placeholder_ids() {
+        assert!(is_valid_image_id("1.2.840.10008.1"));
+        assert!(is_valid_image_id("1.2.840.10008.1.frame12"));
+        assert!(is_valid_image_id("unknown.1.2.3.4.5"));
+        assert!(is_valid_image_id("1.2.3.4.5.6.7.8.9.0"));
+        
+        for id in ["", "unknown.", "1.2.3.frame", "../1.2.3", "1.2..3", "1.2.3/frame1", "x.1.2.3"] {
+            assert!(!is_valid_image_id(id), "{:?} should be rejected", id);
+        }
+    }
+    
+    #[test]
+    fn parent_sop_instance_uid_strips_frame_suffixes() {
+        assert_eq!(parent_sop_instance_uid("1.2.3.frame12"), "1.2.3");
+        assert_eq!(parent_sop_instance_uid("1.2.3"), "1.2.3");
+        assert_eq!(parent_sop_instance_uid("1.2.3.frame"), "1.2.3.frame");
+        assert_eq!(parent_sop_instance_uid("1.2.3.framex"), "1.2.3.framex");
+    }
+    
+    #[test]
+    fn dicom_json_sidecars_read_zip_entries_one_at_a_time() {
+        let archive = zip_of(&[test_object("1.2.3.1", "1.2.3"), b"not dicom".to_vec(), test_object("1.2.3.2", "1.2.3")]);
+        assert_eq!(sidecar_uids(&archive), vec!["1.2.3.1", "1.2.3.2"]);
+        
+        let sidecar = dicom_json_sidecars(&archive).expect("sidecars").next().expect("sidecar");
+        assert_eq!(sidecar.study_instance_uid, "1.2.3");
+        assert_eq!(sidecar.json["00080018"]["Value"][0], "1.2.3.1");
+    }
+    
+    #[test]
+    fn process_zip_archive_stops_past_the_unpacked_size_cap() {
+        let session_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&session_dir).expect("session dir");
+        let session_dir = session_dir.to_string_lossy().to_string();
+        
+        let first = test_object("1.2.3.1", "1.2.3");
+        let archive = zip_of(&[first.clone(), test_object("1.2.3.2", "1.2.3")]);
+        let mut warnings = Vec::new();
+        
+        let instances = process_zip_archive(&archive, &session_dir, u64::MAX, &mut warnings).expect("instances");
+        assert_eq!(instances.len(), 2);
+        
+        let err = process_zip_archive(&archive, &session_dir, first.len() as u64 + 10, &mut warnings).unwrap_err();
+        assert!(matches!(dicom_error(&err), Some(DicomError::InsufficientTempSpace { .. })));
+        assert!(warnings.is_empty());
+        
+        fs::remove_dir_all(&session_dir).expect("cleanup");
+    }
+    
+    #[test]
+    fn bounded_map_keeps_item_order_and_the_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        
+        for limit in [1, 3] {
+            let running = AtomicUsize::new(0);
+            let peak = AtomicUsize::new(0);
+            let results = bounded_map(0..20u64, limit, |item| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(20 - item));
+                running.fetch_sub(1, Ordering::SeqCst);
+                item * 2
+            });
+            
+            assert_eq!(results, (0..20).map(|item| item * 2).collect::<Vec<_>>());
+            assert!(peak.load(Ordering::SeqCst) <= limit, "limit {}", limit);
+        }
+    }
+    
+    #[test]
+    fn parse_integer_string_reads_the_first_whole_number() {
+        assert_eq!(parse_integer_string(" 0012 "), Some(12));
+        assert_eq!(parse_integer_string("12\0"), Some(12));
+        assert_eq!(parse_integer_string("+3"), Some(3));
+        assert_eq!(parse_integer_string("-4"), Some(-4));
+        assert_eq!(parse_integer_string("4\\5\\6"), Some(4));
+        assert_eq!(parse_integer_string("7.0"), Some(7));
+        
+        assert_eq!(parse_integer_string("7.5"), None);
+        assert_eq!(parse_integer_string(""), None);
+        assert_eq!(parse_integer_string("\\5"), None);
+        assert_eq!(parse_integer_string("1e12"), None);
+    }
+    
+    // Helper to write an implicit VR meta element: tag, 4-byte length, even-padded value
+    fn implicit_meta_element(out: &mut Vec<u8>, element: u16, value: &[u8]) {
+        let mut value = value.to_vec();
+        if value.len() % 2 == 1 {
+            value.push(0);
+        }
+        out.extend_from_slice(&0x0002u16.to_le_bytes());
+        out.extend_from_slice(&element.to_le_bytes());
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(&value);
+    }
+    
+    #[test]
+    fn open_with_implicit_meta_rebuilds_the_meta_group() {
+        // The explicit VR data set of a well-formed object follows its meta group
+        let explicit = test_object("1.2.3.1", "1.2.3");
+        let meta_length = u32::from_le_bytes(explicit[140..144].try_into().unwrap()) as usize;
+        let dataset = &explicit[144 + meta_length..];
+        
+        let mut data = vec![0u8; 128];
+        data.extend_from_slice(DICM_MAGIC);
+        implicit_meta_element(&mut data, 0x0001, &[0, 1]);
+        implicit_meta_element(&mut data, 0x0002, SECONDARY_CAPTURE.as_bytes());
+        implicit_meta_element(&mut data, 0x0003, b"1.2.3.1");
+        implicit_meta_element(&mut data, 0x0010, EXPLICIT_VR_LITTLE_ENDIAN.as_bytes());
+        data.extend_from_slice(dataset);
+        
+        let (obj, transfer_syntax) = open_with_implicit_meta(&data).expect("implicit meta").expect("object");
+        assert_eq!(transfer_syntax, EXPLICIT_VR_LITTLE_ENDIAN);
+        assert_eq!(obj.meta().media_storage_sop_instance_uid().trim_end_matches('\0'), "1.2.3.1");
+        let study = obj.element(Tag(0x0020, 0x000D)).expect("study").to_str().expect("text").into_owned();
+        assert_eq!(study.trim_end_matches('\0'), "1.2.3");
+        
+        assert!(open_with_implicit_meta(&explicit).is_none());
+    }
+    
+    #[test]
+    fn metadata_extracts_from_objects_missing_the_preamble() {
+        let full = test_object("1.2.3.1", "1.2.3");
+        let meta_length = u32::from_le_bytes(full[140..144].try_into().unwrap()) as usize;
+        
+        // "DICM" without the preamble, the meta group without "DICM", and a bare data set
+        for data in [&full[128..], &full[132..], &full[144 + meta_length..]] {
+            assert!(open_without_preamble(data).expect("recognized").is_ok());
+            let metadata = extract_metadata(data).expect("metadata");
+            assert_eq!(metadata.study_instance_uid, "1.2.3");
+            assert_eq!(metadata.modality, "OT");
+        }
+        
+        assert!(open_without_preamble(b"plain text, not DICOM").is_none());
+    }
+    
+    #[test]
+    fn latin1_text_extracts_as_utf8() {
+        let mut obj = InMemDicomObject::new_empty();
+        put(&mut obj, Tag(0x0008, 0x0005), VR::CS, "ISO_IR 100");
+        put(&mut obj, Tag(0x0008, 0x0016), VR::UI, SECONDARY_CAPTURE);
+        put(&mut obj, Tag(0x0008, 0x0018), VR::UI, "1.2.3.1");
+        put(&mut obj, Tag(0x0008, 0x1030), VR::LO, "Cr#ne sans injection");
+        put(&mut obj, Tag(0x0020, 0x000D), VR::UI, "1.2.3");
+        put(&mut obj, Tag(0x0020, 0x000E), VR::UI, "1.2.3.1");
+        let file = obj.with_meta(FileMetaTableBuilder::new()
+                .media_storage_sop_class_uid(SECONDARY_CAPTURE)
+                .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN))
+            .expect("file meta");
+        let mut data = Vec::new();
+        file.write_all(&mut data).expect("encode");
+        
+        // Latin-1 stores â as the single byte 0xE2
+        let marker = data.windows(2).position(|pair| pair == b"r#").expect("description") + 1;
+        data[marker] = 0xE2;
+        assert_eq!(extract_metadata(&data).expect("metadata").study_description, "Crâne sans injection");
+    }
+    
+    #[test]
+    fn redecode_text_recovers_bytes_read_as_latin1() {
+        assert_eq!(charset_encoding("ISO_IR 100"), Some(encoding_rs::WINDOWS_1252));
+        assert_eq!(charset_encoding("ISO 2022 IR 149"), Some(encoding_rs::EUC_KR));
+        assert_eq!(charset_encoding("ISO_IR 999"), None);
+        
+        // "日本" in Shift JIS, as a Latin-1 reader would have produced it
+        let misread: String = [0x93u8, 0xFA, 0x96, 0x7B].iter().map(|&byte| byte as char).collect();
+        assert_eq!(redecode_text(&misread, encoding_rs::SHIFT_JIS), "日本");
+        assert_eq!(redecode_text("ASCII^NAME", encoding_rs::SHIFT_JIS), "ASCII^NAME");
+        assert_eq!(redecode_text("already ☃", encoding_rs::SHIFT_JIS), "already ☃");
+    }
+    
+    #[test]
+    fn parser_panics_become_malformed_errors() {
+        let err = catch_parser_panic::<()>("testing", || panic!("element length {} is implausible", 0xFFFF_FFF0u32))
+            .unwrap_err();
+        assert!(matches!(dicom_error(&err), Some(DicomError::Malformed(message)) if message.contains("implausible")));
+        
+        let err = catch_parser_panic::<()>("testing", || panic!("static message")).unwrap_err();
+        assert!(matches!(dicom_error(&err), Some(DicomError::Malformed(message)) if message == "static message"));
+        
+        assert_eq!(catch_parser_panic("testing", || Ok(7)).unwrap(), 7);
+    }
+    
+    fn frame(width: u32, samples_per_pixel: u32, samples: &[u16]) -> DecodedFrame {
+        let height = samples.len() as u32 / samples_per_pixel / width;
+        DecodedFrame { width, height, samples_per_pixel, samples: samples.to_vec() }
+    }
+    
+    #[test]
+    fn to_rgb8_follows_the_photometric_interpretation() {
+        let gray = frame(2, 1, &[0, 200]);
+        assert_eq!(to_rgb8(&gray, "MONOCHROME2", None), vec![0, 0, 0, 200, 200, 200]);
+        assert_eq!(to_rgb8(&gray, "MONOCHROME1", None), vec![255, 255, 255, 55, 55, 55]);
+        
+        let rgb = frame(1, 3, &[10, 20, 300]);
+        assert_eq!(to_rgb8(&rgb, "RGB", None), vec![10, 20, 255]);
+        
+        // Neutral chroma leaves luma as grey
+        assert_eq!(to_rgb8(&frame(1, 3, &[90, 128, 128]), "YBR_FULL", None), vec![90, 90, 90]);
+        
+        // Three pixels in 4:2:2: two pairs, the last one half used
+        let ybr_422 = DecodedFrame { width: 3, height: 1, samples_per_pixel: 3, samples: vec![10, 20, 128, 128, 30, 0, 128, 128] };
+        assert_eq!(to_rgb8(&ybr_422, "YBR_FULL_422", None), vec![10, 10, 10, 20, 20, 20, 30, 30, 30]);
+        
+        let lut = PaletteLut { first_mapped: 1, bits: 16, red: vec![0xFF00, 0], green: vec![0, 0xFF00], blue: vec![0, 0] };
+        assert_eq!(to_rgb8(&frame(3, 1, &[0, 1, 9]), "PALETTE COLOR", Some(&lut)),
+                   vec![255, 0, 0, 255, 0, 0, 0, 255, 0]);
+    }
+    
+    #[test]
+    fn downscale_keeps_the_aspect_ratio() {
+        let frame = RgbFrame { width: 4, height: 2, pixels: (0..24).collect() };
+        let small = downscale(frame, 2);
+        assert_eq!((small.width, small.height), (2, 1));
+        assert_eq!(small.pixels, vec![0, 1, 2, 6, 7, 8]);
+        
+        let tiny = RgbFrame { width: 1, height: 1, pixels: vec![1, 2, 3] };
+        assert_eq!(downscale(tiny, 512).pixels, vec![1, 2, 3]);
+    }
+    
+    #[test]
+    fn check_frame_count_allows_one_to_max_frames() {
+        assert!(check_frame_count(1).is_ok());
+        assert!(check_frame_count(max_frames()).is_ok());
+        for frames in [0, -1, max_frames() + 1] {
+            let err = check_frame_count(frames).unwrap_err();
+            assert!(matches!(dicom_error(&err), Some(DicomError::Parse(_))), "{}", frames);
+        }
+    }
+    
+    #[test]
+    fn check_temp_space_compares_against_free_space() {
+        assert!(check_temp_space(1).is_ok());
+        let err = check_temp_space(u64::MAX).unwrap_err();
+        assert!(matches!(dicom_error(&err), Some(DicomError::InsufficientTempSpace { needed: u64::MAX, .. })));
+    }
+    
+    // Helper to build an 8-bit 2x2 MONOCHROME2 object holding `frames` frames
+    fn multi_frame_object(frames: u32) -> Vec<u8> {
+        let mut obj = open_from_bytes(&test_object("1.2.3.1", "1.2.3")).expect("object").into_inner();
+        put(&mut obj, Tag(0x0028, 0x0004), VR::CS, "MONOCHROME2");
+        put(&mut obj, Tag(0x0028, 0x0008), VR::IS, &frames.to_string());
+        let shorts = [(0x0002, 1), (0x0010, 2), (0x0011, 2), (0x0100, 8), (0x0101, 8), (0x0102, 7), (0x0103, 0)];
+        for (element, value) in shorts {
+            obj.put(DataElement::new(Tag(0x0028, element), VR::US, PrimitiveValue::from(value as u16)));
+        }
+        obj.put(DataElement::new(Tag(0x7FE0, 0x0010), VR::OB,
+            PrimitiveValue::from(vec![0x80u8; 4 * frames as usize])));
+        
+        let file = obj.with_meta(FileMetaTableBuilder::new()
+                .media_storage_sop_class_uid(SECONDARY_CAPTURE)
+                .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN))
+            .expect("file meta");
+        let mut data = Vec::new();
+        file.write_all(&mut data).expect("encode");
+        data
+    }
+    
+    #[test]
+    fn cine_builder_caps_frames_not_objects() {
+        let mut cine = CineBuilder::new(WindowOverride::default());
+        cine.add_object(&multi_frame_object(3)).expect("frames");
+        assert_eq!(cine.frames.len(), 3);
+        assert!(!cine.is_full());
+        
+        cine.add_object(&multi_frame_object(MAX_CINE_FRAMES as u32)).expect("frames");
+        assert_eq!(cine.frames.len(), MAX_CINE_FRAMES);
+        assert!(cine.is_full());
+        
+        let gif = cine.finish().expect("gif");
+        assert!(gif.starts_with(b"GIF89a"));
+        assert!(CineBuilder::new(WindowOverride::default()).finish().is_err());
+    }
+    
+    #[test]
+    fn remap_uid_is_deterministic_and_fits_in_64_characters() {
+        let remapped = remap_uid("1.2.840.113619.2.55.3", "2.25");
+        assert_eq!(remapped, remap_uid(" 1.2.840.113619.2.55.3 ", "2.25"));
+        assert_ne!(remapped, remap_uid("1.2.840.113619.2.55.4", "2.25"));
+        assert!(remapped.starts_with("2.25.") && is_valid_uid(&remapped));
+        
+        let long_root = "1.2.826.0.1.3680043.10.1234567";
+        let remapped = remap_uid("1.2.3", long_root);
+        assert!(remapped.len() <= 64 && is_valid_uid(&remapped));
+    }
+    
+    #[test]
+    fn anonymize_upload_keeps_the_study_hierarchy() {
+        let mut named = open_from_bytes(&test_object("1.2.3.1", "1.2.3")).expect("object");
+        put(&mut named, Tag(0x0010, 0x0010), VR::PN, "DOE^JANE");
+        let mut first = Vec::new();
+        named.write_all(&mut first).expect("encode");
+        
+        let stream = [first, test_object("1.2.3.2", "1.2.3")].concat();
+        let anonymized: Vec<DicomMetadata> = upload_objects(&anonymize_upload(&stream).expect("anonymized"))
+            .expect("objects")
+            .map(|(data, _)| extract_metadata(&data).expect("metadata"))
+            .collect();
+        
+        assert_eq!(anonymized.len(), 2);
+        assert_eq!(anonymized[0].study_instance_uid, remap_uid("1.2.3", &uid_root()));
+        assert_eq!(anonymized[0].study_instance_uid, anonymized[1].study_instance_uid);
+        assert_eq!(anonymized[0].series_instance_uid, anonymized[1].series_instance_uid);
+        assert_eq!(anonymized[0].sop_instance_uid, remap_uid("1.2.3.1", &uid_root()));
+        assert_ne!(anonymized[0].sop_instance_uid, anonymized[1].sop_instance_uid);
+        assert_eq!(anonymized[0].patient_name, "Anonymous");
+    }
+    
+    #[test]
+    fn default_window_for_ignores_the_side() {
+        let bone = Some((400.0, 1800.0));
+        assert_eq!(default_window_for("CT", "Lower Extremity (Left)"), bone);
+        assert_eq!(default_window_for("CT", "KNEE"), bone);
+        assert_eq!(default_window_for("ct", "widget"), Some((40.0, 400.0)));
+        assert_eq!(default_window_for("MR", "Brain"), None);
+    }
+}
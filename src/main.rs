@@ -1,41 +1,71 @@
 use lambda_runtime::{run, service_fn, LambdaEvent, Error as LambdaError};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use std::time::Instant;
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_s3::Client as S3Client;
 use aws_sdk_xray::Client as XRayClient;
 
 mod api;
+mod config;
 mod db;
 mod dicom;
 mod models;
+mod ratelimit;
+mod remote;
 mod routes;
 mod s3;
+mod share;
+mod sr;
+mod staging;
+mod taxonomy;
 mod telemetry;
+mod upstream;
 
-use api::request::{Request, extract_method_and_path};
+use api::request::{Request, extract_method_and_path, normalize_path};
 use api::response::options_response;
 
 /// Main Lambda handler function
-async fn function_handler(event: LambdaEvent<Request>) -> Result<api::response::Response, LambdaError> {
+async fn function_handler(event: LambdaEvent<Request>, config: &config::Config) -> Result<api::response::Response, LambdaError> {
+    let started = Instant::now();
     info!("FULL EVENT DUMP: {:?}", event);
     
+    // Keep-warm pings only need the container up, so skip client setup, rate limiting and routing
+    let (http_method, path) = extract_method_and_path(&event.payload);
+    let path = normalize_path(&path);
+    if event.payload.is_scheduled_ping() || (http_method == "GET" && path == "/api/warmup") {
+        info!("Warmup ping, skipping request handling");
+        return routes::warmup::warm_up().await;
+    }
+    
     // Initialize AWS clients
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-    let dynamodb_client = DynamoDbClient::new(&config);
-    let s3_client = S3Client::new(&config);
-    let xray_client = XRayClient::new(&config);
+    let aws_config = load_aws_config(config).await;
+    let dynamodb_client = DynamoDbClient::new(&aws_config);
+    let s3_client = S3Client::new(&aws_config);
+    let xray_client = XRayClient::new(&aws_config);
 
     // Send X-Ray trace for request start
     telemetry::send_xray_trace(&xray_client, "request-start").await;
     
-    // Extract method and path from request
-    let (http_method, path) = extract_method_and_path(&event.payload);
-    
     info!("PROCESSED REQUEST: method={}, path={}", http_method, path);
 
-    // Handle OPTIONS request with proper CORS headers
+    // Handle OPTIONS request with CORS headers for the methods this path supports
     if http_method == "OPTIONS" {
-        return Ok(options_response());
+        return Ok(options_response(&allowed_methods(&path)));
+    }
+
+    // Throttle API callers per IP before doing any expensive work; a failing
+    // limiter lets requests through rather than taking the API down
+    if path.starts_with("/api") {
+        if let (Some((class, limit)), Some(client_ip)) = (ratelimit::limit_for(config, &http_method), event.payload.client_ip()) {
+            match ratelimit::check(&dynamodb_client, class, client_ip, limit).await {
+                Ok(ratelimit::RateDecision::Limited { retry_after_secs }) => {
+                    warn!("Rate limit exceeded: ip={}, method={}, path={}", client_ip, http_method, path);
+                    return api::response::too_many_requests("Too many requests, please slow down", retry_after_secs);
+                },
+                Ok(ratelimit::RateDecision::Allowed) => {},
+                Err(err) => warn!("Rate limit check failed, allowing request: {:?}", err),
+            }
+        }
     }
 
     // Route the request
@@ -43,58 +73,440 @@ async fn function_handler(event: LambdaEvent<Request>) -> Result<api::response::
         // Serve frontend files
         routes::frontend::serve_frontend(&s3_client, &path).await
     } else {
-        // Handle API routes based on method and path
-        match (http_method.as_str(), path.as_str()) {
+        // Handle API routes through the route table, which preflights read too
+        match route(&http_method, &path) {
             // Case-related routes
-            ("GET", "/api/cases") => 
-                routes::cases::list_cases(&dynamodb_client).await,
+            Some(Route::ListCases) => 
+                routes::cases::list_cases(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::ListIncompleteCases) => 
+                routes::cases::list_incomplete_cases(&dynamodb_client).await,
+                
+            Some(Route::ListCasesByAnatomy) => 
+                routes::cases::list_cases_by_anatomy(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::ListPopularCases) => 
+                routes::cases::list_popular_cases(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::CompareCases) => 
+                routes::cases::compare_cases(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::GetOriginal) => 
+                routes::cases::get_original(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::GetSeriesInstances) => 
+                routes::dicom_routes::get_series_instances(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::GetSeriesWindow) => 
+                routes::dicom_routes::get_series_window(&dynamodb_client, &event.payload, &path).await,
+                
+            Some(Route::GetSeriesCine) => 
+                routes::dicom_routes::get_series_cine(&dynamodb_client, &s3_client, &event.payload, &path).await,
+                
+            Some(Route::ListImages) => 
+                routes::cases::list_images(&dynamodb_client, &event.payload, &path).await,
+                
+            Some(Route::GetHistory) => 
+                routes::cases::get_history(&dynamodb_client, &path).await,
+                
+            Some(Route::GetAttachment) => 
+                routes::cases::get_attachment(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::GetThumbnail) => 
+                routes::cases::get_thumbnail(&dynamodb_client, &s3_client, &event.payload, &path).await,
+                
+            Some(Route::GetThumbnailSprite) => 
+                routes::cases::get_thumbnail_sprite(&dynamodb_client, &s3_client, &event.payload, &path).await,
+                
+            Some(Route::GetCaseSr) => 
+                routes::cases::get_case_sr(&dynamodb_client, &path).await,
+                
+            Some(Route::GetBlindCase) => 
+                routes::sharing::get_blind_case(&dynamodb_client, &path).await,
+                
+            Some(Route::GetCasePreview) => 
+                routes::sharing::get_case_preview(&dynamodb_client, &event.payload, &path).await,
+                
+            Some(Route::GetCase) => 
+                routes::cases::get_case(&dynamodb_client, &path).await,
+                
+            Some(Route::ValidateCase) => 
+                routes::cases::validate_case(&xray_client, &event.payload.body).await,
+                
+            Some(Route::CreateCase) => 
+                routes::cases::create_case(&dynamodb_client, &s3_client, &xray_client, &event.payload).await,
+                
+            Some(Route::BulkTag) => 
+                routes::tags::bulk_tag(&dynamodb_client, &event.payload.body).await,
                 
-            ("GET", p) if p.starts_with("/api/cases/") => 
-                routes::cases::get_case(&dynamodb_client, p).await,
+            Some(Route::CreateCaseFromUrl) => 
+                routes::cases::create_case_from_url(&dynamodb_client, &s3_client, &xray_client, &event.payload).await,
                 
-            ("POST", "/api/cases") => 
-                routes::cases::create_case(&dynamodb_client, &s3_client, &xray_client, &event.payload.body).await,
+            Some(Route::SetKeyImage) => 
+                routes::cases::set_key_image(&dynamodb_client, &path, &event.payload.body).await,
+                
+            Some(Route::UpdateCase) => 
+                routes::cases::update_case(&dynamodb_client, &path, &event.payload.body).await,
+                
+            Some(Route::DeleteCase) => 
+                routes::cases::delete_case(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::MoveImage) => 
+                routes::cases::move_image(&dynamodb_client, &path, &event.payload.body).await,
+                
+            Some(Route::CreateShareLink) => 
+                routes::sharing::create_share_link(&dynamodb_client, &path, &event.payload.body).await,
+                
+            Some(Route::AddAttachment) => 
+                routes::cases::add_attachment(&dynamodb_client, &s3_client, &path, &event.payload.body).await,
+                
+            Some(Route::ResumeCase) => 
+                routes::cases::resume_case(&dynamodb_client, &s3_client, &xray_client, &path).await,
+                
+            Some(Route::SplitCase) => 
+                routes::cases::split_case(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::AddImages) => 
+                routes::cases::add_images(&dynamodb_client, &s3_client, &xray_client, &path, &event.payload.body).await,
+                
+            
+            // Favorites routes
+            Some(Route::ListFavorites) => 
+                routes::favorites::list_favorites(&dynamodb_client, &path).await,
+                
+            Some(Route::AddFavorite) => 
+                routes::favorites::add_favorite(&dynamodb_client, &path).await,
+                
+            Some(Route::RemoveFavorite) => 
+                routes::favorites::remove_favorite(&dynamodb_client, &path).await,
+                
+            Some(Route::GetSharedPreview) => 
+                routes::sharing::get_shared_preview(&dynamodb_client, &event.payload, &path).await,
+                
+            Some(Route::GetSharedCase) => 
+                routes::sharing::get_shared_case(&dynamodb_client, &path).await,
+                
+            Some(Route::GetCaseByStudy) => 
+                routes::studies::get_case_by_study(&dynamodb_client, &path).await,
+                
+            Some(Route::ReindexCases) => 
+                routes::admin::reindex_cases(&dynamodb_client, &s3_client, &event.payload).await,
+                
+            Some(Route::RunGc) => 
+                routes::admin::run_gc(&dynamodb_client, &s3_client, &event.payload).await,
+                
+            Some(Route::ExportCases) => 
+                routes::admin::export_cases(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::ImportCases) => 
+                routes::admin::import_cases(&dynamodb_client, &event.payload).await,
+                
+            Some(Route::AuditCase) => 
+                routes::admin::audit_case(&dynamodb_client, &s3_client, &event.payload, &path).await,
+                
+            Some(Route::ListTags) => 
+                routes::tags::list_tags(&dynamodb_client).await,
+                
+            Some(Route::GetVersion) => 
+                routes::version::get_version().await,
+                
+            Some(Route::WarmUp) => 
+                routes::warmup::warm_up().await,
+                
+            
+            // Taxonomy routes
+            Some(Route::ListAnatomy) => 
+                routes::taxonomy_routes::list_anatomy().await,
                 
-            ("POST", p) if p.starts_with("/api/cases/") && p.contains("/images") => 
-                routes::cases::add_images(&dynamodb_client, &s3_client, &xray_client, p, &event.payload.body).await,
             
             // DICOM-related routes
-            ("GET", p) if p.starts_with("/api/dicom/") => 
-                routes::dicom_routes::get_dicom(&dynamodb_client, &s3_client, &xray_client, p).await,
+            Some(Route::GetWadoUri) => 
+                routes::dicom_routes::get_wado_uri(&dynamodb_client, &s3_client, &event.payload).await,
+                
+            Some(Route::GetDicomMetadata) => 
+                routes::dicom_routes::get_dicom_metadata(&dynamodb_client, &s3_client, &path).await,
+                
+            Some(Route::RenderDicom) => 
+                routes::dicom_routes::render_dicom(&dynamodb_client, &s3_client, &event.payload, &path).await,
+                
+            Some(Route::GetDicom) => 
+                routes::dicom_routes::get_dicom(&dynamodb_client, &s3_client, &xray_client, &path).await,
             
             // Not found
-            _ => {
+            None => {
                 error!("Route not found: {} {}", http_method, path);
                 api::response::not_found("Route not found")
             }
         }
     };
     
+    // Convert handler failures into the standard JSON error envelope instead
+    // of letting the platform surface an unstructured 502
+    let result = match result {
+        Err(err) if upstream::is_timeout_error(err.as_ref()) => {
+            error!("Upstream timeout while handling {} {}: {}", http_method, path, err);
+            api::response::gateway_timeout("Upstream timeout")
+        },
+        Err(err) => {
+            error!("Unhandled error while handling {} {}: {:?}", http_method, path, err);
+            api::response::server_error("Internal server error")
+        },
+        ok => ok,
+    };
+    
+    // Integrators can opt out of the ApiResponse wrapper on reads
+    let result = if http_method == "GET" && event.payload.wants_raw_response() {
+        result.map(api::response::Response::without_envelope)
+    } else {
+        result
+    };
+    
     // Send X-Ray trace for request end
     telemetry::send_xray_trace(&xray_client, "request-end").await;
     
+    log_request_latency(&http_method, &path, &result, started, config.slow_request_ms);
+    
     result
 }
 
+// Methods the API routes on, in the order a preflight lists them
+const ROUTED_METHODS: &[&str] = &["GET", "POST", "PUT", "DELETE"];
+
+/// Methods the routing table serves for a path; frontend paths are read-only
+fn allowed_methods(path: &str) -> Vec<&'static str> {
+    if !path.starts_with("/api") {
+        return vec!["GET"];
+    }
+    
+    ROUTED_METHODS.iter()
+        .copied()
+        .filter(|method| is_routed(method, path))
+        .collect()
+}
+
+/// An API endpoint, as resolved by route
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Route {
+    ListCases,
+    ListIncompleteCases,
+    ListCasesByAnatomy,
+    ListPopularCases,
+    CompareCases,
+    GetOriginal,
+    GetSeriesInstances,
+    GetSeriesWindow,
+    GetSeriesCine,
+    ListImages,
+    GetHistory,
+    GetAttachment,
+    GetThumbnail,
+    GetThumbnailSprite,
+    GetCaseSr,
+    GetBlindCase,
+    GetCasePreview,
+    GetCase,
+    ValidateCase,
+    CreateCase,
+    BulkTag,
+    CreateCaseFromUrl,
+    SetKeyImage,
+    UpdateCase,
+    DeleteCase,
+    MoveImage,
+    CreateShareLink,
+    AddAttachment,
+    ResumeCase,
+    SplitCase,
+    AddImages,
+    ListFavorites,
+    AddFavorite,
+    RemoveFavorite,
+    GetSharedPreview,
+    GetSharedCase,
+    GetCaseByStudy,
+    ReindexCases,
+    RunGc,
+    ExportCases,
+    ImportCases,
+    AuditCase,
+    ListTags,
+    GetVersion,
+    WarmUp,
+    ListAnatomy,
+    GetWadoUri,
+    GetDicomMetadata,
+    RenderDicom,
+    GetDicom,
+}
+
+/// The API route table: the endpoint serving a method and path, if any.
+/// Arms are tried in order, so specific paths come before the prefixes they share.
+fn route(method: &str, path: &str) -> Option<Route> {
+    let route = match (method, path) {
+        // Case-related routes
+        ("GET", "/api/cases") => Route::ListCases,
+        ("GET", "/api/cases/incomplete") => Route::ListIncompleteCases,
+        ("GET", "/api/cases/by-anatomy") => Route::ListCasesByAnatomy,
+        ("GET", "/api/cases/popular") => Route::ListPopularCases,
+        ("GET", "/api/cases/compare") => Route::CompareCases,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/original") => Route::GetOriginal,
+        ("GET", p) if p.starts_with("/api/cases/") && p.contains("/series/") && p.ends_with("/instances") => Route::GetSeriesInstances,
+        ("GET", p) if p.starts_with("/api/cases/") && p.contains("/series/") && p.ends_with("/window") => Route::GetSeriesWindow,
+        ("GET", p) if p.starts_with("/api/cases/") && p.contains("/series/") && p.ends_with("/cine") => Route::GetSeriesCine,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/images") => Route::ListImages,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/history") => Route::GetHistory,
+        ("GET", p) if p.starts_with("/api/cases/") && p.contains("/attachments/") => Route::GetAttachment,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/thumbnail") => Route::GetThumbnail,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/thumbnails") => Route::GetThumbnailSprite,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/sr") => Route::GetCaseSr,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/blind") => Route::GetBlindCase,
+        ("GET", p) if p.starts_with("/api/cases/") && p.ends_with("/preview") => Route::GetCasePreview,
+        ("GET", p) if p.starts_with("/api/cases/") => Route::GetCase,
+        ("POST", "/api/cases/validate") => Route::ValidateCase,
+        ("POST", "/api/cases") => Route::CreateCase,
+        ("POST", "/api/cases/bulk-tag") => Route::BulkTag,
+        ("POST", "/api/cases/from-url") => Route::CreateCaseFromUrl,
+        ("PUT", p) if p.starts_with("/api/cases/") && p.ends_with("/key-image") => Route::SetKeyImage,
+        ("PUT", p) if p.starts_with("/api/cases/") => Route::UpdateCase,
+        ("DELETE", p) if p.starts_with("/api/cases/") => Route::DeleteCase,
+        ("POST", p) if p.starts_with("/api/cases/") && p.ends_with("/move-image") => Route::MoveImage,
+        ("POST", p) if p.starts_with("/api/cases/") && p.ends_with("/share") => Route::CreateShareLink,
+        ("POST", p) if p.starts_with("/api/cases/") && p.ends_with("/attachments") => Route::AddAttachment,
+        ("POST", p) if p.starts_with("/api/cases/") && p.ends_with("/resume") => Route::ResumeCase,
+        ("POST", p) if p.starts_with("/api/cases/") && p.ends_with("/split") => Route::SplitCase,
+        ("POST", p) if p.starts_with("/api/cases/") && p.contains("/images") => Route::AddImages,
+        
+        // Favorites routes
+        ("GET", p) if p.starts_with("/api/users/") && p.ends_with("/favorites") => Route::ListFavorites,
+        ("POST", p) if p.starts_with("/api/users/") && p.contains("/favorites/") => Route::AddFavorite,
+        ("DELETE", p) if p.starts_with("/api/users/") && p.contains("/favorites/") => Route::RemoveFavorite,
+        ("GET", p) if p.starts_with("/api/shared/") && p.ends_with("/preview") => Route::GetSharedPreview,
+        ("GET", p) if p.starts_with("/api/shared/") => Route::GetSharedCase,
+        ("GET", p) if p.starts_with("/api/studies/") => Route::GetCaseByStudy,
+        ("POST", "/api/admin/reindex") => Route::ReindexCases,
+        ("POST", "/api/admin/gc") => Route::RunGc,
+        ("GET", "/api/admin/export") => Route::ExportCases,
+        ("POST", "/api/admin/import") => Route::ImportCases,
+        ("GET", p) if p.starts_with("/api/admin/cases/") && p.ends_with("/audit") => Route::AuditCase,
+        ("GET", "/api/tags") => Route::ListTags,
+        ("GET", "/api/version") => Route::GetVersion,
+        ("GET", "/api/warmup") => Route::WarmUp,
+        
+        // Taxonomy routes
+        ("GET", "/api/taxonomy/anatomy") => Route::ListAnatomy,
+        
+        // DICOM-related routes
+        ("GET", "/api/wado") => Route::GetWadoUri,
+        ("GET", p) if p.starts_with("/api/dicom/") && p.ends_with("/metadata") => Route::GetDicomMetadata,
+        ("GET", p) if p.starts_with("/api/dicom/") && p.ends_with("/render") => Route::RenderDicom,
+        ("GET", p) if p.starts_with("/api/dicom/") => Route::GetDicom,
+        
+        _ => return None,
+    };
+    Some(route)
+}
+
+/// Whether function_handler serves a method and path
+fn is_routed(method: &str, path: &str) -> bool {
+    route(method, path).is_some()
+}
+
+/// Load the AWS SDK config, pinned to REGION_OVERRIDE when one is configured
+async fn load_aws_config(config: &config::Config) -> aws_config::SdkConfig {
+    let loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+    match &config.region {
+        Some(region) => loader.region(aws_config::Region::new(region.clone())).load().await,
+        None => loader.load().await,
+    }
+}
+
+/// Log how long a request took, warning when it exceeds the SLOW_REQUEST_MS threshold
+fn log_request_latency(
+    http_method: &str,
+    path: &str,
+    result: &Result<api::response::Response, LambdaError>,
+    started: Instant,
+    threshold_ms: u64
+) {
+    let elapsed_ms = started.elapsed().as_millis();
+    let status = match result {
+        Ok(response) => response.status_code.to_string(),
+        Err(_) => "error".to_string(),
+    };
+    
+    if elapsed_ms > u128::from(threshold_ms) {
+        warn!("SLOW REQUEST: method={}, path={}, status={}, elapsed_ms={}, threshold_ms={}",
+              http_method, path, status, elapsed_ms, threshold_ms);
+    } else {
+        info!("REQUEST COMPLETE: method={}, path={}, status={}, elapsed_ms={}",
+              http_method, path, status, elapsed_ms);
+    }
+}
+
+// Log level used when neither RUST_LOG nor LOG_LEVEL is usable
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// Retrieves the log filter from environment variables or falls back to a default.
+/// RUST_LOG takes per-module directives such as "info,radiology_teaching_files::dicom=debug";
+/// LOG_LEVEL is a plain level. An invalid value is returned alongside the default filter.
+fn log_filter() -> (tracing_subscriber::EnvFilter, Option<String>) {
+    let configured = |name: &str| std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let default = || tracing_subscriber::EnvFilter::new(DEFAULT_LOG_LEVEL);
+    
+    if let Some(directives) = configured("RUST_LOG") {
+        return match tracing_subscriber::EnvFilter::try_new(&directives) {
+            Ok(filter) => (filter, None),
+            Err(_) => (default(), Some(directives)),
+        };
+    }
+    
+    // A bare word would parse as a module name, so LOG_LEVEL must be a real level
+    match configured("LOG_LEVEL") {
+        Some(level) => match level.parse::<tracing::level_filters::LevelFilter>() {
+            Ok(level) => (tracing_subscriber::EnvFilter::new(level.to_string()), None),
+            Err(_) => (default(), Some(level)),
+        },
+        None => (default(), None),
+    }
+}
+
 /// Entry point for the Lambda function
 #[tokio::main]
 async fn main() -> Result<(), LambdaError> {
     // Initialize tracing
+    let (filter, invalid_filter) = log_filter();
     tracing_subscriber::fmt()
         .with_ansi(false)
         .without_time()
-        .with_max_level(tracing::Level::INFO)
+        .with_env_filter(filter)
         .init();
+    
+    if let Some(value) = invalid_filter {
+        warn!("Ignoring invalid log level '{}', using {}", value, DEFAULT_LOG_LEVEL);
+    }
+
+    // Read and validate settings once; a misconfigured function fails here rather than per request
+    let config = match config::Config::from_env() {
+        Ok(config) => config::init(config),
+        Err(err) => {
+            error!("{}", err);
+            return Err(err.into());
+        }
+    };
+    info!("Configuration loaded: bucket={}, table={}", config.bucket, config.table_name);
 
     // Initialize X-Ray
     telemetry::init_xray();
     info!("X-Ray tracing initialized");
 
     // Set up AWS clients
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-    let dynamodb_client = DynamoDbClient::new(&config);
-    let s3_client = S3Client::new(&config);
-    let xray_client = XRayClient::new(&config);
+    let aws_config = load_aws_config(config).await;
+    let dynamodb_client = DynamoDbClient::new(&aws_config);
+    let s3_client = S3Client::new(&aws_config);
+    let xray_client = XRayClient::new(&aws_config);
     
     // Send X-Ray trace for Lambda startup
     telemetry::send_xray_trace(&xray_client, "lambda-startup").await;
@@ -104,11 +516,81 @@ async fn main() -> Result<(), LambdaError> {
         error!("Failed to ensure DynamoDB table exists: {:?}", err);
     }
 
+    if let Err(err) = db::ensure_favorites_table_exists(&dynamodb_client).await {
+        error!("Failed to ensure favorites table exists: {:?}", err);
+    }
+
+    if let Err(err) = db::ensure_rate_limit_table_exists(&dynamodb_client).await {
+        error!("Failed to ensure rate limit table exists: {:?}", err);
+    }
+
     if let Err(err) = s3::ensure_bucket_exists(&s3_client).await {
         error!("Failed to ensure S3 bucket exists: {:?}", err);
     }
 
+    // Case instance lists too large for a DynamoDB item are kept in S3
+    db::set_offload_client(s3_client.clone());
+
+    // Load the extra DICOM tag allowlist from S3 when configured
+    if let Some(key) = &config.extra_dicom_tags_key {
+        match s3::download_file(&s3_client, key).await
+            .and_then(|data| Ok(serde_json::from_slice::<Vec<String>>(&data)?))
+        {
+            Ok(tags) => {
+                info!("Loaded {} extra DICOM tags from {}", tags.len(), key);
+                dicom::set_extra_tags(tags);
+            },
+            Err(err) => error!("Failed to load extra DICOM tags from {}: {:?}", key, err),
+        }
+    }
+
+    // Load window presets for objects without a stored window when configured
+    if let Some(key) = &config.window_presets_key {
+        match s3::download_file(&s3_client, key).await
+            .and_then(|data| Ok(serde_json::from_slice::<Vec<dicom::WindowPreset>>(&data)?))
+        {
+            Ok(presets) => {
+                info!("Loaded {} window presets from {}", presets.len(), key);
+                dicom::set_window_presets(presets);
+            },
+            Err(err) => error!("Failed to load window presets from {}: {:?}", key, err),
+        }
+    }
+
     // Run the Lambda service
     info!("Starting Lambda service with X-Ray tracing enabled");
-    run(service_fn(function_handler)).await
-}
\ No newline at end of file
+    run(service_fn(|event| function_handler(event, config))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preflights_list_the_methods_each_path_serves() {
+        let case = "/api/cases/11111111-1111-1111-1111-111111111111";
+        assert_eq!(allowed_methods("/api/cases"), vec!["GET", "POST"]);
+        assert_eq!(allowed_methods(case), vec!["GET", "PUT", "DELETE"]);
+        assert_eq!(allowed_methods("/api/admin/reindex"), vec!["POST"]);
+        assert_eq!(allowed_methods("/api/dicom/case/1.2.3"), vec!["GET"]);
+        assert_eq!(allowed_methods("/api/users/u1/favorites/c1"), vec!["POST", "DELETE"]);
+        assert_eq!(allowed_methods("/index.html"), vec!["GET"]);
+        assert!(allowed_methods("/api/nothing-here").is_empty());
+        
+        let preflight = options_response(&allowed_methods("/api/dicom/case/1.2.3"));
+        assert_eq!(preflight.headers["Access-Control-Allow-Methods"], "GET, OPTIONS");
+    }
+
+    #[test]
+    fn route_prefers_specific_paths_over_shared_prefixes() {
+        let case = "/api/cases/11111111-1111-1111-1111-111111111111";
+        assert_eq!(route("GET", "/api/cases/incomplete"), Some(Route::ListIncompleteCases));
+        assert_eq!(route("GET", &format!("{}/series/1.2.3/window", case)), Some(Route::GetSeriesWindow));
+        assert_eq!(route("GET", &format!("{}/thumbnails", case)), Some(Route::GetThumbnailSprite));
+        assert_eq!(route("GET", case), Some(Route::GetCase));
+        assert_eq!(route("PUT", &format!("{}/key-image", case)), Some(Route::SetKeyImage));
+        assert_eq!(route("POST", "/api/cases/validate"), Some(Route::ValidateCase));
+        assert_eq!(route("GET", "/api/dicom/case/1.2.3/render"), Some(Route::RenderDicom));
+        assert_eq!(route("PATCH", case), None);
+    }
+}
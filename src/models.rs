@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Case {
@@ -13,6 +14,10 @@ pub struct Case {
     pub image_ids: Vec<String>,
     pub created_at: String,
     
+    // Last time the case was saved, used for incremental sync
+    #[serde(default)]
+    pub updated_at: String,
+    
     // New DICOM metadata fields - these were previously only in DicomMetadata
     #[serde(default)]
     pub study_instance_uid: String,
@@ -30,6 +35,60 @@ pub struct Case {
     // Series information for organizing multiple images
     #[serde(default)]
     pub series: Vec<SeriesInfo>,
+    
+    // The instance that best illustrates the finding, used for previews
+    #[serde(default)]
+    pub key_image_sop: Option<String>,
+    
+    // Audit trail of changes to the teaching answer
+    #[serde(default)]
+    pub edit_history: Vec<EditRecord>,
+    
+    // Supplementary non-DICOM files such as reports, stored under attachments/{case_id}/{id}
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    
+    // Images each instance references, keyed by the referencing SOP Instance UID
+    #[serde(default)]
+    pub image_references: HashMap<String, Vec<String>>,
+    
+    // S3 key of the instance lists when they were too large to keep in the DynamoDB item
+    #[serde(skip)]
+    pub instances_key: Option<String>,
+    
+    // How many times the case has been opened, for the most viewed ranking
+    #[serde(default)]
+    pub view_count: u64,
+    
+    // Instances the source said the study has, to spot partial uploads
+    #[serde(default)]
+    pub expected_instance_count: Option<u32>,
+}
+
+// A file attached to a case alongside its images
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+}
+
+// Body of POST /api/cases/{id}/attachments
+#[derive(Debug, Deserialize)]
+pub struct AttachmentUpload {
+    pub filename: String,
+    pub content_type: String,
+    // Base64 encoded file contents
+    pub data: String,
+}
+
+// A single change to a case field
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EditRecord {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+    pub at: String,
 }
 
 // New struct for representing series within a case
@@ -40,9 +99,24 @@ pub struct SeriesInfo {
     pub series_description: String,
     pub modality: String,
     pub image_ids: Vec<String>,
+    // Earliest acquisition time among the series' instances, for chronological ordering
+    #[serde(default)]
+    pub acquisition_datetime: Option<String>,
+    // Instances the source said the series has, to spot partial uploads
+    #[serde(default)]
+    pub expected_instance_count: Option<u32>,
+}
+
+impl SeriesInfo {
+    /// Teaching order: series with an acquisition time first, chronologically (e.g. contrast
+    /// phases), then by series number
+    pub fn display_order(&self, other: &SeriesInfo) -> std::cmp::Ordering {
+        (self.acquisition_datetime.is_none(), &self.acquisition_datetime, self.series_number)
+            .cmp(&(other.acquisition_datetime.is_none(), &other.acquisition_datetime, other.series_number))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CaseUpload {
     pub title: String,
     pub description: String,
@@ -52,19 +126,329 @@ pub struct CaseUpload {
     pub diagnosis: String,
     pub findings: String,
     pub tags: Vec<String>,
-    #[serde(rename = "dicomFile")]
+    #[serde(default, rename = "dicomFile")]
     pub dicom_file: String, // Base64 encoded DICOM file
+    // Client-chosen id that makes the create resumable and safe to retry
+    #[serde(default, rename = "uploadId", skip_serializing_if = "Option::is_none")]
+    pub upload_id: Option<String>,
+    // Strip patient identifiers and remap UIDs before anything is stored
+    #[serde(default)]
+    pub anonymize: bool,
+}
+
+// Case upload whose DICOM is fetched from a URL instead of sent inline
+#[derive(Debug, Deserialize)]
+pub struct CaseUrlUpload {
+    #[serde(rename = "dicomUrl", alias = "dicom_url")]
+    pub dicom_url: String,
+    #[serde(flatten)]
+    pub upload: CaseUpload,
 }
 
+// Steps of a staged create, in the order they complete
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StagingStage {
+    Received,
+    Uploaded,
+    MetadataExtracted,
+    Saved,
+}
+
+// Progress of a staged create, keyed by the client's upload id
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct CaseMetadata {
+pub struct StagingRecord {
+    pub upload_id: String,
+    pub case_id: String,
+    pub stage: StagingStage,
+    pub is_test_data: bool,
+    // Teaching fields from the upload, without the DICOM payload
+    pub upload: CaseUpload,
+    // The extracted case, once metadata extraction has completed
+    #[serde(default)]
+    pub case: Option<Case>,
+    // The create asked for ?force=true, so a study that already has a case is not refused
+    #[serde(default)]
+    pub force: bool,
+    pub updated_at: String,
+}
+
+impl CaseUpload {
+    /// Clean the free-text fields; Err names a required field that ended up empty
+    pub fn sanitize(&mut self) -> Result<(), String> {
+        self.title = sanitize::required("title", &self.title, false)?;
+        self.diagnosis = sanitize::required("diagnosis", &self.diagnosis, false)?;
+        self.description = sanitize::text(&self.description, true);
+        self.findings = sanitize::text(&self.findings, true);
+        self.modality = sanitize::text(&self.modality, false);
+        self.anatomy = sanitize::text(&self.anatomy, false);
+        self.tags = sanitize::tags(&self.tags);
+        Ok(())
+    }
+}
+
+// Partial update of the teaching fields of a case
+#[derive(Debug, Deserialize)]
+pub struct CaseUpdate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub modality: Option<String>,
+    pub anatomy: Option<String>,
+    pub diagnosis: Option<String>,
+    pub findings: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+impl CaseUpdate {
+    /// Clean the fields being updated; Err names a required field that ended up empty
+    pub fn sanitize(&mut self) -> Result<(), String> {
+        if let Some(title) = &self.title {
+            self.title = Some(sanitize::required("title", title, false)?);
+        }
+        if let Some(diagnosis) = &self.diagnosis {
+            self.diagnosis = Some(sanitize::required("diagnosis", diagnosis, false)?);
+        }
+        self.description = self.description.as_deref().map(|value| sanitize::text(value, true));
+        self.findings = self.findings.as_deref().map(|value| sanitize::text(value, true));
+        self.modality = self.modality.as_deref().map(|value| sanitize::text(value, false));
+        self.anatomy = self.anatomy.as_deref().map(|value| sanitize::text(value, false));
+        self.tags = self.tags.as_deref().map(sanitize::tags);
+        Ok(())
+    }
+}
+
+// Input cleaning for user-supplied strings before they are stored
+pub mod sanitize {
+    /// Trim and drop control characters; multi-line fields keep newlines and tabs
+    pub fn text(value: &str, multiline: bool) -> String {
+        value.replace("\r\n", "\n")
+            .chars()
+            .filter(|c| !c.is_control() || (multiline && (*c == '\n' || *c == '\t')))
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Clean a required field, returning the field name if nothing is left
+    pub fn required(field: &str, value: &str, multiline: bool) -> Result<String, String> {
+        let cleaned = text(value, multiline);
+        if cleaned.is_empty() {
+            Err(field.to_string())
+        } else {
+            Ok(cleaned)
+        }
+    }
+
+    /// Clean each tag, dropping tags that end up empty
+    pub fn tags(tags: &[String]) -> Vec<String> {
+        tags.iter()
+            .map(|tag| text(tag, false))
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+}
+
+// A case failing the completeness check, with the fields a curator needs to fill in
+#[derive(Debug, Serialize)]
+pub struct IncompleteCase {
+    pub case_id: String,
+    pub title: String,
+    pub missing: Vec<String>,
+}
+
+// The fields a browse view shows for a case, without its series and image lists
+#[derive(Debug, Serialize)]
+pub struct CaseSummary {
     pub case_id: String,
     pub title: String,
     pub modality: String,
     pub anatomy: String,
+    pub tags: Vec<String>,
+    pub image_count: usize,
+    pub key_image_sop: Option<String>,
+    pub created_at: String,
+}
+
+impl From<&Case> for CaseSummary {
+    fn from(case: &Case) -> Self {
+        Self {
+            case_id: case.case_id.clone(),
+            title: case.title.clone(),
+            modality: case.modality.clone(),
+            anatomy: case.anatomy.clone(),
+            tags: case.tags.clone(),
+            image_count: case.image_ids.len(),
+            key_image_sop: case.key_image_sop.clone(),
+            created_at: case.created_at.clone(),
+        }
+    }
+}
+
+// Outcome of a garbage collection pass over stored DICOM objects
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    pub applied: bool,
+    pub scanned: usize,
+    // Objects younger than the minimum age, which are never collected
+    pub skipped_recent: usize,
+    pub orphaned: Vec<String>,
+    pub deleted: usize,
+}
+
+// Outcome of removing every S3 object that belongs to a case
+#[derive(Debug, Serialize)]
+pub struct CleanupReport {
+    pub case_id: String,
+    pub deleted: usize,
+    // Keys that could not be removed, and prefixes that could not be listed
+    pub failed: Vec<String>,
+    pub unlisted_prefixes: Vec<String>,
+}
+
+impl CleanupReport {
+    /// Whether anything belonging to the case may have been left behind
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.unlisted_prefixes.is_empty()
+    }
+}
+
+// Outcome of writing a case's instances to their own S3 keys
+#[derive(Debug, Serialize)]
+pub struct SplitReport {
+    pub case_id: String,
+    pub instances_found: usize,
+    pub written: usize,
+    pub already_present: usize,
+    // Objects in the stored uploads whose SOP instance is not part of the case
+    pub not_in_case: usize,
+    pub failed: Vec<String>,
+}
+
+// Outcome of rebuilding series grouping across all cases
+#[derive(Debug, Serialize)]
+pub struct ReindexReport {
+    pub scanned: usize,
+    pub changed: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+// Outcome of restoring cases from an NDJSON export
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub received: usize,
+    pub imported: usize,
+    pub failed: Vec<String>,
+}
+
+// Differences between a case's stored DICOM objects and its registered instances
+#[derive(Debug, Serialize)]
+pub struct CaseAuditReport {
+    pub case_id: String,
+    pub registered: usize,
+    pub stored: usize,
+    // Whole-study uploads that instances may still be served from
+    pub uploads: Vec<String>,
+    // Per-instance objects whose SOP Instance UID is not on the case
+    pub unregistered_objects: Vec<String>,
+    // Registered SOP Instance UIDs with no per-instance object
+    pub missing_objects: Vec<String>,
+}
+
+// Identifies the deployed build
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub built_at: &'static str,
+}
+
+// Number of cases carrying a tag, for the tag listing
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+// One side of a case comparison, with the image a split viewer opens first
+#[derive(Debug, Serialize)]
+pub struct ComparedCase {
+    pub case: Case,
+    pub key_image_sop: Option<String>,
+}
+
+// Body of POST /api/cases/bulk-tag
+#[derive(Debug, Deserialize)]
+pub struct BulkTagRequest {
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+// Outcome of a bulk tag request for one case; tags on success, error otherwise
+#[derive(Debug, Serialize)]
+pub struct BulkTagResult {
+    pub case_id: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Body of POST /api/cases/{id}/move-image
+#[derive(Debug, Deserialize)]
+pub struct ImageMove {
+    pub sop: String,
+    pub to_series: String,
+}
+
+// Options for minting a share link; omitted fields use the configured defaults
+#[derive(Debug, Default, Deserialize)]
+pub struct ShareRequest {
+    // Quiz mode leaves the diagnosis out of the shared views; the token still names the case,
+    // so this is not access control over the answer
+    #[serde(default)]
+    pub hide_diagnosis: Option<bool>,
+    #[serde(default)]
+    pub expires_in_secs: Option<i64>,
+}
+
+// A read-only link to a single case
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    pub url: String,
+    pub token: String,
+    pub expires_at: String,
+    pub hide_diagnosis: bool,
+}
+
+// What create_case would store for an upload, returned by the validate endpoint
+#[derive(Debug, Serialize)]
+pub struct CasePreview {
+    pub case: Case,
+    pub instance_count: usize,
+    pub series_count: usize,
+    pub detected_modality: String,
+    pub phi_warnings: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyImageUpdate {
+    pub sop_instance_uid: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaseMetadata {
+    pub case_id: String,
+    pub title: String,
+    pub modality: String,
+    pub anatomy: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct DicomMetadata {
     pub sop_instance_uid: String,
     pub study_instance_uid: String,
@@ -76,6 +460,106 @@ pub struct DicomMetadata {
     pub study_description: String,
     pub series_description: String,
     pub instance_number: i32,
+    #[serde(default)]
+    pub series_number: i32,
+    
+    // Instance counts the source reported (NumberOfStudy/SeriesRelatedInstances), when present
+    #[serde(default)]
+    pub study_related_instances: Option<u32>,
+    #[serde(default)]
+    pub series_related_instances: Option<u32>,
+    
+    // Program-specific tags captured via the extra_tags allowlist
+    #[serde(default)]
+    pub extra: HashMap<String, String>,
+    
+    // Per-frame attributes for frames of an enhanced multi-frame object
+    #[serde(default)]
+    pub frame: Option<FrameAttributes>,
+    
+    // Instances this one points at through ReferencedImageSequence
+    #[serde(default)]
+    pub referenced_sop_instance_uids: Vec<String>,
+    
+    // Acquisition date and time as YYYY-MM-DDTHH:MM:SS.ffffff, so the text sorts chronologically
+    #[serde(default)]
+    pub acquisition_datetime: Option<String>,
+    
+    // BodyPartExamined and the L/R/B laterality code, used to default a case's anatomy
+    #[serde(default)]
+    pub body_part_examined: String,
+    #[serde(default)]
+    pub laterality: String,
+}
+
+// Position and windowing of one frame, from the per-frame functional groups
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FrameAttributes {
+    pub frame_number: i32,
+    pub image_position_patient: Option<Vec<f64>>,
+    pub window_center: Option<f64>,
+    pub window_width: Option<f64>,
+}
+
+// One page of a case's image manifest
+#[derive(Debug, Serialize)]
+pub struct ImagePage {
+    pub images: Vec<String>,
+    pub total: usize,
+    pub offset: usize,
+    pub next_offset: Option<usize>,
+}
+
+// A run of consecutive instances from one series, for viewers that page through large series
+#[derive(Debug, Serialize)]
+pub struct SeriesWindow {
+    pub series_instance_uid: String,
+    pub total: usize,
+    // Where the window starts after clamping to the series, and how many instances it holds
+    pub start: usize,
+    pub count: usize,
+    pub instances: Vec<WindowInstance>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowInstance {
+    // 1-based slice position in the series; images are kept in InstanceNumber order,
+    // but the tag itself is not stored per image
+    pub instance_number: usize,
+    pub sop: String,
+    pub render_url: String,
+    pub download_url: String,
+}
+
+// Previews of a case's series packed into one base64 PNG for the case-detail gallery
+#[derive(Debug, Serialize)]
+pub struct ThumbnailSprite {
+    pub content_type: String,
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: u32,
+    // Series UID to where its preview sits in the image
+    pub cells: HashMap<String, SpriteCoordinates>,
+    // Series left out of the sheet because they could not be loaded or rendered
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpriteCoordinates {
+    pub sop_instance_uid: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// A file too large to return inline, offered as a presigned S3 link instead
+#[derive(Debug, Serialize, Clone)]
+pub struct PresignedFile {
+    pub name: String,
+    pub size: i64,
+    pub url: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -84,6 +568,27 @@ pub struct ApiResponse<T> {
     pub data: T,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<ProcessingReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    // Cases deleted since a ?modified_since sync point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_ids: Option<Vec<String>>,
+}
+
+// What create_case found while processing an upload, returned next to the new case
+#[derive(Debug, Serialize)]
+pub struct ProcessingReport {
+    pub instances_found: usize,
+    pub series_found: usize,
+    pub parse_strategy: String,
+    pub fallback_used: bool,
+    pub warnings: Vec<String>,
+    pub phi_warning: bool,
+    // SOP instances of the case whose DICOM data could not be stored
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unstored_instances: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -93,14 +598,135 @@ pub struct ErrorResponse {
     pub error_code: String,
 }
 
+// 409 body when an upload repeats a study that already has a case
+#[derive(Debug, Serialize)]
+pub struct DuplicateStudyResponse {
+    pub success: bool,
+    pub error: String,
+    pub error_code: String,
+    pub existing_case: Case,
+}
+
+impl DuplicateStudyResponse {
+    pub fn new(existing_case: Case) -> Self {
+        Self {
+            success: false,
+            error: format!(
+                "Study {} already has case {}; add ?force=true to create another",
+                existing_case.study_instance_uid, existing_case.case_id
+            ),
+            error_code: "DUPLICATE_STUDY".to_string(),
+            existing_case,
+        }
+    }
+}
+
+impl Case {
+    /// Record that the case is being modified now
+    pub fn touch(&mut self) {
+        self.updated_at = chrono::Utc::now().to_rfc3339();
+    }
+    
+    /// Record the images an instance references, replacing any earlier entry for it
+    pub fn record_references(&mut self, metadata: &DicomMetadata) {
+        if metadata.referenced_sop_instance_uids.is_empty() {
+            return;
+        }
+        self.image_references.insert(metadata.sop_instance_uid.clone(), metadata.referenced_sop_instance_uids.clone());
+    }
+    
+    /// Fields that are empty or still hold the placeholders used when DICOM extraction failed.
+    /// Required teaching fields use the same check as uploads.
+    pub fn missing_fields(&self) -> Vec<String> {
+        let mut missing: Vec<String> = [("title", &self.title), ("diagnosis", &self.diagnosis)].iter()
+            .filter_map(|(field, value)| sanitize::required(field, value, false).err())
+            .collect();
+        
+        if self.findings.trim().is_empty() {
+            missing.push("findings".to_string());
+        }
+        if self.anatomy.trim().is_empty() {
+            missing.push("anatomy".to_string());
+        }
+        if self.modality.trim().is_empty() || self.modality.eq_ignore_ascii_case("unknown") {
+            missing.push("modality".to_string());
+        }
+        if self.study_instance_uid.is_empty() || self.study_instance_uid.starts_with("unknown.") {
+            missing.push("study_instance_uid".to_string());
+        }
+        if self.image_ids.is_empty() && self.series.is_empty() {
+            missing.push("images".to_string());
+        }
+        missing
+    }
+    
+    /// Check whether an instance is registered anywhere in this case
+    pub fn contains_instance(&self, sop_instance_uid: &str) -> bool {
+        self.image_ids.iter().any(|id| id == sop_instance_uid)
+            || self.series.iter().any(|series| series.image_ids.iter().any(|id| id == sop_instance_uid))
+    }
+    
+    /// Check whether a stored object belongs to this case, either under its own id or
+    /// through the virtual ids ({sop}.frame{N}) of its frames
+    pub fn contains_object(&self, sop_instance_uid: &str) -> bool {
+        let owns = |id: &String| crate::dicom::parent_sop_instance_uid(id) == sop_instance_uid;
+        self.image_ids.iter().any(owns)
+            || self.series.iter().any(|series| series.image_ids.iter().any(owns))
+    }
+    
+    /// All image ids in display order: series in display order, each in its stored order,
+    /// then any images not assigned to a series
+    pub fn ordered_image_ids(&self) -> Vec<String> {
+        let mut series: Vec<&SeriesInfo> = self.series.iter().collect();
+        series.sort_by(|a, b| a.display_order(b));
+        
+        let mut seen = std::collections::HashSet::new();
+        series.iter()
+            .flat_map(|series| series.image_ids.iter())
+            .chain(self.image_ids.iter())
+            .filter(|id| seen.insert(id.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
 impl<T> ApiResponse<T> {
     pub fn success(data: T) -> Self {
         Self {
             success: true,
             data,
             error: None,
+            report: None,
+            warning: None,
+            deleted_ids: None,
         }
     }
+    
+    pub fn failure(data: T, error: String) -> Self {
+        Self {
+            success: false,
+            data,
+            error: Some(error),
+            report: None,
+            warning: None,
+            deleted_ids: None,
+        }
+    }
+    
+    pub fn with_report(mut self, report: ProcessingReport) -> Self {
+        self.report = Some(report);
+        self
+    }
+    
+    pub fn with_warning(mut self, warning: String) -> Self {
+        self.warning = Some(warning);
+        self
+    }
+    
+    pub fn with_deleted_ids(mut self, deleted_ids: Vec<String>) -> Self {
+        self.deleted_ids = Some(deleted_ids);
+        self
+    }
 }
 
 impl ErrorResponse {
@@ -128,6 +754,70 @@ impl ErrorResponse {
         }
     }
 
+    pub fn conflict(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "CONFLICT".to_string(),
+        }
+    }
+
+    pub fn unsupported_media_type(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "UNSUPPORTED_MEDIA_TYPE".to_string(),
+        }
+    }
+
+    pub fn unprocessable(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "UNPROCESSABLE_ENTITY".to_string(),
+        }
+    }
+
+    pub fn payload_too_large(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "PAYLOAD_TOO_LARGE".to_string(),
+        }
+    }
+
+    pub fn forbidden(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "FORBIDDEN".to_string(),
+        }
+    }
+
+    pub fn too_many_requests(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "RATE_LIMITED".to_string(),
+        }
+    }
+
+    pub fn bad_gateway(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "BAD_GATEWAY".to_string(),
+        }
+    }
+
+    pub fn upstream_timeout(message: &str) -> Self {
+        Self {
+            success: false,
+            error: message.to_string(),
+            error_code: "UPSTREAM_TIMEOUT".to_string(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn not_implemented(message: &str) -> Self {
         Self {
@@ -136,4 +826,61 @@ impl ErrorResponse {
             error_code: "NOT_IMPLEMENTED".to_string(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn case_with_images(image_ids: &[&str]) -> Case {
+        serde_json::from_value(serde_json::json!({
+            "case_id": "case", "title": "t", "description": "", "modality": "CT", "anatomy": "",
+            "diagnosis": "d", "findings": "", "tags": [], "image_ids": image_ids,
+            "study_instance_uid": "", "series_instance_uid": "", "created_at": "", "updated_at": ""
+        })).expect("case")
+    }
+    
+    #[test]
+    fn contains_object_resolves_frame_ids_to_their_object() {
+        let case = case_with_images(&["1.2.3.frame1", "1.2.3.frame2", "1.2.4"]);
+        assert!(case.contains_object("1.2.3"));
+        assert!(case.contains_object("1.2.4"));
+        assert!(!case.contains_object("1.2.5"));
+        assert!(!case.contains_instance("1.2.3"));
+    }
+    
+    #[test]
+    fn sanitize_drops_control_characters_and_trims() {
+        assert_eq!(sanitize::text("  Left\u{0} knee\u{7}  ", false), "Left knee");
+        assert_eq!(sanitize::text("line one\r\n\tline two\n", true), "line one\n\tline two");
+        assert_eq!(sanitize::text("line one\nline two", false), "line oneline two");
+        
+        assert_eq!(sanitize::required("title", " \u{1b} ", false), Err("title".to_string()));
+        assert_eq!(sanitize::required("title", " Title ", false), Ok("Title".to_string()));
+        
+        let tags = ["  ct ".to_string(), "\u{0}".to_string(), "neuro".to_string()];
+        assert_eq!(sanitize::tags(&tags), vec!["ct", "neuro"]);
+    }
+    
+    #[test]
+    fn case_upload_sanitize_names_the_empty_required_field() {
+        let mut upload: CaseUpload = serde_json::from_value(serde_json::json!({
+            "title": " Case ", "description": "", "anatomy": "", "diagnosis": "\u{7}",
+            "findings": "", "tags": []
+        })).expect("upload");
+        assert_eq!(upload.sanitize(), Err("diagnosis".to_string()));
+        assert_eq!(upload.title, "Case");
+    }
+    
+    #[test]
+    fn duplicate_study_response_carries_the_existing_case() {
+        let existing = Case { study_instance_uid: "1.2.3".to_string(), ..case_with_images(&["1.2.3.1"]) };
+        let body = serde_json::to_value(DuplicateStudyResponse::new(existing)).expect("json");
+        
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error_code"], "DUPLICATE_STUDY");
+        assert_eq!(body["existing_case"]["case_id"], "case");
+        let error = body["error"].as_str().expect("error");
+        assert!(error.contains("1.2.3") && error.contains("force=true"));
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use aws_sdk_dynamodb::Client;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::db;
+
+// Idle buckets are kept this long past a full refill before TTL removes them
+const BUCKET_EXPIRY_SECS: i64 = 120;
+
+// Read-modify-write attempts before a contended request is limited instead of charged
+const MAX_BUCKET_ATTEMPTS: u32 = 5;
+
+/// Outcome of charging one request to a client's token bucket
+#[derive(Debug, PartialEq)]
+pub enum RateDecision {
+    Allowed,
+    Limited { retry_after_secs: u64 },
+}
+
+/// The bucket name and per-minute limit that apply to a method, or None when it is not limited.
+/// Writes use RATE_LIMIT_PER_MIN; reads are unlimited unless RATE_LIMIT_GET_PER_MIN is set.
+pub fn limit_for(config: &Config, method: &str) -> Option<(&'static str, u32)> {
+    let (class, limit) = match method {
+        "POST" | "PUT" | "DELETE" => ("write", config.rate_limit_per_min),
+        "GET" => ("read", config.rate_limit_get_per_min),
+        _ => return None,
+    };
+    
+    if limit == 0 { None } else { Some((class, limit)) }
+}
+
+/// Take one token from the client's bucket, refilling it at limit_per_min tokens per minute
+pub async fn check(client: &Client, class: &str, client_ip: &str, limit_per_min: u32) -> Result<RateDecision> {
+    let client_key = format!("{}#{}", class, client_ip);
+    let capacity = limit_per_min as f64;
+    
+    for _ in 0..MAX_BUCKET_ATTEMPTS {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let stored = db::get_rate_bucket(client, &client_key).await?;
+        let tokens = refilled_tokens(stored, now_ms, capacity);
+        
+        if tokens < 1.0 {
+            let retry_after_secs = retry_after_secs(tokens, capacity);
+            info!("Rate limited {}: retry after {}s", client_key, retry_after_secs);
+            return Ok(RateDecision::Limited { retry_after_secs });
+        }
+        
+        // The write is conditional on the refill time read above, so a concurrent
+        // request that charged the bucket first makes this one read it again
+        let expires_at = now_ms / 1000 + BUCKET_EXPIRY_SECS;
+        if db::put_rate_bucket(client, &client_key, tokens - 1.0, now_ms,
+                               stored.map(|(_, refilled_at)| refilled_at), expires_at).await? {
+            return Ok(RateDecision::Allowed);
+        }
+    }
+    
+    // Only a burst from the same client keeps losing the write, so treat it as over the limit
+    warn!("Rate limit bucket {} still contended after {} attempts", client_key, MAX_BUCKET_ATTEMPTS);
+    Ok(RateDecision::Limited { retry_after_secs: 1 })
+}
+
+// Helper to top up a stored (tokens, refilled_at_ms) bucket for the time since its last refill
+fn refilled_tokens(stored: Option<(f64, i64)>, now_ms: i64, capacity: f64) -> f64 {
+    match stored {
+        Some((tokens, refilled_at)) => (tokens + (now_ms - refilled_at).max(0) as f64 * capacity / 60_000.0).min(capacity),
+        None => capacity,
+    }
+}
+
+// Helper to compute whole seconds until the bucket holds one token again
+fn retry_after_secs(tokens: f64, capacity: f64) -> u64 {
+    ((1.0 - tokens) * 60.0 / capacity).ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refilled_tokens_accrue_per_minute_up_to_capacity() {
+        assert_eq!(refilled_tokens(None, 0, 30.0), 30.0);
+        assert_eq!(refilled_tokens(Some((0.0, 0)), 2_000, 30.0), 1.0);
+        assert_eq!(refilled_tokens(Some((29.5, 0)), 60_000, 30.0), 30.0);
+        // A refill time ahead of this Lambda's clock adds nothing
+        assert_eq!(refilled_tokens(Some((0.5, 5_000)), 0, 30.0), 0.5);
+    }
+
+    #[test]
+    fn retry_after_rounds_up_to_whole_seconds() {
+        assert_eq!(retry_after_secs(0.0, 30.0), 2);
+        assert_eq!(retry_after_secs(0.9, 30.0), 1);
+        assert_eq!(retry_after_secs(0.0, 1.0), 60);
+    }
+}
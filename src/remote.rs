@@ -0,0 +1,162 @@
+use std::fmt;
+use std::time::Duration;
+use reqwest::Url;
+use tracing::info;
+
+use crate::config;
+
+// Default time allowed for fetching a DICOM object by URL
+const DEFAULT_FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// Reasons a DICOM URL is refused or could not be fetched
+#[derive(Debug, PartialEq)]
+pub enum FetchError {
+    NotEnabled,
+    InvalidUrl(String),
+    HostNotAllowed(String),
+    TooLarge { limit: usize },
+    TimedOut,
+    Failed(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::NotEnabled => write!(f, "Ingest from URL is not enabled"),
+            FetchError::InvalidUrl(reason) => write!(f, "Invalid dicomUrl: {}", reason),
+            FetchError::HostNotAllowed(host) => write!(f, "Host '{}' is not in the allowed list", host),
+            FetchError::TooLarge { limit } => write!(f, "DICOM object exceeds the {} byte limit", limit),
+            FetchError::TimedOut => write!(f, "Timed out fetching the DICOM object"),
+            FetchError::Failed(reason) => write!(f, "Failed to fetch the DICOM object: {}", reason),
+        }
+    }
+}
+
+/// Hosts DICOM may be fetched from. Ingest from URL is disabled when DICOM_URL_ALLOWED_HOSTS is unset or empty.
+pub fn allowed_hosts() -> &'static [String] {
+    &config::get().dicom_url_allowed_hosts
+}
+
+/// Size cap on a DICOM object fetched by URL
+pub fn max_fetch_bytes() -> usize {
+    config::get().dicom_url_max_bytes
+}
+
+/// Check a URL against the allowlist. Only https is accepted, and a host matches an
+/// entry exactly; an entry starting with '.' also matches any subdomain of it.
+/// IP literals only ever match exactly, since they have no subdomains.
+pub fn check_url(url: &str, allowed_hosts: &[String]) -> Result<Url, FetchError> {
+    if allowed_hosts.is_empty() {
+        return Err(FetchError::NotEnabled);
+    }
+
+    let url = Url::parse(url.trim()).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+    if url.scheme() != "https" {
+        return Err(FetchError::InvalidUrl("only https URLs are accepted".to_string()));
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(FetchError::InvalidUrl("credentials in the URL are not accepted".to_string()));
+    }
+
+    let host = url.host_str()
+        .ok_or_else(|| FetchError::InvalidUrl("missing host".to_string()))?
+        .to_lowercase();
+    let is_domain = host.trim_start_matches('[').trim_end_matches(']').parse::<std::net::IpAddr>().is_err();
+    let allowed = allowed_hosts.iter().any(|entry| match entry.strip_prefix('.') {
+        Some(domain) => is_domain && (host == domain || host.ends_with(entry.as_str())),
+        None => host == *entry,
+    });
+    if !allowed {
+        return Err(FetchError::HostNotAllowed(host));
+    }
+
+    Ok(url)
+}
+
+/// Download an object, stopping as soon as it grows past the limit.
+/// Redirects are not followed, since they could lead off the allowed hosts.
+pub async fn fetch(url: Url, limit: usize) -> Result<Vec<u8>, FetchError> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(DEFAULT_FETCH_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| FetchError::Failed(e.to_string()))?;
+
+    // reqwest's errors print the full URL, which for a presigned link includes its
+    // signature; only the host is allowed into logs and responses
+    let failed = |e: reqwest::Error| if e.is_timeout() {
+        FetchError::TimedOut
+    } else {
+        FetchError::Failed(e.without_url().to_string())
+    };
+
+    let mut response = client.get(url.clone()).send().await.map_err(failed)?;
+    if !response.status().is_success() {
+        return Err(FetchError::Failed(format!("source returned {}", response.status())));
+    }
+    if response.content_length().is_some_and(|length| length > limit as u64) {
+        return Err(FetchError::TooLarge { limit });
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(failed)? {
+        if data.len() + chunk.len() > limit {
+            return Err(FetchError::TooLarge { limit });
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    info!("Fetched {} bytes from {}", data.len(), url.host_str().unwrap_or_default());
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|entry| entry.to_string()).collect()
+    }
+
+    #[test]
+    fn check_url_requires_an_allowlist() {
+        assert_eq!(check_url("https://pacs.example.com/a.dcm", &[]), Err(FetchError::NotEnabled));
+    }
+
+    #[test]
+    fn check_url_accepts_only_https() {
+        let allowed = hosts(&["pacs.example.com"]);
+        assert!(check_url("https://pacs.example.com/a.dcm", &allowed).is_ok());
+        for url in ["http://pacs.example.com/a.dcm", "ftp://pacs.example.com/a.dcm", "file:///etc/passwd"] {
+            assert!(matches!(check_url(url, &allowed), Err(FetchError::InvalidUrl(_))), "{}", url);
+        }
+        assert!(matches!(check_url("https://user:pw@pacs.example.com/a.dcm", &allowed), Err(FetchError::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn check_url_matches_exact_hosts() {
+        let allowed = hosts(&["pacs.example.com"]);
+        assert!(check_url("https://PACS.example.com/a.dcm", &allowed).is_ok());
+        assert_eq!(check_url("https://sub.pacs.example.com/a.dcm", &allowed),
+                   Err(FetchError::HostNotAllowed("sub.pacs.example.com".to_string())));
+        assert!(check_url("https://pacs.example.com.evil.net/a.dcm", &allowed).is_err());
+    }
+
+    #[test]
+    fn check_url_matches_subdomains_of_dot_entries() {
+        let allowed = hosts(&[".example.com"]);
+        assert!(check_url("https://example.com/a.dcm", &allowed).is_ok());
+        assert!(check_url("https://a.b.example.com/a.dcm", &allowed).is_ok());
+        assert!(check_url("https://badexample.com/a.dcm", &allowed).is_err());
+    }
+
+    #[test]
+    fn check_url_matches_ip_literals_exactly() {
+        let allowed = hosts(&["10.0.0.5", ".0.0.5", "[::1]"]);
+        assert!(check_url("https://10.0.0.5/a.dcm", &allowed).is_ok());
+        assert!(check_url("https://[::1]/a.dcm", &allowed).is_ok());
+        assert!(check_url("https://10.0.0.6/a.dcm", &allowed).is_err());
+        assert!(check_url("https://192.0.0.5/a.dcm", &allowed).is_err());
+        assert!(check_url("https://169.254.169.254/latest/meta-data", &allowed).is_err());
+    }
+}
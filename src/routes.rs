@@ -4,35 +4,129 @@ use lambda_runtime::Error as LambdaError;
 use tracing::{error, info, debug, warn};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use uuid::Uuid;
-use std::env;
 
-use crate::api::response::{Response, create_cors_headers, not_found, bad_request, server_error};
-use crate::models::{ApiResponse, Case, DicomMetadata, CaseUpload, SeriesInfo};
+use crate::api::response::{Response, create_cors_headers, not_found, bad_request, conflict, server_error, gateway_timeout, bad_gateway, unsupported_media_type, unprocessable, payload_too_large, forbidden};
+use crate::models::{ApiResponse, Case, DicomMetadata, CaseUpload, CaseUrlUpload, SeriesInfo, PresignedFile, KeyImageUpdate, CasePreview, ProcessingReport, CaseUpdate, EditRecord, GcReport, ImagePage, ThumbnailSprite, SpriteCoordinates, StagingRecord, StagingStage, CaseSummary, ReindexReport, SplitReport, ImportReport, TagCount, BulkTagRequest, BulkTagResult, VersionInfo, ComparedCase, Attachment, AttachmentUpload, CaseAuditReport, DuplicateStudyResponse, ShareRequest, ShareLink, ImageMove, IncompleteCase, CleanupReport, SeriesWindow, WindowInstance};
+use crate::config;
 use crate::db;
 use crate::s3;
+use crate::staging;
 use crate::telemetry;
+use crate::remote::{self, FetchError};
+use crate::share;
+use crate::sr;
+use crate::taxonomy;
+use crate::upstream::{self, with_timeout};
+
+// Lambda caps responses at 6MB and binary bodies grow by a third when base64 encoded
+const MAX_INLINE_RESPONSE_BYTES: i64 = 4 * 1024 * 1024;
+
+// How long presigned download links stay valid
+const PRESIGNED_URL_EXPIRY_SECS: u64 = 900;
+
+// Page sizes for the image manifest
+const DEFAULT_IMAGE_PAGE_SIZE: usize = 50;
+const MAX_IMAGE_PAGE_SIZE: usize = 500;
+
+// Size of the most viewed ranking
+const DEFAULT_POPULAR_LIMIT: usize = 10;
+const MAX_POPULAR_LIMIT: usize = 100;
+
+// Sprite sheet bounds: cell edge in pixels and the most series packed into one sheet
+const DEFAULT_SPRITE_CELL_SIZE: u32 = 128;
+const MIN_SPRITE_CELL_SIZE: u32 = 32;
+const MAX_SPRITE_CELLS: usize = 64;
 
 // Import specific functions from dicom module
 use crate::dicom::ensure_dicom_dir_exists;
-use crate::dicom::process_study_data;
+use crate::dicom::{process_study_data, ParseStrategy, StudyParseResult};
 use crate::dicom::extract_metadata;
+use crate::dicom::{is_valid_uid, is_valid_image_id};
+use crate::dicom::{render_thumbnail, render_frame, ImageFormat, WindowOverride, THUMBNAIL_SIZE};
+use crate::dicom::CineBuilder;
+use crate::dicom::render_sprite_sheet;
+use crate::dicom::{dicom_json_sidecars, split_instances};
+use crate::dicom::anonymize_upload;
+use crate::dicom::{dicom_error, DicomError};
+use crate::api::request::{Request, is_valid_case_id};
+
+// Map a DICOM read failure onto the status that tells the client what to fix
+fn dicom_error_response(err: &anyhow::Error) -> Result<Response, LambdaError> {
+    match dicom_error(err) {
+        Some(DicomError::NotDicom) => unsupported_media_type("File is not a DICOM object"),
+        Some(DicomError::UnsupportedTransferSyntax(uid)) => {
+            unsupported_media_type(&format!("Unsupported DICOM transfer syntax: {}", uid))
+        },
+        Some(DicomError::MissingRequiredTag(tag)) => {
+            unprocessable(&format!("DICOM file is missing required tag {}", tag))
+        },
+        Some(DicomError::Parse(message)) => bad_request(&format!("Invalid DICOM file: {}", message)),
+        Some(DicomError::Io(_)) => server_error("Failed to read DICOM data"),
+        Some(too_many @ DicomError::TooManyInstances { .. }) => payload_too_large(&too_many.to_string()),
+        Some(out_of_range @ DicomError::FrameOutOfRange { .. }) => bad_request(&out_of_range.to_string()),
+        Some(malformed @ DicomError::Malformed(_)) => bad_request(&malformed.to_string()),
+        Some(no_space @ DicomError::InsufficientTempSpace { .. }) => payload_too_large(&no_space.to_string()),
+        None => bad_request(&format!("Invalid DICOM file: {}", err)),
+    }
+}
+
+// The body of a mutating request. API Gateway can deliver an empty string instead of
+// no body, so blank bodies get the same "Missing request body" error as absent ones.
+fn require_body(body: &Option<String>) -> Result<&str, Result<Response, LambdaError>> {
+    match body.as_deref() {
+        Some(body) if !body.trim().is_empty() => Ok(body),
+        _ => Err(bad_request("Missing request body")),
+    }
+}
+
+// Check whether a DICOM failure should refuse the upload rather than fall back:
+// the per-upload instance limit, data corrupt enough to crash the parser, or
+// an upload too large for the temporary disk
+fn is_refused_upload(err: &anyhow::Error) -> bool {
+    matches!(dicom_error(err), Some(
+        DicomError::TooManyInstances { .. } | DicomError::Malformed(_) | DicomError::InsufficientTempSpace { .. }
+    ))
+}
 
 // Frontend routes
 pub mod frontend {
     use super::*;
 
+    // Page served for client-side routes that have no object of their own
+    const SPA_INDEX_PATH: &str = "/index.html";
+
+    // Extensionless misses fall back to the SPA index unless SPA_FALLBACK is false
+    fn spa_fallback_enabled() -> bool {
+        config::get().spa_fallback
+    }
+
     pub async fn serve_frontend(s3_client: &S3Client, path: &str) -> Result<Response, LambdaError> {
-        let bucket_name = env::var("S3_BUCKET").unwrap_or_else(|_| "radiology-teaching-files".to_string());
+        let response = serve_frontend_file(s3_client, path).await?;
+        
+        // Deep links such as /cases/abc are routes of the single-page app, not files; a missing
+        // asset with an extension (e.g. a stale .js bundle) stays a real 404
+        let last_segment = path.rsplit('/').next().unwrap_or_default();
+        if response.status_code == 404 && !last_segment.contains('.') && spa_fallback_enabled() {
+            info!("No frontend file for {}, serving the SPA index", path);
+            return serve_frontend_file(s3_client, SPA_INDEX_PATH).await;
+        }
+        
+        Ok(response)
+    }
+
+    // Helper to return one file from the frontend prefix of the bucket
+    async fn serve_frontend_file(s3_client: &S3Client, path: &str) -> Result<Response, LambdaError> {
+        let bucket_name = &config::get().bucket;
         let key = format!("frontend/{}", path.trim_start_matches('/'));
         info!("Serving frontend file: {}/{}", bucket_name, key);
         
-        match s3_client.get_object()
+        match with_timeout("s3:get_object", s3_client.get_object()
             .bucket(bucket_name)
             .key(&key)
-            .send()
+            .send())
             .await {
             Ok(output) => {
-                let body = output.body.collect().await?.into_bytes();
+                let body = with_timeout("s3:get_object_body", output.body.collect()).await?.into_bytes();
                 let content_type = match path.split('.').last() {
                     Some("html") => "text/html; charset=utf-8",
                     Some("js") => "application/javascript; charset=utf-8",
@@ -55,6 +149,10 @@ pub mod frontend {
                     body: BASE64.encode(body),
                 })
             }
+            Err(e) if upstream::is_timeout(&e) => {
+                error!("Frontend file read timed out: {:?} - {}", key, e);
+                gateway_timeout("Upstream timeout")
+            }
             Err(e) => {
                 error!("Frontend file read error: {:?} - {}", key, e);
                 not_found("File Not Found")
@@ -68,11 +166,304 @@ pub mod cases {
     use super::*;
     use serde::Deserialize;
 
-    // GET /api/cases - List all cases
-    pub async fn list_cases(db_client: &DynamoDbClient) -> Result<Response, LambdaError> {
-        match db::list_cases(db_client).await? {
-            cases => Ok(Response::new(200, ApiResponse::success(cases))?)
+    // GET /api/cases - List cases, optionally filtered by modality, tag or modified_since,
+    // and with ?dedupe=study collapsed to one case per study. At most MAX_LIST_ITEMS come
+    // back at once; X-Next-Cursor then names the ?cursor= that continues the listing.
+    pub async fn list_cases(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        let wants_csv = request.query_param("format") == Some("csv")
+            || request.header("Accept").is_some_and(|accept| {
+                accept.split(',').any(|media| media.trim().starts_with("text/csv"))
+            });
+        
+        // ?modality=CT&tag=trauma&tag=chest - every given tag must be present
+        let modality = request.query_param("modality").map(str::trim).filter(|m| !m.is_empty());
+        let tags: Vec<String> = request.query_params("tag").into_iter()
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        
+        // ?modified_since=2025-02-01T00:00:00Z - only cases saved after the timestamp, for client sync
+        let modified_since = match request.query_param("modified_since") {
+            Some(value) => match chrono::DateTime::parse_from_rfc3339(value.trim()) {
+                Ok(since) => Some(since),
+                Err(_) => return bad_request("modified_since must be an RFC 3339 timestamp"),
+            },
+            None => None,
+        };
+        
+        // ?sort=title|modality|created_at&order=asc|desc, newest first by default
+        let sort = request.query_param("sort").map(str::trim).unwrap_or("created_at");
+        if !CASE_SORT_FIELDS.contains(&sort) {
+            return bad_request(&format!("sort must be one of: {}", CASE_SORT_FIELDS.join(", ")));
+        }
+        let descending = match request.query_param("order").map(str::trim) {
+            Some("asc") => false,
+            Some("desc") => true,
+            None => sort == "created_at",
+            Some(_) => return bad_request("order must be asc or desc"),
+        };
+        
+        // ?dedupe=study - show only the most recently updated case of each study
+        let dedupe = match request.query_param("dedupe").map(str::trim) {
+            None => false,
+            Some("study") => true,
+            Some(_) => return bad_request("dedupe must be study"),
+        };
+        
+        // ?cursor={case_id} - continue a listing from the X-Next-Cursor of the previous response
+        let cursor = request.query_param("cursor").map(str::trim).filter(|c| !c.is_empty());
+        if cursor.is_some_and(|case_id| !is_valid_case_id(case_id)) {
+            return bad_request("Invalid cursor: expected a case id");
+        }
+        
+        let keep = |case: &Case| {
+            matches_filters(case, modality, &tags)
+                && modified_since.is_none_or(|since| {
+                    chrono::DateTime::parse_from_rfc3339(&case.updated_at).is_ok_and(|updated| updated > since)
+                })
+        };
+        
+        // The CSV export is the whole catalog; the JSON listing is read a page at a time so a
+        // large catalog never has to fit in memory at once
+        let (mut cases, next_cursor) = if wants_csv {
+            let mut cases = db::list_cases(db_client).await?;
+            cases.retain(|case| keep(case));
+            (cases, None)
+        } else {
+            list_case_page(db_client, cursor, max_list_items(), keep).await?
+        };
+        info!("Listing {} cases for modality={:?} tags={:?} modified_since={:?}, next cursor {:?}",
+              cases.len(), modality, tags, modified_since, next_cursor);
+        
+        // Syncing clients also need the cases deleted since then, which no longer appear in the listing
+        let deleted_ids = match modified_since {
+            Some(since) if !wants_csv => Some(db::list_deleted_case_ids(db_client, since.with_timezone(&chrono::Utc)).await?),
+            _ => None,
+        };
+        
+        // Sorting and ?dedupe=study apply within the returned page
+        let collapsed = dedupe.then(|| {
+            let collapsed = dedupe_by_study(&mut cases);
+            info!("Collapsed {} cases sharing a study", collapsed);
+            collapsed
+        });
+        sort_cases(&mut cases, sort, descending);
+        
+        let mut response = if wants_csv {
+            info!("Exporting {} cases as CSV", cases.len());
+            let mut response = Response::new(200, "")?
+                .with_content_type("text/csv; charset=utf-8")
+                .into_text(cases_to_csv(&cases));
+            response.headers.insert("Content-Disposition".to_string(),
+                                    "attachment; filename=\"cases.csv\"".to_string());
+            response
+        } else {
+            let mut body = ApiResponse::success(cases);
+            if let Some(deleted_ids) = deleted_ids {
+                body = body.with_deleted_ids(deleted_ids);
+            }
+            Response::new(200, body)?
+        };
+        
+        if let Some(next_cursor) = next_cursor {
+            response.headers.insert("X-Next-Cursor".to_string(), next_cursor);
+        }
+        if let Some(collapsed) = collapsed {
+            response.headers.insert("X-Collapsed-Count".to_string(), collapsed.to_string());
+        }
+        Ok(response)
+    }
+
+    // Helper to keep only the most recently updated case of each study, returning how many
+    // were dropped. Cases without a study UID are never collapsed.
+    fn dedupe_by_study(cases: &mut Vec<Case>) -> usize {
+        let last_saved = |case: &Case| {
+            let updated = if case.updated_at.is_empty() { &case.created_at } else { &case.updated_at };
+            chrono::DateTime::parse_from_rfc3339(updated).ok()
+        };
+        
+        let mut newest: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+        for (index, case) in cases.iter().enumerate() {
+            if case.study_instance_uid.is_empty() {
+                continue;
+            }
+            newest.entry(&case.study_instance_uid)
+                .and_modify(|kept| if last_saved(case) > last_saved(&cases[*kept]) { *kept = index })
+                .or_insert(index);
+        }
+        
+        let keep: std::collections::HashSet<usize> = newest.into_values().collect();
+        let before = cases.len();
+        let mut index = 0;
+        cases.retain(|case| {
+            let kept = case.study_instance_uid.is_empty() || keep.contains(&index);
+            index += 1;
+            kept
+        });
+        
+        before - cases.len()
+    }
+
+    // Most full cases one listing returns (MAX_LIST_ITEMS)
+    fn max_list_items() -> usize {
+        config::get().max_list_items
+    }
+
+    // Items read per scan while filling a page of the case listing
+    const LIST_SCAN_PAGE_SIZE: i32 = 100;
+
+    // Helper to read cases in table order, starting after the cursor, until limit of them pass
+    // the filter. Returns them with the cursor to continue from while the table has more.
+    async fn list_case_page(
+        db_client: &DynamoDbClient,
+        cursor: Option<&str>,
+        limit: usize,
+        keep: impl Fn(&Case) -> bool
+    ) -> Result<(Vec<Case>, Option<String>), LambdaError> {
+        let mut cases = Vec::new();
+        let mut cursor = cursor.map(str::to_string);
+        loop {
+            let page = db::scan_cases_page(db_client, cursor.as_deref(), LIST_SCAN_PAGE_SIZE).await?;
+            if let Some(resume) = fill_page(&mut cases, page.cases, limit, &keep) {
+                return Ok((cases, Some(resume)));
+            }
+            if page.next.is_none() || cases.len() >= limit {
+                return Ok((cases, page.next));
+            }
+            cursor = page.next;
+        }
+    }
+
+    // Helper to add scanned cases that pass the filter until limit is reached. Returns the id of
+    // the last case taken when the page filled up before every scanned case was looked at.
+    fn fill_page(cases: &mut Vec<Case>, scanned: Vec<Case>, limit: usize, keep: impl Fn(&Case) -> bool) -> Option<String> {
+        let mut scanned = scanned.into_iter().peekable();
+        while let Some(case) = scanned.next() {
+            if !keep(&case) {
+                continue;
+            }
+            cases.push(case);
+            if cases.len() >= limit {
+                return scanned.peek().and(cases.last()).map(|case| case.case_id.clone());
+            }
+        }
+        None
+    }
+
+    // Fields the case listing can be sorted by
+    const CASE_SORT_FIELDS: &[&str] = &["created_at", "title", "modality"];
+
+    // Helper to order the case listing. Pages are read in table (scan) order, so every sort,
+    // newest first included, is done here. Timestamps are parsed so mixed precision sorts
+    // chronologically; unparseable ones go last and case_id breaks ties for a stable order.
+    fn sort_cases(cases: &mut [Case], field: &str, descending: bool) {
+        let direction = |ordering: std::cmp::Ordering| if descending { ordering.reverse() } else { ordering };
+        
+        match field {
+            "title" => cases.sort_by(|a, b| direction(a.title.to_lowercase().cmp(&b.title.to_lowercase()))),
+            "modality" => cases.sort_by(|a, b| direction(a.modality.to_lowercase().cmp(&b.modality.to_lowercase()))),
+            _ => cases.sort_by_cached_key(|case| {
+                let created = chrono::DateTime::parse_from_rfc3339(&case.created_at).ok()
+                    .map(|created| created.timestamp_micros())
+                    .map(|micros| if descending { -micros } else { micros });
+                (created.is_none(), created, case.case_id.clone())
+            }),
+        }
+    }
+
+    // Helper to check a case against the listing filters, ignoring case
+    fn matches_filters(case: &Case, modality: Option<&str>, tags: &[String]) -> bool {
+        if let Some(modality) = modality {
+            if !case.modality.eq_ignore_ascii_case(modality) {
+                return false;
+            }
+        }
+        
+        tags.iter().all(|wanted| case.tags.iter().any(|tag| tag.to_lowercase() == *wanted))
+    }
+
+    // Helper to render the case listing as CSV for spreadsheet export
+    fn cases_to_csv(cases: &[Case]) -> String {
+        // Quote every field so commas, quotes and newlines in free text survive. Text that a
+        // spreadsheet would run as a formula gets a leading ' so it opens as plain text.
+        let field = |value: &str| {
+            let guard = if value.starts_with(['=', '+', '-', '@']) { "'" } else { "" };
+            format!("\"{}{}\"", guard, value.replace('"', "\"\""))
+        };
+        
+        let mut csv = String::from("case_id,title,modality,anatomy,diagnosis,created_at,num_images\r\n");
+        for case in cases {
+            let row = [
+                field(&case.case_id),
+                field(&case.title),
+                field(&case.modality),
+                field(&case.anatomy),
+                field(&case.diagnosis),
+                field(&case.created_at),
+                case.ordered_image_ids().len().to_string(),
+            ];
+            csv.push_str(&row.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    // GET /api/cases/incomplete - Cases with missing teaching fields or placeholder DICOM metadata
+    pub async fn list_incomplete_cases(db_client: &DynamoDbClient) -> Result<Response, LambdaError> {
+        let incomplete: Vec<IncompleteCase> = db::list_cases(db_client).await?
+            .into_iter()
+            .filter_map(|case| {
+                let missing = case.missing_fields();
+                if missing.is_empty() {
+                    None
+                } else {
+                    Some(IncompleteCase { case_id: case.case_id, title: case.title, missing })
+                }
+            })
+            .collect();
+        
+        info!("Found {} incomplete cases", incomplete.len());
+        Response::new(200, ApiResponse::success(incomplete))
+    }
+
+    // GET /api/cases/by-anatomy[?modality=CT] - Case summaries grouped by canonical anatomy
+    pub async fn list_cases_by_anatomy(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        let modality = request.query_param("modality").map(str::trim).filter(|m| !m.is_empty());
+        
+        // Stored anatomy predates the taxonomy for older cases, so normalize again to collapse
+        // variants; a side from the DICOM laterality does not make a region of its own
+        let mut groups: std::collections::BTreeMap<String, Vec<CaseSummary>> = std::collections::BTreeMap::new();
+        for case in db::list_cases(db_client).await? {
+            if !matches_filters(&case, modality, &[]) {
+                continue;
+            }
+            
+            let anatomy = match taxonomy::region_of(&taxonomy::normalize_anatomy(&case.anatomy).value) {
+                "" => "Unknown".to_string(),
+                region => region.to_string(),
+            };
+            groups.entry(anatomy).or_default().push(CaseSummary::from(&case));
         }
+        
+        info!("Grouped cases into {} anatomy regions (modality={:?})", groups.len(), modality);
+        Response::new(200, ApiResponse::success(groups))
+    }
+
+    // GET /api/cases/popular?limit=10 - Most viewed cases first
+    pub async fn list_popular_cases(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        let limit = match request.query_param("limit").map(str::parse::<usize>) {
+            None => DEFAULT_POPULAR_LIMIT,
+            Some(Ok(limit)) if limit > 0 => limit.min(MAX_POPULAR_LIMIT),
+            Some(_) => return bad_request("limit must be a positive integer"),
+        };
+        
+        // Ties go to the newer case
+        let mut cases = db::list_cases(db_client).await?;
+        cases.sort_by(|a, b| b.view_count.cmp(&a.view_count).then_with(|| b.created_at.cmp(&a.created_at)));
+        cases.truncate(limit);
+        
+        info!("Returning {} most viewed cases", cases.len());
+        Response::new(200, ApiResponse::success(cases))
     }
 
     // GET /api/cases/{id} - Get case by ID
@@ -80,6 +471,17 @@ pub mod cases {
         let case_id = path.trim_start_matches("/api/cases/");
         info!("Fetching case by ID: {}", case_id);
         
+        // The view count is bumped on a detached task so the response never waits on it. A
+        // failed increment is only logged, and one still in flight when the invocation ends
+        // finishes when the environment is next thawed
+        let counter = db_client.clone();
+        let counted_id = case_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = db::increment_view_count(&counter, &counted_id).await {
+                warn!("Failed to count view of case {}: {:?}", counted_id, e);
+            }
+        });
+        
         match db::get_case(db_client, case_id).await? {
             Some(case) => {
                 Ok(Response::new(200, ApiResponse::success(case))?)
@@ -91,330 +493,308 @@ pub mod cases {
         }
     }
 
-    // POST /api/cases - Create a new case
-    pub async fn create_case(
-        db_client: &DynamoDbClient, 
-        s3_client: &S3Client, 
-        xray_client: &aws_sdk_xray::Client,
-        body: &Option<String>
-    ) -> Result<Response, LambdaError> {
-        telemetry::send_xray_trace(xray_client, "create-case-start").await;
-        
-        if let Some(body) = body {
-            info!("Processing new case submission");
-            debug!("Received POST body length: {}", body.len());
-            
-            // Parse the case upload request
-            let case_upload: CaseUpload = match serde_json::from_str::<CaseUpload>(body) {
-                Ok(upload) => {
-                    info!("JSON parsed successfully");
-                    debug!("Title: {}", upload.title);
-                    debug!("Modality value: '{}'", upload.modality);
-                    upload
-                },
-                Err(e) => {
-                    error!("Failed to parse JSON: {:?}", e);
-                    return bad_request(&format!("Invalid JSON: {}", e));
-                }
-            };
-            
-            // Special handling for test cases or problematic data
-            let is_test_data = case_upload.dicom_file == "QVRFTVBJT1JSVEVS=" || 
-                              case_upload.dicom_file.starts_with("QVRFTVBJT1JSVEVS");
-            
-            // Decode or create test DICOM data
-            let dicom_data = if is_test_data {
-                info!("Detected test case, using dummy DICOM data");
-                vec![0u8; 10] // Dummy data
-            } else {
-                // Decode the base64 data
-                match BASE64.decode(&case_upload.dicom_file) {
-                    Ok(data) => {
-                        info!("Successfully decoded base64 data. Size: {} bytes", data.len());
-                        data
-                    },
-                    Err(e) => {
-                        error!("Error decoding base64: {:?}", e);
-                        return bad_request(&format!("Invalid base64 encoding: {}", e));
-                    }
-                }
-            };
-            
-            // Ensure DICOM directory exists
-            if let Err(e) = ensure_dicom_dir_exists() {
-                warn!("Failed to create DICOM directory: {:?}", e);
+    // GET /api/cases/compare?ids=a,b - Fetch cases side by side for a split viewer
+    pub async fn compare_cases(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        let mut case_ids: Vec<String> = Vec::new();
+        for case_id in request.query_params("ids").into_iter().flat_map(|value| value.split(',')) {
+            let case_id = case_id.trim();
+            if case_id.is_empty() || case_ids.iter().any(|id| id == case_id) {
+                continue;
             }
-            
-            telemetry::send_xray_trace(xray_client, "dicom-extraction-start").await;
-            
-            // Process DICOM data
-            let metadata_list = process_dicom_data(&dicom_data, is_test_data, &case_upload.modality).await?;
-            
-            info!("DICOM processing complete. Found {} instances/series", metadata_list.len());
-            telemetry::send_xray_trace(xray_client, "dicom-extraction-complete").await;
-            
-            // Generate a new case ID
-            let case_id = Uuid::new_v4().to_string();
-            
-            // Group metadata by series
-            let mut series_map: std::collections::HashMap<String, Vec<&DicomMetadata>> = std::collections::HashMap::new();
-            for metadata in &metadata_list {
-                series_map.entry(metadata.series_instance_uid.clone())
-                    .or_insert_with(Vec::new)
-                    .push(metadata);
+            if !is_valid_case_id(case_id) {
+                return bad_request(&format!("Invalid case id: {}", case_id));
             }
-            
-            info!("Organized into {} unique series", series_map.len());
-            
-            // Upload to S3 if this isn't a test case
-            if !is_test_data {
-                telemetry::send_xray_trace(xray_client, "s3-upload-start").await;
-                
-                // Save the complete original file
-                let original_key = format!("dicom/{}/original.dcm", case_id);
-                
-                match s3::upload_file(s3_client, &original_key, dicom_data.clone()).await {
-                    Ok(_) => info!("Uploaded original DICOM file to S3: {}", original_key),
-                    Err(e) => error!("Error uploading original DICOM file: {:?}", e),
-                }
-                
-                // Register paths for individual instances
-                for metadata in &metadata_list {
-                    let instance_key = format!("dicom/{}/{}/{}.dcm", 
-                                             case_id, 
-                                             metadata.study_instance_uid,
-                                             metadata.sop_instance_uid);
-                    
-                    debug!("Registered instance in database: {}", instance_key);
-                }
-                
-                telemetry::send_xray_trace(xray_client, "s3-upload-complete").await;
+            case_ids.push(case_id.to_string());
+        }
+        
+        if case_ids.len() < 2 {
+            return bad_request("At least two distinct case ids are required in ids");
+        }
+        
+        let cases = db::get_cases(db_client, &case_ids).await?;
+        
+        let missing: Vec<&str> = case_ids.iter()
+            .filter(|case_id| !cases.iter().any(|case| &case.case_id == *case_id))
+            .map(|case_id| case_id.as_str())
+            .collect();
+        if !missing.is_empty() {
+            error!("Cases not found for comparison: {:?}", missing);
+            return not_found(&format!("Cases not found: {}", missing.join(", ")));
+        }
+        
+        // Keep the order the ids were requested in; the batch read does not preserve it
+        let compared: Vec<ComparedCase> = case_ids.iter()
+            .filter_map(|case_id| cases.iter().find(|case| &case.case_id == case_id))
+            .map(|case| ComparedCase {
+                key_image_sop: case.key_image_sop.clone()
+                    .or_else(|| case.ordered_image_ids().into_iter().next()),
+                case: case.clone(),
+            })
+            .collect();
+        
+        info!("Comparing {} cases", compared.len());
+        Response::new(200, ApiResponse::success(compared))
+    }
+
+    // GET /api/cases/{id}/sr - Export the teaching findings and diagnosis as a DICOM Basic Text SR
+    pub async fn get_case_sr(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/sr");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => return not_found(&format!("Case not found: {}", case_id)),
+        };
+        
+        match sr::build_text_sr(&case) {
+            Ok(data) => {
+                let response = Response::new(200, "")?.with_content_type("application/dicom");
+                Ok(response.into_binary_named(data, &format!("{}-sr.dcm", case_id)))
+            },
+            Err(e) => {
+                error!("Failed to build SR for case {}: {:?}", case_id, e);
+                server_error("Failed to build structured report")
             }
+        }
+    }
+
+    // GET /api/cases/{id}/original - Download the originally uploaded study bytes
+    pub async fn get_original(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/original");
+        info!("Fetching original upload for case: {}", case_id);
+        
+        if db::get_case_cached(db_client, case_id).await?.is_none() {
+            error!("Case not found: {}", case_id);
+            return not_found(&format!("Case not found: {}", case_id));
+        }
+        
+        // The original upload plus any files later added through add_images
+        let prefix = format!("dicom/{}/", case_id);
+        let mut originals: Vec<(String, i64)> = s3::list_files(s3_client, &prefix).await?
+            .into_iter()
+            .filter(|(key, _)| {
+                let name = key.trim_start_matches(&prefix);
+                name == "original.dcm" || (name.starts_with("additional_") && name.ends_with(".dcm"))
+            })
+            .collect();
+        
+        if originals.is_empty() {
+            warn!("No original upload stored for case: {}", case_id);
+            return not_found("Original upload not found");
+        }
+        
+        // Keep original.dcm first, followed by additional uploads
+        originals.sort_by_key(|(key, _)| (!key.ends_with("/original.dcm"), key.clone()));
+        
+        let total_size: i64 = originals.iter().map(|(_, size)| size).sum();
+        info!("Found {} original files totalling {} bytes", originals.len(), total_size);
+        
+        if total_size > MAX_INLINE_RESPONSE_BYTES {
+            info!("Original upload too large to return inline, presigning downloads");
+            let mut files = Vec::with_capacity(originals.len());
             
-            // Create SeriesInfo objects and collect image IDs
-            let (series_info_list, all_image_ids) = create_series_info(&series_map);
-            
-            // Use modality from the upload if provided, otherwise from the DICOM
-            let modality = if !case_upload.modality.is_empty() {
-                case_upload.modality.clone()
-            } else if !metadata_list.is_empty() && !metadata_list[0].modality.is_empty() {
-                metadata_list[0].modality.clone()
-            } else {
-                "Unknown".to_string()
-            };
-            
-            // Create the case with all collected information
-            let case = Case {
-                case_id: case_id.clone(),
-                title: case_upload.title,
-                description: case_upload.description,
-                modality,
-                anatomy: case_upload.anatomy,
-                diagnosis: case_upload.diagnosis,
-                findings: case_upload.findings,
-                tags: case_upload.tags,
-                image_ids: all_image_ids,
-                created_at: chrono::Utc::now().to_rfc3339(),
-                
-                // Use metadata from the first instance
-                study_instance_uid: metadata_list[0].study_instance_uid.clone(),
-                series_instance_uid: metadata_list[0].series_instance_uid.clone(),
-                study_date: metadata_list[0].study_date.clone(),
-                study_description: metadata_list[0].study_description.clone(),
-                patient_id: metadata_list[0].patient_id.clone(),
-                patient_name: metadata_list[0].patient_name.clone(),
+            for (key, size) in &originals {
+                let url = s3::presign_download(
+                    s3_client,
+                    key,
+                    std::time::Duration::from_secs(PRESIGNED_URL_EXPIRY_SECS)
+                ).await?;
                 
-                // Include all series information
-                series: series_info_list,
-            };
-            
-            // Save to DynamoDB
-            telemetry::send_xray_trace(xray_client, "dynamodb-save-start").await;
-            
-            match db::save_case(db_client, &case).await {
-                Ok(_) => info!("DynamoDB save successful"),
-                Err(e) => error!("DynamoDB save error: {:?}", e),
+                files.push(PresignedFile {
+                    name: key.trim_start_matches(&prefix).to_string(),
+                    size: *size,
+                    url,
+                });
             }
             
-            telemetry::send_xray_trace(xray_client, "dynamodb-save-complete").await;
-            telemetry::send_xray_trace(xray_client, "create-case-complete").await;
+            return Response::new(200, ApiResponse::success(files));
+        }
+        
+        if originals.len() == 1 {
+            let dicom_data = s3::download_file(s3_client, &originals[0].0).await?;
             
-            Ok(Response::new(201, ApiResponse::success(case))?)
-        } else {
-            error!("Missing request body in POST");
-            bad_request("Missing request body")
+            let response = Response::new(200, "")?.with_content_type("application/dicom");
+            return Ok(response.into_binary_named(dicom_data, &format!("{}-original.dcm", case_id)));
         }
+        
+        // Several uploads were made for this case, bundle them together
+        let mut files = Vec::with_capacity(originals.len());
+        for (key, _) in &originals {
+            let data = s3::download_file(s3_client, key).await?;
+            files.push((key.trim_start_matches(&prefix).to_string(), data));
+        }
+        
+        let archive = match build_zip(files) {
+            Ok(archive) => archive,
+            Err(e) => {
+                error!("Failed to bundle original uploads: {:?}", e);
+                return server_error(&format!("Failed to bundle original uploads: {}", e));
+            }
+        };
+        
+        let response = Response::new(200, "")?.with_content_type("application/zip");
+        Ok(response.into_binary_named(archive, &format!("{}-original.zip", case_id)))
     }
 
-    // POST /api/cases/{id}/images - Add images to existing case
-    pub async fn add_images(
-        db_client: &DynamoDbClient, 
+    // DELETE /api/cases/{id} - Remove a case and every S3 object it owns. Retrying after an
+    // incomplete cleanup finishes the purge even though the item itself is already gone.
+    pub async fn delete_case(
+        db_client: &DynamoDbClient,
         s3_client: &S3Client,
-        xray_client: &aws_sdk_xray::Client, 
-        path: &str, 
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches('/');
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        info!("Deleting case: {}", case_id);
+        
+        if db::get_case(db_client, case_id).await?.is_none() {
+            // Either the case never existed or an earlier delete left objects behind;
+            // the purge is idempotent, so finish it and report what it found
+            let report = purge_case_objects(s3_client, case_id).await;
+            if report.deleted == 0 && report.is_complete() {
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+            info!("Case {} was already deleted, purged {} leftover S3 objects", case_id, report.deleted);
+            return cleanup_response(case_id, report);
+        }
+        
+        // The item goes first so the case stops being listed even if S3 cleanup falls short
+        db::delete_case(db_client, case_id).await?;
+        
+        let report = purge_case_objects(s3_client, case_id).await;
+        info!("Deleted case {}", case_id);
+        cleanup_response(case_id, report)
+    }
+    
+    // Helper to answer a delete with its cleanup report: 200 when every object is gone,
+    // otherwise 500 so the caller knows to retry the DELETE
+    fn cleanup_response(case_id: &str, report: CleanupReport) -> Result<Response, LambdaError> {
+        if !report.is_complete() {
+            error!("Case {} deleted but {} S3 objects and {} prefixes were left behind",
+                   case_id, report.failed.len(), report.unlisted_prefixes.len());
+            let message = format!("Case {} was deleted but its S3 cleanup was incomplete", case_id);
+            return Response::new(500, ApiResponse::failure(report, message));
+        }
+        
+        info!("Removed {} S3 objects of case {}", report.deleted, case_id);
+        Response::new(200, ApiResponse::success(report))
+    }
+
+    // PUT /api/cases/{id} - Update the teaching fields of a case
+    pub async fn update_case(
+        db_client: &DynamoDbClient,
+        path: &str,
         body: &Option<String>
     ) -> Result<Response, LambdaError> {
-        // Extract case_id from path: format is /api/cases/{case_id}/images
-        let parts: Vec<&str> = path.split('/').collect();
-        if parts.len() < 5 {
-            return bad_request("Invalid URL format for adding images");
+        let case_id = path.trim_start_matches("/api/cases/");
+        info!("Updating case: {}", case_id);
+        
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body for case update");
+                return response;
+            }
+        };
+        
+        let mut update: CaseUpdate = match serde_json::from_str(body) {
+            Ok(update) => update,
+            Err(e) => {
+                error!("Error parsing case update JSON: {:?}", e);
+                return bad_request(&format!("Invalid JSON: {}", e));
+            }
+        };
+        
+        if let Err(field) = update.sanitize() {
+            error!("Required field is empty after sanitizing: {}", field);
+            return bad_request(&format!("Field '{}' must not be empty", field));
         }
         
-        let case_id = parts[3];
-        info!("Adding images to case: {}", case_id);
-        telemetry::send_xray_trace(xray_client, &format!("add-images-{}", case_id)).await;
+        let mut case = match db::get_case(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let now = chrono::Utc::now().to_rfc3339();
+        
+        // Changes to the teaching answer are recorded before they are applied
+        if let Some(diagnosis) = update.diagnosis {
+            if diagnosis != case.diagnosis {
+                case.edit_history.push(EditRecord {
+                    field: "diagnosis".to_string(),
+                    old: std::mem::replace(&mut case.diagnosis, diagnosis.clone()),
+                    new: diagnosis,
+                    at: now.clone(),
+                });
+            }
+        }
+        
+        if let Some(findings) = update.findings {
+            if findings != case.findings {
+                case.edit_history.push(EditRecord {
+                    field: "findings".to_string(),
+                    old: std::mem::replace(&mut case.findings, findings.clone()),
+                    new: findings,
+                    at: now,
+                });
+            }
+        }
+        
+        if let Some(title) = update.title {
+            case.title = title;
+        }
+        if let Some(description) = update.description {
+            case.description = description;
+        }
+        if let Some(modality) = update.modality {
+            case.modality = modality;
+        }
+        if let Some(tags) = update.tags {
+            case.tags = tags;
+        }
+        if let Some(anatomy) = update.anatomy {
+            let anatomy = taxonomy::normalize_anatomy(&anatomy);
+            if !anatomy.recognized {
+                warn!("Anatomy '{}' is not in the taxonomy, storing as entered", anatomy.value);
+            }
+            case.anatomy = anatomy.value;
+        }
+        
+        case.touch();
+        match db::save_case(db_client, &case).await {
+            Ok(_) => info!("Case updated: {}", case_id),
+            Err(e) if db::is_case_deleted(&e) => return not_found(&format!("Case not found: {}", case_id)),
+            Err(e) if upstream::is_timeout(&e) => {
+                error!("DynamoDB update timed out: {:?}", e);
+                return gateway_timeout("Upstream timeout");
+            },
+            Err(e) => {
+                error!("DynamoDB update error: {:?}", e);
+                return server_error(&format!("Failed to update case: {}", e));
+            }
+        }
+        
+        Response::new(200, ApiResponse::success(case))
+    }
+
+    // GET /api/cases/{id}/history - Edit history of the teaching answer
+    pub async fn get_history(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/history");
+        info!("Fetching edit history for case: {}", case_id);
         
-        // Verify the case exists
         match db::get_case(db_client, case_id).await? {
-            Some(mut existing_case) => {
-                // Case exists, now process the uploaded file
-                if let Some(body) = body {
-                    info!("Received request to add image to case: {}", case_id);
-                    
-                    // Parse the upload data
-                    #[derive(Deserialize)]
-                    struct ImageUpload {
-                        #[serde(rename = "dicomFile")]
-                        dicom_file: String,
-                    }
-                    
-                    let image_upload: ImageUpload = match serde_json::from_str(body) {
-                        Ok(upload) => upload,
-                        Err(e) => {
-                            error!("Error parsing image upload JSON: {:?}", e);
-                            return bad_request(&format!("Invalid JSON: {}", e));
-                        }
-                    };
-                    
-                    // Check if this is test data
-                    let is_test_data = image_upload.dicom_file == "QVRFTVBJT1JSVEVS=" || 
-                                       image_upload.dicom_file.starts_with("QVRFTVBJT1JSVEVS") ||
-                                       image_upload.dicom_file.starts_with("AA");
-                    
-                    // Ensure the DICOM directory exists
-                    if let Err(e) = ensure_dicom_dir_exists() {
-                        warn!("Failed to create DICOM directory: {:?}", e);
-                    }
-                    
-                    // Decode or create test DICOM data
-                    let dicom_data = if is_test_data {
-                        info!("Detected test data, using dummy data");
-                        vec![0u8; 10]
-                    } else {
-                        match BASE64.decode(&image_upload.dicom_file) {
-                            Ok(data) => {
-                                info!("Successfully decoded base64 data. Size: {} bytes", data.len());
-                                data
-                            },
-                            Err(e) => {
-                                error!("Error decoding base64: {:?}", e);
-                                return bad_request(&format!("Invalid base64 encoding: {}", e));
-                            }
-                        }
-                    };
-                    
-                    telemetry::send_xray_trace(xray_client, "dicom-processing").await;
-                    
-                    // Process the DICOM data
-                    let metadata_list = if is_test_data {
-                        // For test data, create a dummy metadata entry
-                        vec![
-                            DicomMetadata {
-                                sop_instance_uid: format!("1.2.3.4.5.6.7.8.9.{}", Uuid::new_v4()),
-                                modality: "CT".to_string(),
-                                study_instance_uid: existing_case.study_instance_uid.clone(),
-                                series_instance_uid: existing_case.series_instance_uid.clone(),
-                                patient_name: "TEST PATIENT".to_string(),
-                                patient_id: "TEST123".to_string(),
-                                study_date: "20250228".to_string(),
-                                study_description: "TEST STUDY".to_string(),
-                                series_description: "TEST SERIES".to_string(),
-                                instance_number: 1,
-                            }
-                        ]
-                    } else {
-                        // For real data, process all series in the study
-                        match process_study_data(&dicom_data) {
-                            Ok(metadata_vec) => {
-                                info!("Successfully extracted metadata for {} instances", metadata_vec.len());
-                                metadata_vec
-                            },
-                            Err(e) => {
-                                error!("Error processing DICOM study: {:?}", e);
-                                
-                                // Fallback to single extraction
-                                match extract_metadata(&dicom_data) {
-                                    Ok(metadata) => {
-                                        info!("Successfully extracted basic metadata");
-                                        vec![metadata]
-                                    },
-                                    Err(e) => {
-                                        error!("Error extracting metadata: {:?}", e);
-                                        return bad_request(&format!("Invalid DICOM file: {}", e));
-                                    }
-                                }
-                            }
-                        }
-                    };
-                    
-                    info!("Found {} instances in the additional DICOM data", metadata_list.len());
-                    
-                    // Group by series
-                    let mut series_map: std::collections::HashMap<String, Vec<&DicomMetadata>> = std::collections::HashMap::new();
-                    for metadata in &metadata_list {
-                        series_map.entry(metadata.series_instance_uid.clone())
-                            .or_insert_with(Vec::new)
-                            .push(metadata);
-                    }
-                    
-                    info!("New DICOM data contains {} series", series_map.len());
-                    
-                    // Upload to S3 if this isn't a test case
-                    if !is_test_data {
-                        telemetry::send_xray_trace(xray_client, &format!("s3-upload-additional-{}", case_id)).await;
-                        
-                        // First save the complete original file
-                        let original_key = format!("dicom/{}/additional_{}.dcm", 
-                                                 case_id, 
-                                                 Uuid::new_v4());
-                        
-                        match s3::upload_file(s3_client, &original_key, dicom_data.clone()).await {
-                            Ok(_) => info!("Uploaded additional DICOM file to S3: {}", original_key),
-                            Err(e) => error!("Error uploading additional DICOM file: {:?}", e),
-                        }
-                        
-                        // Also register paths for individual instances
-                        for metadata in &metadata_list {
-                            let instance_key = format!("dicom/{}/{}/{}.dcm", 
-                                                     case_id, 
-                                                     metadata.study_instance_uid,
-                                                     metadata.sop_instance_uid);
-                            
-                            debug!("Registered instance: {}", instance_key);
-                        }
-                    }
-                    
-                    // Update the case with new instances
-                    update_case_with_new_instances(&mut existing_case, &series_map);
-                    
-                    // Update the case in the database
-                    telemetry::send_xray_trace(xray_client, &format!("dynamodb-update-{}", case_id)).await;
-                    
-                    match db::save_case(db_client, &existing_case).await {
-                        Ok(_) => info!("DynamoDB update successful"),
-                        Err(e) => {
-                            error!("DynamoDB update error: {:?}", e);
-                            return server_error(&format!("Failed to update case: {}", e));
-                        }
-                    }
-                    
-                    // Return success response with updated case
-                    Ok(Response::new(200, ApiResponse::success(existing_case))?)
-                } else {
-                    error!("Missing request body for image upload");
-                    bad_request("Missing request body")
-                }
-            },
+            Some(case) => Response::new(200, ApiResponse::success(case.edit_history)),
             None => {
                 error!("Case not found: {}", case_id);
                 not_found(&format!("Case not found: {}", case_id))
@@ -422,168 +802,3336 @@ pub mod cases {
         }
     }
 
-    // Helper function for processing DICOM data
-    async fn process_dicom_data(
-        dicom_data: &[u8], 
-        is_test_data: bool, 
-        modality: &str
-    ) -> Result<Vec<DicomMetadata>, LambdaError> {
-        if is_test_data {
-            // For test data, create a dummy metadata entry
-            info!("Using dummy metadata for test case");
-            Ok(vec![
-                DicomMetadata {
-                    sop_instance_uid: "1.2.3.4.5.6.7.8.9.0".to_string(),
-                    modality: if !modality.is_empty() { modality.to_string() } else { "CT".to_string() },
-                    study_instance_uid: "1.2.3.4.5.6.7.8.9.1".to_string(),
-                    series_instance_uid: "1.2.3.4.5.6.7.8.9.2".to_string(),
-                    patient_name: "TEST PATIENT".to_string(),
-                    patient_id: "TEST123".to_string(),
-                    study_date: "20250228".to_string(),
-                    study_description: "TEST STUDY".to_string(),
-                    series_description: "TEST SERIES".to_string(),
-                    instance_number: 1,
+    // PUT /api/cases/{id}/key-image - Choose the key image for a case
+    pub async fn set_key_image(
+        db_client: &DynamoDbClient,
+        path: &str,
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/key-image");
+        info!("Setting key image for case: {}", case_id);
+        
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body for key image update");
+                return response;
+            }
+        };
+        
+        let update: KeyImageUpdate = match serde_json::from_str(body) {
+            Ok(update) => update,
+            Err(e) => {
+                error!("Error parsing key image JSON: {:?}", e);
+                return bad_request(&format!("Invalid JSON: {}", e));
+            }
+        };
+        
+        match db::get_case(db_client, case_id).await? {
+            Some(mut case) => {
+                if !case.contains_instance(&update.sop_instance_uid) {
+                    warn!("SOP {} is not part of case {}", update.sop_instance_uid, case_id);
+                    return bad_request(&format!("Image {} does not belong to case {}",
+                                                update.sop_instance_uid, case_id));
                 }
-            ])
-        } else {
-            // For real data, process the study to extract all series
-            match process_study_data(dicom_data) {
-                Ok(metadata_vec) => {
-                    info!("Successfully extracted metadata for {} series/instances", metadata_vec.len());
-                    Ok(metadata_vec)
-                },
-                Err(e) => {
-                    warn!("Error extracting metadata: {:?}, falling back to basic extraction", e);
-                    
-                    // Fallback to basic extraction
-                    match extract_metadata(dicom_data) {
-                        Ok(metadata) => {
-                            info!("Successfully extracted basic metadata");
-                            Ok(vec![metadata])
-                        },
-                        Err(e) => {
-                            error!("Error extracting basic metadata: {:?}, using default metadata", e);
-                            
-                            // Last resort: use default metadata
-                            Ok(vec![
-                                DicomMetadata {
-                                    sop_instance_uid: "unknown.1.2.3.4.5".to_string(),
-                                    modality: if !modality.is_empty() { 
-                                        modality.to_string() 
-                                    } else { 
-                                        "CT".to_string() 
-                                    },
-                                    study_instance_uid: "unknown.1.2.3".to_string(),
-                                    series_instance_uid: "unknown.1.2.3.4".to_string(),
-                                    patient_name: "Unknown Patient".to_string(),
-                                    patient_id: "Unknown ID".to_string(),
-                                    study_date: chrono::Utc::now().format("%Y%m%d").to_string(),
-                                    study_description: "Unknown Study".to_string(),
-                                    series_description: "Unknown Series".to_string(),
-                                    instance_number: 1,
-                                }
-                            ])
-                        }
+                
+                case.key_image_sop = Some(update.sop_instance_uid);
+                case.touch();
+                
+                match db::save_case(db_client, &case).await {
+                    Ok(_) => info!("Key image saved for case: {}", case_id),
+                    Err(e) if db::is_case_deleted(&e) => return not_found(&format!("Case not found: {}", case_id)),
+                    Err(e) => {
+                        error!("DynamoDB update error: {:?}", e);
+                        return server_error(&format!("Failed to update case: {}", e));
                     }
                 }
+                
+                Ok(Response::new(200, ApiResponse::success(case))?)
+            },
+            None => {
+                error!("Case not found: {}", case_id);
+                not_found(&format!("Case not found: {}", case_id))
             }
         }
     }
 
-    // Helper function to create SeriesInfo objects
-    fn create_series_info(
-        series_map: &std::collections::HashMap<String, Vec<&DicomMetadata>>
-    ) -> (Vec<SeriesInfo>, Vec<String>) {
-        let mut series_info_list = Vec::new();
-        let mut all_image_ids = Vec::new();
+    // POST /api/cases/{id}/move-image - Reassign an image to another series, creating it if needed
+    pub async fn move_image(
+        db_client: &DynamoDbClient,
+        path: &str,
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/move-image");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
         
-        for (series_uid, instances) in series_map {
-            // Collect image IDs for this series
-            let image_ids: Vec<String> = instances.iter()
-                .map(|meta| meta.sop_instance_uid.clone())
-                .collect();
-            
-            all_image_ids.extend(image_ids.clone());
-            
-            // Use the first instance for series metadata
-            let first_instance = instances[0];
-            
-            let series_info = SeriesInfo {
-                series_instance_uid: series_uid.clone(),
-                series_number: first_instance.instance_number,
-                series_description: first_instance.series_description.clone(),
-                modality: first_instance.modality.clone(),
-                image_ids,
-            };
-            
-            series_info_list.push(series_info);
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body for image move");
+                return response;
+            }
+        };
+        
+        let image_move: ImageMove = match serde_json::from_str(body) {
+            Ok(image_move) => image_move,
+            Err(e) => {
+                error!("Error parsing image move JSON: {:?}", e);
+                return bad_request(&format!("Invalid JSON: {}", e));
+            }
+        };
+        
+        let to_series = image_move.to_series.trim();
+        if !is_valid_uid(to_series) {
+            return bad_request("to_series must be a Series Instance UID");
         }
         
-        (series_info_list, all_image_ids)
+        let mut case = match db::get_case(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        if !case.contains_instance(&image_move.sop) {
+            warn!("SOP {} is not part of case {}", image_move.sop, case_id);
+            return bad_request(&format!("Image {} does not belong to case {}", image_move.sop, case_id));
+        }
+        
+        move_to_series(&mut case, &image_move.sop, to_series);
+        case.touch();
+        
+        match db::save_case(db_client, &case).await {
+            Ok(_) => info!("Moved image {} to series {} in case {}", image_move.sop, to_series, case_id),
+            Err(e) if db::is_case_deleted(&e) => return not_found(&format!("Case not found: {}", case_id)),
+            Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+            Err(e) => {
+                error!("DynamoDB update error: {:?}", e);
+                return server_error(&format!("Failed to update case: {}", e));
+            }
+        }
+        
+        Response::new(200, ApiResponse::success(case))
     }
 
-    // Helper function to update a case with new instances
-    fn update_case_with_new_instances(
-        existing_case: &mut Case,
-        series_map: &std::collections::HashMap<String, Vec<&DicomMetadata>>
-    ) {
-        for (series_uid, instances) in series_map {
-            let mut found_series = false;
-            
-            // Check if this series already exists in the case
-            for existing_series in &mut existing_case.series {
-                if &existing_series.series_instance_uid == series_uid {
-                    found_series = true;
-                    
-                    // Add new instances to existing series
-                    for instance in instances {
-                        // Only add if not already present
-                        if !existing_series.image_ids.contains(&instance.sop_instance_uid) {
-                            existing_series.image_ids.push(instance.sop_instance_uid.clone());
-                            info!("Added instance {} to existing series {}", 
-                                     instance.sop_instance_uid, series_uid);
-                            
-                            // Also add to the flat list for backward compatibility
-                            if !existing_case.image_ids.contains(&instance.sop_instance_uid) {
-                                existing_case.image_ids.push(instance.sop_instance_uid.clone());
-                            }
-                        }
-                    }
-                    
-                    break;
+    // Helper to take an image out of its current series and append it to the target.
+    // A series left empty is dropped; a new target series is numbered after the existing ones.
+    fn move_to_series(case: &mut Case, sop_instance_uid: &str, to_series: &str) {
+        let mut modality = case.modality.clone();
+        for series in case.series.iter_mut().filter(|series| series.series_instance_uid != to_series) {
+            if let Some(index) = series.image_ids.iter().position(|id| id == sop_instance_uid) {
+                series.image_ids.remove(index);
+                modality = series.modality.clone();
+            }
+        }
+        case.series.retain(|series| !series.image_ids.is_empty() || series.series_instance_uid == to_series);
+        
+        match case.series.iter_mut().find(|series| series.series_instance_uid == to_series) {
+            Some(series) => {
+                if !series.image_ids.iter().any(|id| id == sop_instance_uid) {
+                    series.image_ids.push(sop_instance_uid.to_string());
                 }
+            },
+            None => {
+                let series_number = case.series.iter().map(|series| series.series_number).max().unwrap_or(0) + 1;
+                case.series.push(SeriesInfo {
+                    series_instance_uid: to_series.to_string(),
+                    series_number,
+                    series_description: String::new(),
+                    modality,
+                    image_ids: vec![sop_instance_uid.to_string()],
+                    acquisition_datetime: None,
+                    expected_instance_count: None,
+                });
             }
-            
-            // If the series doesn't exist, create a new one
-            if !found_series {
-                let first_instance = instances[0];
-                
-                // Get all instance IDs for this series
-                let image_ids: Vec<String> = instances.iter()
-                    .map(|meta| meta.sop_instance_uid.clone())
-                    .collect();
-                
-                let new_series = SeriesInfo {
-                    series_instance_uid: series_uid.clone(),
-                    series_number: first_instance.instance_number,
-                    series_description: first_instance.series_description.clone(),
-                    modality: first_instance.modality.clone(),
-                    image_ids: image_ids.clone(),
-                };
-                
-                info!("Added new series {} with {} instances", 
-                         series_uid, image_ids.len());
-                
-                // Add all new image IDs to the flat list for backward compatibility
-                for image_id in &image_ids {
-                    if !existing_case.image_ids.contains(image_id) {
-                        existing_case.image_ids.push(image_id.clone());
+        }
+        
+        if !case.image_ids.iter().any(|id| id == sop_instance_uid) {
+            case.image_ids.push(sop_instance_uid.to_string());
+        }
+    }
+
+    // POST /api/cases/{id}/attachments - Attach a report or reference file to a case
+    pub async fn add_attachment(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str,
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/attachments");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body for attachment upload");
+                return response;
+            }
+        };
+        
+        let upload: AttachmentUpload = match serde_json::from_str(body) {
+            Ok(upload) => upload,
+            Err(e) => {
+                error!("Error parsing attachment JSON: {:?}", e);
+                return bad_request(&format!("Invalid JSON: {}", e));
+            }
+        };
+        
+        // Keep only the final path component of whatever name the client sent
+        let filename = upload.filename.rsplit(['/', '\\']).next().unwrap_or_default();
+        let filename = match crate::models::sanitize::required("filename", filename, false) {
+            Ok(filename) => filename,
+            Err(field) => return bad_request(&format!("Field '{}' must not be empty", field)),
+        };
+        
+        let content_type = match attachment_content_type(&upload.content_type) {
+            Some(content_type) => content_type.to_string(),
+            None => return bad_request(&format!("content_type must be one of: {}", ATTACHMENT_CONTENT_TYPES.join(", "))),
+        };
+        
+        let data = match BASE64.decode(&upload.data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Error decoding base64: {:?}", e);
+                return bad_request(&format!("Invalid base64 encoding: {}", e));
+            }
+        };
+        
+        // Attachments are returned inline, so they must fit in a Lambda response
+        if data.len() as i64 > MAX_INLINE_RESPONSE_BYTES {
+            return payload_too_large(&format!("Attachments are limited to {} bytes", MAX_INLINE_RESPONSE_BYTES));
+        }
+        
+        let mut case = match db::get_case(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            filename,
+            content_type,
+        };
+        let key = attachment_key(case_id, &attachment.id);
+        s3::upload_object(s3_client, &key, data, &attachment.content_type).await?;
+        info!("Stored attachment {} for case {} at {}", attachment.filename, case_id, key);
+        
+        case.attachments.push(attachment.clone());
+        case.touch();
+        if let Err(e) = db::save_case(db_client, &case).await {
+            error!("Failed to record attachment, removing {}: {:?}", key, e);
+            if let Err(e) = s3::delete_files(s3_client, &[key]).await {
+                warn!("Failed to remove unrecorded attachment: {:?}", e);
+            }
+            if db::is_case_deleted(&e) {
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+            return Err(e.into());
+        }
+        
+        Response::new(201, ApiResponse::success(attachment))
+    }
+
+    // GET /api/cases/{id}/attachments/{attachment_id} - Download an attachment with its content type
+    pub async fn get_attachment(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        let (case_id, attachment_id) = match parts.as_slice() {
+            ["", "api", "cases", case_id, "attachments", attachment_id] => (*case_id, *attachment_id),
+            _ => return bad_request("Invalid attachment URL format"),
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let attachment = match case.attachments.iter().find(|attachment| attachment.id == attachment_id) {
+            Some(attachment) => attachment,
+            None => return not_found(&format!("Attachment not found: {}", attachment_id)),
+        };
+        
+        let data = s3::download_file(s3_client, &attachment_key(case_id, &attachment.id)).await?;
+        
+        // Always a download, never rendered on the app's origin; types stored before the
+        // allowlist existed are sent as opaque bytes
+        let content_type = attachment_content_type(&attachment.content_type).unwrap_or("application/octet-stream");
+        let mut response = Response::new(200, "")?.with_content_type(content_type);
+        response.headers.insert("X-Content-Type-Options".to_string(), "nosniff".to_string());
+        Ok(response.into_binary_named(data, &attachment.filename))
+    }
+
+    // Helper to build the S3 key of an attachment
+    fn attachment_key(case_id: &str, attachment_id: &str) -> String {
+        format!("attachments/{}/{}", case_id, attachment_id)
+    }
+
+    // Attachment types that are safe to hand back to a browser: documents and images only,
+    // never anything a browser would run as a page or script
+    const ATTACHMENT_CONTENT_TYPES: &[&str] = &["application/pdf", "image/png", "image/jpeg", "text/plain"];
+
+    // Helper to match a declared content type against the allowlist, ignoring parameters such as charset
+    fn attachment_content_type(declared: &str) -> Option<&'static str> {
+        let essence = declared.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        ATTACHMENT_CONTENT_TYPES.iter().copied().find(|allowed| *allowed == essence)
+    }
+
+    // GET /api/cases/{id}/thumbnail[?sop=...] - Render a PNG preview, defaulting to the key image
+    pub async fn get_thumbnail(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/thumbnail");
+        info!("Rendering thumbnail for case: {}", case_id);
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        // Explicit SOP, then the key image, then the first registered image
+        let sop_instance_uid = match request.query_param("sop") {
+            Some(sop) => {
+                if !case.contains_instance(sop) {
+                    return bad_request(&format!("Image {} does not belong to case {}", sop, case_id));
+                }
+                sop.to_string()
+            },
+            None => match default_thumbnail_id(&case) {
+                Some(sop) => sop,
+                None => return not_found("Case has no images"),
+            },
+        };
+        
+        // Serve a thumbnail precomputed at upload time when there is one
+        let key = thumbnail_key(&case.case_id, &sop_instance_uid);
+        if s3::file_size(s3_client, &key).await?.is_some() {
+            debug!("Serving precomputed thumbnail: {}", key);
+            let png_data = s3::download_file(s3_client, &key).await?;
+            let response = Response::new(200, "")?.with_content_type("image/png");
+            return Ok(response.into_binary(png_data));
+        }
+        
+        let dicom_data = match super::dicom_routes::load_instance(s3_client, &case, &sop_instance_uid).await? {
+            Some(data) => data,
+            None => return not_found("DICOM file not found"),
+        };
+        
+        match render_thumbnail(&dicom_data, THUMBNAIL_SIZE) {
+            Ok(png_data) => {
+                // Fill the cache so the next gallery load skips rendering
+                if precompute_thumbnails_enabled() {
+                    if let Err(e) = s3::upload_object(s3_client, &key, png_data.clone(), "image/png").await {
+                        warn!("Failed to store thumbnail {}: {:?}", key, e);
                     }
                 }
                 
-                existing_case.series.push(new_series);
+                let mut response = Response::new(200, "")?;
+                response = response.with_content_type("image/png");
+                Ok(response.into_binary(png_data))
+            },
+            Err(e) if dicom_error(&e).is_some() => {
+                error!("Failed to render thumbnail for {}: {:?}", sop_instance_uid, e);
+                dicom_error_response(&e)
+            },
+            Err(e) => {
+                error!("Failed to render thumbnail for {}: {:?}", sop_instance_uid, e);
+                server_error(&format!("Failed to render thumbnail: {}", e))
+            }
+        }
+    }
+
+    // GET /api/cases/{id}/thumbnails[?size=...] - Key image of every series packed into one sprite sheet
+    pub async fn get_thumbnail_sprite(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/thumbnails");
+        info!("Rendering thumbnail sprite for case: {}", case_id);
+        
+        let cell_size = match request.query_param("size").map(str::parse::<u32>) {
+            None => DEFAULT_SPRITE_CELL_SIZE,
+            Some(Ok(size)) if (MIN_SPRITE_CELL_SIZE..=THUMBNAIL_SIZE).contains(&size) => size,
+            Some(_) => return bad_request(&format!(
+                "size must be between {} and {}", MIN_SPRITE_CELL_SIZE, THUMBNAIL_SIZE)),
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        // The case's key image represents its own series; other series use their first image
+        let mut missing = Vec::new();
+        let mut sources = Vec::new();
+        let mut objects = Vec::new();
+        for series in &case.series {
+            if sources.len() >= MAX_SPRITE_CELLS {
+                missing.push(series.series_instance_uid.clone());
+                continue;
+            }
+            
+            let sop_instance_uid = match case.key_image_sop.as_ref()
+                .filter(|sop| series.image_ids.contains(sop))
+                .or_else(|| series.image_ids.first()) {
+                Some(sop) => sop.clone(),
+                None => {
+                    missing.push(series.series_instance_uid.clone());
+                    continue;
+                }
+            };
+            
+            match super::dicom_routes::load_instance(s3_client, &case, &sop_instance_uid).await? {
+                Some(data) => {
+                    sources.push((series.series_instance_uid.clone(), sop_instance_uid));
+                    objects.push(data);
+                },
+                None => {
+                    warn!("No stored object for sprite image {}", sop_instance_uid);
+                    missing.push(series.series_instance_uid.clone());
+                }
+            }
+        }
+        
+        if sources.is_empty() {
+            return not_found("Case has no images to render");
+        }
+        
+        let sheet = match render_sprite_sheet(&objects, cell_size) {
+            Ok(sheet) if sheet.png.len() as i64 > MAX_INLINE_RESPONSE_BYTES => {
+                return payload_too_large("Sprite sheet is too large to return inline; request a smaller size");
+            },
+            Ok(sheet) => sheet,
+            Err(e) => {
+                error!("Failed to render thumbnail sprite for case {}: {:?}", case_id, e);
+                return server_error(&format!("Failed to render thumbnail sprite: {}", e));
+            }
+        };
+        
+        let mut cells = std::collections::HashMap::new();
+        for ((series_uid, sop_instance_uid), cell) in sources.into_iter().zip(sheet.cells) {
+            match cell {
+                Some(cell) => {
+                    cells.insert(series_uid, SpriteCoordinates {
+                        sop_instance_uid,
+                        x: cell.x,
+                        y: cell.y,
+                        width: cell.width,
+                        height: cell.height,
+                    });
+                },
+                None => missing.push(series_uid),
+            }
+        }
+        
+        Response::new(200, ApiResponse::success(ThumbnailSprite {
+            content_type: "image/png".to_string(),
+            image: BASE64.encode(&sheet.png),
+            width: sheet.width,
+            height: sheet.height,
+            cell_size,
+            cells,
+            missing,
+        }))
+    }
+
+    // GET /api/cases/{id}/images - Page through a case's image ids in display order
+    pub async fn list_images(
+        db_client: &DynamoDbClient,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/images");
+        info!("Listing images for case: {}", case_id);
+        
+        let offset = match request.query_param("offset").map(str::parse::<usize>) {
+            None => 0,
+            Some(Ok(offset)) => offset,
+            Some(Err(_)) => return bad_request("offset must be a non-negative integer"),
+        };
+        let limit = match request.query_param("limit").map(str::parse::<usize>) {
+            None => DEFAULT_IMAGE_PAGE_SIZE,
+            Some(Ok(limit)) if limit > 0 => limit.min(MAX_IMAGE_PAGE_SIZE),
+            Some(_) => return bad_request("limit must be a positive integer"),
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        // An offset past the end yields an empty page rather than an error
+        let image_ids = case.ordered_image_ids();
+        let total = image_ids.len();
+        let images: Vec<String> = image_ids.into_iter().skip(offset).take(limit).collect();
+        let end = offset.saturating_add(images.len());
+        let next_offset = if end < total { Some(end) } else { None };
+        
+        Response::new(200, ApiResponse::success(ImagePage { images, total, offset, next_offset }))
+    }
+
+    // S3 key of the precomputed thumbnail for one image
+    fn thumbnail_key(case_id: &str, sop_instance_uid: &str) -> String {
+        format!("thumbnails/{}/{}.png", case_id, sop_instance_uid)
+    }
+
+    // Thumbnails are rendered at upload time only when PRECOMPUTE_THUMBNAILS is enabled
+    fn precompute_thumbnails_enabled() -> bool {
+        config::get().precompute_thumbnails
+    }
+
+    // Helper to pick the image a case's thumbnail shows by default: the key image, then the
+    // first image of the first series, then the first registered image
+    fn default_thumbnail_id(case: &Case) -> Option<String> {
+        case.key_image_sop.clone()
+            .or_else(|| case.series.first().and_then(|series| series.image_ids.first().cloned()))
+            .or_else(|| case.image_ids.first().cloned())
+    }
+
+    // Helper to list the images whose thumbnails are worth rendering up front: the case's
+    // default thumbnail, then the first image of every series in display order
+    fn thumbnail_image_ids(case: &Case) -> Vec<String> {
+        let mut series: Vec<&SeriesInfo> = case.series.iter().collect();
+        series.sort_by(|a, b| a.display_order(b));
+        
+        let mut image_ids: Vec<String> = Vec::new();
+        let first_images = series.iter().filter_map(|series| series.image_ids.first().cloned());
+        for image_id in default_thumbnail_id(case).into_iter().chain(first_images) {
+            if !image_ids.contains(&image_id) {
+                image_ids.push(image_id);
+            }
+        }
+        image_ids
+    }
+
+    // Helper to render and store the previews of a case's default and per-series images found in
+    // an upload; failures only log
+    async fn precompute_thumbnails(s3_client: &S3Client, case: &Case, dicom_data: &[u8]) {
+        if !precompute_thumbnails_enabled() {
+            return;
+        }
+        let image_ids = thumbnail_image_ids(case);
+        if image_ids.is_empty() {
+            return;
+        }
+        
+        // Each object renders from its own bytes, so archive entries and the parts of a
+        // multi-part upload are covered as well as single objects
+        let instances = match split_instances(dicom_data) {
+            Ok(instances) => instances,
+            Err(e) => {
+                info!("Skipping thumbnail precompute for case {}: {}", case.case_id, e);
+                return;
+            }
+        };
+        
+        let mut rendered = Vec::new();
+        for instance in instances {
+            let wanted: Vec<&String> = image_ids.iter()
+                .filter(|image_id| crate::dicom::parent_sop_instance_uid(image_id) == instance.sop_instance_uid)
+                .collect();
+            if wanted.is_empty() {
+                continue;
+            }
+            
+            match render_thumbnail(&instance.data, THUMBNAIL_SIZE) {
+                Ok(png_data) => {
+                    for image_id in wanted {
+                        rendered.push((thumbnail_key(&case.case_id, image_id), png_data.clone()));
+                    }
+                },
+                Err(e) => info!("Skipping thumbnail precompute for {}: {}", instance.sop_instance_uid, e),
+            }
+            if rendered.len() >= image_ids.len() {
+                break;
+            }
+        }
+        
+        for (key, png_data) in rendered {
+            match s3::upload_object(s3_client, &key, png_data, "image/png").await {
+                Ok(_) => info!("Stored precomputed thumbnail: {}", key),
+                Err(e) => warn!("Failed to store thumbnail {}: {:?}", key, e),
+            }
+        }
+    }
+
+    // DICOM JSON sidecars are written at upload time only when DICOM_JSON_SIDECARS is enabled
+    fn json_sidecars_enabled() -> bool {
+        config::get().dicom_json_sidecars
+    }
+
+    // Helper to store the full tag set of each uploaded object next to its instance key; failures only log
+    async fn store_json_sidecars(s3_client: &S3Client, case_id: &str, dicom_data: &[u8]) {
+        if !json_sidecars_enabled() {
+            return;
+        }
+        
+        let sidecars = match dicom_json_sidecars(dicom_data) {
+            Ok(sidecars) => sidecars,
+            Err(e) => {
+                warn!("Skipping DICOM JSON sidecars for case {}: {}", case_id, e);
+                return;
+            }
+        };
+        
+        for sidecar in sidecars {
+            let key = super::dicom_routes::sidecar_key(case_id, &sidecar.study_instance_uid, &sidecar.sop_instance_uid);
+            // Sidecars are keyed by SOPInstanceUID and never change, so an existing one is kept
+            match s3::upload_if_absent(s3_client, &key, sidecar.json.to_string().into_bytes(), "application/dicom+json").await {
+                Ok(true) => debug!("Stored DICOM JSON sidecar: {}", key),
+                Ok(false) => debug!("DICOM JSON sidecar already stored: {}", key),
+                Err(e) => warn!("Failed to store DICOM JSON sidecar {}: {:?}", key, e),
+            }
+        }
+    }
+
+    // Helper function to bundle files into an uncompressed zip archive
+    fn build_zip(files: Vec<(String, Vec<u8>)>) -> anyhow::Result<Vec<u8>> {
+        use std::io::Write;
+        
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        
+        for (name, data) in files {
+            writer.start_file(name, options)?;
+            writer.write_all(&data)?;
+        }
+        
+        Ok(writer.finish()?.into_inner())
+    }
+
+    // Case assembled from an upload, ready to persist
+    struct PreparedCase {
+        case: Case,
+        dicom_data: Vec<u8>,
+        metadata_list: Vec<DicomMetadata>,
+        is_test_data: bool,
+        report: ProcessingReport,
+    }
+    
+    // Instances found in an upload, with how they were found
+    struct ProcessedDicom {
+        metadata_list: Vec<DicomMetadata>,
+        strategy: ParseStrategy,
+        fallback_used: bool,
+        warnings: Vec<String>,
+    }
+
+    // Upload body decoded, before any DICOM processing
+    struct ParsedUpload {
+        upload: CaseUpload,
+        dicom_data: Vec<u8>,
+        is_test_data: bool,
+    }
+
+    // Helper shared by create and validate: parse, decode and extract an upload into a Case.
+    // A rejected upload comes back as the response to return.
+    async fn prepare_case(
+        xray_client: &aws_sdk_xray::Client,
+        body: &Option<String>
+    ) -> Result<PreparedCase, Result<Response, LambdaError>> {
+        let ParsedUpload { upload, dicom_data, is_test_data } = parse_upload(body)?;
+        build_case(xray_client, Uuid::new_v4().to_string(), &upload, dicom_data, is_test_data).await
+    }
+
+    // Helper to decode an uploaded dicom_file field. Bare base64 is expected, but a
+    // data URL ("data:application/dicom;base64,...") is accepted and its prefix dropped.
+    fn decode_dicom_field(field: &str) -> Result<Vec<u8>, String> {
+        let field = field.trim();
+        
+        let encoded = match field.get(..5) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("data:") => match field.split_once(',') {
+                Some((header, payload)) if header.to_ascii_lowercase().ends_with(";base64") => payload,
+                _ => return Err("dicom_file is a data URL but not a base64 one; send bare base64".to_string()),
+            },
+            _ => field,
+        };
+        
+        BASE64.decode(encoded)
+            .map_err(|e| format!("dicom_file is not valid base64 ({}); send the file bytes as bare base64", e))
+    }
+
+    // Helper to parse the upload JSON and decode its DICOM payload
+    fn parse_upload(body: &Option<String>) -> Result<ParsedUpload, Result<Response, LambdaError>> {
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body in POST");
+                return Err(response);
+            }
+        };
+        
+        info!("Processing new case submission");
+        debug!("Received POST body length: {}", body.len());
+        
+        // Parse the case upload request
+        let mut case_upload: CaseUpload = match serde_json::from_str::<CaseUpload>(body) {
+            Ok(upload) => {
+                info!("JSON parsed successfully");
+                debug!("Title: {}", upload.title);
+                debug!("Modality value: '{}'", upload.modality);
+                upload
+            },
+            Err(e) => {
+                error!("Failed to parse JSON: {:?}", e);
+                return Err(bad_request(&format!("Invalid JSON: {}", e)));
+            }
+        };
+        
+        if let Err(field) = case_upload.sanitize() {
+            error!("Required field is empty after sanitizing: {}", field);
+            return Err(bad_request(&format!("Field '{}' must not be empty", field)));
+        }
+        if case_upload.dicom_file.is_empty() {
+            return Err(bad_request("Field 'dicomFile' must not be empty"));
+        }
+        
+        // Special handling for test cases or problematic data
+        let is_test_data = case_upload.dicom_file == "QVRFTVBJT1JSVEVS=" || 
+                          case_upload.dicom_file.starts_with("QVRFTVBJT1JSVEVS");
+        
+        // Decode or create test DICOM data
+        let dicom_data = if is_test_data {
+            info!("Detected test case, using dummy DICOM data");
+            vec![0u8; 10] // Dummy data
+        } else {
+            // Decode the base64 data
+            match decode_dicom_field(&case_upload.dicom_file) {
+                Ok(data) => {
+                    info!("Successfully decoded base64 data. Size: {} bytes", data.len());
+                    data
+                },
+                Err(message) => {
+                    error!("Error decoding base64: {}", message);
+                    return Err(bad_request(&message));
+                }
+            }
+        };
+        
+        Ok(ParsedUpload {
+            upload: case_upload,
+            dicom_data,
+            is_test_data,
+        })
+    }
+
+    // Helper to extract metadata from decoded DICOM data and assemble the Case
+    async fn build_case(
+        xray_client: &aws_sdk_xray::Client,
+        case_id: String,
+        case_upload: &CaseUpload,
+        dicom_data: Vec<u8>,
+        is_test_data: bool
+    ) -> Result<PreparedCase, Result<Response, LambdaError>> {
+        // Ensure DICOM directory exists
+        if let Err(e) = ensure_dicom_dir_exists() {
+            warn!("Failed to create DICOM directory: {:?}", e);
+        }
+        
+        telemetry::send_xray_trace(xray_client, "dicom-extraction-start").await;
+        
+        // Process DICOM data
+        let ProcessedDicom { metadata_list, strategy, fallback_used, mut warnings } =
+            match process_dicom_data(&dicom_data, is_test_data, &case_upload.modality).await {
+                Ok(processed) => processed,
+                Err(rejection) => return Err(rejection),
+            };
+        
+        info!("DICOM processing complete. Found {} instances/series", metadata_list.len());
+        telemetry::send_xray_trace(xray_client, "dicom-extraction-complete").await;
+        
+        // Group metadata by series
+        let mut series_map: std::collections::HashMap<String, Vec<&DicomMetadata>> = std::collections::HashMap::new();
+        for metadata in &metadata_list {
+            series_map.entry(metadata.series_instance_uid.clone())
+                .or_insert_with(Vec::new)
+                .push(metadata);
+        }
+        
+        info!("Organized into {} unique series", series_map.len());
+        
+        // Create SeriesInfo objects and collect image IDs
+        let (series_info_list, all_image_ids) = create_series_info(&series_map);
+        
+        let modality = resolve_modality(&case_upload.modality, &metadata_list);
+        
+        // Map free-text anatomy onto the controlled vocabulary; left blank, it comes from the DICOM
+        let anatomy = if case_upload.anatomy.trim().is_empty() {
+            anatomy_from_dicom(&metadata_list)
+        } else {
+            taxonomy::normalize_anatomy(&case_upload.anatomy)
+        };
+        if !anatomy.recognized {
+            warn!("Anatomy '{}' is not in the taxonomy, storing as entered", anatomy.value);
+        }
+        
+        // Create the case with all collected information
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut case = Case {
+            case_id,
+            title: case_upload.title.clone(),
+            description: case_upload.description.clone(),
+            modality,
+            anatomy: anatomy.value,
+            diagnosis: case_upload.diagnosis.clone(),
+            findings: case_upload.findings.clone(),
+            tags: case_upload.tags.clone(),
+            image_ids: all_image_ids,
+            created_at: now.clone(),
+            updated_at: now,
+            
+            // Use metadata from the first instance
+            study_instance_uid: metadata_list[0].study_instance_uid.clone(),
+            series_instance_uid: metadata_list[0].series_instance_uid.clone(),
+            study_date: metadata_list[0].study_date.clone(),
+            study_description: metadata_list[0].study_description.clone(),
+            patient_id: metadata_list[0].patient_id.clone(),
+            patient_name: metadata_list[0].patient_name.clone(),
+            
+            // Include all series information
+            series: series_info_list,
+            key_image_sop: None,
+            edit_history: Vec::new(),
+            attachments: Vec::new(),
+            image_references: std::collections::HashMap::new(),
+            instances_key: None,
+            view_count: 0,
+            expected_instance_count: metadata_list.iter().filter_map(|meta| meta.study_related_instances).max(),
+        };
+        for metadata in &metadata_list {
+            case.record_references(metadata);
+        }
+        
+        let phi = phi_warnings(&metadata_list);
+        let phi_warning = !phi.is_empty();
+        warnings.extend(phi);
+        warnings.extend(instance_count_warnings(&case, metadata_list.len()));
+        if !anatomy.recognized {
+            warnings.push(format!("Anatomy '{}' is not in the taxonomy and was stored as entered", case.anatomy));
+        }
+        
+        let report = ProcessingReport {
+            instances_found: metadata_list.len(),
+            series_found: case.series.len(),
+            parse_strategy: strategy.as_str().to_string(),
+            fallback_used,
+            warnings,
+            phi_warning,
+            unstored_instances: Vec::new(),
+        };
+        
+        Ok(PreparedCase {
+            case,
+            dicom_data,
+            metadata_list,
+            is_test_data,
+            report,
+        })
+    }
+
+    // Helper to derive anatomy from the first instance recording BodyPartExamined, with its laterality
+    fn anatomy_from_dicom(metadata_list: &[DicomMetadata]) -> taxonomy::AnatomyMatch {
+        let metadata = match metadata_list.iter().find(|metadata| !metadata.body_part_examined.is_empty()) {
+            Some(metadata) => metadata,
+            None => return taxonomy::normalize_anatomy(""),
+        };
+        
+        let mut anatomy = taxonomy::normalize_anatomy(&metadata.body_part_examined);
+        if anatomy.recognized {
+            anatomy.value = taxonomy::with_laterality(&anatomy.value, &metadata.laterality);
+        }
+        info!("Anatomy defaulted to '{}' from BodyPartExamined '{}'", anatomy.value, metadata.body_part_examined);
+        anatomy
+    }
+
+    // Helper to flag an upload with fewer instances than the source said it has. Multi-frame
+    // objects extract to one instance per frame, so only a shortfall is reported.
+    fn instance_count_warnings(case: &Case, extracted: usize) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(expected) = case.expected_instance_count.filter(|expected| (*expected as usize) > extracted) {
+            warnings.push(format!("Source says {} instances, extracted {}; the upload may be partial", expected, extracted));
+        }
+        
+        for series in &case.series {
+            if let Some(expected) = series.expected_instance_count.filter(|expected| (*expected as usize) > series.image_ids.len()) {
+                warnings.push(format!("Series {} ({}): source says {} instances, extracted {}",
+                                      series.series_number, series.series_instance_uid, expected, series.image_ids.len()));
             }
         }
+        
+        warnings
+    }
+
+    // POST /api/cases - Create a new case
+    pub async fn create_case(
+        db_client: &DynamoDbClient, 
+        s3_client: &S3Client, 
+        xray_client: &aws_sdk_xray::Client,
+        request: &Request
+    ) -> Result<Response, LambdaError> {
+        telemetry::send_xray_trace(xray_client, "create-case-start").await;
+        
+        let parsed = match parse_upload(&request.body) {
+            Ok(parsed) => parsed,
+            Err(rejection) => return rejection,
+        };
+        
+        create_parsed_case(db_client, s3_client, xray_client, request, parsed).await
+    }
+
+    // POST /api/cases/from-url - Create a case from DICOM fetched from an allowed https URL
+    pub async fn create_case_from_url(
+        db_client: &DynamoDbClient, 
+        s3_client: &S3Client, 
+        xray_client: &aws_sdk_xray::Client,
+        request: &Request
+    ) -> Result<Response, LambdaError> {
+        telemetry::send_xray_trace(xray_client, "create-case-start").await;
+        
+        let body = match require_body(&request.body) {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        let CaseUrlUpload { dicom_url, upload: mut case_upload } = match serde_json::from_str::<CaseUrlUpload>(body) {
+            Ok(upload) => upload,
+            Err(e) => return bad_request(&format!("Invalid JSON: {}", e)),
+        };
+        if let Err(field) = case_upload.sanitize() {
+            return bad_request(&format!("Field '{}' must not be empty", field));
+        }
+        if !case_upload.dicom_file.is_empty() {
+            return bad_request("Send either dicomFile or dicomUrl, not both");
+        }
+        
+        // The URL is checked before any request is made, so callers cannot reach internal hosts
+        let url = match remote::check_url(&dicom_url, remote::allowed_hosts()) {
+            Ok(url) => url,
+            Err(e @ FetchError::NotEnabled) => return forbidden(&e.to_string()),
+            Err(e) => return bad_request(&e.to_string()),
+        };
+        
+        telemetry::send_xray_trace(xray_client, "url-fetch-start").await;
+        let dicom_data = match remote::fetch(url, remote::max_fetch_bytes()).await {
+            Ok(data) => data,
+            Err(e @ FetchError::TooLarge { .. }) => return payload_too_large(&e.to_string()),
+            Err(e @ FetchError::TimedOut) => return gateway_timeout(&e.to_string()),
+            Err(e) => {
+                error!("Fetching DICOM by URL failed: {}", e);
+                return bad_gateway(&e.to_string());
+            }
+        };
+        telemetry::send_xray_trace(xray_client, "url-fetch-complete").await;
+        
+        let parsed = ParsedUpload {
+            upload: case_upload,
+            dicom_data,
+            is_test_data: false,
+        };
+        create_parsed_case(db_client, s3_client, xray_client, request, parsed).await
+    }
+
+    // Helper shared by the create routes once the upload's DICOM bytes are in hand
+    async fn create_parsed_case(
+        db_client: &DynamoDbClient, 
+        s3_client: &S3Client, 
+        xray_client: &aws_sdk_xray::Client,
+        request: &Request,
+        mut parsed: ParsedUpload
+    ) -> Result<Response, LambdaError> {
+        // Anonymize first so neither the staged copy nor the stored objects carry PHI
+        if parsed.upload.anonymize && !parsed.is_test_data {
+            parsed.dicom_data = match anonymize_upload(&parsed.dicom_data) {
+                Ok(anonymized) => anonymized,
+                Err(e) => {
+                    error!("Failed to anonymize upload: {:?}", e);
+                    return dicom_error_response(&e);
+                }
+            };
+        }
+        
+        // Uploads carrying an upload id go through the resumable staged flow
+        if let Some(upload_id) = parsed.upload.upload_id.clone() {
+            let force = request.query_param("force") == Some("true");
+            return create_staged_case(db_client, s3_client, xray_client, &upload_id, parsed, force).await;
+        }
+        
+        let ParsedUpload { upload, dicom_data, is_test_data } = parsed;
+        let PreparedCase { case, dicom_data, metadata_list, is_test_data, mut report } = match build_case(
+            xray_client, Uuid::new_v4().to_string(), &upload, dicom_data, is_test_data
+        ).await {
+            Ok(prepared) => prepared,
+            Err(rejection) => return rejection,
+        };
+        let case_id = &case.case_id;
+        
+        // A study that already has a case is refused unless the caller wants a second variant
+        let force = request.query_param("force") == Some("true");
+        if !is_test_data && !force {
+            if let Some(existing) = find_duplicate_study(db_client, &case).await {
+                warn!("Study {} already has case {}, refusing duplicate", case.study_instance_uid, existing.case_id);
+                return Response::new(409, DuplicateStudyResponse::new(existing));
+            }
+        }
+        
+        // Upload to S3 if this isn't a test case
+        if !is_test_data {
+            telemetry::send_xray_trace(xray_client, "s3-upload-start").await;
+            
+            // Save the complete original file
+            let original_key = format!("dicom/{}/original.dcm", case_id);
+            
+            // The original is written before the case record so a timeout here never
+            // leaves a saved case pointing at a missing object
+            precompute_thumbnails(s3_client, &case, &dicom_data).await;
+            store_json_sidecars(s3_client, case_id, &dicom_data).await;
+            
+            match upload_original(s3_client, &original_key, dicom_data).await {
+                Ok(_) => info!("Uploaded original DICOM file to S3: {}", original_key),
+                Err(e) if upstream::is_timeout(&e) => {
+                    error!("Timed out uploading original DICOM file, aborting create: {:?}", e);
+                    return gateway_timeout("Upstream timeout");
+                },
+                Err(e) => {
+                    // Every instance is served from the original, so none of them can be viewed
+                    error!("Error uploading original DICOM file: {:?}", e);
+                    report.unstored_instances = metadata_list.iter()
+                        .map(|metadata| metadata.sop_instance_uid.clone())
+                        .collect();
+                },
+            }
+            
+            // Register paths for individual instances
+            for metadata in metadata_list.iter().filter(|metadata| !report.unstored_instances.contains(&metadata.sop_instance_uid)) {
+                let instance_key = format!("dicom/{}/{}/{}.dcm", 
+                                         case_id, 
+                                         metadata.study_instance_uid,
+                                         metadata.sop_instance_uid);
+                
+                debug!("Registered instance in database: {}", instance_key);
+            }
+            
+            telemetry::send_xray_trace(xray_client, "s3-upload-complete").await;
+        }
+        
+        // Save to DynamoDB
+        telemetry::send_xray_trace(xray_client, "dynamodb-save-start").await;
+        
+        match db::save_case(db_client, &case).await {
+            Ok(_) => info!("DynamoDB save successful"),
+            Err(e) if upstream::is_timeout(&e) => {
+                // put_item is a single atomic write, so the record either landed whole or not at all;
+                // the uploaded original is left in place rather than risk deleting data a saved case needs
+                error!("DynamoDB save timed out for case {}: {:?}", case.case_id, e);
+                return gateway_timeout("Upstream timeout");
+            },
+            Err(e) => error!("DynamoDB save error: {:?}", e),
+        }
+        
+        telemetry::send_xray_trace(xray_client, "dynamodb-save-complete").await;
+        telemetry::send_xray_trace(xray_client, "create-case-complete").await;
+        
+        created_response(case, report)
+    }
+
+    // Helper to answer a create. The case is saved either way; 207 tells the caller some of its images are missing
+    fn created_response(case: Case, report: ProcessingReport) -> Result<Response, LambdaError> {
+        if !report.unstored_instances.is_empty() {
+            let warning = format!("{} of {} instances could not be stored and will not be viewable",
+                                  report.unstored_instances.len(), report.instances_found);
+            return Response::new(207, ApiResponse::success(case).with_report(report).with_warning(warning));
+        }
+        
+        Response::new(201, ApiResponse::success(case).with_report(report))
+    }
+
+    // Attempts at storing an upload's original before its instances are reported as unstored
+    const ORIGINAL_UPLOAD_ATTEMPTS: usize = 2;
+
+    // Helper to store an upload's original, retrying failures other than a timeout
+    async fn upload_original(s3_client: &S3Client, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        for attempt in 1..ORIGINAL_UPLOAD_ATTEMPTS {
+            match s3::upload_file(s3_client, key, data.clone()).await {
+                Err(e) if !upstream::is_timeout(&e) => warn!("Upload of {} failed on attempt {}, retrying: {:?}", key, attempt, e),
+                result => return result,
+            }
+        }
+        s3::upload_file(s3_client, key, data).await
+    }
+
+    // Helper to find an existing case for the same study. A failed lookup (e.g. while the
+    // study index is still building) lets the create go ahead rather than block it.
+    async fn find_duplicate_study(db_client: &DynamoDbClient, case: &Case) -> Option<Case> {
+        if !is_identifying_study_uid(&case.study_instance_uid) {
+            return None;
+        }
+        
+        match db::get_case_by_study(db_client, &case.study_instance_uid).await {
+            Ok(existing) => existing.filter(|existing| existing.case_id != case.case_id),
+            Err(e) => {
+                warn!("Duplicate study check failed for {}: {:?}", case.study_instance_uid, e);
+                None
+            }
+        }
+    }
+
+    // Helper to check a study UID can tell two studies apart. Placeholder (unknown.*) and
+    // malformed fallback values are shared by unrelated uploads, so they never mark a duplicate.
+    fn is_identifying_study_uid(study_instance_uid: &str) -> bool {
+        !study_instance_uid.starts_with("unknown.") && is_valid_uid(study_instance_uid)
+    }
+
+    // Helper to start or continue a staged create for a client-supplied upload id
+    async fn create_staged_case(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        xray_client: &aws_sdk_xray::Client,
+        upload_id: &str,
+        parsed: ParsedUpload,
+        force: bool
+    ) -> Result<Response, LambdaError> {
+        if !staging::is_valid_upload_id(upload_id) {
+            return bad_request("uploadId may only contain letters, digits, '-' and '_' (max 128)");
+        }
+        
+        let record = match staging::load_record(s3_client, upload_id).await {
+            Ok(Some(mut record)) => {
+                info!("Retrying staged upload {} from stage {:?}", upload_id, record.stage);
+                record.force |= force;
+                record
+            },
+            Ok(None) => {
+                let mut upload = parsed.upload.clone();
+                upload.dicom_file = String::new();
+                
+                let mut record = StagingRecord {
+                    upload_id: upload_id.to_string(),
+                    case_id: Uuid::new_v4().to_string(),
+                    stage: StagingStage::Received,
+                    is_test_data: parsed.is_test_data,
+                    upload,
+                    case: None,
+                    force,
+                    updated_at: String::new(),
+                };
+                
+                if let Err(e) = staging::save_record(s3_client, &mut record).await {
+                    error!("Failed to create staging record for {}: {:?}", upload_id, e);
+                    return staging_failure(e);
+                }
+                record
+            },
+            Err(e) => {
+                error!("Failed to load staging record for {}: {:?}", upload_id, e);
+                return staging_failure(e);
+            }
+        };
+        
+        advance_staged_case(db_client, s3_client, xray_client, record, Some(parsed.dicom_data)).await
+    }
+
+    // POST /api/cases/{id}/split - Write each instance of the stored uploads to its own key
+    pub async fn split_case(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/split");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        info!("Splitting stored uploads into instances for case: {}", case_id);
+        
+        let case = match db::get_case(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let upload_keys = stored_upload_keys(s3_client, case_id).await?;
+        if upload_keys.is_empty() {
+            return not_found("Case has no stored uploads to split");
+        }
+        
+        let mut report = SplitReport {
+            case_id: case.case_id.clone(),
+            instances_found: 0,
+            written: 0,
+            already_present: 0,
+            not_in_case: 0,
+            failed: Vec::new(),
+        };
+        
+        for upload_key in upload_keys {
+            let data = s3::download_file(s3_client, &upload_key).await?;
+            let instances = match split_instances(&data) {
+                Ok(instances) => instances,
+                Err(e) => {
+                    warn!("Skipping unreadable upload {}: {:?}", upload_key, e);
+                    report.failed.push(upload_key);
+                    continue;
+                }
+            };
+            
+            // Each instance is written and dropped before the next is read from the upload
+            let found_before = report.instances_found;
+            for instance in instances {
+                report.instances_found += 1;
+                if !case.contains_object(&instance.sop_instance_uid) {
+                    report.not_in_case += 1;
+                    continue;
+                }
+                
+                // Same key get_dicom tries first; existing objects are kept, so reruns are safe
+                let key = if case.study_instance_uid.is_empty() {
+                    format!("dicom/{}/{}.dcm", case.case_id, instance.sop_instance_uid)
+                } else {
+                    format!("dicom/{}/{}/{}.dcm", case.case_id, case.study_instance_uid, instance.sop_instance_uid)
+                };
+                
+                match s3::upload_if_absent(s3_client, &key, instance.data, "application/dicom").await {
+                    Ok(true) => report.written += 1,
+                    Ok(false) => report.already_present += 1,
+                    Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+                    Err(e) => {
+                        error!("Failed to write instance {}: {:?}", key, e);
+                        report.failed.push(key);
+                    }
+                }
+            }
+            if report.instances_found == found_before {
+                warn!("Skipping upload {} with no DICOM objects to split", upload_key);
+                report.failed.push(upload_key);
+            }
+        }
+        
+        // Lookups for this case may have learned to go straight to the original
+        super::dicom_routes::forget_key_layout(case_id);
+        
+        info!("Split case {}: {} instances found, {} written, {} already present, {} not in case, {} failed",
+              case_id, report.instances_found, report.written, report.already_present,
+              report.not_in_case, report.failed.len());
+        Response::new(200, ApiResponse::success(report))
+    }
+
+    // Helper to list a case's stored uploads: the original and any later additions
+    pub async fn stored_upload_keys(s3_client: &S3Client, case_id: &str) -> anyhow::Result<Vec<String>> {
+        let prefix = format!("dicom/{}/", case_id);
+        Ok(s3::list_files(s3_client, &prefix).await?
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| {
+                let name = key.trim_start_matches(&prefix);
+                name == "original.dcm" || (name.starts_with("additional_") && !name.contains('/'))
+            })
+            .collect())
+    }
+
+    // Every S3 prefix holding objects that belong to a case (DICOM sidecars live under dicom/,
+    // offloaded instance lists under cases/)
+    const CASE_OBJECT_PREFIXES: [&str; 4] = ["dicom", "thumbnails", "attachments", "cases"];
+
+    // Helper to remove everything a case owns in S3. Safe to re-run: each pass lists what
+    // is still there, and deleting a key that is already gone succeeds.
+    pub async fn purge_case_objects<S: s3::ObjectStore>(store: &S, case_id: &str) -> CleanupReport {
+        let mut keys = Vec::new();
+        let mut unlisted_prefixes = Vec::new();
+        for prefix in CASE_OBJECT_PREFIXES {
+            let prefix = format!("{}/{}/", prefix, case_id);
+            match store.list_keys(&prefix).await {
+                Ok(listed) => keys.extend(listed),
+                Err(e) => {
+                    warn!("Failed to list {} for cleanup: {:?}", prefix, e);
+                    unlisted_prefixes.push(prefix);
+                }
+            }
+        }
+
+        let failed = s3::delete_files_tolerant(store, &keys).await;
+        let report = CleanupReport {
+            case_id: case_id.to_string(),
+            deleted: keys.len() - failed.len(),
+            failed,
+            unlisted_prefixes,
+        };
+        if !report.is_complete() {
+            warn!("Cleanup of case {} left {} keys and {} prefixes behind",
+                case_id, report.failed.len(), report.unlisted_prefixes.len());
+        }
+        report
+    }
+
+    // POST /api/cases/{upload_id}/resume - Continue a staged create from its last completed stage
+    pub async fn resume_case(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        xray_client: &aws_sdk_xray::Client,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let upload_id = path.trim_start_matches("/api/cases/").trim_end_matches("/resume");
+        info!("Resuming staged upload: {}", upload_id);
+        
+        if !staging::is_valid_upload_id(upload_id) {
+            return bad_request("Invalid upload id");
+        }
+        
+        match staging::load_record(s3_client, upload_id).await {
+            Ok(Some(record)) => advance_staged_case(db_client, s3_client, xray_client, record, None).await,
+            Ok(None) => not_found(&format!("No staged upload found: {}", upload_id)),
+            Err(e) => {
+                error!("Failed to load staging record for {}: {:?}", upload_id, e);
+                staging_failure(e)
+            }
+        }
+    }
+
+    // Helper that drives a staged create through its remaining stages, saving progress after each.
+    // dicom_data is the uploaded payload when called from create; resume reloads it from S3.
+    async fn advance_staged_case(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        xray_client: &aws_sdk_xray::Client,
+        mut record: StagingRecord,
+        dicom_data: Option<Vec<u8>>
+    ) -> Result<Response, LambdaError> {
+        let original_key = format!("dicom/{}/original.dcm", record.case_id);
+        
+        if record.stage == StagingStage::Saved {
+            info!("Staged upload {} already saved as case {}", record.upload_id, record.case_id);
+            return match record.case {
+                Some(case) => Response::new(200, ApiResponse::success(case)),
+                None => server_error("Staging record is missing its case"),
+            };
+        }
+        
+        if record.stage == StagingStage::Received {
+            let data = match &dicom_data {
+                Some(data) => data,
+                None => return conflict("Upload never reached storage; resubmit it with the same uploadId"),
+            };
+            
+            if !record.is_test_data {
+                telemetry::send_xray_trace(xray_client, "s3-upload-start").await;
+                if let Err(e) = s3::upload_file(s3_client, &original_key, data.clone()).await {
+                    error!("Error uploading original DICOM file for {}: {:?}", record.upload_id, e);
+                    return staging_failure(e);
+                }
+                telemetry::send_xray_trace(xray_client, "s3-upload-complete").await;
+            }
+            
+            record.stage = StagingStage::Uploaded;
+            if let Err(e) = staging::save_record(s3_client, &mut record).await {
+                return staging_failure(e);
+            }
+        }
+        
+        if record.stage == StagingStage::Uploaded {
+            let data = match dicom_data {
+                Some(data) => data,
+                None if record.is_test_data => vec![0u8; 10],
+                None => match s3::download_file(s3_client, &original_key).await {
+                    Ok(data) => data,
+                    Err(e) => {
+                        error!("Failed to reload original DICOM file {}: {:?}", original_key, e);
+                        return staging_failure(e);
+                    }
+                },
+            };
+            
+            let prepared = match build_case(
+                xray_client, record.case_id.clone(), &record.upload, data, record.is_test_data
+            ).await {
+                Ok(prepared) => prepared,
+                Err(rejection) => return rejection,
+            };
+            
+            // Same refusal as the direct create; the record stays at Uploaded so a forced retry can finish it
+            if !record.is_test_data && !record.force {
+                if let Some(existing) = find_duplicate_study(db_client, &prepared.case).await {
+                    warn!("Study {} already has case {}, refusing staged upload {}",
+                          prepared.case.study_instance_uid, existing.case_id, record.upload_id);
+                    return Response::new(409, DuplicateStudyResponse::new(existing));
+                }
+            }
+            
+            if !record.is_test_data {
+                precompute_thumbnails(s3_client, &prepared.case, &prepared.dicom_data).await;
+                store_json_sidecars(s3_client, &record.case_id, &prepared.dicom_data).await;
+            }
+            
+            record.case = Some(prepared.case);
+            record.stage = StagingStage::MetadataExtracted;
+            if let Err(e) = staging::save_record(s3_client, &mut record).await {
+                return staging_failure(e);
+            }
+        }
+        
+        let case = match record.case.clone() {
+            Some(case) => case,
+            None => return server_error("Staging record is missing its case"),
+        };
+        
+        telemetry::send_xray_trace(xray_client, "dynamodb-save-start").await;
+        if let Err(e) = db::save_case(db_client, &case).await {
+            error!("DynamoDB save error for staged upload {}: {:?}", record.upload_id, e);
+            return staging_failure(e);
+        }
+        telemetry::send_xray_trace(xray_client, "dynamodb-save-complete").await;
+        
+        // The case is saved; a stale record only means a retry saves the same item again
+        record.stage = StagingStage::Saved;
+        if let Err(e) = staging::save_record(s3_client, &mut record).await {
+            warn!("Failed to mark staged upload {} as saved: {:?}", record.upload_id, e);
+        }
+        
+        telemetry::send_xray_trace(xray_client, "create-case-complete").await;
+        Response::new(201, ApiResponse::success(case))
+    }
+
+    // Helper to report a failed stage; progress so far stays recorded for resume
+    fn staging_failure(e: anyhow::Error) -> Result<Response, LambdaError> {
+        if upstream::is_timeout(&e) {
+            gateway_timeout("Upstream timeout")
+        } else {
+            server_error(&format!("Staged create failed, retry or resume to continue: {}", e))
+        }
+    }
+
+    // POST /api/cases/validate - Dry run of create_case that persists nothing; data that
+    // cannot be read as DICOM gets the same 4xx create_case would return
+    pub async fn validate_case(
+        xray_client: &aws_sdk_xray::Client,
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        telemetry::send_xray_trace(xray_client, "validate-case-start").await;
+        
+        let PreparedCase { case, metadata_list, .. } = match prepare_case(xray_client, body).await {
+            Ok(prepared) => prepared,
+            Err(rejection) => return rejection,
+        };
+        
+        let preview = CasePreview {
+            instance_count: metadata_list.len(),
+            series_count: case.series.len(),
+            detected_modality: metadata_list.iter()
+                .map(|metadata| metadata.modality.clone())
+                .find(|modality| !modality.is_empty())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            phi_warnings: phi_warnings(&metadata_list),
+            case,
+        };
+        
+        info!("Validated upload: {} instances in {} series, {} PHI warnings",
+              preview.instance_count, preview.series_count, preview.phi_warnings.len());
+        telemetry::send_xray_trace(xray_client, "validate-case-complete").await;
+        
+        Response::new(200, ApiResponse::success(preview))
+    }
+
+    // Helper function to flag identifying patient data left in the upload
+    fn phi_warnings(metadata_list: &[DicomMetadata]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        
+        let named = metadata_list.iter()
+            .any(|meta| !meta.patient_name.is_empty() && meta.patient_name != "Anonymous");
+        if named {
+            warnings.push("PatientName is present; the study may not be anonymized".to_string());
+        }
+        
+        let identified = metadata_list.iter()
+            .any(|meta| !meta.patient_id.is_empty() && meta.patient_id != "Unknown");
+        if identified {
+            warnings.push("PatientID is present; the study may not be anonymized".to_string());
+        }
+        
+        warnings
+    }
+
+    // POST /api/cases/{id}/images - Add images to existing case
+    // Extract case_id from path: format is /api/cases/{case_id}/images (a trailing slash is allowed)
+    fn images_case_id(path: &str) -> Result<&str, Result<Response, LambdaError>> {
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        if parts.len() != 5 || parts[4] != "images" {
+            return Err(bad_request("Invalid URL format for adding images"));
+        }
+        
+        let case_id = parts[3];
+        if case_id.is_empty() {
+            return Err(bad_request("Missing case id in path"));
+        }
+        if !is_valid_case_id(case_id) {
+            return Err(bad_request(&format!("Invalid case id: {}", case_id)));
+        }
+        Ok(case_id)
+    }
+    
+    pub async fn add_images(
+        db_client: &DynamoDbClient, 
+        s3_client: &S3Client,
+        xray_client: &aws_sdk_xray::Client, 
+        path: &str, 
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        let case_id = match images_case_id(path) {
+            Ok(case_id) => case_id,
+            Err(response) => return response,
+        };
+        info!("Adding images to case: {}", case_id);
+        telemetry::send_xray_trace(xray_client, &format!("add-images-{}", case_id)).await;
+        
+        // Verify the case exists
+        match db::get_case(db_client, case_id).await? {
+            Some(mut existing_case) => {
+                // Case exists, now process the uploaded file
+                if let Ok(body) = require_body(body) {
+                    info!("Received request to add image to case: {}", case_id);
+                    
+                    // Parse the upload data
+                    #[derive(Deserialize)]
+                    struct ImageUpload {
+                        #[serde(rename = "dicomFile")]
+                        dicom_file: String,
+                    }
+                    
+                    let image_upload: ImageUpload = match serde_json::from_str(body) {
+                        Ok(upload) => upload,
+                        Err(e) => {
+                            error!("Error parsing image upload JSON: {:?}", e);
+                            return bad_request(&format!("Invalid JSON: {}", e));
+                        }
+                    };
+                    
+                    // Check if this is test data
+                    let is_test_data = image_upload.dicom_file == "QVRFTVBJT1JSVEVS=" || 
+                                       image_upload.dicom_file.starts_with("QVRFTVBJT1JSVEVS") ||
+                                       image_upload.dicom_file.starts_with("AA");
+                    
+                    // Ensure the DICOM directory exists
+                    if let Err(e) = ensure_dicom_dir_exists() {
+                        warn!("Failed to create DICOM directory: {:?}", e);
+                    }
+                    
+                    // Decode or create test DICOM data
+                    let dicom_data = if is_test_data {
+                        info!("Detected test data, using dummy data");
+                        vec![0u8; 10]
+                    } else {
+                        match decode_dicom_field(&image_upload.dicom_file) {
+                            Ok(data) => {
+                                info!("Successfully decoded base64 data. Size: {} bytes", data.len());
+                                data
+                            },
+                            Err(message) => {
+                                error!("Error decoding base64: {}", message);
+                                return bad_request(&message);
+                            }
+                        }
+                    };
+                    
+                    telemetry::send_xray_trace(xray_client, "dicom-processing").await;
+                    
+                    // Process the DICOM data
+                    let metadata_list = if is_test_data {
+                        // For test data, create a dummy metadata entry
+                        vec![
+                            DicomMetadata {
+                                sop_instance_uid: format!("1.2.3.4.5.6.7.8.9.{}", Uuid::new_v4()),
+                                modality: "CT".to_string(),
+                                study_instance_uid: existing_case.study_instance_uid.clone(),
+                                series_instance_uid: existing_case.series_instance_uid.clone(),
+                                patient_name: "TEST PATIENT".to_string(),
+                                patient_id: "TEST123".to_string(),
+                                study_date: "20250228".to_string(),
+                                study_description: "TEST STUDY".to_string(),
+                                series_description: "TEST SERIES".to_string(),
+                                instance_number: 1,
+                                series_number: 1,
+                                study_related_instances: None,
+                                series_related_instances: None,
+                                extra: std::collections::HashMap::new(),
+                                frame: None,
+                                referenced_sop_instance_uids: Vec::new(),
+                                acquisition_datetime: None,
+                                body_part_examined: String::new(),
+                                laterality: String::new(),
+                            }
+                        ]
+                    } else {
+                        // For real data, process all series in the study
+                        match process_study_data(&dicom_data) {
+                            Ok(parsed) => {
+                                log_parse_result(&parsed);
+                                parsed.instances
+                            },
+                            Err(e) if is_refused_upload(&e) => return dicom_error_response(&e),
+                            Err(e) => {
+                                error!("Error processing DICOM study: {:?}", e);
+                                
+                                // Fallback to single extraction
+                                match extract_metadata(&dicom_data) {
+                                    Ok(metadata) => {
+                                        info!("Successfully extracted basic metadata");
+                                        vec![metadata]
+                                    },
+                                    Err(e) => {
+                                        error!("Error extracting metadata: {:?}", e);
+                                        return dicom_error_response(&e);
+                                    }
+                                }
+                            }
+                        }
+                    };
+                    
+                    info!("Found {} instances in the additional DICOM data", metadata_list.len());
+                    
+                    // Make sure the new images come from the same study as the case
+                    let foreign_studies = foreign_study_uids(&existing_case.study_instance_uid, &metadata_list);
+                    if !foreign_studies.is_empty() {
+                        if reject_study_mismatch() {
+                            error!("Refusing images from study {} for case {} (study {})",
+                                   foreign_studies.join(", "), case_id, existing_case.study_instance_uid);
+                            return bad_request(&format!(
+                                "Images belong to study {} but case {} is study {}",
+                                foreign_studies.join(", "), case_id, existing_case.study_instance_uid));
+                        }
+                        warn!("Adding images from study {} to case {} (study {}) as STUDY_UID_MISMATCH=warn",
+                              foreign_studies.join(", "), case_id, existing_case.study_instance_uid);
+                    }
+                    
+                    // Legacy cases were saved without a study UID; adopt the one from this upload
+                    if existing_case.study_instance_uid.is_empty() {
+                        if let Some(metadata) = metadata_list.iter().find(|m| !m.study_instance_uid.is_empty()) {
+                            info!("Case {} has no study UID, adopting {}", case_id, metadata.study_instance_uid);
+                            existing_case.study_instance_uid = metadata.study_instance_uid.clone();
+                        }
+                    }
+                    
+                    // Group by series
+                    let mut series_map: std::collections::HashMap<String, Vec<&DicomMetadata>> = std::collections::HashMap::new();
+                    for metadata in &metadata_list {
+                        series_map.entry(metadata.series_instance_uid.clone())
+                            .or_insert_with(Vec::new)
+                            .push(metadata);
+                    }
+                    
+                    info!("New DICOM data contains {} series", series_map.len());
+                    
+                    // Upload to S3 if this isn't a test case
+                    if !is_test_data {
+                        telemetry::send_xray_trace(xray_client, &format!("s3-upload-additional-{}", case_id)).await;
+                        
+                        // First save the complete original file
+                        let original_key = format!("dicom/{}/additional_{}.dcm", 
+                                                 case_id, 
+                                                 Uuid::new_v4());
+                        
+                        store_json_sidecars(s3_client, case_id, &dicom_data).await;
+                        
+                        match s3::upload_file(s3_client, &original_key, dicom_data.clone()).await {
+                            Ok(_) => info!("Uploaded additional DICOM file to S3: {}", original_key),
+                            Err(e) if upstream::is_timeout(&e) => {
+                                error!("Timed out uploading additional DICOM file, leaving case unchanged: {:?}", e);
+                                return gateway_timeout("Upstream timeout");
+                            },
+                            Err(e) => error!("Error uploading additional DICOM file: {:?}", e),
+                        }
+                        
+                        // Also register paths for individual instances
+                        for metadata in &metadata_list {
+                            let instance_key = format!("dicom/{}/{}/{}.dcm", 
+                                                     case_id, 
+                                                     metadata.study_instance_uid,
+                                                     metadata.sop_instance_uid);
+                            
+                            debug!("Registered instance: {}", instance_key);
+                        }
+                    }
+                    
+                    // Update the case with new instances
+                    update_case_with_new_instances(&mut existing_case, &series_map);
+                    for metadata in &metadata_list {
+                        existing_case.record_references(metadata);
+                    }
+                    existing_case.touch();
+                    
+                    // New series get their previews once they are part of the case
+                    if !is_test_data {
+                        precompute_thumbnails(s3_client, &existing_case, &dicom_data).await;
+                    }
+                    
+                    // Update the case in the database
+                    telemetry::send_xray_trace(xray_client, &format!("dynamodb-update-{}", case_id)).await;
+                    
+                    match db::save_case(db_client, &existing_case).await {
+                        Ok(_) => info!("DynamoDB update successful"),
+                        Err(e) if db::is_case_deleted(&e) => return not_found(&format!("Case not found: {}", case_id)),
+                        Err(e) if upstream::is_timeout(&e) => {
+                            error!("DynamoDB update timed out: {:?}", e);
+                            return gateway_timeout("Upstream timeout");
+                        },
+                        Err(e) => {
+                            error!("DynamoDB update error: {:?}", e);
+                            return server_error(&format!("Failed to update case: {}", e));
+                        }
+                    }
+                    
+                    // Return success response with updated case
+                    Ok(Response::new(200, ApiResponse::success(existing_case))?)
+                } else {
+                    error!("Missing request body for image upload");
+                    bad_request("Missing request body")
+                }
+            },
+            None => {
+                error!("Case not found: {}", case_id);
+                not_found(&format!("Case not found: {}", case_id))
+            }
+        }
+    }
+
+    // Cross-study uploads are rejected unless STUDY_UID_MISMATCH is set to "warn"
+    fn reject_study_mismatch() -> bool {
+        !config::get().warn_on_study_mismatch
+    }
+
+    // Helper to list study UIDs in an upload that differ from the case's study.
+    // Legacy cases with no study UID only conflict when the upload itself mixes studies.
+    fn foreign_study_uids(case_study_uid: &str, metadata_list: &[DicomMetadata]) -> Vec<String> {
+        let mut uids: Vec<String> = Vec::new();
+        for metadata in metadata_list {
+            let uid = &metadata.study_instance_uid;
+            if !uid.is_empty() && !uids.contains(uid) {
+                uids.push(uid.clone());
+            }
+        }
+        
+        if case_study_uid.is_empty() {
+            return if uids.len() > 1 { uids } else { Vec::new() };
+        }
+        
+        uids.retain(|uid| uid != case_study_uid);
+        uids
+    }
+
+    // Helper to pick a case's modality: the uploader's choice, then the first instance with one
+    // (extraction already falls back to the SOP Class), then "Unknown" - never a guessed CT
+    fn resolve_modality(upload_modality: &str, metadata_list: &[DicomMetadata]) -> String {
+        std::iter::once(upload_modality)
+            .chain(metadata_list.iter().map(|metadata| metadata.modality.as_str()))
+            .map(str::trim)
+            .find(|modality| !modality.is_empty())
+            .unwrap_or(UNKNOWN_MODALITY)
+            .to_string()
+    }
+
+    // Modality recorded when neither the upload nor the DICOM data says what it is
+    const UNKNOWN_MODALITY: &str = "Unknown";
+
+    // Helper function for processing DICOM data
+    async fn process_dicom_data(
+        dicom_data: &[u8], 
+        is_test_data: bool, 
+        modality: &str
+    ) -> Result<ProcessedDicom, Result<Response, LambdaError>> {
+        if is_test_data {
+            // For test data, create a dummy metadata entry
+            info!("Using dummy metadata for test case");
+            let metadata_list = vec![
+                DicomMetadata {
+                    sop_instance_uid: "1.2.3.4.5.6.7.8.9.0".to_string(),
+                    modality: if !modality.is_empty() { modality.to_string() } else { "CT".to_string() },
+                    study_instance_uid: "1.2.3.4.5.6.7.8.9.1".to_string(),
+                    series_instance_uid: "1.2.3.4.5.6.7.8.9.2".to_string(),
+                    patient_name: "TEST PATIENT".to_string(),
+                    patient_id: "TEST123".to_string(),
+                    study_date: "20250228".to_string(),
+                    study_description: "TEST STUDY".to_string(),
+                    series_description: "TEST SERIES".to_string(),
+                    instance_number: 1,
+                    series_number: 1,
+                    study_related_instances: None,
+                    series_related_instances: None,
+                    extra: std::collections::HashMap::new(),
+                    frame: None,
+                    referenced_sop_instance_uids: Vec::new(),
+                    acquisition_datetime: None,
+                    body_part_examined: String::new(),
+                    laterality: String::new(),
+                }
+            ];
+            Ok(ProcessedDicom {
+                metadata_list,
+                strategy: ParseStrategy::TestData,
+                fallback_used: false,
+                warnings: Vec::new(),
+            })
+        } else {
+            // For real data, process the study to extract all series
+            match process_study_data(dicom_data) {
+                Ok(parsed) => {
+                    log_parse_result(&parsed);
+                    Ok(ProcessedDicom {
+                        fallback_used: matches!(parsed.strategy, ParseStrategy::SingleFallback | ParseStrategy::EnhancedDetection),
+                        strategy: parsed.strategy,
+                        metadata_list: parsed.instances,
+                        warnings: parsed.warnings,
+                    })
+                },
+                // Oversized or malformed uploads are refused outright rather than reduced to one instance
+                Err(e) if is_refused_upload(&e) => Err(dicom_error_response(&e)),
+                Err(e) => {
+                    warn!("Error extracting metadata: {:?}, falling back to basic extraction", e);
+                    let warnings = vec![format!("Study parse failed, used basic extraction: {}", e)];
+                    
+                    // Fallback to basic extraction
+                    match extract_metadata(dicom_data) {
+                        Ok(metadata) => {
+                            info!("Successfully extracted basic metadata");
+                            Ok(ProcessedDicom {
+                                metadata_list: vec![metadata],
+                                strategy: ParseStrategy::BasicExtraction,
+                                fallback_used: true,
+                                warnings,
+                            })
+                        },
+                        // Nothing could be read, so there are no real UIDs to store the case under;
+                        // tell the client what is wrong with the file instead
+                        Err(e) => {
+                            error!("Error extracting basic metadata: {:?}", e);
+                            Err(dicom_error_response(&e))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Helper to log which parser strategy handled an upload, for monitoring parser quality
+    pub fn log_parse_result(parsed: &StudyParseResult) {
+        let summary = format!("STUDY PARSED: strategy={}, instances={}, warnings={}, elapsed_ms={}",
+                              parsed.strategy.as_str(), parsed.instances.len(), parsed.warnings.len(), parsed.elapsed_ms);
+        if parsed.warnings.is_empty() {
+            info!("{}", summary);
+        } else {
+            warn!("{}", summary);
+            for warning in &parsed.warnings {
+                warn!("Study parse warning: {}", warning);
+            }
+        }
+    }
+
+    // Helper function to create SeriesInfo objects
+    pub fn create_series_info(
+        series_map: &std::collections::HashMap<String, Vec<&DicomMetadata>>
+    ) -> (Vec<SeriesInfo>, Vec<String>) {
+        let mut series_info_list = Vec::new();
+        let mut all_image_ids = Vec::new();
+        
+        for (series_uid, instances) in series_map {
+            // Collect image IDs for this series, in spatial order when frame positions are known
+            let image_ids: Vec<String> = sort_by_frame_position(&sort_by_acquisition(instances)).iter()
+                .map(|meta| meta.sop_instance_uid.clone())
+                .collect();
+            
+            // Use the first instance for series metadata
+            let first_instance = instances[0];
+            
+            let series_info = SeriesInfo {
+                series_instance_uid: series_uid.clone(),
+                series_number: first_instance.series_number,
+                series_description: first_instance.series_description.clone(),
+                modality: first_instance.modality.clone(),
+                image_ids,
+                acquisition_datetime: earliest_acquisition(instances),
+                expected_instance_count: instances.iter().filter_map(|meta| meta.series_related_instances).max(),
+            };
+            
+            series_info_list.push(series_info);
+        }
+        
+        // Multi-phase studies read in acquisition order
+        series_info_list.sort_by(|a, b| a.display_order(b));
+        for series_info in &series_info_list {
+            all_image_ids.extend(series_info.image_ids.iter().cloned());
+        }
+        
+        (series_info_list, all_image_ids)
+    }
+
+    // Helper to order instances by InstanceNumber, breaking ties by acquisition time
+    fn sort_by_acquisition<'a>(instances: &[&'a DicomMetadata]) -> Vec<&'a DicomMetadata> {
+        let mut sorted = instances.to_vec();
+        sorted.sort_by(|a, b| {
+            (a.instance_number, a.acquisition_datetime.is_none(), &a.acquisition_datetime)
+                .cmp(&(b.instance_number, b.acquisition_datetime.is_none(), &b.acquisition_datetime))
+        });
+        sorted
+    }
+
+    // Helper to find when a group of instances was first acquired
+    fn earliest_acquisition(instances: &[&DicomMetadata]) -> Option<String> {
+        instances.iter().filter_map(|meta| meta.acquisition_datetime.clone()).min()
+    }
+
+    // Helper to order enhanced multi-frame instances along the axis their positions vary most.
+    // Instances without a position for every frame keep their original order.
+    fn sort_by_frame_position<'a>(instances: &[&'a DicomMetadata]) -> Vec<&'a DicomMetadata> {
+        let positions: Option<Vec<&Vec<f64>>> = instances.iter()
+            .map(|meta| meta.frame.as_ref().and_then(|frame| frame.image_position_patient.as_ref()))
+            .collect();
+        
+        let mut sorted = instances.to_vec();
+        let positions = match positions {
+            Some(positions) if positions.len() > 1 => positions,
+            _ => return sorted,
+        };
+        
+        let range = |axis: usize| {
+            let values = positions.iter().map(|position| position[axis]);
+            let min = values.clone().fold(f64::INFINITY, f64::min);
+            let max = values.fold(f64::NEG_INFINITY, f64::max);
+            max - min
+        };
+        let axis = (0..3)
+            .max_by(|a, b| range(*a).total_cmp(&range(*b)))
+            .unwrap_or(2);
+        
+        sorted.sort_by(|a, b| {
+            let position = |meta: &DicomMetadata| meta.frame.as_ref()
+                .and_then(|frame| frame.image_position_patient.as_ref())
+                .map_or(0.0, |position| position[axis]);
+            position(a).total_cmp(&position(b))
+        });
+        sorted
+    }
+
+    // Helper function to update a case with new instances
+    fn update_case_with_new_instances(
+        existing_case: &mut Case,
+        series_map: &std::collections::HashMap<String, Vec<&DicomMetadata>>
+    ) {
+        for (series_uid, instances) in series_map {
+            let mut found_series = false;
+            
+            // Check if this series already exists in the case
+            for existing_series in &mut existing_case.series {
+                if &existing_series.series_instance_uid == series_uid {
+                    found_series = true;
+                    
+                    // An earlier acquisition among the new instances moves the series start
+                    existing_series.acquisition_datetime = existing_series.acquisition_datetime.take()
+                        .into_iter()
+                        .chain(earliest_acquisition(instances))
+                        .min();
+                    
+                    // Add new instances to existing series
+                    for instance in sort_by_acquisition(instances) {
+                        // Only add if not already present
+                        if !existing_series.image_ids.contains(&instance.sop_instance_uid) {
+                            existing_series.image_ids.push(instance.sop_instance_uid.clone());
+                            info!("Added instance {} to existing series {}", 
+                                     instance.sop_instance_uid, series_uid);
+                            
+                            // Also add to the flat list for backward compatibility
+                            if !existing_case.image_ids.contains(&instance.sop_instance_uid) {
+                                existing_case.image_ids.push(instance.sop_instance_uid.clone());
+                            }
+                        }
+                    }
+                    
+                    break;
+                }
+            }
+            
+            // If the series doesn't exist, create a new one
+            if !found_series {
+                let first_instance = instances[0];
+                
+                // Get all instance IDs for this series
+                let image_ids: Vec<String> = sort_by_acquisition(instances).iter()
+                    .map(|meta| meta.sop_instance_uid.clone())
+                    .collect();
+                
+                let new_series = SeriesInfo {
+                    series_instance_uid: series_uid.clone(),
+                    series_number: first_instance.series_number,
+                    series_description: first_instance.series_description.clone(),
+                    modality: first_instance.modality.clone(),
+                    image_ids: image_ids.clone(),
+                    acquisition_datetime: earliest_acquisition(instances),
+                    expected_instance_count: instances.iter().filter_map(|meta| meta.series_related_instances).max(),
+                };
+                
+                info!("Added new series {} with {} instances", 
+                         series_uid, image_ids.len());
+                
+                // Add all new image IDs to the flat list for backward compatibility
+                for image_id in &image_ids {
+                    if !existing_case.image_ids.contains(image_id) {
+                        existing_case.image_ids.push(image_id.clone());
+                    }
+                }
+                
+                existing_case.series.push(new_series);
+            }
+        }
+    }
+    
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        
+        #[test]
+        fn attachment_content_type_accepts_allowlisted_types() {
+            assert_eq!(attachment_content_type("application/pdf"), Some("application/pdf"));
+            assert_eq!(attachment_content_type(" Image/PNG "), Some("image/png"));
+            assert_eq!(attachment_content_type("text/plain; charset=utf-8"), Some("text/plain"));
+        }
+        
+        #[test]
+        fn attachment_content_type_rejects_active_content() {
+            for declared in ["text/html", "image/svg+xml", "application/xhtml+xml", "text/javascript", "", "image/png/x"] {
+                assert_eq!(attachment_content_type(declared), None, "{}", declared);
+            }
+        }
+        
+        #[tokio::test]
+        async fn unreadable_upload_is_refused_not_stored_with_placeholders() {
+            let rejection = match process_dicom_data(b"this is not a DICOM file at all", false, "CT").await {
+                Ok(processed) => panic!("stored with strategy {}", processed.strategy.as_str()),
+                Err(rejection) => rejection.expect("a response"),
+            };
+            assert_eq!(rejection.status_code, 415);
+        }
+        
+        #[tokio::test]
+        async fn purge_case_objects_covers_every_prefix() {
+            let case_id = "11111111-1111-1111-1111-111111111111";
+            let owned: Vec<String> = CASE_OBJECT_PREFIXES.iter()
+                .map(|prefix| format!("{}/{}/object", prefix, case_id))
+                .collect();
+            let other = "dicom/22222222-2222-2222-2222-222222222222/original.dcm".to_string();
+            let store = s3::tests::FakeStore::with_objects(owned.iter().cloned().chain([other.clone()]));
+            
+            let report = purge_case_objects(&store, case_id).await;
+            assert!(report.is_complete());
+            assert_eq!(report.deleted, CASE_OBJECT_PREFIXES.len());
+            assert_eq!(*store.objects.lock().unwrap(), vec![other]);
+        }
+        
+        #[tokio::test]
+        async fn purge_case_objects_reports_what_it_could_not_remove() {
+            let case_id = "11111111-1111-1111-1111-111111111111";
+            let stuck = format!("thumbnails/{}/key.png", case_id);
+            let store = s3::tests::FakeStore {
+                unlistable_prefixes: std::collections::HashSet::from([format!("attachments/{}/", case_id)]),
+                undeletable_keys: std::collections::HashSet::from([stuck.clone()]),
+                ..s3::tests::FakeStore::with_objects([format!("dicom/{}/original.dcm", case_id), stuck.clone()])
+            };
+            
+            let report = purge_case_objects(&store, case_id).await;
+            assert!(!report.is_complete());
+            assert_eq!(report.deleted, 1);
+            assert_eq!(report.failed, vec![stuck]);
+            assert_eq!(report.unlisted_prefixes, vec![format!("attachments/{}/", case_id)]);
+        }
+        
+        fn case_with_modality(case_id: &str, modality: &str) -> Case {
+            serde_json::from_value(serde_json::json!({
+                "case_id": case_id, "title": "t", "description": "", "modality": modality,
+                "anatomy": "", "diagnosis": "d", "findings": "", "tags": [], "image_ids": [],
+                "study_instance_uid": "", "series_instance_uid": "", "created_at": "", "updated_at": ""
+            })).expect("case")
+        }
+        
+        #[test]
+        fn thumbnail_image_ids_cover_the_default_and_each_series_in_display_order() {
+            let mut case = case_with_modality("c", "CT");
+            for (uid, number, first) in [("1.2.2", 2, "1.2.2.1"), ("1.2.1", 1, "1.2.1.1")] {
+                case.series.push(SeriesInfo {
+                    series_instance_uid: uid.to_string(),
+                    series_number: number,
+                    series_description: String::new(),
+                    modality: "CT".to_string(),
+                    image_ids: vec![first.to_string(), format!("{}0", first)],
+                    acquisition_datetime: None,
+                    expected_instance_count: None,
+                });
+            }
+            
+            // Without a key image the default is the first stored series' first image
+            assert_eq!(thumbnail_image_ids(&case), vec!["1.2.2.1", "1.2.1.1"]);
+            
+            case.key_image_sop = Some("1.2.1.10".to_string());
+            assert_eq!(thumbnail_image_ids(&case), vec!["1.2.1.10", "1.2.1.1", "1.2.2.1"]);
+        }
+        
+        #[test]
+        fn images_case_id_rejects_empty_and_malformed_segments() {
+            let case_id = "11111111-1111-1111-1111-111111111111";
+            assert_eq!(images_case_id(&format!("/api/cases/{}/images", case_id)).ok(), Some(case_id));
+            assert_eq!(images_case_id(&format!("/api/cases/{}/images/", case_id)).ok(), Some(case_id));
+            
+            for path in ["/api/cases//images", "/api/cases//images/", "/api/cases/not-a-uuid/images",
+                         "/api/cases/images", "/api/cases/{}/images/extra"] {
+                let response = images_case_id(path).unwrap_err().expect("response");
+                assert_eq!(response.status_code, 400, "{}", path);
+            }
+            let response = images_case_id("/api/cases//images").unwrap_err().expect("response");
+            assert!(response.body.contains("Missing case id in path"));
+        }
+        
+        fn instances_of_studies(study_uids: &[&str]) -> Vec<DicomMetadata> {
+            study_uids.iter()
+                .map(|uid| DicomMetadata { study_instance_uid: uid.to_string(), ..Default::default() })
+                .collect()
+        }
+        
+        #[test]
+        fn foreign_study_uids_flags_instances_from_other_studies() {
+            let upload = instances_of_studies(&["1.2.3", "1.2.3", "", "4.5.6", "4.5.6"]);
+            assert_eq!(foreign_study_uids("1.2.3", &upload), vec!["4.5.6"]);
+            assert!(foreign_study_uids("1.2.3", &instances_of_studies(&["1.2.3", ""])).is_empty());
+        }
+        
+        #[test]
+        fn foreign_study_uids_on_legacy_cases_only_flags_mixed_uploads() {
+            assert!(foreign_study_uids("", &instances_of_studies(&["4.5.6", "4.5.6"])).is_empty());
+            assert_eq!(foreign_study_uids("", &instances_of_studies(&["1.2.3", "4.5.6"])), vec!["1.2.3", "4.5.6"]);
+        }
+        
+        #[test]
+        fn resolve_modality_prefers_the_upload_then_instances_never_ct() {
+            let instances = |modalities: &[&str]| -> Vec<DicomMetadata> {
+                modalities.iter()
+                    .map(|modality| DicomMetadata { modality: modality.to_string(), ..Default::default() })
+                    .collect()
+            };
+            assert_eq!(resolve_modality(" MR ", &instances(&["CT"])), "MR");
+            assert_eq!(resolve_modality("", &instances(&["", " US", "CT"])), "US");
+            assert_eq!(resolve_modality("  ", &instances(&["", ""])), UNKNOWN_MODALITY);
+            assert_eq!(resolve_modality("", &[]), UNKNOWN_MODALITY);
+        }
+        
+        #[test]
+        fn decode_dicom_field_accepts_bare_base64_and_data_urls() {
+            assert_eq!(decode_dicom_field(" RElDTQ== ").unwrap(), b"DICM");
+            assert_eq!(decode_dicom_field("data:application/dicom;base64,RElDTQ==").unwrap(), b"DICM");
+            assert_eq!(decode_dicom_field("DATA:application/octet-stream;BASE64,RElDTQ==").unwrap(), b"DICM");
+            
+            assert!(decode_dicom_field("data:text/plain,DICM").unwrap_err().contains("not a base64 one"));
+            assert!(decode_dicom_field("not base64!").unwrap_err().contains("not valid base64"));
+        }
+        
+        #[test]
+        fn created_response_reports_unstored_instances_as_207() {
+            let report = |unstored_instances: Vec<String>| ProcessingReport {
+                instances_found: 2,
+                series_found: 1,
+                parse_strategy: "full".to_string(),
+                fallback_used: false,
+                warnings: Vec::new(),
+                phi_warning: false,
+                unstored_instances,
+            };
+            assert_eq!(created_response(case_with_modality("a", "CT"), report(Vec::new())).unwrap().status_code, 201);
+            
+            let response = created_response(case_with_modality("a", "CT"), report(vec!["1.2.3.1".to_string()])).unwrap();
+            assert_eq!(response.status_code, 207);
+            assert!(response.body.contains("1 of 2 instances could not be stored"));
+            assert!(response.body.contains("1.2.3.1"));
+        }
+        
+        #[test]
+        fn instance_count_warnings_flag_only_shortfalls() {
+            let mut case = case_with_modality("a", "CT");
+            assert!(instance_count_warnings(&case, 118).is_empty());
+            
+            case.expected_instance_count = Some(120);
+            assert_eq!(instance_count_warnings(&case, 118),
+                       vec!["Source says 120 instances, extracted 118; the upload may be partial"]);
+            // Multi-frame objects can extract to more instances than the source counted
+            assert!(instance_count_warnings(&case, 240).is_empty());
+            
+            case.expected_instance_count = None;
+            case.series = serde_json::from_value(serde_json::json!([
+                { "series_instance_uid": "1.2.3.1", "series_number": 1, "series_description": "", "modality": "CT",
+                  "image_ids": ["1.2.3.1.1"], "expected_instance_count": 2 },
+                { "series_instance_uid": "1.2.3.2", "series_number": 2, "series_description": "", "modality": "CT",
+                  "image_ids": ["1.2.3.2.1"], "expected_instance_count": 1 }
+            ])).expect("series");
+            assert_eq!(instance_count_warnings(&case, 2),
+                       vec!["Series 1 (1.2.3.1): source says 2 instances, extracted 1"]);
+        }
+        
+        #[test]
+        fn cases_to_csv_defuses_formula_cells() {
+            let mut case = case_with_modality("a", "CT");
+            case.title = "=HYPERLINK(\"http://x\")".to_string();
+            case.diagnosis = "-2+3".to_string();
+            case.anatomy = "Knee, \"left\"".to_string();
+            
+            let csv = cases_to_csv(&[case]);
+            let row = csv.lines().nth(1).expect("row");
+            assert_eq!(row, "\"a\",\"'=HYPERLINK(\"\"http://x\"\")\",\"CT\",\"Knee, \"\"left\"\"\",\"'-2+3\",\"\",0");
+            
+            let mut case = case_with_modality("b", "MR");
+            case.title = "@SUM(A1)".to_string();
+            case.diagnosis = "+1".to_string();
+            assert!(cases_to_csv(&[case]).contains("\"'@SUM(A1)\",\"MR\",\"\",\"'+1\""));
+        }
+        
+        #[test]
+        fn sort_cases_orders_scanned_cases_by_created_at() {
+            let created = |case_id: &str, created_at: &str| Case { created_at: created_at.to_string(), ..case_with_modality(case_id, "CT") };
+            let mut cases = vec![created("a", "2025-01-01T00:00:00+00:00"), created("b", "not a date"),
+                                 created("c", "2025-03-01T00:00:00.123+00:00"), created("d", "2025-02-01T00:00:00Z")];
+            let ids = |cases: &[Case]| cases.iter().map(|case| case.case_id.clone()).collect::<Vec<_>>();
+            
+            sort_cases(&mut cases, "created_at", true);
+            assert_eq!(ids(&cases), vec!["c", "d", "a", "b"]);
+            sort_cases(&mut cases, "created_at", false);
+            assert_eq!(ids(&cases), vec!["a", "d", "c", "b"]);
+        }
+        
+        #[test]
+        fn fill_page_resumes_after_the_last_case_taken() {
+            let scanned = vec![case_with_modality("a", "CT"), case_with_modality("b", "MR"),
+                               case_with_modality("c", "CT"), case_with_modality("d", "CT")];
+            let mut cases = Vec::new();
+            let resume = fill_page(&mut cases, scanned, 2, |case| case.modality == "CT");
+            assert_eq!(resume.as_deref(), Some("c"));
+            assert_eq!(cases.iter().map(|case| case.case_id.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+        }
+        
+        #[test]
+        fn fill_page_consumes_pages_that_do_not_fill_it() {
+            let mut cases = vec![case_with_modality("a", "CT")];
+            let resume = fill_page(&mut cases, vec![case_with_modality("b", "CT"), case_with_modality("c", "MR")], 3, |_| true);
+            assert_eq!(resume, None);
+            assert_eq!(cases.len(), 3);
+            
+            // Filling up on the last scanned case leaves the scan's own cursor in charge
+            let mut cases = Vec::new();
+            assert_eq!(fill_page(&mut cases, vec![case_with_modality("a", "CT")], 1, |_| true), None);
+        }
+        
+        #[test]
+        fn cleanup_response_asks_for_retry_until_complete() {
+            let report = |failed: Vec<String>| CleanupReport {
+                case_id: "case".to_string(),
+                deleted: 3,
+                failed,
+                unlisted_prefixes: Vec::new(),
+            };
+            assert_eq!(cleanup_response("case", report(Vec::new())).unwrap().status_code, 200);
+            assert_eq!(cleanup_response("case", report(vec!["dicom/case/a.dcm".to_string()])).unwrap().status_code, 500);
+        }
+        
+        #[test]
+        fn placeholder_study_uids_never_mark_duplicates() {
+            assert!(is_identifying_study_uid("1.2.840.113619.2.55.3.1234"));
+            for uid in ["", "unknown.1.2.3", "not-a-uid", "1.2..3"] {
+                assert!(!is_identifying_study_uid(uid), "{}", uid);
+            }
+        }
+        
+        #[test]
+        fn phi_warnings_flag_identifying_fields() {
+            let anonymized = DicomMetadata {
+                patient_name: "Anonymous".to_string(),
+                patient_id: "Unknown".to_string(),
+                ..Default::default()
+            };
+            assert!(phi_warnings(std::slice::from_ref(&anonymized)).is_empty());
+            
+            let named = DicomMetadata { patient_name: "DOE^JANE".to_string(), ..anonymized.clone() };
+            let identified = DicomMetadata { patient_id: "MRN123".to_string(), ..anonymized.clone() };
+            assert_eq!(phi_warnings(&[anonymized, named, identified]).len(), 2);
+        }
+    }
+}
+
+// Admin maintenance routes
+pub mod admin {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    // Items read per scan page while exporting
+    const EXPORT_SCAN_PAGE_SIZE: i32 = 100;
+
+    // The admin API key from the loaded config. Admin endpoints are disabled when ADMIN_API_KEY is unset.
+    fn admin_api_key() -> Option<String> {
+        config::get().admin_api_key.clone()
+    }
+
+    // Helper to check the X-Api-Key header against ADMIN_API_KEY
+    fn check_admin_key(request: &Request) -> Result<(), Result<Response, LambdaError>> {
+        let expected = match admin_api_key() {
+            Some(key) => key,
+            None => return Err(forbidden("Admin endpoints are disabled: ADMIN_API_KEY is not set")),
+        };
+        
+        // Compare digests so the check takes the same time however much of the key matches
+        let digest = |key: &str| ring::digest::digest(&ring::digest::SHA256, key.as_bytes());
+        match request.header("X-Api-Key") {
+            Some(given) if digest(given).as_ref() == digest(&expected).as_ref() => Ok(()),
+            _ => {
+                warn!("Rejected admin request with a missing or wrong API key");
+                Err(forbidden("Missing or invalid X-Api-Key"))
+            }
+        }
+    }
+
+    // GET /api/admin/export?cursor={case_id} - Dump cases as NDJSON, one Case per line.
+    // A response stops short of the inline size limit; X-Next-Cursor then names where to continue.
+    pub async fn export_cases(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        if let Err(rejection) = check_admin_key(request) {
+            return rejection;
+        }
+        
+        let mut cursor = request.query_param("cursor").map(str::to_string);
+        if cursor.as_deref().is_some_and(|case_id| !is_valid_case_id(case_id)) {
+            return bad_request("Invalid cursor: expected a case id");
+        }
+        
+        let mut body = String::new();
+        let mut exported = 0;
+        'pages: loop {
+            let db::CasePage { cases, next, .. } = db::scan_cases_page(db_client, cursor.as_deref(), EXPORT_SCAN_PAGE_SIZE).await?;
+            for case in cases {
+                let line = serde_json::to_string(&case)?;
+                if !body.is_empty() && (body.len() + line.len() + 1) as i64 > MAX_INLINE_RESPONSE_BYTES {
+                    // The next call resumes right after the last case written here
+                    break 'pages;
+                }
+                
+                body.push_str(&line);
+                body.push('\n');
+                exported += 1;
+                cursor = Some(case.case_id);
+            }
+            
+            if next.is_none() {
+                cursor = None;
+                break;
+            }
+            cursor = next;
+        }
+        
+        info!("Exported {} cases, next cursor {:?}", exported, cursor);
+        let mut response = Response::new(200, "")?
+            .with_content_type("application/x-ndjson")
+            .into_text(body);
+        if let Some(cursor) = cursor {
+            response.headers.insert("X-Next-Cursor".to_string(), cursor);
+        }
+        Ok(response)
+    }
+
+    // POST /api/admin/import - Restore cases from NDJSON as produced by the export
+    pub async fn import_cases(db_client: &DynamoDbClient, request: &Request) -> Result<Response, LambdaError> {
+        if let Err(rejection) = check_admin_key(request) {
+            return rejection;
+        }
+        
+        let body = match require_body(&request.body) {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        let body = if request.is_base64_encoded {
+            match BASE64.decode(body.trim()) {
+                Ok(decoded) => String::from_utf8_lossy(&decoded).into_owned(),
+                Err(e) => return bad_request(&format!("Invalid base64 body: {}", e)),
+            }
+        } else {
+            body.to_string()
+        };
+        
+        // Every line is checked before anything is written, so a bad file changes nothing
+        let mut cases: Vec<Case> = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, line) in body.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let case: Case = match serde_json::from_str(line) {
+                Ok(case) => case,
+                Err(e) => return bad_request(&format!("Line {}: invalid case JSON: {}", index + 1, e)),
+            };
+            if !is_valid_case_id(&case.case_id) {
+                return bad_request(&format!("Line {}: invalid case id {}", index + 1, case.case_id));
+            }
+            if !seen.insert(case.case_id.clone()) {
+                return bad_request(&format!("Line {}: case {} appears more than once", index + 1, case.case_id));
+            }
+            cases.push(case);
+        }
+        
+        if cases.is_empty() {
+            return bad_request("No cases to import");
+        }
+        
+        let failed = match db::save_cases(db_client, &cases).await {
+            Ok(failed) => failed,
+            Err(e) if upstream::is_timeout(&e) => {
+                error!("Import timed out: {:?}", e);
+                return gateway_timeout("Upstream timeout");
+            },
+            Err(e) => {
+                error!("Import failed: {:?}", e);
+                return server_error(&format!("Failed to import cases: {}", e));
+            }
+        };
+        
+        let report = ImportReport {
+            received: cases.len(),
+            imported: cases.len() - failed.len(),
+            failed,
+        };
+        info!("Imported {} of {} cases", report.imported, report.received);
+        Response::new(200, ApiResponse::success(report))
+    }
+
+    // Cases read per scan request by the whole-table admin sweeps (GC and reindex)
+    const GC_SCAN_PAGE_SIZE: i32 = 100;
+    
+    // Objects younger than this are never collected, so creates still in flight keep their uploads
+    const GC_MIN_AGE_SECS: i64 = 24 * 60 * 60;
+
+    // POST /api/admin/gc - Remove DICOM objects not referenced by any case (dry run unless ?apply=true)
+    pub async fn run_gc(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request
+    ) -> Result<Response, LambdaError> {
+        if let Err(rejection) = check_admin_key(request) {
+            return rejection;
+        }
+        
+        let apply = request.query_param("apply") == Some("true");
+        info!("Running S3 garbage collection (apply={})", apply);
+        
+        // Every page of the table is read; a case missed here would have its objects deleted
+        let mut cases = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = match db::scan_cases_page(db_client, cursor.as_deref(), GC_SCAN_PAGE_SIZE).await {
+                Ok(page) => page,
+                Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+                Err(e) => {
+                    error!("Error listing cases for GC: {:?}", e);
+                    return server_error(&format!("Failed to list cases: {}", e));
+                }
+            };
+            if !page.unreadable.is_empty() {
+                error!("GC stopped: cases {:?} could not be read", page.unreadable);
+                return server_error(&format!(
+                    "GC stopped: {} case(s) could not be read, so their objects cannot be told apart from orphans",
+                    page.unreadable.len()
+                ));
+            }
+            cases.extend(page.cases);
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        let cases: HashMap<&str, &Case> = cases.iter()
+            .map(|case| (case.case_id.as_str(), case))
+            .collect();
+        
+        // A staged create stores its original before the case record exists, and can wait on a
+        // forced retry for longer than the minimum age, so its case id counts as referenced
+        let pending = match staging::pending_case_ids(s3_client).await {
+            Ok(pending) => pending,
+            Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+            Err(e) => {
+                error!("Error reading staging records for GC: {:?}", e);
+                return server_error(&format!("GC stopped: staging records could not be read: {}", e));
+            }
+        };
+        
+        let files = match s3::list_objects(s3_client, "dicom/").await {
+            Ok(files) => files,
+            Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+            Err(e) => {
+                error!("Error listing DICOM objects for GC: {:?}", e);
+                return server_error(&format!("Failed to list DICOM objects: {}", e));
+            }
+        };
+        
+        // A create uploads its objects before saving the case, so recent objects are left alone
+        let cutoff = chrono::Utc::now().timestamp() - GC_MIN_AGE_SECS;
+        let (old, recent): (Vec<_>, Vec<_>) = files.iter()
+            .partition(|object| object.last_modified.is_some_and(|modified| modified < cutoff));
+        let orphaned: Vec<String> = old.iter()
+            .filter(|object| !is_referenced(&object.key, &cases, &pending))
+            .map(|object| object.key.clone())
+            .collect();
+        
+        info!("GC scanned {} objects, {} orphaned, {} too recent to collect", files.len(), orphaned.len(), recent.len());
+        
+        let deleted = if apply && !orphaned.is_empty() {
+            match s3::delete_files(s3_client, &orphaned).await {
+                Ok(deleted) => deleted,
+                Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+                Err(e) => {
+                    error!("Error deleting orphaned objects: {:?}", e);
+                    return server_error(&format!("Failed to delete orphaned objects: {}", e));
+                }
+            }
+        } else {
+            0
+        };
+        
+        Response::new(200, ApiResponse::success(GcReport {
+            applied: apply,
+            scanned: files.len(),
+            skipped_recent: recent.len(),
+            orphaned,
+            deleted,
+        }))
+    }
+
+    // GET /api/admin/cases/{id}/audit - Compare a case's S3 objects with its registered instances
+    pub async fn audit_case(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        if let Err(rejection) = check_admin_key(request) {
+            return rejection;
+        }
+        
+        let case_id = path.trim_start_matches("/api/admin/cases/").trim_end_matches("/audit");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        let case = match db::get_case(db_client, case_id).await {
+            Ok(Some(case)) => case,
+            Ok(None) => return not_found(&format!("Case not found: {}", case_id)),
+            Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+            Err(e) => {
+                error!("Error loading case {} for audit: {:?}", case_id, e);
+                return server_error(&format!("Failed to load case: {}", e));
+            }
+        };
+        
+        let prefix = format!("dicom/{}/", case_id);
+        let files = match s3::list_files(s3_client, &prefix).await {
+            Ok(files) => files,
+            Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+            Err(e) => {
+                error!("Error listing DICOM objects for case {}: {:?}", case_id, e);
+                return server_error(&format!("Failed to list DICOM objects: {}", e));
+            }
+        };
+        
+        let mut uploads = Vec::new();
+        let mut unregistered_objects = Vec::new();
+        let mut stored_instances: HashSet<&str> = HashSet::new();
+        for (key, _) in &files {
+            let rest = key.trim_start_matches(&prefix);
+            if rest == "original.dcm" || (rest.starts_with("additional_") && !rest.contains('/')) {
+                uploads.push(key.clone());
+                continue;
+            }
+            // DICOM JSON sidecars describe instances rather than being them
+            if rest.ends_with(".json") {
+                continue;
+            }
+            
+            match rest.rsplit('/').next().and_then(|name| name.strip_suffix(".dcm")) {
+                Some(sop_instance_uid) if case.contains_instance(sop_instance_uid) => {
+                    stored_instances.insert(sop_instance_uid);
+                },
+                _ => unregistered_objects.push(key.clone()),
+            }
+        }
+        
+        let missing_objects: Vec<String> = case.ordered_image_ids().into_iter()
+            .filter(|sop_instance_uid| !stored_instances.contains(sop_instance_uid.as_str()))
+            .collect();
+        
+        info!("Audit of case {}: {} objects, {} unregistered, {} missing",
+              case_id, files.len(), unregistered_objects.len(), missing_objects.len());
+        
+        Response::new(200, ApiResponse::success(CaseAuditReport {
+            case_id: case.case_id.clone(),
+            registered: case.ordered_image_ids().len(),
+            stored: files.len(),
+            uploads,
+            unregistered_objects,
+            missing_objects,
+        }))
+    }
+
+    // POST /api/admin/reindex - Rebuild series grouping for every case from its stored uploads
+    pub async fn reindex_cases(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request
+    ) -> Result<Response, LambdaError> {
+        if let Err(rejection) = check_admin_key(request) {
+            return rejection;
+        }
+        
+        info!("Reindexing series for all cases");
+        
+        let mut report = ReindexReport {
+            scanned: 0,
+            changed: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+        };
+        
+        // Every page of the table is read, one page at a time
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = match db::scan_cases_page(db_client, cursor.as_deref(), GC_SCAN_PAGE_SIZE).await {
+                Ok(page) => page,
+                Err(e) if upstream::is_timeout(&e) => return gateway_timeout("Upstream timeout"),
+                Err(e) => {
+                    error!("Error listing cases for reindex: {:?}", e);
+                    return server_error(&format!("Failed to list cases: {}", e));
+                }
+            };
+            report.scanned += page.cases.len() + page.unreadable.len();
+            report.failed.extend(page.unreadable);
+            
+            for case in page.cases {
+                reindex_case(db_client, s3_client, case, &mut report).await;
+            }
+            
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        
+        info!("Reindex complete: {} scanned, {} changed, {} skipped, {} failed",
+              report.scanned, report.changed.len(), report.skipped.len(), report.failed.len());
+        
+        Response::new(200, ApiResponse::success(report))
+    }
+
+    // Helper to rebuild one case's series grouping, recording the outcome in the report
+    async fn reindex_case(db_client: &DynamoDbClient, s3_client: &S3Client, mut case: Case, report: &mut ReindexReport) {
+        let case_id = case.case_id.clone();
+        
+        let instances = match load_stored_instances(s3_client, &case_id).await {
+            Ok(instances) if instances.is_empty() => {
+                debug!("No stored uploads for case {}, skipping", case_id);
+                report.skipped.push(case_id);
+                return;
+            },
+            Ok(instances) => instances,
+            Err(e) => {
+                error!("Failed to read stored uploads for case {}: {:?}", case_id, e);
+                report.failed.push(case_id);
+                return;
+            }
+        };
+        
+        let mut series_map: HashMap<String, Vec<&DicomMetadata>> = HashMap::new();
+        for metadata in &instances {
+            series_map.entry(metadata.series_instance_uid.clone()).or_default().push(metadata);
+        }
+        let (mut series, _) = super::cases::create_series_info(&series_map);
+        series.sort_by(|a, b| a.display_order(b).then_with(|| a.series_instance_uid.cmp(&b.series_instance_uid)));
+        let image_ids: Vec<String> = series.iter()
+            .flat_map(|series| series.image_ids.iter().cloned())
+            .collect();
+        
+        // Compare ignoring order so re-running on an already consistent case changes nothing
+        if same_grouping(&case.series, &series) && same_ids(&case.image_ids, &image_ids) {
+            return;
+        }
+        
+        info!("Case {} regrouped: {} -> {} series, {} -> {} images",
+              case_id, case.series.len(), series.len(), case.image_ids.len(), image_ids.len());
+        case.series = series;
+        case.image_ids = image_ids;
+        case.touch();
+        
+        match db::save_case(db_client, &case).await {
+            Ok(_) => report.changed.push(case_id),
+            Err(e) => {
+                error!("Failed to save reindexed case {}: {:?}", case_id, e);
+                report.failed.push(case_id);
+            }
+        }
+    }
+
+    // Helper to re-extract instance metadata from a case's original and additional uploads
+    async fn load_stored_instances(s3_client: &S3Client, case_id: &str) -> anyhow::Result<Vec<DicomMetadata>> {
+        let uploads = super::cases::stored_upload_keys(s3_client, case_id).await?;
+        
+        let mut instances: Vec<DicomMetadata> = Vec::new();
+        for key in uploads {
+            let data = s3::download_file(s3_client, &key).await?;
+            match process_study_data(&data) {
+                Ok(parsed) => {
+                    super::cases::log_parse_result(&parsed);
+                    for metadata in parsed.instances {
+                        if !instances.iter().any(|existing| existing.sop_instance_uid == metadata.sop_instance_uid) {
+                            instances.push(metadata);
+                        }
+                    }
+                },
+                Err(e) => warn!("Skipping unreadable upload {}: {:?}", key, e),
+            }
+        }
+        
+        Ok(instances)
+    }
+
+    // Helper to compare two series lists irrespective of series order
+    fn same_grouping(current: &[SeriesInfo], rebuilt: &[SeriesInfo]) -> bool {
+        let key = |series: &[SeriesInfo]| {
+            let mut entries: Vec<(String, Vec<String>)> = series.iter()
+                .map(|series| {
+                    let mut ids = series.image_ids.clone();
+                    ids.sort();
+                    (series.series_instance_uid.clone(), ids)
+                })
+                .collect();
+            entries.sort();
+            entries
+        };
+        key(current) == key(rebuilt)
+    }
+
+    // Helper to compare two id lists irrespective of order
+    fn same_ids(current: &[String], rebuilt: &[String]) -> bool {
+        let mut current = current.to_vec();
+        let mut rebuilt = rebuilt.to_vec();
+        current.sort();
+        rebuilt.sort();
+        current == rebuilt
+    }
+
+    // Helper to decide whether an object under dicom/ belongs to an existing case or to a
+    // staged create that has not saved its case yet
+    fn is_referenced(key: &str, cases: &HashMap<&str, &Case>, pending: &HashSet<String>) -> bool {
+        let mut parts = key.trim_start_matches("dicom/").splitn(2, '/');
+        let (case_id, rest) = match (parts.next(), parts.next()) {
+            (Some(case_id), Some(rest)) => (case_id, rest),
+            _ => return false,
+        };
+        
+        if pending.contains(case_id) {
+            return true;
+        }
+        
+        let case = match cases.get(case_id) {
+            Some(case) => case,
+            None => return false,
+        };
+        
+        // Uploaded study archives are served by the original download
+        if rest == "original.dcm" || (rest.starts_with("additional_") && !rest.contains('/')) {
+            return true;
+        }
+        
+        // Per-instance objects, either dicom/{case}/{study}/{sop}.dcm or dicom/{case}/{sop}.dcm,
+        // and their DICOM JSON sidecars
+        let sop_instance_uid = match rest.rsplit('/').next()
+            .and_then(|name| name.strip_suffix(".dcm").or_else(|| name.strip_suffix(".json"))) {
+            Some(sop) => sop,
+            None => return false,
+        };
+        
+        case.contains_instance(sop_instance_uid)
+    }
+    
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        
+        fn case(case_id: &str) -> Case {
+            serde_json::from_value(serde_json::json!({
+                "case_id": case_id, "title": "t", "description": "", "modality": "CT",
+                "anatomy": "", "diagnosis": "d", "findings": "", "tags": [], "image_ids": [],
+                "study_instance_uid": "", "series_instance_uid": "", "created_at": "", "updated_at": ""
+            })).expect("case")
+        }
+        
+        #[test]
+        fn is_referenced_keeps_objects_of_pending_staged_creates() {
+            let saved = case("saved");
+            let cases: HashMap<&str, &Case> = HashMap::from([("saved", &saved)]);
+            let pending: HashSet<String> = HashSet::from(["staged".to_string()]);
+            
+            assert!(is_referenced("dicom/saved/original.dcm", &cases, &pending));
+            assert!(is_referenced("dicom/staged/original.dcm", &cases, &pending));
+            assert!(is_referenced("dicom/staged/1.2.3/4.5.6.dcm", &cases, &pending));
+            assert!(!is_referenced("dicom/gone/original.dcm", &cases, &pending));
+            assert!(!is_referenced("dicom/saved/1.2.3/4.5.6.dcm", &cases, &pending));
+        }
+    }
+}
+
+// Favorites routes
+pub mod favorites {
+    use super::*;
+
+    // Longest accepted user id
+    const MAX_USER_ID_LEN: usize = 128;
+
+    // Helper to split /api/users/{user_id}/favorites[/{case_id}] into its ids
+    fn parse_path(path: &str) -> Option<(&str, Option<&str>)> {
+        let parts: Vec<&str> = path.trim_start_matches("/api/users/").split('/').collect();
+        let user_id = parts[0];
+        let valid_user = !user_id.is_empty()
+            && user_id.len() <= MAX_USER_ID_LEN
+            && user_id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c));
+        
+        match parts.as_slice() {
+            [_, "favorites"] if valid_user => Some((user_id, None)),
+            [_, "favorites", case_id] if valid_user && !case_id.is_empty() => Some((user_id, Some(case_id))),
+            _ => None,
+        }
+    }
+
+    // GET /api/users/{user_id}/favorites - Bookmarked cases for a user
+    pub async fn list_favorites(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let user_id = match parse_path(path) {
+            Some((user_id, None)) => user_id,
+            _ => return bad_request("Invalid favorites path"),
+        };
+        
+        let case_ids = db::get_favorites(db_client, user_id).await?;
+        let cases = db::get_cases(db_client, &case_ids).await?;
+        
+        info!("User {} has {} favorites", user_id, cases.len());
+        Response::new(200, ApiResponse::success(cases))
+    }
+
+    // POST /api/users/{user_id}/favorites/{case_id} - Bookmark a case
+    pub async fn add_favorite(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let (user_id, case_id) = match parse_path(path) {
+            Some((user_id, Some(case_id))) => (user_id, case_id),
+            _ => return bad_request("Invalid favorites path"),
+        };
+        
+        if db::get_case(db_client, case_id).await?.is_none() {
+            error!("Case not found: {}", case_id);
+            return not_found(&format!("Case not found: {}", case_id));
+        }
+        
+        let favorites = db::add_favorite(db_client, user_id, case_id).await?;
+        Response::new(200, ApiResponse::success(favorites))
+    }
+
+    // DELETE /api/users/{user_id}/favorites/{case_id} - Remove a bookmark
+    pub async fn remove_favorite(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let (user_id, case_id) = match parse_path(path) {
+            Some((user_id, Some(case_id))) => (user_id, case_id),
+            _ => return bad_request("Invalid favorites path"),
+        };
+        
+        let favorites = db::remove_favorite(db_client, user_id, case_id).await?;
+        Response::new(200, ApiResponse::success(favorites))
+    }
+}
+
+// Study lookup routes
+pub mod studies {
+    use super::*;
+
+    // GET /api/studies/{study_uid} - Find a case by its StudyInstanceUID
+    pub async fn get_case_by_study(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let study_instance_uid = path.trim_start_matches("/api/studies/");
+        info!("Fetching case for study: {}", study_instance_uid);
+        
+        if !is_valid_uid(study_instance_uid) {
+            return bad_request(&format!("Invalid StudyInstanceUID: {}", study_instance_uid));
+        }
+        
+        match db::get_case_by_study(db_client, study_instance_uid).await? {
+            Some(case) => Response::new(200, ApiResponse::success(case)),
+            None => {
+                error!("No case found for study: {}", study_instance_uid);
+                not_found(&format!("No case found for study: {}", study_instance_uid))
+            }
+        }
+    }
+}
+
+// Taxonomy routes
+pub mod taxonomy_routes {
+    use super::*;
+
+    // GET /api/taxonomy/anatomy - List canonical body regions for autocomplete
+    pub async fn list_anatomy() -> Result<Response, LambdaError> {
+        Response::new(200, ApiResponse::success(taxonomy::ANATOMY_TERMS))
+    }
+}
+
+// Tag routes
+pub mod tags {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    // Tag counts from the last scan, kept for the lifetime of the warm Lambda
+    static TAG_CACHE: Mutex<Option<(Instant, Vec<TagCount>)>> = Mutex::new(None);
+
+    /// The tag cache lifetime (TAGS_CACHE_TTL_SECS) from the loaded config
+    fn cache_ttl() -> Duration {
+        Duration::from_secs(config::get().tags_cache_ttl_secs)
+    }
+
+    // GET /api/tags - List the tags in use with how many cases carry each
+    pub async fn list_tags(db_client: &DynamoDbClient) -> Result<Response, LambdaError> {
+        let ttl = cache_ttl();
+        if let Some((computed_at, counts)) = TAG_CACHE.lock().unwrap().as_ref() {
+            if computed_at.elapsed() < ttl {
+                debug!("Serving {} cached tag counts", counts.len());
+                return Response::new(200, ApiResponse::success(counts));
+            }
+        }
+        
+        let cases = db::list_cases(db_client).await?;
+        let counts = count_tags(&cases);
+        info!("Counted {} distinct tags across {} cases", counts.len(), cases.len());
+        
+        *TAG_CACHE.lock().unwrap() = Some((Instant::now(), counts.clone()));
+        Response::new(200, ApiResponse::success(counts))
+    }
+
+    // Most cases one bulk tag request may touch
+    const MAX_BULK_TAG_CASES: usize = 100;
+
+    // Attempts per case when a concurrent edit changes its tags mid-update
+    const BULK_TAG_ATTEMPTS: usize = 3;
+
+    // POST /api/cases/bulk-tag - Add and remove tags across many cases, reporting each id
+    pub async fn bulk_tag(db_client: &DynamoDbClient, body: &Option<String>) -> Result<Response, LambdaError> {
+        let body = match require_body(body) {
+            Ok(body) => body,
+            Err(response) => {
+                error!("Missing request body for bulk tag");
+                return response;
+            }
+        };
+        
+        let request: BulkTagRequest = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => {
+                error!("Error parsing bulk tag JSON: {:?}", e);
+                return bad_request(&format!("Invalid JSON: {}", e));
+            }
+        };
+        
+        let add = clean_tags(&request.add);
+        let remove = clean_tags(&request.remove);
+        if add.is_empty() && remove.is_empty() {
+            return bad_request("Nothing to change: give at least one tag to add or remove");
+        }
+        
+        // Listing an id twice would apply its changes twice, so keep the first mention
+        let mut seen = std::collections::HashSet::new();
+        let ids: Vec<String> = request.ids.iter()
+            .map(|id| id.trim().to_string())
+            .filter(|id| seen.insert(id.clone()))
+            .collect();
+        if ids.is_empty() {
+            return bad_request("ids must list at least one case");
+        }
+        if ids.len() > MAX_BULK_TAG_CASES {
+            return bad_request(&format!("At most {} cases can be tagged at once", MAX_BULK_TAG_CASES));
+        }
+        
+        let mut results = Vec::with_capacity(ids.len());
+        for case_id in &ids {
+            results.push(tag_case(db_client, case_id, &add, &remove).await);
+        }
+        
+        let updated = results.iter().filter(|result| result.success).count();
+        info!("Bulk tag: {} of {} cases updated (add {:?}, remove {:?})", updated, ids.len(), add, remove);
+        
+        // Counts served from the cache would miss these changes
+        *TAG_CACHE.lock().unwrap() = None;
+        Response::new(200, ApiResponse::success(results))
+    }
+
+    // Helper to apply the tag changes to one case, re-reading it if another edit lands first
+    async fn tag_case(db_client: &DynamoDbClient, case_id: &str, add: &[String], remove: &[String]) -> BulkTagResult {
+        let failed = |message: String| BulkTagResult {
+            case_id: case_id.to_string(),
+            success: false,
+            tags: None,
+            error: Some(message),
+        };
+        
+        if !is_valid_case_id(case_id) {
+            return failed("Invalid case id: expected a UUID".to_string());
+        }
+        
+        for _ in 0..BULK_TAG_ATTEMPTS {
+            let case = match db::get_case(db_client, case_id).await {
+                Ok(Some(case)) => case,
+                Ok(None) => return failed(format!("Case not found: {}", case_id)),
+                Err(e) => {
+                    error!("Failed to read case {} for bulk tag: {:?}", case_id, e);
+                    return failed(format!("Failed to read case: {}", e));
+                }
+            };
+            
+            let tags = apply_tag_changes(&case.tags, add, remove);
+            if tags == case.tags {
+                return BulkTagResult { case_id: case_id.to_string(), success: true, tags: Some(tags), error: None };
+            }
+            
+            let now = chrono::Utc::now().to_rfc3339();
+            match db::update_case_tags(db_client, case_id, &case.tags, &tags, &now).await {
+                Ok(true) => {
+                    return BulkTagResult { case_id: case_id.to_string(), success: true, tags: Some(tags), error: None };
+                },
+                Ok(false) => warn!("Case {} changed during bulk tag, retrying", case_id),
+                Err(e) => {
+                    error!("Failed to update tags of case {}: {:?}", case_id, e);
+                    return failed(format!("Failed to update case: {}", e));
+                }
+            }
+        }
+        
+        failed("Case kept changing during the update, try again".to_string())
+    }
+
+    // Helper to trim tags and drop blanks and repeats
+    fn clean_tags(tags: &[String]) -> Vec<String> {
+        let mut cleaned: Vec<String> = Vec::new();
+        for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            if !cleaned.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                cleaned.push(tag.to_string());
+            }
+        }
+        cleaned
+    }
+
+    // Helper to drop removed tags (ignoring case) and append added ones the case lacks
+    fn apply_tag_changes(tags: &[String], add: &[String], remove: &[String]) -> Vec<String> {
+        let mut updated: Vec<String> = tags.iter()
+            .filter(|tag| !remove.iter().any(|removed| removed.eq_ignore_ascii_case(tag)))
+            .cloned()
+            .collect();
+        for tag in add {
+            if !updated.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                updated.push(tag.clone());
+            }
+        }
+        updated
+    }
+
+    // Helper to aggregate tags, most used first and alphabetical among ties
+    fn count_tags(cases: &[Case]) -> Vec<TagCount> {
+        let mut totals: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+        for case in cases {
+            // A tag repeated on one case still counts that case once
+            let distinct: std::collections::HashSet<&str> = case.tags.iter().map(|tag| tag.as_str()).collect();
+            for tag in distinct {
+                *totals.entry(tag).or_default() += 1;
+            }
+        }
+        
+        let mut counts: Vec<TagCount> = totals.into_iter()
+            .map(|(tag, count)| TagCount { tag: tag.to_string(), count })
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        counts
+    }
+}
+
+// Read-only share link routes
+pub mod sharing {
+    use super::*;
+
+    // POST /api/cases/{id}/share - Mint a signed, expiring read-only link to a case
+    pub async fn create_share_link(
+        db_client: &DynamoDbClient,
+        path: &str,
+        body: &Option<String>
+    ) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/share");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        let secret = match share::share_secret() {
+            Some(secret) => secret,
+            None => {
+                error!("Share link requested but SHARE_LINK_SECRET is not configured");
+                return server_error("Sharing is not configured");
+            }
+        };
+        
+        // The body is optional; an empty one takes the defaults
+        let options: ShareRequest = match body.as_deref().map(str::trim).filter(|body| !body.is_empty()) {
+            Some(body) => match serde_json::from_str(body) {
+                Ok(options) => options,
+                Err(e) => {
+                    error!("Error parsing share request JSON: {:?}", e);
+                    return bad_request(&format!("Invalid JSON: {}", e));
+                }
+            },
+            None => ShareRequest::default(),
+        };
+        
+        if db::get_case_cached(db_client, case_id).await?.is_none() {
+            error!("Case not found: {}", case_id);
+            return not_found(&format!("Case not found: {}", case_id));
+        }
+        
+        let ttl_secs = options.expires_in_secs
+            .map(share::clamp_ttl_secs)
+            .unwrap_or_else(share::default_ttl_secs);
+        let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl_secs);
+        let grant = share::ShareGrant {
+            case_id: case_id.to_string(),
+            expires_at: expires_at.timestamp(),
+            hide_diagnosis: options.hide_diagnosis.unwrap_or_else(share::hide_diagnosis_by_default),
+        };
+        
+        let token = share::mint(&secret, &grant);
+        info!("Minted share link for case {} expiring {} (hide_diagnosis={})",
+              case_id, expires_at.to_rfc3339(), grant.hide_diagnosis);
+        
+        Response::new(201, ApiResponse::success(ShareLink {
+            url: format!("/api/shared/{}", token),
+            token,
+            expires_at: expires_at.to_rfc3339(),
+            hide_diagnosis: grant.hide_diagnosis,
+        }))
+    }
+
+    // GET /api/shared/{token} - Return the case a share link grants access to
+    pub async fn get_shared_case(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let token = path.trim_start_matches("/api/shared/").trim_end_matches('/');
+        
+        let secret = match share::share_secret() {
+            Some(secret) => secret,
+            None => return forbidden("Sharing is not configured"),
+        };
+        
+        let grant = match share::verify(&secret, token, chrono::Utc::now().timestamp()) {
+            Ok(grant) => grant,
+            Err(e) => {
+                warn!("Rejected share link: {}", e);
+                return forbidden(&e.to_string());
+            }
+        };
+        
+        let mut case = match db::get_case_cached(db_client, &grant.case_id).await? {
+            Some(case) => case,
+            None => return not_found("Shared case no longer exists"),
+        };
+        
+        if grant.hide_diagnosis {
+            hide_answers(&mut case);
+        }
+        
+        Response::new(200, ApiResponse::success(case))
+    }
+
+    // Title shown in place of the real one on a blinded case
+    const BLIND_CASE_TITLE: &str = "Unknown case";
+
+    // GET /api/cases/{id}/blind - The case as an unknown: imaging only, with the answer and study context removed
+    pub async fn get_blind_case(db_client: &DynamoDbClient, path: &str) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/blind");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        let mut case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => return not_found(&format!("Case not found: {}", case_id)),
+        };
+        
+        blind_case(&mut case);
+        Response::new(200, ApiResponse::success(case))
+    }
+
+    // Longest og:description before it is cut off; chat clients show about this much
+    const PREVIEW_DESCRIPTION_CHARS: usize = 200;
+
+    // GET /api/cases/{id}/preview - HTML page with OpenGraph/Twitter tags so pasted links unfurl
+    pub async fn get_case_preview(db_client: &DynamoDbClient, request: &Request, path: &str) -> Result<Response, LambdaError> {
+        let case_id = path.trim_start_matches("/api/cases/").trim_end_matches("/preview");
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        
+        match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => preview_response(&case, false, request),
+            None => not_found(&format!("Case not found: {}", case_id)),
+        }
+    }
+
+    // GET /api/shared/{token}/preview - Unfurl page for a share link; quiz links leave out the answer
+    pub async fn get_shared_preview(db_client: &DynamoDbClient, request: &Request, path: &str) -> Result<Response, LambdaError> {
+        let token = path.trim_start_matches("/api/shared/").trim_end_matches("/preview");
+        
+        let secret = match share::share_secret() {
+            Some(secret) => secret,
+            None => return forbidden("Sharing is not configured"),
+        };
+        let grant = match share::verify(&secret, token, chrono::Utc::now().timestamp()) {
+            Ok(grant) => grant,
+            Err(e) => {
+                warn!("Rejected share link preview: {}", e);
+                return forbidden(&e.to_string());
+            }
+        };
+        
+        match db::get_case_cached(db_client, &grant.case_id).await? {
+            Some(case) => preview_response(&case, grant.hide_diagnosis, request),
+            None => not_found("Shared case no longer exists"),
+        }
+    }
+
+    // Helper to render the unfurl page for a case
+    fn preview_response(case: &Case, hide_diagnosis: bool, request: &Request) -> Result<Response, LambdaError> {
+        // Unfurlers fetch og:image on their own, so it must be an absolute URL
+        let base_url = match request.header("Host") {
+            Some(host) => format!("{}://{}", request.header("X-Forwarded-Proto").unwrap_or("https"), host),
+            None => String::new(),
+        };
+        let image_url = format!("{}/api/cases/{}/thumbnail", base_url, case.case_id);
+        
+        let mut summary: Vec<String> = [case.modality.as_str(), case.anatomy.as_str()].iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !case.description.trim().is_empty() {
+            summary.push(case.description.trim().to_string());
+        }
+        if !hide_diagnosis && !case.diagnosis.trim().is_empty() {
+            summary.push(format!("Diagnosis: {}", case.diagnosis.trim()));
+        }
+        let mut description = summary.join(" - ");
+        if description.chars().count() > PREVIEW_DESCRIPTION_CHARS {
+            description = description.chars().take(PREVIEW_DESCRIPTION_CHARS - 1).collect::<String>() + "\u{2026}";
+        }
+        
+        let title = escape_html(&case.title);
+        let description = escape_html(&description);
+        let image_url = escape_html(&image_url);
+        let html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <meta property=\"og:type\" content=\"article\">\n\
+             <meta property=\"og:title\" content=\"{title}\">\n\
+             <meta property=\"og:description\" content=\"{description}\">\n\
+             <meta property=\"og:image\" content=\"{image_url}\">\n\
+             <meta name=\"twitter:card\" content=\"summary_large_image\">\n\
+             <meta name=\"twitter:title\" content=\"{title}\">\n\
+             <meta name=\"twitter:description\" content=\"{description}\">\n\
+             <meta name=\"twitter:image\" content=\"{image_url}\">\n\
+             </head>\n<body>\n<h1>{title}</h1>\n<p>{description}</p>\n<img src=\"{image_url}\" alt=\"{title}\">\n</body>\n</html>\n"
+        );
+        
+        Ok(Response::new(200, "")?
+            .with_content_type("text/html; charset=utf-8")
+            .into_text(html))
+    }
+
+    // Helper to escape text for HTML element content and quoted attributes
+    fn escape_html(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    // Helper to strip the teaching answer from a case for quiz-style sharing.
+    // The edit history is dropped too since it records earlier diagnoses.
+    fn hide_answers(case: &mut Case) {
+        case.diagnosis = String::new();
+        case.findings = String::new();
+        case.edit_history.clear();
+    }
+
+    // Helper to turn a case into an unknown for exam practice. Beyond the answer, the
+    // demographics and study context go, along with the description, tags and attachments
+    // (reports), which usually give the diagnosis away. Series and images are kept.
+    fn blind_case(case: &mut Case) {
+        hide_answers(case);
+        case.title = BLIND_CASE_TITLE.to_string();
+        case.description = String::new();
+        case.patient_name = String::new();
+        case.patient_id = String::new();
+        case.study_date = String::new();
+        case.study_description = String::new();
+        case.tags.clear();
+        case.attachments.clear();
+    }
+}
+
+// Build information routes
+pub mod version {
+    use super::*;
+
+    // GET /api/version - Report the crate version, git SHA and build time of this deployment
+    pub async fn get_version() -> Result<Response, LambdaError> {
+        Response::new(200, ApiResponse::success(VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: option_env!("GIT_SHA").unwrap_or("unknown"),
+            built_at: option_env!("BUILT_AT").unwrap_or("unknown"),
+        }))
+    }
+}
+
+pub mod warmup {
+    use super::*;
+
+    // GET /api/warmup - Keep-warm ping; prepares /tmp and returns without touching AWS
+    pub async fn warm_up() -> Result<Response, LambdaError> {
+        if let Err(e) = ensure_dicom_dir_exists() {
+            warn!("Failed to create DICOM directory during warmup: {:?}", e);
+        }
+        
+        Response::new(200, ApiResponse::success("warm"))
     }
 }
 
@@ -601,61 +4149,50 @@ pub mod dicom_routes {
         // Format should be /api/dicom/{case_id}/{sop_instance_uid}
         let path_parts: Vec<&str> = path.split('/').collect();
         
-        if path_parts.len() >= 4 {
+        if path_parts.len() == 5 {
             let case_id = path_parts[3];
-            let sop_instance_uid = path_parts.get(4).unwrap_or(&"");
+            let sop_instance_uid = path_parts[4];
+            
+            // Validate the segments before they are used to build S3 keys
+            if !is_valid_case_id(case_id) {
+                warn!("Rejecting malformed case id in DICOM path: {:?}", case_id);
+                return bad_request("Invalid case id: expected a UUID");
+            }
+            
+            if !is_valid_image_id(sop_instance_uid) {
+                warn!("Rejecting malformed SOP Instance UID in DICOM path: {:?}", sop_instance_uid);
+                return bad_request("Invalid SOP Instance UID: expected a dotted numeric UID");
+            }
             
             info!("Fetching DICOM file: case={}, sop={}", case_id, sop_instance_uid);
             telemetry::send_xray_trace(xray_client, &format!("get-dicom-{}", case_id)).await;
             
-            // Get the case to find the correct study_instance_uid for more structured S3 path
-            match db::get_case(db_client, case_id).await? {
-                Some(case) => {
-                    // Use the case's study_instance_uid if available
-                    let s3_key = if !case.study_instance_uid.is_empty() {
-                        format!("dicom/{}/{}/{}.dcm", case_id, case.study_instance_uid, sop_instance_uid)
-                    } else {
-                        format!("dicom/{}/{}.dcm", case_id, sop_instance_uid)
-                    };
+            // Try the case's key layouts in order; without a case only the direct path is known
+            let resolved = match db::get_case_cached(db_client, case_id).await? {
+                Some(case) => resolve_instance_key(s3_client, &case, sop_instance_uid).await?,
+                None => {
+                    warn!("Case not found for DICOM retrieval: {}", case_id);
+                    s3::resolve_dicom_key(s3_client, &[format!("dicom/{}/{}.dcm", case_id, sop_instance_uid)]).await?
+                }
+            };
+            
+            match resolved {
+                Some((s3_key, _)) => {
+                    let dicom_data = s3::download_file(s3_client, &s3_key).await?;
+                    info!("Successfully downloaded DICOM from S3: {}", s3_key);
                     
-                    match s3::download_file(s3_client, &s3_key).await {
-                        Ok(dicom_data) => {
-                            info!("Successfully downloaded DICOM from S3: {}", s3_key);
-                            
-                            let mut response = Response::new(200, "")?;
-                            response = response.with_content_type("application/dicom");
-                            response = response.into_binary(dicom_data);
-                            
-                            Ok(response)
-                        },
-                        Err(e) => {
-                            error!("Error downloading DICOM from S3: {:?}", e);
-                            error!("Trying alternate S3 path...");
-                            
-                            // Try the original file as fallback
-                            try_alternate_dicom_paths(s3_client, case_id, sop_instance_uid).await
-                        }
+                    let mut response = Response::new(200, "")?;
+                    response = response.with_content_type("application/dicom");
+                    if sign_responses_enabled() {
+                        response = response.with_content_sha256(&dicom_data);
                     }
+                    response = response.into_binary(dicom_data);
+                    
+                    Ok(response)
                 },
                 None => {
-                    warn!("Case not found for DICOM retrieval: {}", case_id);
-                    // Try direct S3 path without case lookup
-                    let direct_key = format!("dicom/{}/{}.dcm", case_id, sop_instance_uid);
-                    match s3::download_file(s3_client, &direct_key).await {
-                        Ok(dicom_data) => {
-                            debug!("Successfully downloaded DICOM using direct path: {}", direct_key);
-                            
-                            let mut response = Response::new(200, "")?;
-                            response = response.with_content_type("application/dicom");
-                            response = response.into_binary(dicom_data);
-                            
-                            Ok(response)
-                        },
-                        Err(e) => {
-                            error!("Error downloading DICOM using direct path: {:?}", e);
-                            not_found("DICOM file not found")
-                        }
-                    }
+                    error!("DICOM file not found for case={}, sop={}", case_id, sop_instance_uid);
+                    not_found("DICOM file not found")
                 }
             }
         } else {
@@ -663,45 +4200,690 @@ pub mod dicom_routes {
         }
     }
 
-    // Helper to try alternative DICOM file paths
-    async fn try_alternate_dicom_paths(
-        s3_client: &S3Client, 
-        case_id: &str, 
-        sop_instance_uid: &str
+    // DICOM downloads carry an X-Content-SHA256 header only when SIGN_RESPONSES is enabled
+    fn sign_responses_enabled() -> bool {
+        config::get().sign_responses
+    }
+
+    // GET /api/dicom/{case_id}/{sop_instance_uid}/metadata - Full tag set as DICOM JSON
+    pub async fn get_dicom_metadata(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str
     ) -> Result<Response, LambdaError> {
-        // Try the original file as fallback
-        let fallback_key = format!("dicom/{}/original.dcm", case_id);
-        match s3::download_file(s3_client, &fallback_key).await {
-            Ok(dicom_data) => {
-                info!("Successfully downloaded DICOM from original file: {}", fallback_key);
-                
-                let mut response = Response::new(200, "")?;
-                response = response.with_content_type("application/dicom");
-                response = response.into_binary(dicom_data);
-                
-                Ok(response)
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        let (case_id, sop_instance_uid) = match parts.as_slice() {
+            ["", "api", "dicom", case_id, sop_instance_uid, "metadata"] => (*case_id, *sop_instance_uid),
+            _ => return bad_request("Invalid metadata URL format"),
+        };
+        
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        if !is_valid_image_id(sop_instance_uid) {
+            return bad_request("Invalid SOP Instance UID: expected a dotted numeric UID");
+        }
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        // Serve the sidecar written at ingest when there is one
+        let key = sidecar_key(case_id, &case.study_instance_uid, sop_instance_uid);
+        if s3::file_size(s3_client, &key).await?.is_some() {
+            debug!("Serving DICOM JSON sidecar: {}", key);
+            let json = s3::download_file(s3_client, &key).await?;
+            return Ok(Response::new(200, "")?
+                .with_content_type("application/dicom+json")
+                .into_text(String::from_utf8_lossy(&json).into_owned()));
+        }
+        
+        // Otherwise read the headers from the stored object
+        let dicom_data = match load_instance(s3_client, &case, sop_instance_uid).await? {
+            Some(data) => data,
+            None => return not_found("DICOM file not found"),
+        };
+        
+        let sidecars = match dicom_json_sidecars(&dicom_data) {
+            Ok(sidecars) => sidecars,
+            Err(e) => {
+                error!("Failed to read DICOM metadata for {}: {:?}", sop_instance_uid, e);
+                return dicom_error_response(&e);
+            }
+        };
+        
+        // Whole-study uploads hold several objects; a lone object also covers its virtual frame ids.
+        // Objects are read one at a time until the match is found.
+        let mut sidecars = sidecars.peekable();
+        let first = match sidecars.next() {
+            Some(first) => first,
+            None => return dicom_error_response(&anyhow::Error::new(DicomError::NotDicom)),
+        };
+        let sidecar = if first.sop_instance_uid == sop_instance_uid || sidecars.peek().is_none() {
+            Some(first)
+        } else {
+            sidecars.find(|sidecar| sidecar.sop_instance_uid == sop_instance_uid)
+        };
+        match sidecar {
+            Some(sidecar) => Ok(Response::new(200, "")?
+                .with_content_type("application/dicom+json")
+                .into_text(sidecar.json.to_string())),
+            None => not_found(&format!("No DICOM object found for {}", sop_instance_uid)),
+        }
+    }
+
+    // S3 key of the DICOM JSON sidecar stored next to an instance
+    pub fn sidecar_key(case_id: &str, study_instance_uid: &str, sop_instance_uid: &str) -> String {
+        if study_instance_uid.is_empty() {
+            format!("dicom/{}/{}.json", case_id, sop_instance_uid)
+        } else {
+            format!("dicom/{}/{}/{}.json", case_id, study_instance_uid, sop_instance_uid)
+        }
+    }
+
+    // GET /api/dicom/{case_id}/{sop_instance_uid}/render?ww=400&wc=40&frame=3 - Render a frame as PNG
+    pub async fn render_dicom(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        let (case_id, sop_instance_uid) = match parts.as_slice() {
+            ["", "api", "dicom", case_id, sop_instance_uid, "render"] => (*case_id, *sop_instance_uid),
+            _ => return bad_request("Invalid render URL format"),
+        };
+        
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        if !is_valid_image_id(sop_instance_uid) {
+            return bad_request("Invalid SOP Instance UID: expected a dotted numeric UID");
+        }
+        
+        let window = match window_override(request) {
+            Ok(window) => window,
+            Err(message) => return bad_request(&message),
+        };
+        
+        let frame = match request.query_param("frame").map(|value| value.trim().parse::<u32>()) {
+            Some(Ok(frame)) if frame > 0 => frame,
+            Some(_) => return bad_request("frame must be a positive frame number"),
+            None => 1,
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let dicom_data = match load_instance(s3_client, &case, sop_instance_uid).await? {
+            Some(data) => data,
+            None => return not_found("DICOM file not found"),
+        };
+        
+        info!("Rendering case={}, sop={}, frame={}, window={:?}", case_id, sop_instance_uid, frame, window);
+        match render_frame(&dicom_data, frame, window, ImageFormat::Png) {
+            Ok(png_data) if png_data.len() as i64 > MAX_INLINE_RESPONSE_BYTES => {
+                payload_too_large("Rendered image is too large to return inline; download the DICOM instead")
+            },
+            Ok(png_data) => Ok(Response::new(200, "")?.with_content_type("image/png").into_binary(png_data)),
+            Err(e) if dicom_error(&e).is_some() => {
+                error!("Failed to render {}: {:?}", sop_instance_uid, e);
+                dicom_error_response(&e)
             },
             Err(e) => {
-                error!("Error downloading original DICOM: {:?}", e);
-                
-                // Try the simple path as a last resort
-                let simple_key = format!("dicom/{}/{}.dcm", case_id, sop_instance_uid);
-                match s3::download_file(s3_client, &simple_key).await {
-                    Ok(dicom_data) => {
-                        info!("Successfully downloaded DICOM from simple path: {}", simple_key);
-                        
-                        let mut response = Response::new(200, "")?;
-                        response = response.with_content_type("application/dicom");
-                        response = response.into_binary(dicom_data);
-                        
-                        Ok(response)
-                    },
-                    Err(e) => {
-                        error!("Error downloading from simple path: {:?}", e);
-                        not_found("DICOM file not found")
+                error!("Failed to render {}: {:?}", sop_instance_uid, e);
+                server_error(&format!("Failed to render image: {}", e))
+            }
+        }
+    }
+
+    // Helper to read the optional wc/ww window query parameters. Window values may be
+    // negative (e.g. a lung window centre of -600) but must be finite.
+    fn window_override(request: &Request) -> Result<WindowOverride, String> {
+        let parse_window = |name: &str| -> Result<Option<f64>, String> {
+            match request.query_param(name) {
+                Some(value) => match value.trim().parse::<f64>() {
+                    Ok(number) if number.is_finite() => Ok(Some(number)),
+                    _ => Err(format!("{} must be a number", name)),
+                },
+                None => Ok(None),
+            }
+        };
+        
+        Ok(WindowOverride { center: parse_window("wc")?, width: parse_window("ww")? })
+    }
+
+    // GET /api/cases/{case_id}/series/{series_uid}/cine - Series frames as a looping animated GIF
+    pub async fn get_series_cine(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        let (case_id, series_uid) = match parts.as_slice() {
+            ["", "api", "cases", case_id, "series", series_uid, "cine"] => (*case_id, *series_uid),
+            _ => return bad_request("Invalid cine URL format"),
+        };
+        
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        if !is_valid_image_id(series_uid) {
+            return bad_request("Invalid Series Instance UID: expected a dotted numeric UID");
+        }
+        
+        let window = match window_override(request) {
+            Ok(window) => window,
+            Err(message) => return bad_request(&message),
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let series = match case.series.iter().find(|series| series.series_instance_uid == series_uid) {
+            Some(series) => series,
+            None => return not_found(&format!("Series not found: {}", series_uid)),
+        };
+        
+        let failed = |e: anyhow::Error| {
+            error!("Failed to render cine for series {}: {:?}", series_uid, e);
+            if dicom_error(&e).is_some() {
+                dicom_error_response(&e)
+            } else {
+                server_error(&format!("Failed to render cine: {}", e))
+            }
+        };
+        
+        // Decode instances in display order, one stored object at a time, until the
+        // frames decoded so far fill the cap; a multi-frame object can fill it alone
+        let mut cine = CineBuilder::new(window);
+        let mut loaded = 0;
+        let mut seen_keys = std::collections::HashSet::new();
+        for sop_instance_uid in series.image_ids.iter() {
+            if cine.is_full() {
+                break;
+            }
+            match resolve_instance_key(s3_client, &case, sop_instance_uid).await? {
+                Some((key, _)) if seen_keys.insert(key.clone()) => {
+                    let data = s3::download_file(s3_client, &key).await?;
+                    if let Err(e) = cine.add_object(&data) {
+                        return failed(e);
+                    }
+                    loaded += 1;
+                },
+                Some(_) => debug!("Instance {} shares a stored object already loaded", sop_instance_uid),
+                None => warn!("No stored object for instance {}", sop_instance_uid),
+            }
+        }
+        
+        if loaded == 0 {
+            return not_found("No DICOM files found for series");
+        }
+        
+        info!("Rendering cine for case={}, series={} from {} objects", case_id, series_uid, loaded);
+        match cine.finish() {
+            Ok(gif) if gif.len() as i64 > MAX_INLINE_RESPONSE_BYTES => {
+                payload_too_large("Cine is too large to return inline; download the series instead")
+            },
+            Ok(gif) => Ok(Response::new(200, "")?.with_content_type("image/gif").into_binary(gif)),
+            Err(e) => failed(e),
+        }
+    }
+
+    // GET /api/wado?requestType=WADO&studyUID=..&seriesUID=..&objectUID=..&contentType=.. - Classic WADO-URI retrieval
+    pub async fn get_wado_uri(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        request: &Request
+    ) -> Result<Response, LambdaError> {
+        let query = match request.wado_uri_query() {
+            Ok(query) => query,
+            Err(message) => return bad_request(&message),
+        };
+        
+        let uids = [Some(&query.study_uid), query.series_uid.as_ref(), Some(&query.object_uid)];
+        if !uids.into_iter().flatten().all(|uid| is_valid_image_id(uid)) {
+            return bad_request("Invalid UID: expected a dotted numeric UID");
+        }
+        
+        // Serve the first requested type we can produce
+        let content_type = match query.content_types.iter()
+            .find(|content_type| WADO_CONTENT_TYPES.contains(&content_type.as_str())) {
+            Some(content_type) => content_type.as_str(),
+            None => return unsupported_media_type(&format!(
+                "Unsupported contentType {}; expected one of {}", query.content_types.join(","), WADO_CONTENT_TYPES.join(", "))),
+        };
+        
+        info!("WADO-URI request: study={}, object={}, contentType={}", query.study_uid, query.object_uid, content_type);
+        
+        let case = match db::get_case_by_study(db_client, &query.study_uid).await? {
+            Some(case) => case,
+            None => return not_found(&format!("Study not found: {}", query.study_uid)),
+        };
+        
+        // The object must belong to the study, and to the series when one is named
+        let in_series = match &query.series_uid {
+            Some(series_uid) => case.series.iter().any(|series| {
+                series.series_instance_uid == *series_uid && series.image_ids.contains(&query.object_uid)
+            }),
+            None => case.contains_instance(&query.object_uid),
+        };
+        if !in_series {
+            return not_found(&format!("Object not found: {}", query.object_uid));
+        }
+        
+        let dicom_data = match load_instance(s3_client, &case, &query.object_uid).await? {
+            Some(data) => data,
+            None => return not_found("DICOM file not found"),
+        };
+        
+        let format = match content_type {
+            "image/jpeg" => ImageFormat::Jpeg,
+            "image/png" => ImageFormat::Png,
+            _ => {
+                let mut response = Response::new(200, "")?.with_content_type("application/dicom");
+                if sign_responses_enabled() {
+                    response = response.with_content_sha256(&dicom_data);
+                }
+                return Ok(response.into_binary(dicom_data));
+            }
+        };
+        
+        let window = WindowOverride { center: query.window_center, width: query.window_width };
+        match render_frame(&dicom_data, query.frame_number.unwrap_or(1), window, format) {
+            Ok(image) if image.len() as i64 > MAX_INLINE_RESPONSE_BYTES => {
+                payload_too_large("Rendered image is too large to return inline; request application/dicom instead")
+            },
+            Ok(image) => Ok(Response::new(200, "")?.with_content_type(format.content_type()).into_binary(image)),
+            Err(e) if dicom_error(&e).is_some() => {
+                error!("Failed to render {}: {:?}", query.object_uid, e);
+                dicom_error_response(&e)
+            },
+            Err(e) => {
+                error!("Failed to render {}: {:?}", query.object_uid, e);
+                server_error(&format!("Failed to render image: {}", e))
+            }
+        }
+    }
+
+    // Media types a WADO-URI request can ask for
+    const WADO_CONTENT_TYPES: &[&str] = &["application/dicom", "image/jpeg", "image/png"];
+
+    // GET /api/cases/{case_id}/series/{series_uid}/window?start=&count= - A run of a series' instances in slice order.
+    // start may be negative or past the end (e.g. a neighborhood around the first slice) and is clamped.
+    pub async fn get_series_window(
+        db_client: &DynamoDbClient,
+        request: &Request,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        let (case_id, series_uid) = match parts.as_slice() {
+            ["", "api", "cases", case_id, "series", series_uid, "window"] => (*case_id, *series_uid),
+            _ => return bad_request("Invalid series URL format"),
+        };
+        
+        if !is_valid_case_id(case_id) {
+            return bad_request("Invalid case id: expected a UUID");
+        }
+        if !is_valid_image_id(series_uid) {
+            return bad_request("Invalid Series Instance UID: expected a dotted numeric UID");
+        }
+        
+        let start = match request.query_param("start").map(str::parse::<i64>) {
+            None => 0,
+            Some(Ok(start)) => start,
+            Some(Err(_)) => return bad_request("start must be an integer"),
+        };
+        let count = match request.query_param("count").map(str::parse::<usize>) {
+            None => DEFAULT_IMAGE_PAGE_SIZE,
+            Some(Ok(count)) if count > 0 => count.min(MAX_IMAGE_PAGE_SIZE),
+            Some(_) => return bad_request("count must be a positive integer"),
+        };
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let series = match case.series.iter().find(|series| series.series_instance_uid == series_uid) {
+            Some(series) => series,
+            None => return not_found(&format!("Series not found: {}", series_uid)),
+        };
+        
+        Response::new(200, ApiResponse::success(series_window(case_id, series, start, count)))
+    }
+
+    // Helper to cut a window from a series, clamping start and count to its bounds.
+    // Series image ids are stored in slice order, so a window is a plain slice of them.
+    fn series_window(case_id: &str, series: &SeriesInfo, start: i64, count: usize) -> SeriesWindow {
+        let total = series.image_ids.len();
+        let start = usize::try_from(start.max(0)).unwrap_or(usize::MAX).min(total);
+        let end = start.saturating_add(count).min(total);
+        let instances: Vec<WindowInstance> = series.image_ids[start..end].iter()
+            .enumerate()
+            .map(|(i, sop)| WindowInstance {
+                instance_number: start + i + 1,
+                sop: sop.clone(),
+                render_url: format!("/api/dicom/{}/{}/render", case_id, sop),
+                download_url: format!("/api/dicom/{}/{}", case_id, sop),
+            })
+            .collect();
+        
+        SeriesWindow {
+            series_instance_uid: series.series_instance_uid.clone(),
+            total,
+            start,
+            count: instances.len(),
+            instances,
+        }
+    }
+
+    // GET /api/cases/{case_id}/series/{series_uid}/instances - All instances of a series as multipart
+    pub async fn get_series_instances(
+        db_client: &DynamoDbClient,
+        s3_client: &S3Client,
+        path: &str
+    ) -> Result<Response, LambdaError> {
+        // Format should be /api/cases/{case_id}/series/{series_uid}/instances
+        let path_parts: Vec<&str> = path.split('/').collect();
+        if path_parts.len() != 7 {
+            return bad_request("Invalid series URL format");
+        }
+        
+        let case_id = path_parts[3];
+        let series_uid = path_parts[5];
+        if !is_valid_image_id(series_uid) {
+            return bad_request("Invalid Series Instance UID: expected a dotted numeric UID");
+        }
+        
+        info!("Fetching series instances: case={}, series={}", case_id, series_uid);
+        
+        let case = match db::get_case_cached(db_client, case_id).await? {
+            Some(case) => case,
+            None => {
+                error!("Case not found: {}", case_id);
+                return not_found(&format!("Case not found: {}", case_id));
+            }
+        };
+        
+        let series = match case.series.iter().find(|series| series.series_instance_uid == series_uid) {
+            Some(series) => series,
+            None => return not_found(&format!("Series not found: {}", series_uid)),
+        };
+        
+        // Resolve every instance to its stored object, skipping objects already included
+        // (legacy cases only have the original upload to fall back on)
+        let mut parts: Vec<(String, String, i64)> = Vec::new();
+        let mut seen_keys = std::collections::HashSet::new();
+        for sop_instance_uid in &series.image_ids {
+            match resolve_instance_key(s3_client, &case, sop_instance_uid).await? {
+                Some((key, size)) => {
+                    if seen_keys.insert(key.clone()) {
+                        parts.push((sop_instance_uid.clone(), key, size));
+                    } else {
+                        warn!("Instance {} shares stored object {}, not repeating it", sop_instance_uid, key);
                     }
+                },
+                None => warn!("No stored object for instance {}", sop_instance_uid),
+            }
+        }
+        
+        if parts.is_empty() {
+            return not_found("No DICOM files found for series");
+        }
+        
+        let total_size: i64 = parts.iter().map(|(_, _, size)| size).sum();
+        info!("Series has {} stored objects totalling {} bytes", parts.len(), total_size);
+        
+        if total_size > MAX_INLINE_RESPONSE_BYTES {
+            info!("Series too large to return inline, presigning downloads");
+            let mut files = Vec::with_capacity(parts.len());
+            
+            for (sop_instance_uid, key, size) in &parts {
+                let url = s3::presign_download(
+                    s3_client,
+                    key,
+                    std::time::Duration::from_secs(PRESIGNED_URL_EXPIRY_SECS)
+                ).await?;
+                
+                files.push(PresignedFile {
+                    name: sop_instance_uid.clone(),
+                    size: *size,
+                    url,
+                });
+            }
+            
+            return Response::new(200, ApiResponse::success(files));
+        }
+        
+        // Assemble a multipart/related body with one application/dicom part per instance
+        let boundary = format!("dicom-{}", Uuid::new_v4().simple());
+        let mut body = Vec::with_capacity(total_size as usize + parts.len() * 128);
+        for (sop_instance_uid, key, _) in &parts {
+            let dicom_data = s3::download_file(s3_client, key).await?;
+            
+            body.extend_from_slice(format!(
+                "--{}\r\nContent-Type: application/dicom\r\nContent-Location: /api/dicom/{}/{}\r\n\r\n",
+                boundary, case.case_id, sop_instance_uid
+            ).as_bytes());
+            body.extend_from_slice(&dicom_data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        
+        let mut response = Response::new(200, "")?;
+        response = response.with_content_type(
+            &format!("multipart/related; type=\"application/dicom\"; boundary={}", boundary));
+        Ok(response.into_binary(body))
+    }
+
+    // Ways an instance's object has been keyed in S3 over time
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum DicomKeyLayout {
+        Structured,
+        Flat,
+        Original,
+    }
+
+    // Most cases remembered by the adaptive key order before the memory is reset
+    const MAX_REMEMBERED_LAYOUTS: usize = 1000;
+
+    // Layout that last resolved for each case in this warm container
+    static RESOLVED_LAYOUTS: std::sync::OnceLock<std::sync::Mutex<std::collections::HashMap<String, DicomKeyLayout>>> = std::sync::OnceLock::new();
+
+    // Key lookups start with the layout that last worked unless DICOM_KEY_ADAPTIVE is false
+    fn adaptive_key_order_enabled() -> bool {
+        config::get().dicom_key_adaptive
+    }
+
+    // Helper to list where an instance may be stored, preferred layout first
+    // (legacy cases only have the original upload to fall back on). The layout that
+    // last resolved for the case moves to the front; the rest keep their order and
+    // the original upload is always tried last.
+    fn dicom_key_candidates(case: &Case, sop_instance_uid: &str) -> Vec<(DicomKeyLayout, String)> {
+        let mut candidates = Vec::new();
+        if !case.study_instance_uid.is_empty() {
+            candidates.push((DicomKeyLayout::Structured,
+                             format!("dicom/{}/{}/{}.dcm", case.case_id, case.study_instance_uid, sop_instance_uid)));
+        }
+        candidates.push((DicomKeyLayout::Flat, format!("dicom/{}/{}.dcm", case.case_id, sop_instance_uid)));
+        candidates.push((DicomKeyLayout::Original, format!("dicom/{}/original.dcm", case.case_id)));
+        
+        if adaptive_key_order_enabled() {
+            let remembered = RESOLVED_LAYOUTS.get()
+                .and_then(|layouts| layouts.lock().ok()?.get(&case.case_id).copied());
+            promote_layout(&mut candidates, remembered);
+        }
+        
+        candidates
+    }
+
+    // Helper to move a remembered per-instance layout to the front. The original upload is
+    // the whole study rather than one instance, so it never jumps ahead of the others.
+    fn promote_layout(candidates: &mut Vec<(DicomKeyLayout, String)>, remembered: Option<DicomKeyLayout>) {
+        let remembered = match remembered {
+            Some(DicomKeyLayout::Original) | None => return,
+            Some(layout) => layout,
+        };
+        if let Some(position) = candidates.iter().position(|(layout, _)| *layout == remembered) {
+            let candidate = candidates.remove(position);
+            candidates.insert(0, candidate);
+        }
+    }
+
+    // Helper to drop what was learned about a case's layout, e.g. after its objects were rewritten
+    pub fn forget_key_layout(case_id: &str) {
+        if let Some(Ok(mut layouts)) = RESOLVED_LAYOUTS.get().map(|layouts| layouts.lock()) {
+            layouts.remove(case_id);
+        }
+    }
+
+    // Helper to remember which layout resolved for a case. A fall back to the original upload
+    // is not remembered: it only means this instance had no object of its own.
+    fn remember_key_layout(case_id: &str, layout: DicomKeyLayout) {
+        if layout == DicomKeyLayout::Original {
+            return;
+        }
+        let layouts = RESOLVED_LAYOUTS.get_or_init(|| std::sync::Mutex::new(std::collections::HashMap::new()));
+        if let Ok(mut layouts) = layouts.lock() {
+            if layouts.len() >= MAX_REMEMBERED_LAYOUTS && !layouts.contains_key(case_id) {
+                layouts.clear();
+            }
+            layouts.insert(case_id.to_string(), layout);
+        }
+    }
+
+    // Helper to find the stored object for one instance, trying each known key layout
+    async fn resolve_instance_key(
+        s3_client: &S3Client,
+        case: &Case,
+        sop_instance_uid: &str
+    ) -> Result<Option<(String, i64)>, LambdaError> {
+        let candidates = dicom_key_candidates(case, sop_instance_uid);
+        let keys: Vec<String> = candidates.iter().map(|(_, key)| key.clone()).collect();
+        
+        let resolved = s3::resolve_dicom_key(s3_client, &keys).await?;
+        if let Some((key, _)) = &resolved {
+            if let Some((layout, _)) = candidates.iter().find(|(_, candidate)| candidate == key) {
+                if adaptive_key_order_enabled() {
+                    remember_key_layout(&case.case_id, *layout);
                 }
             }
         }
+        
+        Ok(resolved)
+    }
+
+    // Helper to fetch the bytes of one instance
+    pub async fn load_instance(
+        s3_client: &S3Client,
+        case: &Case,
+        sop_instance_uid: &str
+    ) -> Result<Option<Vec<u8>>, LambdaError> {
+        match resolve_instance_key(s3_client, case, sop_instance_uid).await? {
+            Some((key, _)) => Ok(Some(s3::download_file(s3_client, &key).await?)),
+            None => Ok(None),
+        }
+    }
+    
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        
+        fn layouts(candidates: &[(DicomKeyLayout, String)]) -> Vec<DicomKeyLayout> {
+            candidates.iter().map(|(layout, _)| *layout).collect()
+        }
+        
+        fn all_candidates() -> Vec<(DicomKeyLayout, String)> {
+            vec![(DicomKeyLayout::Structured, "a".to_string()),
+                 (DicomKeyLayout::Flat, "b".to_string()),
+                 (DicomKeyLayout::Original, "c".to_string())]
+        }
+        
+        #[test]
+        fn promote_layout_moves_remembered_layout_first() {
+            let mut candidates = all_candidates();
+            promote_layout(&mut candidates, Some(DicomKeyLayout::Flat));
+            assert_eq!(layouts(&candidates),
+                       vec![DicomKeyLayout::Flat, DicomKeyLayout::Structured, DicomKeyLayout::Original]);
+        }
+        
+        #[test]
+        fn promote_layout_keeps_original_last() {
+            let mut candidates = all_candidates();
+            promote_layout(&mut candidates, Some(DicomKeyLayout::Original));
+            assert_eq!(layouts(&candidates),
+                       vec![DicomKeyLayout::Structured, DicomKeyLayout::Flat, DicomKeyLayout::Original]);
+        }
+        
+        #[test]
+        fn series_window_clamps_to_the_series() {
+            let series: SeriesInfo = serde_json::from_value(serde_json::json!({
+                "series_instance_uid": "1.2.3.1", "series_number": 1, "series_description": "", "modality": "CT",
+                "image_ids": (1..=10).map(|index| format!("1.2.3.1.{}", index)).collect::<Vec<_>>()
+            })).expect("series");
+            
+            let window = series_window("case", &series, 8, 5);
+            assert_eq!((window.total, window.start, window.count), (10, 8, 2));
+            assert_eq!(window.instances[0].instance_number, 9);
+            assert_eq!(window.instances[0].sop, "1.2.3.1.9");
+            assert_eq!(window.instances[1].render_url, "/api/dicom/case/1.2.3.1.10/render");
+            
+            // A neighborhood around the first slice starts before it
+            let window = series_window("case", &series, -3, 4);
+            assert_eq!((window.start, window.count), (0, 4));
+            
+            let window = series_window("case", &series, i64::MAX, usize::MAX);
+            assert_eq!((window.start, window.count), (10, 0));
+        }
+        
+        #[test]
+        fn original_layout_is_never_remembered() {
+            remember_key_layout("case-original-layout", DicomKeyLayout::Original);
+            let remembered = RESOLVED_LAYOUTS.get()
+                .and_then(|layouts| layouts.lock().ok()?.get("case-original-layout").copied());
+            assert_eq!(remembered, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn require_body_rejects_missing_and_blank_bodies() {
+        for body in [None, Some(String::new()), Some("   ".to_string()), Some("\r\n\t".to_string())] {
+            let response = require_body(&body).unwrap_err().expect("response");
+            assert_eq!(response.status_code, 400, "{:?}", body);
+        }
+        
+        let body = Some(" {\"title\":\"x\"} ".to_string());
+        assert_eq!(require_body(&body).ok(), Some(" {\"title\":\"x\"} "));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn malformed_dicom_is_a_refused_bad_request() {
+        let err = anyhow::Error::from(DicomError::Malformed("element length overflows".to_string()));
+        assert!(is_refused_upload(&err));
+        assert_eq!(dicom_error_response(&err).unwrap().status_code, 400);
+    }
+}
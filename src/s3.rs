@@ -1,27 +1,45 @@
 use anyhow::{Context, Result};
-use aws_sdk_s3::{Client, primitives::ByteStream};
-use tracing::info;
-use std::env;
+use aws_sdk_s3::{Client, primitives::ByteStream, presigning::PresigningConfig};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::operation::get_bucket_lifecycle_configuration::GetBucketLifecycleConfigurationError;
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::operation::put_object::PutObjectError;
+use aws_sdk_s3::types::{
+    AbortIncompleteMultipartUpload, BucketLifecycleConfiguration, Delete, ExpirationStatus,
+    LifecycleRule, LifecycleRuleFilter, ObjectIdentifier,
+};
+use tracing::{info, warn};
+use std::future::Future;
+use std::time::Duration;
 
-/// Retrieves the bucket name from environment variables or falls back to a default.
+use crate::config;
+use crate::upstream::{self, with_timeout};
+
+/// The bucket name from the loaded config (S3_BUCKET)
 fn get_bucket_name() -> String {
-    env::var("S3_BUCKET").unwrap_or_else(|_| "radiology-teaching-files".to_string())
+    config::get().bucket.clone()
 }
 
 /// Upload a file to S3
 pub async fn upload_file(client: &Client, key: &str, data: Vec<u8>) -> Result<()> {
+    upload_object(client, key, data, "application/dicom").await
+}
+
+/// Upload an object to S3 with an explicit content type
+pub async fn upload_object(client: &Client, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
     let bucket_name = get_bucket_name();
     info!("Uploading file to S3: {}/{}", bucket_name, key);
     
     let len = data.len();
     let body = ByteStream::from(data);
     
-    client.put_object()
+    with_timeout("s3:put_object", client.put_object()
         .bucket(&bucket_name)
         .key(key)
         .body(body)
-        .content_type("application/dicom")
-        .send()
+        .content_type(content_type)
+        .send())
         .await
         .context(format!("Failed to upload file to S3 at {}/{}", bucket_name, key))?;
     
@@ -29,46 +47,258 @@ pub async fn upload_file(client: &Client, key: &str, data: Vec<u8>) -> Result<()
     Ok(())
 }
 
+/// Upload an object only if nothing exists at the key yet (If-None-Match: *).
+/// Returns true when the object was written and false when an existing one was kept.
+pub async fn upload_if_absent(client: &Client, key: &str, data: Vec<u8>, content_type: &str) -> Result<bool> {
+    let bucket_name = get_bucket_name();
+    info!("Uploading file to S3 if absent: {}/{}", bucket_name, key);
+    
+    let len = data.len();
+    let body = ByteStream::from(data);
+    
+    let result = with_timeout("s3:put_object", client.put_object()
+        .bucket(&bucket_name)
+        .key(key)
+        .body(body)
+        .content_type(content_type)
+        .if_none_match("*")
+        .send())
+        .await;
+    
+    match result {
+        Ok(_) => {
+            info!("File uploaded successfully: {} ({} bytes)", key, len);
+            Ok(true)
+        },
+        Err(err) => {
+            if is_existing_object_error(&err) {
+                info!("File already exists, skipped upload: {}", key);
+                Ok(false)
+            } else {
+                Err(err.context(format!("Failed to upload file to S3 at {}/{}", bucket_name, key)))
+            }
+        }
+    }
+}
+
+/// Whether a conditional put failed because the key is taken: 412 means the key already
+/// exists, 409 means a concurrent conditional write won
+fn is_existing_object_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<SdkError<PutObjectError>>()
+        .and_then(|sdk_err| sdk_err.as_service_error())
+        .map(|service_err| matches!(service_err.code(), Some("PreconditionFailed") | Some("ConditionalRequestConflict")))
+        .unwrap_or(false)
+}
+
 /// Download a file from S3
 pub async fn download_file(client: &Client, key: &str) -> Result<Vec<u8>> {
     let bucket_name = get_bucket_name();
     info!("Downloading file from S3: {}/{}", bucket_name, key);
     
-    let result = client.get_object()
+    let result = with_timeout("s3:get_object", client.get_object()
         .bucket(&bucket_name)
         .key(key)
-        .send()
+        .send())
         .await
         .context(format!("Failed to download file from S3 at {}/{}", bucket_name, key))?;
     
-    let data = result.body.collect().await?;
+    let data = with_timeout("s3:get_object_body", result.body.collect()).await?;
     let bytes = data.into_bytes().to_vec();
     
     info!("File downloaded successfully: {} ({} bytes)", key, bytes.len());
     Ok(bytes)
 }
 
-/// Check if a file exists in S3
-#[allow(dead_code)]
-pub async fn file_exists(client: &Client, key: &str) -> Result<bool> {
+/// List the keys and sizes of all files under a prefix
+pub async fn list_files(client: &Client, prefix: &str) -> Result<Vec<(String, i64)>> {
+    Ok(list_objects(client, prefix).await?
+        .into_iter()
+        .map(|object| (object.key, object.size))
+        .collect())
+}
+
+/// An object found by list_objects
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: i64,
+    // Seconds since the epoch
+    pub last_modified: Option<i64>,
+}
+
+/// List files under a prefix with their sizes and modification times
+pub async fn list_objects(client: &Client, prefix: &str) -> Result<Vec<ObjectInfo>> {
+    let bucket_name = get_bucket_name();
+    info!("Listing files in S3: {}/{}", bucket_name, prefix);
+    
+    let mut files = Vec::new();
+    let mut continuation_token: Option<String> = None;
+    
+    loop {
+        let result = with_timeout("s3:list_objects_v2", client.list_objects_v2()
+            .bucket(&bucket_name)
+            .prefix(prefix)
+            .set_continuation_token(continuation_token.clone())
+            .send())
+            .await
+            .context(format!("Failed to list files in S3 at {}/{}", bucket_name, prefix))?;
+        
+        for object in result.contents() {
+            if let Some(key) = object.key() {
+                files.push(ObjectInfo {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or(0),
+                    last_modified: object.last_modified().map(|modified| modified.secs()),
+                });
+            }
+        }
+        
+        match result.next_continuation_token() {
+            Some(token) if result.is_truncated().unwrap_or(false) => {
+                continuation_token = Some(token.to_string());
+            },
+            _ => break,
+        }
+    }
+    
+    info!("Found {} files under {}", files.len(), prefix);
+    Ok(files)
+}
+
+// DeleteObjects accepts at most this many keys per request
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Delete files from S3 in batches, returning how many were removed
+pub async fn delete_files(client: &Client, keys: &[String]) -> Result<usize> {
     let bucket_name = get_bucket_name();
-    info!("Checking if file exists in S3: {}/{}", bucket_name, key);
+    info!("Deleting {} files from S3: {}", keys.len(), bucket_name);
+    
+    let mut deleted = 0;
+    for batch in keys.chunks(DELETE_BATCH_SIZE) {
+        let failed = delete_batch(client, &bucket_name, batch).await?;
+        deleted += batch.len() - failed.len();
+    }
     
-    let result = client.head_object()
+    info!("Deleted {} files", deleted);
+    Ok(deleted)
+}
+
+/// The lookups and batch delete that purges and key resolution need, so they can run against a fake store in tests
+pub trait ObjectStore {
+    /// Size of the object, or None when it does not exist
+    fn object_size(&self, key: &str) -> impl Future<Output = Result<Option<i64>>> + Send;
+    /// Keys of every object under the prefix
+    fn list_keys(&self, prefix: &str) -> impl Future<Output = Result<Vec<String>>> + Send;
+    /// Delete up to DELETE_BATCH_SIZE keys, returning the ones that were not deleted
+    fn delete_batch(&self, keys: &[String]) -> impl Future<Output = Result<Vec<String>>> + Send;
+}
+
+impl ObjectStore for Client {
+    async fn object_size(&self, key: &str) -> Result<Option<i64>> {
+        file_size(self, key).await
+    }
+    
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(list_objects(self, prefix).await?.into_iter().map(|object| object.key).collect())
+    }
+    
+    async fn delete_batch(&self, keys: &[String]) -> Result<Vec<String>> {
+        delete_batch(self, &get_bucket_name(), keys).await
+    }
+}
+
+/// Delete files in batches, carrying on past failures. Returns the keys that could not
+/// be removed; deleting a key that is already gone counts as success.
+pub async fn delete_files_tolerant<S: ObjectStore>(store: &S, keys: &[String]) -> Vec<String> {
+    info!("Deleting {} files", keys.len());
+    
+    let mut failed = Vec::new();
+    for batch in keys.chunks(DELETE_BATCH_SIZE) {
+        match store.delete_batch(batch).await {
+            Ok(batch_failed) => failed.extend(batch_failed),
+            Err(e) => {
+                warn!("Delete batch of {} files failed: {:?}", batch.len(), e);
+                failed.extend(batch.iter().cloned());
+            }
+        }
+    }
+    
+    info!("Deleted {} files, {} failed", keys.len() - failed.len(), failed.len());
+    failed
+}
+
+// Delete up to DELETE_BATCH_SIZE keys in one request, returning the keys S3 reported as not deleted
+async fn delete_batch(client: &Client, bucket_name: &str, batch: &[String]) -> Result<Vec<String>> {
+    let objects = batch.iter()
+        .map(|key| ObjectIdentifier::builder().key(key).build())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("Failed to build delete request")?;
+    
+    let delete = Delete::builder()
+        .set_objects(Some(objects))
+        .quiet(true)
+        .build()
+        .context("Failed to build delete request")?;
+    
+    let result = with_timeout("s3:delete_objects", client.delete_objects()
+        .bucket(bucket_name)
+        .delete(delete)
+        .send())
+        .await
+        .context(format!("Failed to delete files from S3 bucket: {}", bucket_name))?;
+    
+    Ok(result.errors().iter()
+        .map(|failure| {
+            warn!("Failed to delete {}: {}", failure.key().unwrap_or_default(), failure.message().unwrap_or_default());
+            failure.key().unwrap_or_default().to_string()
+        })
+        .collect())
+}
+
+/// Create a presigned download URL for a file
+pub async fn presign_download(client: &Client, key: &str, expires_in: Duration) -> Result<String> {
+    let bucket_name = get_bucket_name();
+    info!("Presigning download for S3: {}/{}", bucket_name, key);
+    
+    let presigning_config = PresigningConfig::expires_in(expires_in)
+        .context("Invalid presigned URL expiry")?;
+    
+    let request = client.get_object()
         .bucket(&bucket_name)
         .key(key)
-        .send()
+        .presigned(presigning_config)
+        .await
+        .context(format!("Failed to presign download for {}/{}", bucket_name, key))?;
+    
+    Ok(request.uri().to_string())
+}
+
+/// Get the size of a file in S3, or None if it does not exist
+pub async fn file_size(client: &Client, key: &str) -> Result<Option<i64>> {
+    let bucket_name = get_bucket_name();
+    info!("Checking file in S3: {}/{}", bucket_name, key);
+    
+    let result = with_timeout("s3:head_object", client.head_object()
+        .bucket(&bucket_name)
+        .key(key)
+        .send())
         .await;
     
     match result {
-        Ok(_) => {
+        Ok(output) => {
             info!("File exists: {}", key);
-            Ok(true)
+            Ok(Some(output.content_length().unwrap_or(0)))
         },
         Err(err) => {
-            if err.to_string().contains("NotFound") {
+            let not_found = err.downcast_ref::<SdkError<HeadObjectError>>()
+                .and_then(|sdk_err| sdk_err.as_service_error())
+                .map(|service_err| service_err.is_not_found())
+                .unwrap_or(false);
+            
+            if not_found {
                 info!("File does not exist: {}", key);
-                Ok(false)
+                Ok(None)
+            } else if upstream::is_timeout(&err) {
+                Err(err)
             } else {
                 Err(anyhow::anyhow!("Error checking if file exists: {:?}", err))
             }
@@ -76,6 +306,26 @@ pub async fn file_exists(client: &Client, key: &str) -> Result<bool> {
     }
 }
 
+/// Find the first candidate key that exists, returning it with its size.
+/// Candidates are tried in order, so callers list the preferred layout first.
+pub async fn resolve_dicom_key<S: ObjectStore>(store: &S, candidates: &[String]) -> Result<Option<(String, i64)>> {
+    for key in candidates {
+        if let Some(size) = store.object_size(key).await? {
+            info!("Resolved DICOM object at {}", key);
+            return Ok(Some((key.clone(), size)));
+        }
+    }
+    
+    warn!("None of {} candidate DICOM keys exist", candidates.len());
+    Ok(None)
+}
+
+/// Check if a file exists in S3
+#[allow(dead_code)]
+pub async fn file_exists(client: &Client, key: &str) -> Result<bool> {
+    Ok(file_size(client, key).await?.is_some())
+}
+
 /// Ensure the S3 bucket exists (only create if necessary)
 pub async fn ensure_bucket_exists(client: &Client) -> Result<()> {
     let bucket_name = get_bucket_name();
@@ -89,7 +339,6 @@ pub async fn ensure_bucket_exists(client: &Client) -> Result<()> {
     match result {
         Ok(_) => {
             info!("Bucket already exists: {}", bucket_name);
-            Ok(())
         },
         Err(err) => {
             if err.to_string().contains("NotFound") {
@@ -102,10 +351,233 @@ pub async fn ensure_bucket_exists(client: &Client) -> Result<()> {
                     .context(format!("Failed to create S3 bucket: {}", bucket_name))?;
                 
                 info!("Bucket created successfully: {}", bucket_name);
-                Ok(())
             } else {
-                Err(anyhow::anyhow!("Error checking if bucket exists: {:?}", err))
+                return Err(anyhow::anyhow!("Error checking if bucket exists: {:?}", err));
             }
         }
     }
+    
+    // Lifecycle rules are housekeeping, so a failure should not block startup
+    if let Err(err) = apply_lifecycle_rules(client).await {
+        warn!("Failed to apply lifecycle rules to {}: {:?}", bucket_name, err);
+    }
+    
+    Ok(())
+}
+
+// Days before an abandoned multipart upload is cleaned up
+const ABORT_INCOMPLETE_UPLOAD_DAYS: i32 = 1;
+
+// ID of the lifecycle rule this service owns; rules with other IDs belong to operators
+const ABORT_INCOMPLETE_UPLOAD_RULE_ID: &str = "abort-incomplete-multipart-uploads";
+
+/// Configure bucket lifecycle rules so storage does not grow unbounded. PutBucketLifecycleConfiguration
+/// replaces every rule, so the existing rules are read first and ours is merged in by ID; nothing is
+/// written when the bucket already has it.
+pub async fn apply_lifecycle_rules(client: &Client) -> Result<()> {
+    let bucket_name = get_bucket_name();
+    info!("Applying lifecycle rules to S3 bucket: {}", bucket_name);
+    
+    let abort_incomplete_uploads = LifecycleRule::builder()
+        .id(ABORT_INCOMPLETE_UPLOAD_RULE_ID)
+        .status(ExpirationStatus::Enabled)
+        .filter(LifecycleRuleFilter::builder().prefix("").build())
+        .abort_incomplete_multipart_upload(AbortIncompleteMultipartUpload::builder()
+            .days_after_initiation(ABORT_INCOMPLETE_UPLOAD_DAYS)
+            .build())
+        .build()
+        .context("Failed to build lifecycle rule")?;
+    
+    let existing = match with_timeout("s3:get_bucket_lifecycle_configuration", client.get_bucket_lifecycle_configuration()
+        .bucket(&bucket_name)
+        .send())
+        .await
+    {
+        Ok(output) => output.rules().to_vec(),
+        Err(err) if is_missing_lifecycle_error(&err) => Vec::new(),
+        Err(err) => return Err(err.context(format!("Failed to read lifecycle rules of S3 bucket: {}", bucket_name))),
+    };
+    
+    let rules = match merge_lifecycle_rule(existing, abort_incomplete_uploads) {
+        Some(rules) => rules,
+        None => {
+            info!("Lifecycle rules of {} are already up to date", bucket_name);
+            return Ok(());
+        }
+    };
+    let configuration = BucketLifecycleConfiguration::builder()
+        .set_rules(Some(rules))
+        .build()
+        .context("Failed to build lifecycle configuration")?;
+    
+    with_timeout("s3:put_bucket_lifecycle_configuration", client.put_bucket_lifecycle_configuration()
+        .bucket(&bucket_name)
+        .lifecycle_configuration(configuration)
+        .send())
+        .await
+        .context(format!("Failed to apply lifecycle rules to S3 bucket: {}", bucket_name))?;
+    
+    info!("Lifecycle rules applied to {}", bucket_name);
+    Ok(())
+}
+
+/// Whether reading the lifecycle configuration failed only because the bucket has none
+fn is_missing_lifecycle_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<SdkError<GetBucketLifecycleConfigurationError>>()
+        .and_then(|sdk_err| sdk_err.as_service_error())
+        .is_some_and(|service_err| service_err.code() == Some("NoSuchLifecycleConfiguration"))
+}
+
+/// The bucket's rules with ours put in place of any rule sharing its ID, keeping every other
+/// rule as it was. None when the bucket already holds the rule unchanged.
+fn merge_lifecycle_rule(existing: Vec<LifecycleRule>, rule: LifecycleRule) -> Option<Vec<LifecycleRule>> {
+    if existing.contains(&rule) {
+        return None;
+    }
+    
+    let mut rules: Vec<LifecycleRule> = existing.into_iter()
+        .filter(|existing| existing.id() != rule.id())
+        .collect();
+    rules.push(rule);
+    Some(rules)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    
+    /// In-memory ObjectStore that can refuse listings, single keys or whole batches
+    #[derive(Default)]
+    pub(crate) struct FakeStore {
+        pub objects: Mutex<Vec<String>>,
+        pub unlistable_prefixes: HashSet<String>,
+        pub undeletable_keys: HashSet<String>,
+        pub failing_batches: HashSet<usize>,
+        pub batch_sizes: Mutex<Vec<usize>>,
+    }
+    
+    impl FakeStore {
+        pub fn with_objects(keys: impl IntoIterator<Item = String>) -> Self {
+            Self { objects: Mutex::new(keys.into_iter().collect()), ..Default::default() }
+        }
+    }
+    
+    impl ObjectStore for FakeStore {
+        async fn object_size(&self, key: &str) -> Result<Option<i64>> {
+            Ok(self.objects.lock().unwrap().iter().find(|object| *object == key).map(|object| object.len() as i64))
+        }
+        
+        async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+            if self.unlistable_prefixes.contains(prefix) {
+                anyhow::bail!("listing {} failed", prefix);
+            }
+            Ok(self.objects.lock().unwrap().iter().filter(|key| key.starts_with(prefix)).cloned().collect())
+        }
+        
+        async fn delete_batch(&self, keys: &[String]) -> Result<Vec<String>> {
+            let batch = {
+                let mut sizes = self.batch_sizes.lock().unwrap();
+                sizes.push(keys.len());
+                sizes.len() - 1
+            };
+            if self.failing_batches.contains(&batch) {
+                anyhow::bail!("batch {} failed", batch);
+            }
+            let failed: Vec<String> = keys.iter().filter(|key| self.undeletable_keys.contains(*key)).cloned().collect();
+            self.objects.lock().unwrap().retain(|key| !keys.contains(key) || failed.contains(key));
+            Ok(failed)
+        }
+    }
+    
+    fn keys(count: usize) -> Vec<String> {
+        (0..count).map(|index| format!("dicom/case/{}.dcm", index)).collect()
+    }
+    
+    #[tokio::test]
+    async fn delete_files_tolerant_splits_into_batches_of_1000() {
+        let store = FakeStore::with_objects(keys(2500));
+        let failed = delete_files_tolerant(&store, &keys(2500)).await;
+        assert!(failed.is_empty());
+        assert_eq!(*store.batch_sizes.lock().unwrap(), vec![1000, 1000, 500]);
+        assert!(store.objects.lock().unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn delete_files_tolerant_reports_failed_keys_and_batches() {
+        let all = keys(2500);
+        let store = FakeStore {
+            undeletable_keys: HashSet::from([all[2100].clone()]),
+            failing_batches: HashSet::from([1]),
+            ..FakeStore::with_objects(all.clone())
+        };
+        let failed = delete_files_tolerant(&store, &all).await;
+        
+        // The whole second batch plus the one key S3 refused in the third
+        assert_eq!(failed.len(), 1001);
+        assert_eq!(failed[..1000], all[1000..2000]);
+        assert_eq!(failed[1000], all[2100]);
+        assert_eq!(store.batch_sizes.lock().unwrap().len(), 3);
+    }
+    
+    fn put_object_error(status: u16, code: &str) -> anyhow::Error {
+        let metadata = aws_sdk_s3::error::ErrorMetadata::builder().code(code).build();
+        let response = aws_sdk_s3::config::http::HttpResponse::new(
+            status.try_into().expect("status"), aws_sdk_s3::primitives::SdkBody::empty());
+        SdkError::service_error(PutObjectError::generic(metadata), response).into()
+    }
+    
+    #[test]
+    fn conditional_put_conflicts_mean_the_object_exists() {
+        assert!(is_existing_object_error(&put_object_error(412, "PreconditionFailed")));
+        assert!(is_existing_object_error(&put_object_error(409, "ConditionalRequestConflict")));
+        assert!(!is_existing_object_error(&put_object_error(403, "AccessDenied")));
+        assert!(!is_existing_object_error(&anyhow::anyhow!("connection reset")));
+    }
+    
+    fn lifecycle_rule(id: &str, days: i32) -> LifecycleRule {
+        LifecycleRule::builder()
+            .id(id)
+            .status(ExpirationStatus::Enabled)
+            .filter(LifecycleRuleFilter::builder().prefix("").build())
+            .abort_incomplete_multipart_upload(AbortIncompleteMultipartUpload::builder()
+                .days_after_initiation(days)
+                .build())
+            .build()
+            .expect("rule")
+    }
+    
+    #[test]
+    fn merge_lifecycle_rule_keeps_operator_rules() {
+        let ours = lifecycle_rule(ABORT_INCOMPLETE_UPLOAD_RULE_ID, ABORT_INCOMPLETE_UPLOAD_DAYS);
+        let operator = lifecycle_rule("operator-archive", 30);
+        
+        let merged = merge_lifecycle_rule(vec![operator.clone()], ours.clone()).expect("changed");
+        assert_eq!(merged, vec![operator.clone(), ours.clone()]);
+        
+        // An outdated copy of our rule is replaced rather than duplicated
+        let outdated = lifecycle_rule(ABORT_INCOMPLETE_UPLOAD_RULE_ID, 7);
+        let merged = merge_lifecycle_rule(vec![outdated, operator.clone()], ours.clone()).expect("changed");
+        assert_eq!(merged, vec![operator.clone(), ours.clone()]);
+        
+        assert_eq!(merge_lifecycle_rule(vec![operator, ours.clone()], ours), None);
+    }
+    
+    #[tokio::test]
+    async fn resolve_dicom_key_returns_the_first_candidate_that_exists() {
+        let candidates = vec![
+            "dicom/case/1.2.3.dcm".to_string(),
+            "dicom/case/study/1.2.3.dcm".to_string(),
+            "dicom/case/original.dcm".to_string(),
+            "dicom/case/simple.dcm".to_string(),
+        ];
+        let store = FakeStore::with_objects([candidates[2].clone(), candidates[3].clone()]);
+        
+        let resolved = resolve_dicom_key(&store, &candidates).await.unwrap();
+        assert_eq!(resolved, Some((candidates[2].clone(), candidates[2].len() as i64)));
+        
+        let empty = FakeStore::default();
+        assert_eq!(resolve_dicom_key(&empty, &candidates).await.unwrap(), None);
+    }
 }
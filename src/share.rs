@@ -0,0 +1,176 @@
+use std::fmt;
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::hmac;
+
+/// Longest lifetime a caller may request for a share link
+pub const MAX_SHARE_TTL_SECS: i64 = 90 * 24 * 60 * 60;
+
+/// What a share token grants: read access to one case until it expires.
+///
+/// Tokens are signed, not encrypted: the case id travels in plaintext, as it also does in the
+/// preview's thumbnail URL. A quiz link therefore hides the diagnosis only by convention; anyone
+/// holding it can read the case id and fetch the full case from the regular case routes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareGrant {
+    pub case_id: String,
+    pub expires_at: i64,
+    pub hide_diagnosis: bool,
+}
+
+/// Reasons a share token is refused
+#[derive(Debug, PartialEq)]
+pub enum ShareError {
+    Malformed,
+    BadSignature,
+    Expired,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareError::Malformed => write!(f, "Share link is malformed"),
+            ShareError::BadSignature => write!(f, "Share link signature is invalid"),
+            ShareError::Expired => write!(f, "Share link has expired"),
+        }
+    }
+}
+
+/// The signing secret from the loaded config. Sharing is disabled when SHARE_LINK_SECRET is unset.
+pub fn share_secret() -> Option<String> {
+    crate::config::get().share_link_secret.clone()
+}
+
+/// The default share link lifetime (SHARE_LINK_TTL_SECS) from the loaded config
+pub fn default_ttl_secs() -> i64 {
+    crate::config::get().share_link_ttl_secs
+}
+
+/// Clamp a requested lifetime to the allowed range
+pub fn clamp_ttl_secs(requested: i64) -> i64 {
+    requested.clamp(1, MAX_SHARE_TTL_SECS)
+}
+
+/// Whether share links hide the diagnosis by default.
+/// Links are quiz-style unless SHARE_HIDE_DIAGNOSIS is false.
+pub fn hide_diagnosis_by_default() -> bool {
+    crate::config::get().share_hide_diagnosis
+}
+
+// The signed part of a token: {case_id}.{expires_at}.{q|a}, readable by whoever holds the link
+fn payload(grant: &ShareGrant) -> String {
+    let mode = if grant.hide_diagnosis { "q" } else { "a" };
+    format!("{}.{}.{}", grant.case_id, grant.expires_at, mode)
+}
+
+/// Sign a grant into a URL-safe token
+pub fn mint(secret: &str, grant: &ShareGrant) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let payload = payload(grant);
+    let tag = hmac::sign(&key, payload.as_bytes());
+    format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+}
+
+/// Check a token's signature and expiry, returning what it grants
+pub fn verify(secret: &str, token: &str, now: i64) -> Result<ShareGrant, ShareError> {
+    let (payload, signature) = token.rsplit_once('.').ok_or(ShareError::Malformed)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| ShareError::Malformed)?;
+
+    // Check the signature before trusting anything in the payload
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, payload.as_bytes(), &signature).map_err(|_| ShareError::BadSignature)?;
+
+    let mut parts = payload.split('.');
+    let (case_id, expires_at, mode) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(case_id), Some(expires_at), Some(mode), None) => (case_id, expires_at, mode),
+        _ => return Err(ShareError::Malformed),
+    };
+
+    let expires_at = expires_at.parse::<i64>().map_err(|_| ShareError::Malformed)?;
+    let hide_diagnosis = match mode {
+        "q" => true,
+        "a" => false,
+        _ => return Err(ShareError::Malformed),
+    };
+
+    if now >= expires_at {
+        return Err(ShareError::Expired);
+    }
+
+    Ok(ShareGrant {
+        case_id: case_id.to_string(),
+        expires_at,
+        hide_diagnosis,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn grant() -> ShareGrant {
+        ShareGrant {
+            case_id: "11111111-1111-1111-1111-111111111111".to_string(),
+            expires_at: 1_000,
+            hide_diagnosis: true,
+        }
+    }
+
+    // Re-sign a hand-built payload so only its shape is under test
+    fn signed(payload: &str) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET.as_bytes());
+        let tag = hmac::sign(&key, payload.as_bytes());
+        format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    #[test]
+    fn minted_token_verifies_to_its_grant() {
+        let token = mint(SECRET, &grant());
+        assert_eq!(verify(SECRET, &token, 999), Ok(grant()));
+
+        let answers = ShareGrant { hide_diagnosis: false, ..grant() };
+        assert_eq!(verify(SECRET, &mint(SECRET, &answers), 0), Ok(answers));
+    }
+
+    #[test]
+    fn tampered_token_is_refused() {
+        let token = mint(SECRET, &grant());
+
+        // Revealing the answers of a quiz link changes the signed payload
+        let revealed = token.replacen(".q.", ".a.", 1);
+        assert_eq!(verify(SECRET, &revealed, 0), Err(ShareError::BadSignature));
+
+        let (payload, _) = token.rsplit_once('.').expect("signature");
+        let forged = format!("{}.{}", payload, URL_SAFE_NO_PAD.encode([0u8; 32]));
+        assert_eq!(verify(SECRET, &forged, 0), Err(ShareError::BadSignature));
+
+        assert_eq!(verify("other-secret", &token, 0), Err(ShareError::BadSignature));
+    }
+
+    #[test]
+    fn token_expires_at_its_expiry() {
+        let token = mint(SECRET, &grant());
+        assert!(verify(SECRET, &token, 999).is_ok());
+        assert_eq!(verify(SECRET, &token, 1_000), Err(ShareError::Expired));
+        assert_eq!(verify(SECRET, &token, 1_001), Err(ShareError::Expired));
+    }
+
+    #[test]
+    fn malformed_token_is_refused() {
+        assert_eq!(verify(SECRET, "no-signature", 0), Err(ShareError::Malformed));
+        assert_eq!(verify(SECRET, "case.1000.q.!!!", 0), Err(ShareError::Malformed));
+        assert_eq!(verify(SECRET, &signed("case.1000.x"), 0), Err(ShareError::Malformed));
+        assert_eq!(verify(SECRET, &signed("case.1000.q.extra"), 0), Err(ShareError::Malformed));
+        assert_eq!(verify(SECRET, &signed("case.soon.q"), 0), Err(ShareError::Malformed));
+    }
+
+    #[test]
+    fn clamp_ttl_secs_keeps_lifetimes_in_range() {
+        assert_eq!(clamp_ttl_secs(-5), 1);
+        assert_eq!(clamp_ttl_secs(0), 1);
+        assert_eq!(clamp_ttl_secs(3_600), 3_600);
+        assert_eq!(clamp_ttl_secs(MAX_SHARE_TTL_SECS), MAX_SHARE_TTL_SECS);
+        assert_eq!(clamp_ttl_secs(i64::MAX), MAX_SHARE_TTL_SECS);
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use dicom_core::{DataElement, DicomValue, Length, PrimitiveValue, Tag, VR};
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+
+use crate::dicom::{generate_uid, is_valid_uid, EXPLICIT_VR_LITTLE_ENDIAN};
+use crate::models::Case;
+
+// Basic Text SR Storage
+const BASIC_TEXT_SR_SOP_CLASS_UID: &str = "1.2.840.10008.5.1.4.1.1.88.11";
+
+// UTF-8, so findings in any language survive the export
+const UTF8_CHARACTER_SET: &str = "ISO_IR 192";
+
+/// A coded concept name: code value, coding scheme designator and code meaning
+type Concept = (&'static str, &'static str, &'static str);
+
+// Document title and content item names from the basic diagnostic imaging report template (TID 2000)
+const DOCUMENT_TITLE: Concept = ("18748-4", "LN", "Diagnostic Imaging Report");
+const FINDING: Concept = ("121071", "DCM", "Finding");
+const IMPRESSION: Concept = ("121073", "DCM", "Impression");
+
+/// Build a Basic Text SR holding a case's findings and diagnosis as text content items.
+/// The SR joins the case's study so a PACS files it next to the images it describes;
+/// a case without a valid Study Instance UID gets a new study.
+pub fn build_text_sr(case: &Case) -> Result<Vec<u8>> {
+    let now = chrono::Utc::now();
+    let date = now.format("%Y%m%d").to_string();
+    let time = now.format("%H%M%S").to_string();
+    
+    let study_instance_uid = if is_valid_uid(&case.study_instance_uid) {
+        case.study_instance_uid.clone()
+    } else {
+        generate_uid()
+    };
+    let study_date = if case.study_date.len() == 8 && case.study_date.chars().all(|c| c.is_ascii_digit()) {
+        case.study_date.as_str()
+    } else {
+        ""
+    };
+    
+    let mut obj = InMemDicomObject::new_empty();
+    
+    // SOP common
+    put_text(&mut obj, Tag(0x0008, 0x0005), VR::CS, UTF8_CHARACTER_SET);
+    put_text(&mut obj, Tag(0x0008, 0x0016), VR::UI, BASIC_TEXT_SR_SOP_CLASS_UID);
+    put_text(&mut obj, Tag(0x0008, 0x0018), VR::UI, &generate_uid());
+    
+    // Patient and general study, carried over from the case
+    put_text(&mut obj, Tag(0x0010, 0x0010), VR::PN, &case.patient_name);
+    put_text(&mut obj, Tag(0x0010, 0x0020), VR::LO, &case.patient_id);
+    put_empty(&mut obj, Tag(0x0010, 0x0030), VR::DA);
+    put_empty(&mut obj, Tag(0x0010, 0x0040), VR::CS);
+    put_text(&mut obj, Tag(0x0020, 0x000D), VR::UI, &study_instance_uid);
+    put_text(&mut obj, Tag(0x0008, 0x0020), VR::DA, study_date);
+    put_empty(&mut obj, Tag(0x0008, 0x0030), VR::TM);
+    put_empty(&mut obj, Tag(0x0008, 0x0050), VR::SH);
+    put_empty(&mut obj, Tag(0x0008, 0x0090), VR::PN);
+    put_empty(&mut obj, Tag(0x0020, 0x0010), VR::SH);
+    put_text(&mut obj, Tag(0x0008, 0x1030), VR::LO, &case.study_description);
+    
+    // SR document series and equipment
+    put_text(&mut obj, Tag(0x0008, 0x0060), VR::CS, "SR");
+    put_text(&mut obj, Tag(0x0020, 0x000E), VR::UI, &generate_uid());
+    put_text(&mut obj, Tag(0x0020, 0x0011), VR::IS, "1");
+    put_sequence(&mut obj, Tag(0x0008, 0x1111), Vec::new());
+    put_empty(&mut obj, Tag(0x0008, 0x0070), VR::LO);
+    
+    // SR document general
+    put_text(&mut obj, Tag(0x0020, 0x0013), VR::IS, "1");
+    put_text(&mut obj, Tag(0x0008, 0x0023), VR::DA, &date);
+    put_text(&mut obj, Tag(0x0008, 0x0033), VR::TM, &time);
+    put_text(&mut obj, Tag(0x0040, 0xA491), VR::CS, "COMPLETE");
+    put_text(&mut obj, Tag(0x0040, 0xA493), VR::CS, "UNVERIFIED");
+    put_sequence(&mut obj, Tag(0x0040, 0xA372), Vec::new());
+    
+    // Document content: a titled container of text items
+    put_text(&mut obj, Tag(0x0040, 0xA040), VR::CS, "CONTAINER");
+    put_sequence(&mut obj, Tag(0x0040, 0xA043), vec![concept_item(DOCUMENT_TITLE)]);
+    put_text(&mut obj, Tag(0x0040, 0xA050), VR::CS, "SEPARATE");
+    let content: Vec<InMemDicomObject> = [(FINDING, &case.findings), (IMPRESSION, &case.diagnosis)]
+        .into_iter()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(concept, text)| text_item(concept, text.trim()))
+        .collect();
+    put_sequence(&mut obj, Tag(0x0040, 0xA730), content);
+    
+    let file = obj.with_meta(FileMetaTableBuilder::new()
+            .media_storage_sop_class_uid(BASIC_TEXT_SR_SOP_CLASS_UID)
+            .transfer_syntax(EXPLICIT_VR_LITTLE_ENDIAN))
+        .context("Failed to build SR file meta group")?;
+    
+    let mut data = Vec::new();
+    file.write_all(&mut data).context("Failed to encode SR")?;
+    Ok(data)
+}
+
+// A TEXT content item contained in the document
+fn text_item(concept: Concept, text: &str) -> InMemDicomObject {
+    let mut item = InMemDicomObject::new_empty();
+    put_text(&mut item, Tag(0x0040, 0xA010), VR::CS, "CONTAINS");
+    put_text(&mut item, Tag(0x0040, 0xA040), VR::CS, "TEXT");
+    put_sequence(&mut item, Tag(0x0040, 0xA043), vec![concept_item(concept)]);
+    put_text(&mut item, Tag(0x0040, 0xA160), VR::UT, text);
+    item
+}
+
+// A code sequence item naming a concept
+fn concept_item((value, scheme, meaning): Concept) -> InMemDicomObject {
+    let mut item = InMemDicomObject::new_empty();
+    put_text(&mut item, Tag(0x0008, 0x0100), VR::SH, value);
+    put_text(&mut item, Tag(0x0008, 0x0102), VR::SH, scheme);
+    put_text(&mut item, Tag(0x0008, 0x0104), VR::LO, meaning);
+    item
+}
+
+// Values must have an even length: UIDs are padded with NUL, other text with a space
+pub(crate) fn put_text(obj: &mut InMemDicomObject, tag: Tag, vr: VR, value: &str) {
+    let mut value = value.to_string();
+    if value.len() % 2 == 1 {
+        value.push(if vr == VR::UI { '\0' } else { ' ' });
+    }
+    obj.put(DataElement::new(tag, vr, PrimitiveValue::from(value)));
+}
+
+// A type 2 attribute: required, but allowed to be empty
+fn put_empty(obj: &mut InMemDicomObject, tag: Tag, vr: VR) {
+    obj.put(DataElement::new(tag, vr, PrimitiveValue::Empty));
+}
+
+fn put_sequence(obj: &mut InMemDicomObject, tag: Tag, items: Vec<InMemDicomObject>) {
+    obj.put(DataElement::new(tag, VR::SQ, DicomValue::Sequence {
+        items: items.into(),
+        size: Length::UNDEFINED,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+        let value = obj.element(tag).expect("element").to_str().expect("text").into_owned();
+        value.trim_end_matches(['\0', ' ']).to_string()
+    }
+
+    #[test]
+    fn build_text_sr_round_trips_through_the_parser() {
+        let case: Case = serde_json::from_value(serde_json::json!({
+            "case_id": "c", "title": "t", "description": "", "modality": "CT", "anatomy": "",
+            "diagnosis": "Appendicitis", "findings": "Dilated appendix", "tags": [], "image_ids": [],
+            "study_instance_uid": "1.2.840.113619.2.55", "series_instance_uid": "",
+            "created_at": "", "updated_at": ""
+        })).expect("case");
+
+        let data = build_text_sr(&case).expect("sr");
+        let obj = dicom_object::from_reader(&data[128..]).expect("parsed");
+
+        assert_eq!(obj.meta().media_storage_sop_class_uid().trim_end_matches('\0'), BASIC_TEXT_SR_SOP_CLASS_UID);
+        assert_eq!(text(&obj, Tag(0x0008, 0x0016)), BASIC_TEXT_SR_SOP_CLASS_UID);
+        assert_eq!(text(&obj, Tag(0x0020, 0x000D)), "1.2.840.113619.2.55");
+
+        let items = obj.element(Tag(0x0040, 0xA730)).expect("content").items().expect("sequence");
+        let texts: Vec<(String, String)> = items.iter()
+            .map(|item| {
+                let name = item.element(Tag(0x0040, 0xA043)).expect("concept").items().expect("codes");
+                (text(&name[0], Tag(0x0008, 0x0104)), text(item, Tag(0x0040, 0xA160)))
+            })
+            .collect();
+        assert_eq!(texts, vec![
+            ("Finding".to_string(), "Dilated appendix".to_string()),
+            ("Impression".to_string(), "Appendicitis".to_string()),
+        ]);
+        assert!(items.iter().all(|item| text(item, Tag(0x0040, 0xA040)) == "TEXT"));
+    }
+}
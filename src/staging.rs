@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use std::collections::HashSet;
+use tracing::info;
+
+use crate::models::{StagingRecord, StagingStage};
+use crate::s3;
+
+// Longest accepted client-supplied upload id
+const MAX_UPLOAD_ID_LEN: usize = 128;
+
+/// S3 key holding the staging record for an upload
+fn record_key(upload_id: &str) -> String {
+    format!("staging/{}.json", upload_id)
+}
+
+/// Upload ids become part of an S3 key, so keep them to a safe character set
+pub fn is_valid_upload_id(upload_id: &str) -> bool {
+    !upload_id.is_empty()
+        && upload_id.len() <= MAX_UPLOAD_ID_LEN
+        && upload_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Load the staging record for an upload, if one exists
+pub async fn load_record(client: &Client, upload_id: &str) -> Result<Option<StagingRecord>> {
+    let key = record_key(upload_id);
+    if s3::file_size(client, &key).await?.is_none() {
+        return Ok(None);
+    }
+    
+    let data = s3::download_file(client, &key).await?;
+    let record = serde_json::from_slice(&data)
+        .context(format!("Failed to parse staging record: {}", key))?;
+    
+    Ok(Some(record))
+}
+
+/// Case ids of staged creates that have not reached Saved. Their objects are already in S3
+/// while the case record may not exist yet, so they must not be taken for orphans.
+pub async fn pending_case_ids(client: &Client) -> Result<HashSet<String>> {
+    let mut pending = HashSet::new();
+    for (key, _) in s3::list_files(client, "staging/").await? {
+        let data = s3::download_file(client, &key).await?;
+        let record: StagingRecord = serde_json::from_slice(&data)
+            .context(format!("Failed to parse staging record: {}", key))?;
+        if record.stage != StagingStage::Saved {
+            pending.insert(record.case_id);
+        }
+    }
+    
+    Ok(pending)
+}
+
+/// Persist a staging record, stamping the time of the update
+pub async fn save_record(client: &Client, record: &mut StagingRecord) -> Result<()> {
+    record.updated_at = chrono::Utc::now().to_rfc3339();
+    
+    let data = serde_json::to_vec(record).context("Failed to serialize staging record")?;
+    s3::upload_object(client, &record_key(&record.upload_id), data, "application/json").await?;
+    
+    info!("Staging record {} saved at stage {:?}", record.upload_id, record.stage);
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use serde::Serialize;
+
+/// A canonical body region and the free-text terms that map to it
+#[derive(Debug, Serialize)]
+pub struct AnatomyTerm {
+    pub name: &'static str,
+    pub synonyms: &'static [&'static str],
+}
+
+/// Result of normalizing a free-text anatomy value
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnatomyMatch {
+    pub value: String,
+    pub recognized: bool,
+}
+
+// Curated list of body regions used for anatomy filtering
+pub const ANATOMY_TERMS: &[AnatomyTerm] = &[
+    AnatomyTerm { name: "Head", synonyms: &["head", "skull", "cranium", "face", "facial", "orbit", "orbits", "sinus", "sinuses", "temporal bone"] },
+    AnatomyTerm { name: "Brain", synonyms: &["brain", "cerebrum", "cerebral", "neuro", "intracranial"] },
+    AnatomyTerm { name: "Neck", synonyms: &["neck", "thyroid", "larynx", "pharynx", "soft tissue neck"] },
+    AnatomyTerm { name: "Spine", synonyms: &["spine", "spinal", "c-spine", "cervical spine", "t-spine", "thoracic spine", "l-spine", "lumbar spine", "lumbar", "sacrum"] },
+    AnatomyTerm { name: "Chest", synonyms: &["chest", "thorax", "thoracic", "lung", "lungs", "pulmonary", "mediastinum"] },
+    AnatomyTerm { name: "Heart", synonyms: &["heart", "cardiac", "coronary", "coronaries"] },
+    AnatomyTerm { name: "Breast", synonyms: &["breast", "breasts", "mammo", "mammography"] },
+    AnatomyTerm { name: "Abdomen", synonyms: &["abdomen", "abdominal", "abd", "liver", "hepatic", "pancreas", "spleen", "kidney", "kidneys", "renal", "bowel", "gi"] },
+    AnatomyTerm { name: "Pelvis", synonyms: &["pelvis", "pelvic", "bladder", "prostate", "uterus", "ovary", "ovaries"] },
+    AnatomyTerm { name: "Upper Extremity", synonyms: &["upper extremity", "arm", "shoulder", "elbow", "wrist", "hand", "humerus", "forearm", "finger"] },
+    AnatomyTerm { name: "Lower Extremity", synonyms: &["lower extremity", "leg", "hip", "knee", "ankle", "foot", "femur", "tibia", "toe"] },
+    AnatomyTerm { name: "Vascular", synonyms: &["vascular", "aorta", "aortic", "vessels", "angio", "angiography"] },
+    AnatomyTerm { name: "Whole Body", synonyms: &["whole body", "wholebody", "body", "total body"] },
+];
+
+// Words up to this long tolerate a single typo; longer ones tolerate two
+const SHORT_WORD_LEN: usize = 5;
+
+// DICOM Laterality codes and the side names appended to a region
+const LATERALITIES: &[(&str, &str)] = &[("L", "Left"), ("R", "Right"), ("B", "Bilateral")];
+
+/// Qualify a region with a DICOM Laterality code, e.g. "Lower Extremity (Left)".
+/// Other codes, such as U for unpaired, leave the region as it is.
+pub fn with_laterality(region: &str, laterality: &str) -> String {
+    match LATERALITIES.iter().find(|(code, _)| code.eq_ignore_ascii_case(laterality.trim())) {
+        Some((_, side)) => format!("{} ({})", region, side),
+        None => region.to_string(),
+    }
+}
+
+/// The body region of an anatomy value, without any side added by with_laterality,
+/// so "Lower Extremity (Left)" and "Lower Extremity (Right)" group together.
+pub fn region_of(anatomy: &str) -> &str {
+    let trimmed = anatomy.trim();
+    LATERALITIES.iter()
+        .find_map(|(_, side)| trimmed.strip_suffix(&format!(" ({})", side)))
+        .unwrap_or(trimmed)
+}
+
+/// Map a free-text anatomy value to the closest canonical term.
+/// Unrecognized values are kept as entered (trimmed) and flagged.
+/// A side added by with_laterality is kept on the matched term; use region_of to
+/// compare or group by region alone.
+pub fn normalize_anatomy(input: &str) -> AnatomyMatch {
+    let trimmed = input.trim();
+    let lowered = trimmed.to_lowercase();
+
+    for (_, side) in LATERALITIES {
+        if let Some(region) = trimmed.strip_suffix(&format!(" ({})", side)) {
+            let matched = normalize_anatomy(region);
+            if matched.recognized {
+                return AnatomyMatch {
+                    value: format!("{} ({})", matched.value, side),
+                    recognized: true,
+                };
+            }
+        }
+    }
+
+    // Exact match against canonical names and synonyms
+    for term in ANATOMY_TERMS {
+        if term.name.to_lowercase() == lowered || term.synonyms.contains(&lowered.as_str()) {
+            return recognized(term.name);
+        }
+    }
+
+    // Multi-word values such as "left knee" or "CT chest" - match any word
+    for word in lowered.split(|c: char| !c.is_alphanumeric() && c != '-') {
+        for term in ANATOMY_TERMS {
+            if term.synonyms.contains(&word) {
+                return recognized(term.name);
+            }
+        }
+    }
+
+    // Tolerate small typos on longer words ("abdomn", "pelivs")
+    if lowered.len() >= 5 {
+        let closest = ANATOMY_TERMS.iter()
+            .flat_map(|term| term.synonyms.iter().map(|synonym| (term.name, *synonym)))
+            .map(|(name, synonym)| (name, edit_distance(&lowered, synonym)))
+            .min_by_key(|(_, distance)| *distance);
+
+        if let Some((name, distance)) = closest {
+            if distance <= max_typo_distance(lowered.chars().count()) {
+                return recognized(name);
+            }
+        }
+    }
+
+    AnatomyMatch {
+        value: trimmed.to_string(),
+        recognized: false,
+    }
+}
+
+fn recognized(name: &str) -> AnatomyMatch {
+    AnatomyMatch {
+        value: name.to_string(),
+        recognized: true,
+    }
+}
+
+// Largest edit distance accepted for a word of this length. Two edits would turn short
+// terms into one another ("bones" and "bowel", "fetus" and "femur").
+fn max_typo_distance(word_len: usize) -> usize {
+    if word_len <= SHORT_WORD_LEN { 1 } else { 2 }
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b_chars.len() + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_laterality_qualifies_paired_sides_only() {
+        assert_eq!(with_laterality("Lower Extremity", "L"), "Lower Extremity (Left)");
+        assert_eq!(with_laterality("Breast", " b "), "Breast (Bilateral)");
+        assert_eq!(with_laterality("Chest", "U"), "Chest");
+        assert_eq!(with_laterality("Chest", ""), "Chest");
+    }
+
+    #[test]
+    fn region_of_strips_the_side() {
+        assert_eq!(region_of("Lower Extremity (Left)"), "Lower Extremity");
+        assert_eq!(region_of("Lower Extremity (Right)"), "Lower Extremity");
+        assert_eq!(region_of("Breast (Bilateral)"), "Breast");
+        assert_eq!(region_of(" Chest "), "Chest");
+        assert_eq!(region_of("Head (Other)"), "Head (Other)");
+    }
+
+    #[test]
+    fn typos_are_tolerated_in_proportion_to_word_length() {
+        assert_eq!(normalize_anatomy("abdomn").value, "Abdomen");
+        assert_eq!(normalize_anatomy("pelivs").value, "Pelvis");
+        assert_eq!(normalize_anatomy("anklr").value, "Lower Extremity");
+        
+        assert!(!normalize_anatomy("bones").recognized);
+        assert!(!normalize_anatomy("fetus").recognized);
+    }
+
+    #[test]
+    fn normalize_anatomy_keeps_a_recognized_side() {
+        let matched = normalize_anatomy("knee (Left)");
+        assert_eq!(matched, AnatomyMatch { value: "Lower Extremity (Left)".to_string(), recognized: true });
+        assert_eq!(region_of(&matched.value), "Lower Extremity");
+        
+        let unknown = normalize_anatomy("widget (Left)");
+        assert!(!unknown.recognized);
+        assert_eq!(unknown.value, "widget (Left)");
+    }
+}
@@ -0,0 +1,60 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use anyhow::Result;
+use tracing::error;
+
+/// Error returned when an AWS call exceeds its time budget
+#[derive(Debug)]
+pub struct UpstreamTimeout {
+    pub operation: String,
+    pub budget: Duration,
+}
+
+impl fmt::Display for UpstreamTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {} ms", self.operation, self.budget.as_millis())
+    }
+}
+
+impl StdError for UpstreamTimeout {}
+
+/// The per-call budget (AWS_CALL_TIMEOUT_MS) from the loaded config
+pub fn call_timeout() -> Duration {
+    Duration::from_millis(crate::config::get().aws_call_timeout_ms)
+}
+
+/// Run an AWS call with the configured budget, cancelling it when exceeded
+pub async fn with_timeout<F, T, E>(operation: &str, call: F) -> Result<T>
+where
+    F: Future<Output = std::result::Result<T, E>>,
+    E: StdError + Send + Sync + 'static,
+{
+    let budget = call_timeout();
+
+    match tokio::time::timeout(budget, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            error!("AWS call {} exceeded budget of {} ms", operation, budget.as_millis());
+            Err(UpstreamTimeout { operation: operation.to_string(), budget }.into())
+        }
+    }
+}
+
+/// Check whether an anyhow error was caused by an upstream timeout
+pub fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<UpstreamTimeout>())
+}
+
+/// Check whether a boxed handler error was caused by an upstream timeout
+pub fn is_timeout_error(err: &(dyn StdError + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(cause) = current {
+        if cause.is::<UpstreamTimeout>() {
+            return true;
+        }
+        current = cause.source();
+    }
+    false
+}